@@ -1,2 +1,3 @@
 pub mod operation;
+pub mod role;
 pub mod user;
@@ -1,7 +1,9 @@
+use crate::acl::role::Permission;
 use crate::models::session::SessionKey;
 use crate::models::speaker::SpeakerKey;
 use crate::models::sponsor::SponsorKey;
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum Operation {
     Administration,
     ViewSite,
@@ -12,6 +14,18 @@ pub enum Operation {
     EditSession(SessionKey),
     EditSpeaker(SpeakerKey),
     EditSponsor(SponsorKey),
+    /// Create or delete sessions
+    EditSessions,
+    /// Create or delete speakers
+    EditSpeakers,
+    /// Create or delete sponsors, and their categories
+    EditSponsors,
+    EditTeam,
+    EditProgram,
+    EditSchedule,
+    ManageUsers,
+    Synchronize,
+    Generate,
 }
 
 impl Operation {
@@ -25,6 +39,43 @@ impl Operation {
         }
     }
 
+    /// The permission allowing the operation on any resource
+    pub fn permission(&self) -> Permission {
+        match self {
+            Operation::Administration => Permission::Administration,
+            Operation::ViewSite
+            | Operation::ViewSession(_)
+            | Operation::ViewSpeaker(_)
+            | Operation::ViewSponsor(_) => Permission::ViewSite,
+            Operation::EditSite => Permission::EditSite,
+            Operation::EditSession(_) | Operation::EditSessions => Permission::EditSessions,
+            Operation::EditSpeaker(_) | Operation::EditSpeakers => Permission::EditSpeakers,
+            Operation::EditSponsor(_) | Operation::EditSponsors => Permission::EditSponsors,
+            Operation::EditTeam => Permission::EditTeam,
+            Operation::EditProgram => Permission::EditProgram,
+            Operation::EditSchedule => Permission::EditSchedule,
+            Operation::ManageUsers => Permission::ManageUsers,
+            Operation::Synchronize => Permission::Synchronize,
+            Operation::Generate => Permission::Generate,
+        }
+    }
+
+    /// The permission allowing the operation on the resources of the user
+    pub fn own_permission(&self) -> Option<Permission> {
+        match self {
+            Operation::ViewSession(_) | Operation::EditSession(_) => {
+                Some(Permission::EditOwnSessions)
+            }
+            Operation::ViewSpeaker(_) | Operation::EditSpeaker(_) => {
+                Some(Permission::EditOwnSpeaker)
+            }
+            Operation::ViewSponsor(_) | Operation::EditSponsor(_) => {
+                Some(Permission::EditOwnSponsor)
+            }
+            _ => None,
+        }
+    }
+
     pub fn session(&self) -> Option<SessionKey> {
         match self {
             Operation::ViewSession(k) => Some(k.clone()),
@@ -303,4 +354,31 @@ mod tests {
             assert_eq!(result, None);
         }
     }
+
+    mod permission {
+        use super::*;
+
+        #[test]
+        fn should_need_view_site_to_view_any_resource() {
+            let operation = Operation::ViewSession(SessionKey::new("plop"));
+            assert_eq!(operation.permission(), Permission::ViewSite);
+            assert_eq!(
+                operation.own_permission(),
+                Some(Permission::EditOwnSessions)
+            );
+        }
+
+        #[test]
+        fn should_share_permission_of_collection() {
+            let operation = Operation::EditSponsor(SponsorKey::new("plop"));
+            assert_eq!(operation.permission(), Operation::EditSponsors.permission());
+            assert_eq!(operation.own_permission(), Some(Permission::EditOwnSponsor));
+        }
+
+        #[test]
+        fn should_have_no_own_permission_without_resource() {
+            let result = Operation::EditSchedule.own_permission();
+            assert_eq!(result, None);
+        }
+    }
 }
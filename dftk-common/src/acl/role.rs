@@ -0,0 +1,193 @@
+use serde::{Deserialize, Serialize};
+use slug::slugify;
+
+/// What a role allows, the `Own` permissions are limited to the resources of the user
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Events, backups and history
+    Administration,
+    ViewSite,
+    /// The site info
+    EditSite,
+    EditSessions,
+    EditSpeakers,
+    /// The sponsors and the sponsor categories
+    EditSponsors,
+    /// The team members and the member types
+    EditTeam,
+    /// The session categories and formats
+    EditProgram,
+    /// The rooms, the slots and the schedule
+    EditSchedule,
+    ManageUsers,
+    /// Fetch the sessions and the speakers from Conference Hall
+    Synchronize,
+    /// Generate the Hugo site
+    Generate,
+    /// The sessions of the speaker
+    EditOwnSessions,
+    /// The profile of the speaker
+    EditOwnSpeaker,
+    /// The page of the sponsor
+    EditOwnSponsor,
+}
+
+impl Permission {
+    pub fn all() -> Vec<Permission> {
+        vec![
+            Permission::Administration,
+            Permission::ViewSite,
+            Permission::EditSite,
+            Permission::EditSessions,
+            Permission::EditSpeakers,
+            Permission::EditSponsors,
+            Permission::EditTeam,
+            Permission::EditProgram,
+            Permission::EditSchedule,
+            Permission::ManageUsers,
+            Permission::Synchronize,
+            Permission::Generate,
+            Permission::EditOwnSessions,
+            Permission::EditOwnSpeaker,
+            Permission::EditOwnSponsor,
+        ]
+    }
+
+    /// Holding the permission also allows the other one,
+    /// editing every session, speaker or sponsor includes editing its own
+    pub fn includes(self, other: Permission) -> bool {
+        match (self, other) {
+            (Permission::EditSessions, Permission::EditOwnSessions)
+            | (Permission::EditSpeakers, Permission::EditOwnSpeaker)
+            | (Permission::EditSponsors, Permission::EditOwnSponsor) => true,
+            _ => self == other,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RoleKey(String);
+
+impl RoleKey {
+    pub fn new(name: &str) -> Self {
+        Self(slugify(name))
+    }
+
+    pub fn admin() -> Self {
+        Self::new("admin")
+    }
+    pub fn speaker() -> Self {
+        Self::new("speaker")
+    }
+    pub fn sponsor() -> Self {
+        Self::new("sponsor")
+    }
+}
+
+impl Into<String> for RoleKey {
+    fn into(self) -> String {
+        self.0
+    }
+}
+
+/// A named set of permissions, stored in the database
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Role {
+    #[serde(rename = "_id")]
+    key: RoleKey,
+    name: String,
+    permissions: Vec<Permission>,
+}
+
+impl Role {
+    pub fn new(key: RoleKey, name: String, permissions: Vec<Permission>) -> Self {
+        Self {
+            key,
+            name,
+            permissions,
+        }
+    }
+
+    pub fn key(&self) -> RoleKey {
+        self.key.clone()
+    }
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn permissions(&self) -> &[Permission] {
+        &self.permissions
+    }
+    pub fn has(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    /// The roles created with a new database, they could be changed later
+    pub fn defaults() -> Vec<Role> {
+        vec![
+            Role::new(RoleKey::admin(), "Admin".into(), Permission::all()),
+            Role::new(
+                RoleKey::new("program-committee"),
+                "Program committee".into(),
+                vec![
+                    Permission::ViewSite,
+                    Permission::EditSessions,
+                    Permission::EditSpeakers,
+                    Permission::EditProgram,
+                    Permission::EditSchedule,
+                ],
+            ),
+            Role::new(
+                RoleKey::new("sponsor-manager"),
+                "Sponsor manager".into(),
+                vec![Permission::ViewSite, Permission::EditSponsors],
+            ),
+            Role::new(
+                RoleKey::new("volunteer-lead"),
+                "Volunteer lead".into(),
+                vec![Permission::ViewSite, Permission::EditTeam],
+            ),
+            Role::new(
+                RoleKey::speaker(),
+                "Speaker".into(),
+                vec![Permission::EditOwnSessions, Permission::EditOwnSpeaker],
+            ),
+            Role::new(
+                RoleKey::sponsor(),
+                "Sponsor".into(),
+                vec![Permission::EditOwnSponsor],
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_give_every_permission_to_admin() {
+        let admin = Role::defaults()
+            .into_iter()
+            .find(|it| it.key() == RoleKey::admin())
+            .unwrap();
+
+        for permission in Permission::all() {
+            assert!(admin.has(permission));
+        }
+    }
+
+    #[test]
+    fn should_include_own_permissions() {
+        assert!(Permission::EditSessions.includes(Permission::EditOwnSessions));
+        assert!(Permission::EditSponsors.includes(Permission::EditSponsors));
+        assert!(!Permission::EditOwnSpeaker.includes(Permission::EditSpeakers));
+        assert!(!Permission::EditSite.includes(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn should_serialize_permissions_in_snake_case() {
+        let result = serde_json::to_string(&Permission::EditOwnSessions).unwrap();
+        assert_eq!(result, "\"edit_own_sessions\"");
+    }
+}
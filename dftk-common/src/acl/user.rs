@@ -6,6 +6,8 @@ use email::Mailbox;
 use serde::de::Visitor;
 use serde::{de, Deserialize, Deserializer, Serialize};
 
+use crate::acl::role::{Permission, RoleKey};
+use crate::models::site::EventId;
 use crate::models::speaker::SpeakerKey;
 use crate::models::sponsor::SponsorKey;
 
@@ -55,10 +57,87 @@ impl<'de> Deserialize<'de> for Email {
 #[serde(tag = "type")]
 pub enum User {
     Guest,
-    Admin { email: Email },
-    Team { email: Email },
-    Speaker { email: Email, key: SpeakerKey },
-    Sponsor { email: Email, key: SponsorKey },
+    Admin {
+        email: Email,
+    },
+    Team {
+        email: Email,
+        #[serde(default)]
+        roles: Vec<RoleKey>,
+    },
+    /// A speaker of an event, `None` for the collections without event
+    Speaker {
+        email: Email,
+        key: SpeakerKey,
+        #[serde(default)]
+        event: Option<EventId>,
+    },
+    /// A sponsor of an event, `None` for the collections without event
+    Sponsor {
+        email: Email,
+        key: SponsorKey,
+        #[serde(default)]
+        event: Option<EventId>,
+    },
+    /// An automation client authenticated by an API token
+    ApiClient {
+        token: String,
+        owner: Email,
+        #[serde(default)]
+        roles: Vec<RoleKey>,
+        #[serde(default)]
+        permissions: Vec<Permission>,
+    },
+}
+
+impl User {
+    /// The email of an authenticated user, an API client is not its owner
+    pub fn email(&self) -> Option<Email> {
+        match self {
+            User::Guest | User::ApiClient { .. } => None,
+            User::Admin { email }
+            | User::Team { email, .. }
+            | User::Speaker { email, .. }
+            | User::Sponsor { email, .. } => Some(email.clone()),
+        }
+    }
+
+    /// The event of a speaker or a sponsor, the other users are shared by every event
+    pub fn event(&self) -> Option<EventId> {
+        match self {
+            User::Speaker { event, .. } | User::Sponsor { event, .. } => event.clone(),
+            _ => None,
+        }
+    }
+
+    /// Bind a speaker or a sponsor to the event of its key
+    pub fn in_event(self, event: Option<EventId>) -> Self {
+        match self {
+            User::Speaker { email, key, .. } => User::Speaker { email, key, event },
+            User::Sponsor { email, key, .. } => User::Sponsor { email, key, event },
+            user => user,
+        }
+    }
+
+    /// The roles granting the permissions of the user
+    pub fn roles(&self) -> Vec<RoleKey> {
+        match self {
+            User::Guest => vec![],
+            User::Admin { .. } => vec![RoleKey::admin()],
+            User::Team { roles, .. } => roles.clone(),
+            User::Speaker { .. } => vec![RoleKey::speaker()],
+            User::Sponsor { .. } => vec![RoleKey::sponsor()],
+            User::ApiClient { roles, .. } => roles.clone(),
+        }
+    }
+
+    /// The permissions granted without role
+    pub fn permissions(&self) -> Vec<Permission> {
+        match self {
+            User::ApiClient { permissions, .. } => permissions.clone(),
+            _ => vec![],
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -106,14 +185,25 @@ mod tests {
         vec![
             User::Guest,
             User::Admin { email: email() },
-            User::Team { email: email() },
+            User::Team {
+                email: email(),
+                roles: vec![RoleKey::new("program-committee")],
+            },
             User::Speaker {
                 email: email(),
                 key: speaker_key(),
+                event: Some(EventId::new("2020".into())),
             },
             User::Sponsor {
                 email: email(),
                 key: sponsor_key(),
+                event: None,
+            },
+            User::ApiClient {
+                token: "generate".into(),
+                owner: email(),
+                roles: vec![],
+                permissions: vec![Permission::Generate],
             },
         ]
     }
@@ -164,12 +254,25 @@ mod tests {
     { "type": "Guest" },
     { "type": "Admin", "email": "<plop@plop.org>" },
     { "type": "Team", "email": "<plop@plop.org>" },
+    { "type": "Team", "email": "<plop@plop.org>", "roles": ["program-committee"] },
     { "type": "Speaker", "email": "<plop@plop.org>", "key": "plop" },
     { "type": "Sponsor", "email": "<plop@plop.org>", "key": "plop" }
 ]"#;
             let result = serde_json::from_str::<Vec<User>>(json);
             assert!(result.is_ok());
         }
+
+        #[test]
+        fn should_have_email_when_authenticated() {
+            assert_eq!(User::Guest.email(), None);
+            for user in users().iter().skip(1) {
+                let expected = match user {
+                    User::ApiClient { .. } => None,
+                    _ => Some(email()),
+                };
+                assert_eq!(user.email(), expected);
+            }
+        }
     }
 
     mod user_info {
@@ -63,6 +63,11 @@ impl<'de> Deserialize<'de> for Lang {
 }
 
 impl Lang {
+    /// The language subtag, e.g. `fr` for `fr-FR`
+    pub fn code(&self) -> String {
+        self.0.language.as_str().into()
+    }
+
     pub fn from_user_field(s: &str) -> Self {
         let low = s.to_lowercase();
         if low.contains("francais")
@@ -126,6 +131,12 @@ mod tests {
             assert_eq!(s, "fr-FR");
         }
 
+        #[test]
+        fn should_provide_language_code() {
+            let result = Lang(langid!("fr-FR")).code();
+            assert_eq!(result, "fr");
+        }
+
         #[test]
         fn should_be_serializable() {
             let lang = Lang::default();
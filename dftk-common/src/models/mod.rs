@@ -1,3 +1,7 @@
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::{ensure, Error, Result};
 use serde::{Deserialize, Serialize};
 
 pub mod language;
@@ -9,7 +13,67 @@ pub mod speaker;
 pub mod sponsor;
 pub mod team;
 
-pub type Duration = u8;
+/// The longest allowed duration, a full week in minutes
+const MAX_DURATION: u32 = 7 * 24 * 60;
+
+/// A duration in minutes, strictly positive and up to a week
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct Duration(u32);
+
+impl Duration {
+    pub fn new(minutes: u32) -> Result<Self> {
+        ensure!(
+            minutes > 0,
+            "A duration should be positive, got {}",
+            minutes
+        );
+        ensure!(
+            minutes <= MAX_DURATION,
+            "A duration should not exceed {} minutes, got {}",
+            MAX_DURATION,
+            minutes
+        );
+
+        Ok(Self(minutes))
+    }
+
+    pub fn minutes(&self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for Duration {
+    type Error = Error;
+
+    fn try_from(minutes: u32) -> Result<Self, Self::Error> {
+        Duration::new(minutes)
+    }
+}
+
+impl From<Duration> for u32 {
+    fn from(duration: Duration) -> Self {
+        duration.0
+    }
+}
+
+impl From<Duration> for i64 {
+    fn from(duration: Duration) -> Self {
+        duration.0.into()
+    }
+}
+
+impl From<Duration> for chrono::Duration {
+    fn from(duration: Duration) -> Self {
+        chrono::Duration::minutes(duration.0.into())
+    }
+}
+
+impl Display for Duration {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}min", self.0)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Markdown(String);
@@ -30,6 +94,48 @@ impl Into<String> for Markdown {
 mod tests {
     use super::*;
 
+    mod duration {
+        use super::*;
+
+        #[test]
+        fn should_accept_long_durations() {
+            let result = Duration::new(3 * 24 * 60).unwrap();
+            assert_eq!(result.minutes(), 4320);
+        }
+
+        #[test]
+        fn should_reject_empty_duration() {
+            assert!(Duration::new(0).is_err());
+        }
+
+        #[test]
+        fn should_reject_too_long_duration() {
+            assert!(Duration::new(MAX_DURATION + 1).is_err());
+        }
+
+        #[test]
+        fn should_be_serialized_as_minutes() {
+            let result = serde_json::to_string(&Duration(480)).unwrap();
+            assert_eq!(result, "480");
+        }
+
+        #[test]
+        fn should_be_deserialized_from_minutes() {
+            let result = serde_json::from_str::<Duration>("45").unwrap();
+            assert_eq!(result, Duration(45));
+            assert!(serde_json::from_str::<Duration>("0").is_err());
+        }
+
+        #[test]
+        fn should_convert_to_chrono_duration() {
+            let result: chrono::Duration = Duration(90).into();
+            assert_eq!(
+                result,
+                chrono::Duration::hours(1) + chrono::Duration::minutes(30)
+            );
+        }
+    }
+
     mod markdown {
         use super::*;
 
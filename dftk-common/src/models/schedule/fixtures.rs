@@ -0,0 +1,57 @@
+use crate::models::site::Site;
+
+pub(crate) fn site() -> Site {
+    let json = r#"{
+  "info": {
+    "_id": "devfest-2020",
+    "name": "DevFest Toulouse",
+    "address": {
+      "locality": { "long_name": "Toulouse", "short_name": "Toulouse" },
+      "country": { "long_name": "France", "short_name": "FR" },
+      "lat_lng": { "lat": 43.6, "lng": 1.44 }
+    },
+    "languages": { "main": "fr", "others": ["en"] },
+    "dates": { "start": "2020-10-15T07:00:00Z", "end": "2020-10-15T18:00:00Z" }
+  },
+  "sessions": [{
+    "id": "s1", "key": "rust", "title": "Rust", "level": null, "format": "talk",
+    "speakers": ["jane-doe"], "category": "languages", "language": "en", "video_id": null,
+    "presentation": null, "draft": null, "office_hours": null, "description": ""
+  }, {
+    "id": "s2", "key": "kotlin", "title": "Kotlin", "level": null, "format": "talk",
+    "speakers": [], "category": "languages", "language": "fr", "video_id": null,
+    "presentation": null, "draft": null, "office_hours": null, "description": ""
+  }, {
+    "id": "s3", "key": "java-the-good-parts", "title": "Java, the good parts", "level": null,
+    "format": "talk", "speakers": [], "category": "languages", "language": "fr", "video_id": null,
+    "presentation": null, "draft": null, "office_hours": null, "description": ""
+  }],
+  "speakers": [{
+    "_id": "sp1", "key": "jane-doe", "featured": false, "name": "Jane Doe", "company": null,
+    "city": null, "photo_url": null, "socials": [], "draft": null, "description": ""
+  }],
+  "categories": [],
+  "formats": [],
+  "rooms": [
+    { "key": "amphi", "label": "Amphi A", "description": null, "skip": false },
+    { "key": "lab", "label": "Lab", "description": null, "skip": false },
+    { "key": "hall", "label": "Hall", "description": null, "skip": true }
+  ],
+  "slots": [
+    { "key": "morning", "start": "2020-10-15T09:00:00Z", "duration": 45 },
+    { "key": "noon", "start": "2020-10-15T10:00:00Z", "duration": 50 }
+  ],
+  "schedule": [{
+    "start": "2020-10-15T08:00:00Z",
+    "rooms": [{ "room": "amphi", "slots": [
+      { "slot": "noon", "session": "kotlin" },
+      { "slot": "morning", "session": "rust" }
+    ] }]
+  }],
+  "team": [],
+  "member_types": [],
+  "sponsors": [],
+  "sponsor_categories": []
+}"#;
+    serde_json::from_str(json).unwrap()
+}
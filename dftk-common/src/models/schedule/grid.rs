@@ -0,0 +1,496 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use serde::Serialize;
+use strsim::normalized_levenshtein;
+
+use crate::models::schedule::{
+    Room, RoomKey, ScheduleDay, ScheduleRoom, ScheduleRoomSlot, Slot, SlotKey,
+};
+use crate::models::session::{Session, SessionKey};
+use crate::models::site::Site;
+
+/// Minimal similarity to accept a fuzzy match between a cell and a session title
+const FUZZY_THRESHOLD: f64 = 0.8;
+
+const SLOT_HEADER: &str = "slot";
+const START_HEADER: &str = "start";
+
+/// Export the schedule as a CSV grid, rows are slots, columns are rooms, cells are session keys
+///
+/// Every room is exported, even the skipped ones, so a round trip keeps their sessions.
+pub fn export_grid(site: &Site) -> Result<String> {
+    let rooms: Vec<&Room> = site.rooms().iter().collect();
+    let cells = scheduled_cells(site.schedule());
+
+    let mut slots: Vec<&Slot> = site.slots().iter().collect();
+    slots.sort_by_key(|it| it.start());
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut header = vec![SLOT_HEADER.to_string(), START_HEADER.to_string()];
+    header.extend(rooms.iter().map(|it| it.label()));
+    writer.write_record(&header)?;
+
+    for slot in slots {
+        let slot_key: String = slot.key().into();
+        let mut record = vec![slot_key.clone(), slot.start().to_rfc3339()];
+        for room in rooms.iter() {
+            let room_key: String = room.key().into();
+            let cell = cells
+                .get(&(room_key, slot_key.clone()))
+                .map(|it| it.clone().into())
+                .unwrap_or_default();
+            record.push(cell);
+        }
+        writer.write_record(&record)?;
+    }
+
+    let bytes = writer.into_inner()?;
+    let result = String::from_utf8(bytes)?;
+
+    Ok(result)
+}
+
+/// The result of a CSV grid import, to preview before saving
+#[derive(Serialize, Debug, Clone)]
+pub struct GridImport {
+    schedule: Vec<ScheduleDay>,
+    changes: Vec<ScheduleChange>,
+    fuzzy: Vec<FuzzyMatch>,
+    unknown: Vec<UnknownCell>,
+    duplicated: Vec<DuplicatedCell>,
+}
+
+impl GridImport {
+    pub fn schedule(&self) -> &[ScheduleDay] {
+        self.schedule.as_slice()
+    }
+    pub fn changes(&self) -> &[ScheduleChange] {
+        self.changes.as_slice()
+    }
+    pub fn fuzzy(&self) -> &[FuzzyMatch] {
+        self.fuzzy.as_slice()
+    }
+    pub fn unknown(&self) -> &[UnknownCell] {
+        self.unknown.as_slice()
+    }
+    pub fn duplicated(&self) -> &[DuplicatedCell] {
+        self.duplicated.as_slice()
+    }
+
+    /// A grid could be saved only if every cell has been resolved, and every session placed once
+    pub fn is_valid(&self) -> bool {
+        self.unknown.is_empty() && self.duplicated.is_empty()
+    }
+}
+
+/// A cell of the grid that differs from the current schedule
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ScheduleChange {
+    room: RoomKey,
+    slot: SlotKey,
+    before: Option<SessionKey>,
+    after: Option<SessionKey>,
+}
+
+impl ScheduleChange {
+    pub fn room(&self) -> RoomKey {
+        self.room.clone()
+    }
+    pub fn slot(&self) -> SlotKey {
+        self.slot.clone()
+    }
+    pub fn before(&self) -> Option<SessionKey> {
+        self.before.clone()
+    }
+    pub fn after(&self) -> Option<SessionKey> {
+        self.after.clone()
+    }
+}
+
+/// A cell resolved to a session by approximation
+#[derive(Serialize, Debug, Clone)]
+pub struct FuzzyMatch {
+    value: String,
+    session: SessionKey,
+    score: f64,
+}
+
+impl FuzzyMatch {
+    pub fn value(&self) -> String {
+        self.value.clone()
+    }
+    pub fn session(&self) -> SessionKey {
+        self.session.clone()
+    }
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// A cell that could not be resolved, `line` is 1-based and includes the header
+#[derive(Serialize, Debug, Clone)]
+pub struct UnknownCell {
+    line: usize,
+    column: String,
+    value: String,
+}
+
+impl UnknownCell {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> String {
+        self.column.clone()
+    }
+    pub fn value(&self) -> String {
+        self.value.clone()
+    }
+}
+
+/// A cell placing again a session already placed by a previous cell
+#[derive(Serialize, Debug, Clone)]
+pub struct DuplicatedCell {
+    line: usize,
+    column: String,
+    session: SessionKey,
+}
+
+impl DuplicatedCell {
+    pub fn line(&self) -> usize {
+        self.line
+    }
+    pub fn column(&self) -> String {
+        self.column.clone()
+    }
+    pub fn session(&self) -> SessionKey {
+        self.session.clone()
+    }
+}
+
+/// Read a CSV grid, resolve its cells, and compare it with the current schedule
+pub fn import_grid(site: &Site, data: &[u8]) -> Result<GridImport> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data);
+
+    let headers = reader.headers()?.clone();
+    let slot_column = headers
+        .iter()
+        .position(|it| it.eq_ignore_ascii_case(SLOT_HEADER))
+        .ok_or_else(|| anyhow!("Missing the '{}' column", SLOT_HEADER))?;
+    let mut room_columns = vec![];
+    for (index, header) in headers.iter().enumerate() {
+        if index == slot_column || header.eq_ignore_ascii_case(START_HEADER) {
+            continue;
+        }
+        let room = find_room(site, header)
+            .ok_or_else(|| anyhow!("Unknown room for the column '{}'", header))?;
+        room_columns.push((index, room));
+    }
+
+    let mut cells: Vec<(&Slot, RoomKey, SessionKey)> = vec![];
+    let mut fuzzy = vec![];
+    let mut unknown = vec![];
+    let mut duplicated = vec![];
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = index + 2;
+        let value = record.get(slot_column).unwrap_or_default();
+        if value.is_empty() {
+            continue;
+        }
+        let slot = if let Some(slot) = find_slot(site, value) {
+            slot
+        } else {
+            unknown.push(UnknownCell {
+                line,
+                column: SLOT_HEADER.into(),
+                value: value.into(),
+            });
+            continue;
+        };
+
+        for (column, room) in room_columns.iter() {
+            let value = record.get(*column).unwrap_or_default();
+            if value.is_empty() {
+                continue;
+            }
+            let session = match find_session(site, value) {
+                Some((session, None)) => session,
+                Some((session, Some(score))) => {
+                    fuzzy.push(FuzzyMatch {
+                        value: value.into(),
+                        session: session.key(),
+                        score,
+                    });
+                    session
+                }
+                None => {
+                    unknown.push(UnknownCell {
+                        line,
+                        column: room.label(),
+                        value: value.into(),
+                    });
+                    continue;
+                }
+            };
+            if cells.iter().any(|(_, _, it)| it == &session.key()) {
+                duplicated.push(DuplicatedCell {
+                    line,
+                    column: room.label(),
+                    session: session.key(),
+                });
+            } else {
+                cells.push((slot, room.key(), session.key()));
+            }
+        }
+    }
+
+    let schedule = build_schedule(site, &cells);
+    let changes = diff_schedule(site.schedule(), &schedule);
+
+    Ok(GridImport {
+        schedule,
+        changes,
+        fuzzy,
+        unknown,
+        duplicated,
+    })
+}
+
+fn find_room<'a>(site: &'a Site, header: &str) -> Option<&'a Room> {
+    site.rooms().iter().find(|it| {
+        let key: String = it.key().into();
+        key.eq_ignore_ascii_case(header) || it.label().eq_ignore_ascii_case(header)
+    })
+}
+
+fn find_slot<'a>(site: &'a Site, value: &str) -> Option<&'a Slot> {
+    site.slots().iter().find(|it| {
+        let key: String = it.key().into();
+        key == value
+    })
+}
+
+/// Resolve a cell by session key, then by title, then by the closest title or key
+///
+/// The score is provided only for a fuzzy match.
+fn find_session<'a>(site: &'a Site, value: &str) -> Option<(&'a Session, Option<f64>)> {
+    let value = value.to_lowercase();
+    let exact = site.sessions().iter().find(|it| {
+        let key: String = it.key().into();
+        key == value || it.title().to_lowercase() == value
+    });
+    if let Some(session) = exact {
+        return Some((session, None));
+    }
+
+    site.sessions()
+        .iter()
+        .map(|it| {
+            let key: String = it.key().into();
+            let score = normalized_levenshtein(&value, &it.title().to_lowercase())
+                .max(normalized_levenshtein(&value, &key));
+            (it, score)
+        })
+        .filter(|(_, score)| *score >= FUZZY_THRESHOLD)
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(session, score)| (session, Some(score)))
+}
+
+/// Group the cells by day of the conference local time, keeping the start of existing days
+fn build_schedule(site: &Site, cells: &[(&Slot, RoomKey, SessionKey)]) -> Vec<ScheduleDay> {
+    let offset = site.info().local_offset();
+    let mut days: BTreeMap<NaiveDate, Vec<&(&Slot, RoomKey, SessionKey)>> = BTreeMap::new();
+    for cell in cells.iter() {
+        let date = local_date(cell.0.start(), offset);
+        days.entry(date).or_default().push(cell);
+    }
+
+    days.into_iter()
+        .map(|(date, mut cells)| {
+            cells.sort_by_key(|(slot, _, _)| slot.start());
+            let start = site
+                .schedule()
+                .iter()
+                .map(|it| it.start())
+                .find(|it| local_date(*it, offset) == date)
+                .or_else(|| cells.first().map(|(slot, _, _)| slot.start()))
+                .unwrap_or_else(Utc::now);
+            let rooms = site
+                .rooms()
+                .iter()
+                .filter_map(|room| {
+                    let slots: Vec<ScheduleRoomSlot> = cells
+                        .iter()
+                        .filter(|(_, key, _)| key == &room.key())
+                        .map(|(slot, _, session)| {
+                            ScheduleRoomSlot::new(slot.key(), session.clone())
+                        })
+                        .collect();
+                    if slots.is_empty() {
+                        None
+                    } else {
+                        Some(ScheduleRoom::new(room.key(), slots))
+                    }
+                })
+                .collect();
+
+            ScheduleDay::new(start, rooms)
+        })
+        .collect()
+}
+
+fn local_date(time: DateTime<Utc>, offset: FixedOffset) -> NaiveDate {
+    time.with_timezone(&offset).date().naive_local()
+}
+
+/// Sessions by room and slot keys
+fn scheduled_cells(schedule: &[ScheduleDay]) -> BTreeMap<(String, String), SessionKey> {
+    schedule
+        .iter()
+        .flat_map(|day| day.rooms().iter())
+        .flat_map(|room| {
+            let room_key: String = room.room().into();
+            room.slots()
+                .iter()
+                .map(move |it| ((room_key.clone(), it.slot().into()), it.session()))
+        })
+        .collect()
+}
+
+fn diff_schedule(before: &[ScheduleDay], after: &[ScheduleDay]) -> Vec<ScheduleChange> {
+    let before = scheduled_cells(before);
+    let after = scheduled_cells(after);
+
+    let mut keys: Vec<&(String, String)> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let old = before.get(key).cloned();
+            let new = after.get(key).cloned();
+            if old == new {
+                None
+            } else {
+                Some(ScheduleChange {
+                    room: RoomKey::new(key.0.as_str()),
+                    slot: SlotKey::new(key.1.as_str()),
+                    before: old,
+                    after: new,
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::schedule::fixtures::site;
+
+    #[test]
+    fn should_export_grid() {
+        let result = export_grid(&site()).unwrap();
+        let expected = "slot,start,Amphi A,Lab,Hall
+morning,2020-10-15T09:00:00+00:00,rust,,
+noon,2020-10-15T10:00:00+00:00,kotlin,,
+";
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn should_round_trip_without_changes() {
+        let site = site();
+        let csv = export_grid(&site).unwrap();
+        let result = import_grid(&site, csv.as_bytes()).unwrap();
+
+        assert!(result.is_valid());
+        assert!(result.changes().is_empty());
+        assert_eq!(result.schedule().len(), 1);
+        assert_eq!(result.schedule()[0].start(), site.schedule()[0].start());
+    }
+
+    #[test]
+    fn should_resolve_titles() {
+        let csv = "slot,Amphi A,lab
+morning,Kotlin,Java the good part
+noon,,
+";
+        let result = import_grid(&site(), csv.as_bytes()).unwrap();
+
+        assert!(result.is_valid());
+        assert_eq!(result.fuzzy().len(), 1);
+        assert_eq!(
+            result.fuzzy()[0].session(),
+            SessionKey::new("java-the-good-parts")
+        );
+        assert_eq!(result.changes().len(), 3);
+    }
+
+    #[test]
+    fn should_report_unknown_cells() {
+        let csv = "slot,Amphi A
+morning,Haskell
+evening,rust
+";
+        let result = import_grid(&site(), csv.as_bytes()).unwrap();
+
+        assert!(!result.is_valid());
+        assert_eq!(result.unknown().len(), 2);
+        assert_eq!(result.unknown()[0].line(), 2);
+        assert_eq!(result.unknown()[0].column(), "Amphi A");
+        assert_eq!(result.unknown()[1].column(), "slot");
+    }
+
+    #[test]
+    fn should_import_skipped_rooms() {
+        let csv = "slot,start,Amphi A,Lab,Hall
+morning,2020-10-15T09:00:00+00:00,rust,,Java the good parts
+noon,2020-10-15T10:00:00+00:00,kotlin,,
+";
+        let result = import_grid(&site(), csv.as_bytes()).unwrap();
+
+        assert!(result.is_valid());
+        assert_eq!(result.changes().len(), 1);
+        assert_eq!(result.changes()[0].room(), RoomKey::new("hall"));
+    }
+
+    #[test]
+    fn should_report_duplicated_sessions() {
+        let csv = "slot,Amphi A,Lab
+morning,rust,
+noon,kotlin,Rust
+";
+        let result = import_grid(&site(), csv.as_bytes()).unwrap();
+
+        assert!(!result.is_valid());
+        assert_eq!(result.duplicated().len(), 1);
+        assert_eq!(result.duplicated()[0].line(), 3);
+        assert_eq!(result.duplicated()[0].column(), "Lab");
+        assert_eq!(result.duplicated()[0].session(), SessionKey::new("rust"));
+    }
+
+    #[test]
+    fn should_group_days_in_the_local_time() {
+        let mut json = serde_json::to_value(site()).unwrap();
+        json["info"]["utc_offset"] = 600.into();
+        json["slots"][0]["start"] = "2020-10-14T23:00:00Z".into();
+        let site: Site = serde_json::from_value(json).unwrap();
+        let csv = "slot,Amphi A\nmorning,rust\nnoon,kotlin\n";
+        let result = import_grid(&site, csv.as_bytes()).unwrap();
+
+        assert_eq!(result.schedule().len(), 1);
+        assert_eq!(result.schedule()[0].start(), site.schedule()[0].start());
+    }
+
+    #[test]
+    fn should_reject_unknown_room() {
+        let csv = "slot,Auditorium\nmorning,rust\n";
+        assert!(import_grid(&site(), csv.as_bytes()).is_err());
+    }
+}
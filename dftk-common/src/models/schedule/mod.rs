@@ -6,6 +6,12 @@ use serde::{Deserialize, Serialize};
 use crate::models::session::SessionKey;
 use crate::models::Duration;
 
+#[cfg(test)]
+mod fixtures;
+pub mod grid;
+pub mod now;
+pub mod placement;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ScheduleDay {
     start: DateTime<Utc>,
@@ -13,6 +19,10 @@ pub struct ScheduleDay {
 }
 
 impl ScheduleDay {
+    pub fn new(start: DateTime<Utc>, rooms: Vec<ScheduleRoom>) -> Self {
+        Self { start, rooms }
+    }
+
     pub fn start(&self) -> DateTime<Utc> {
         self.start
     }
@@ -43,6 +53,10 @@ pub struct ScheduleRoom {
 }
 
 impl ScheduleRoom {
+    pub fn new(room: RoomKey, slots: Vec<ScheduleRoomSlot>) -> Self {
+        Self { room, slots }
+    }
+
     pub fn room(&self) -> RoomKey {
         self.room.clone()
     }
@@ -58,6 +72,10 @@ pub struct ScheduleRoomSlot {
 }
 
 impl ScheduleRoomSlot {
+    pub fn new(slot: SlotKey, session: SessionKey) -> Self {
+        Self { slot, session }
+    }
+
     pub fn slot(&self) -> SlotKey {
         self.slot.clone()
     }
@@ -71,10 +89,17 @@ pub struct Slot {
     key: SlotKey,
     start: DateTime<Utc>,
     duration: Duration,
-    row: Range<u32>,
 }
 
 impl Slot {
+    pub fn new(key: SlotKey, start: DateTime<Utc>, duration: Duration) -> Self {
+        Self {
+            key,
+            start,
+            duration,
+        }
+    }
+
     pub fn key(&self) -> SlotKey {
         self.key.clone()
     }
@@ -84,6 +109,27 @@ impl Slot {
     pub fn duration(&self) -> Duration {
         self.duration
     }
+    pub fn end(&self) -> DateTime<Utc> {
+        let duration: chrono::Duration = self.duration.into();
+        self.start + duration
+    }
+}
+
+/// Grid layout hint of a slot, used by the site to display the schedule
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlotLayout {
+    slot: SlotKey,
+    row: Range<u32>,
+}
+
+impl SlotLayout {
+    pub fn new(slot: SlotKey, row: Range<u32>) -> Self {
+        Self { slot, row }
+    }
+
+    pub fn slot(&self) -> SlotKey {
+        self.slot.clone()
+    }
     pub fn row(&self) -> Range<u32> {
         self.row.clone()
     }
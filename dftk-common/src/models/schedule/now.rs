@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::schedule::{Room, RoomKey, Slot, SlotKey};
+use crate::models::session::Session;
+use crate::models::site::Site;
+use crate::models::speaker::Speaker;
+
+/// The current and the next session of a room at a given instant
+#[derive(Serialize, Debug, Clone)]
+pub struct RoomNowNext {
+    room: Room,
+    current: Option<TimedSession>,
+    next: Option<TimedSession>,
+}
+
+impl RoomNowNext {
+    pub fn room(&self) -> Room {
+        self.room.clone()
+    }
+    pub fn current(&self) -> Option<TimedSession> {
+        self.current.clone()
+    }
+    pub fn next(&self) -> Option<TimedSession> {
+        self.next.clone()
+    }
+}
+
+/// A scheduled session, with durations in minutes relative to the requested instant
+#[derive(Serialize, Debug, Clone)]
+pub struct TimedSession {
+    session: Session,
+    speakers: Vec<Speaker>,
+    slot: SlotKey,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    starts_in: i64,
+    remaining: i64,
+}
+
+impl TimedSession {
+    fn new(slot: &Slot, session: &Session, site: &Site, at: DateTime<Utc>) -> Self {
+        let keys = session.speakers();
+        let speakers = site
+            .speakers()
+            .iter()
+            .filter(|it| keys.contains(&it.key()))
+            .cloned()
+            .collect();
+        let start = slot.start();
+        let end = slot.end();
+
+        Self {
+            session: session.clone(),
+            speakers,
+            slot: slot.key(),
+            start,
+            end,
+            starts_in: (start - at).num_minutes().max(0),
+            remaining: (end - at).num_minutes().max(0),
+        }
+    }
+
+    pub fn session(&self) -> Session {
+        self.session.clone()
+    }
+    pub fn speakers(&self) -> &[Speaker] {
+        self.speakers.as_slice()
+    }
+    pub fn slot(&self) -> SlotKey {
+        self.slot.clone()
+    }
+    pub fn start(&self) -> DateTime<Utc> {
+        self.start
+    }
+    pub fn end(&self) -> DateTime<Utc> {
+        self.end
+    }
+    pub fn starts_in(&self) -> i64 {
+        self.starts_in
+    }
+    pub fn remaining(&self) -> i64 {
+        self.remaining
+    }
+}
+
+/// Scheduled slots and sessions of a room, sorted by start
+fn room_sessions<'a>(site: &'a Site, room: &RoomKey) -> Vec<(&'a Slot, &'a Session)> {
+    let mut result: Vec<(&Slot, &Session)> = site
+        .schedule()
+        .iter()
+        .flat_map(|day| day.rooms().iter())
+        .filter(|it| &it.room() == room)
+        .flat_map(|it| it.slots().iter())
+        .filter_map(|it| {
+            let slot = site.slots().iter().find(|slot| slot.key() == it.slot())?;
+            let session = site
+                .sessions()
+                .iter()
+                .find(|session| session.key() == it.session())?;
+            Some((slot, session))
+        })
+        .collect();
+    result.sort_by_key(|(slot, _)| slot.start());
+
+    result
+}
+
+/// Find the current and next session of each room at the given instant
+///
+/// Skipped rooms are ignored, and `room` restricts the result to a single room.
+pub fn now_next(site: &Site, at: DateTime<Utc>, room: Option<RoomKey>) -> Vec<RoomNowNext> {
+    site.rooms()
+        .iter()
+        .filter(|it| !it.skip())
+        .filter(|it| room.is_none() || room.as_ref() == Some(&it.key()))
+        .map(|it| {
+            let sessions = room_sessions(site, &it.key());
+            let current = sessions
+                .iter()
+                .find(|(slot, _)| slot.start() <= at && at < slot.end())
+                .map(|(slot, session)| TimedSession::new(slot, session, site, at));
+            let next = sessions
+                .iter()
+                .find(|(slot, _)| slot.start() > at)
+                .map(|(slot, session)| TimedSession::new(slot, session, site, at));
+
+            RoomNowNext {
+                room: it.clone(),
+                current,
+                next,
+            }
+        })
+        .collect()
+}
+
+/// The first slot boundary (start or end) strictly after the given instant
+pub fn next_boundary(site: &Site, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    site.slots()
+        .iter()
+        .flat_map(|it| vec![it.start(), it.end()])
+        .filter(|it| *it > at)
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::schedule::fixtures::site;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn should_find_current_and_next() {
+        let result = now_next(&site(), at("2020-10-15T09:15:00Z"), None);
+        assert_eq!(result.len(), 2);
+        assert!(result[1].current().is_none());
+
+        let current = result[0].current().unwrap();
+        assert_eq!(current.session().title(), "Rust");
+        assert_eq!(current.speakers().len(), 1);
+        assert_eq!(current.remaining(), 30);
+        assert_eq!(current.starts_in(), 0);
+
+        let next = result[0].next().unwrap();
+        assert_eq!(next.session().title(), "Kotlin");
+        assert_eq!(next.starts_in(), 45);
+    }
+
+    #[test]
+    fn should_have_only_next_between_sessions() {
+        let result = now_next(&site(), at("2020-10-15T09:45:00Z"), None);
+        assert!(result[0].current().is_none());
+        assert_eq!(result[0].next().unwrap().slot(), SlotKey::new("noon"));
+    }
+
+    #[test]
+    fn should_have_nothing_after_the_end() {
+        let result = now_next(&site(), at("2020-10-15T18:00:00Z"), None);
+        assert!(result[0].current().is_none());
+        assert!(result[0].next().is_none());
+    }
+
+    #[test]
+    fn should_filter_by_room() {
+        let result = now_next(
+            &site(),
+            at("2020-10-15T09:15:00Z"),
+            Some(RoomKey::new("hall")),
+        );
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn should_find_next_boundary() {
+        let site = site();
+        assert_eq!(
+            next_boundary(&site, at("2020-10-15T09:00:00Z")),
+            Some(at("2020-10-15T09:45:00Z"))
+        );
+        assert_eq!(
+            next_boundary(&site, at("2020-10-15T09:45:00Z")),
+            Some(at("2020-10-15T10:00:00Z"))
+        );
+        assert_eq!(next_boundary(&site, at("2020-10-15T10:50:00Z")), None);
+    }
+}
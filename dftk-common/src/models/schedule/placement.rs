@@ -0,0 +1,103 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::models::schedule::{RoomKey, ScheduleDay, Slot, SlotKey};
+use crate::models::session::SessionKey;
+
+/// Where and when a session is scheduled
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SessionPlacement {
+    session: SessionKey,
+    slot: SlotKey,
+    room: RoomKey,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+impl SessionPlacement {
+    pub fn session(&self) -> SessionKey {
+        self.session.clone()
+    }
+    pub fn slot(&self) -> SlotKey {
+        self.slot.clone()
+    }
+    pub fn room(&self) -> RoomKey {
+        self.room.clone()
+    }
+    pub fn start(&self) -> DateTime<Utc> {
+        self.start
+    }
+    pub fn end(&self) -> DateTime<Utc> {
+        self.end
+    }
+}
+
+/// Join the schedule with the slots, sorted by start
+pub fn find_placements(schedule: &[ScheduleDay], slots: &[Slot]) -> Vec<SessionPlacement> {
+    let mut result: Vec<SessionPlacement> = schedule
+        .iter()
+        .flat_map(|day| day.rooms().iter())
+        .flat_map(|room| {
+            room.slots().iter().filter_map(move |it| {
+                let slot = slots.iter().find(|slot| slot.key() == it.slot())?;
+                Some(SessionPlacement {
+                    session: it.session(),
+                    slot: slot.key(),
+                    room: room.room(),
+                    start: slot.start(),
+                    end: slot.end(),
+                })
+            })
+        })
+        .collect();
+    result.sort_by_key(|it| it.start);
+
+    result
+}
+
+/// Find where and when a session is scheduled, if it is
+pub fn find_placement(
+    schedule: &[ScheduleDay],
+    slots: &[Slot],
+    session: &SessionKey,
+) -> Option<SessionPlacement> {
+    find_placements(schedule, slots)
+        .into_iter()
+        .find(|it| &it.session == session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::schedule::fixtures::site;
+
+    #[test]
+    fn should_find_placements_sorted_by_start() {
+        let site = site();
+        let result = find_placements(site.schedule(), site.slots());
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].session(), SessionKey::new("rust"));
+        assert_eq!(result[1].session(), SessionKey::new("kotlin"));
+    }
+
+    #[test]
+    fn should_find_placement() {
+        let site = site();
+        let result = find_placement(site.schedule(), site.slots(), &SessionKey::new("kotlin"));
+        let result = result.unwrap();
+
+        assert_eq!(result.room(), RoomKey::new("amphi"));
+        assert_eq!(result.slot(), SlotKey::new("noon"));
+        assert_eq!(result.start().to_rfc3339(), "2020-10-15T10:00:00+00:00");
+        assert_eq!(result.end().to_rfc3339(), "2020-10-15T10:50:00+00:00");
+    }
+
+    #[test]
+    fn should_not_find_unscheduled_session() {
+        let site = site();
+        let key = SessionKey::new("java-the-good-parts");
+
+        assert!(find_placement(site.schedule(), site.slots(), &key).is_none());
+    }
+}
@@ -2,12 +2,12 @@ use core::fmt;
 use std::fmt::Display;
 
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, FixedOffset, Utc};
 use serde::export::Formatter;
 use serde::{Deserialize, Serialize};
 
 use crate::models::language::Languages;
-use crate::models::schedule::{Room, ScheduleDay, Slot};
+use crate::models::schedule::{Room, ScheduleDay, Slot, SlotLayout};
 use crate::models::session::category::SessionCategory;
 use crate::models::session::format::SessionFormat;
 use crate::models::session::Session;
@@ -45,6 +45,9 @@ pub struct SiteInfo {
     address: Address,
     languages: Languages,
     dates: DateRange,
+    /// The offset of the conference local time, in minutes east of UTC
+    #[serde(default)]
+    utc_offset: i32,
 }
 
 impl SiteInfo {
@@ -61,9 +64,15 @@ impl SiteInfo {
             address,
             languages,
             dates,
+            utc_offset: 0,
         }
     }
 
+    /// Set the offset of the conference local time, in minutes east of UTC
+    pub fn with_utc_offset(self, utc_offset: i32) -> Self {
+        Self { utc_offset, ..self }
+    }
+
     pub fn id(&self) -> EventId {
         self._id.clone()
     }
@@ -79,6 +88,14 @@ impl SiteInfo {
     pub fn dates(&self) -> DateRange {
         self.dates.clone()
     }
+    pub fn utc_offset(&self) -> i32 {
+        self.utc_offset
+    }
+
+    /// The time zone of the conference, UTC for an out of range offset
+    pub fn local_offset(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset * 60).unwrap_or_else(|| FixedOffset::east(0))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -203,6 +220,8 @@ pub struct Site {
     formats: Vec<SessionFormat>,
     rooms: Vec<Room>,
     slots: Vec<Slot>,
+    #[serde(default)]
+    slot_layouts: Vec<SlotLayout>,
     schedule: Vec<ScheduleDay>,
     team: Vec<TeamMember>,
     member_types: Vec<MemberType>,
@@ -220,6 +239,7 @@ impl Site {
         formats: Vec<SessionFormat>,
         rooms: Vec<Room>,
         slots: Vec<Slot>,
+        slot_layouts: Vec<SlotLayout>,
         schedule: Vec<ScheduleDay>,
         team: Vec<TeamMember>,
         member_types: Vec<MemberType>,
@@ -234,6 +254,7 @@ impl Site {
             formats,
             rooms,
             slots,
+            slot_layouts,
             schedule,
             team,
             member_types,
@@ -263,6 +284,9 @@ impl Site {
     pub fn slots(&self) -> &[Slot] {
         self.slots.as_slice()
     }
+    pub fn slot_layouts(&self) -> &[SlotLayout] {
+        self.slot_layouts.as_slice()
+    }
     pub fn schedule(&self) -> &[ScheduleDay] {
         self.schedule.as_slice()
     }
@@ -97,6 +97,7 @@ impl ChEvent {
             vec![],
             vec![],
             vec![],
+            vec![],
         )
     }
 
@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use anyhow::{ensure, Result};
+use chrono::{DateTime, Utc};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use dftk_common::acl::role::{Permission, RoleKey};
+use dftk_common::acl::user::{Email, User};
+
+use crate::indexes::IndexDefinition;
+use crate::password_tokens::random_secret;
+use crate::storage::{DocumentStore, Storage};
+use crate::{from_document, from_documents, to_document};
+
+/// The start of an API token, a session token is a JWT
+pub const API_TOKEN_PREFIX: &str = "dftk_";
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// A long-lived token of an automation client, with the permissions of a role and its own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    #[serde(rename = "_id")]
+    id: String,
+    name: String,
+    owner: Email,
+    role: Option<RoleKey>,
+    #[serde(default)]
+    permissions: Vec<Permission>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn owner(&self) -> Email {
+        self.owner.clone()
+    }
+    pub fn role(&self) -> Option<RoleKey> {
+        self.role.clone()
+    }
+    pub fn permissions(&self) -> &[Permission] {
+        &self.permissions
+    }
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+    pub fn last_used_at(&self) -> Option<DateTime<Utc>> {
+        self.last_used_at
+    }
+
+    /// The user of the requests authenticated by the token
+    pub fn user(&self) -> User {
+        User::ApiClient {
+            token: self.id.clone(),
+            owner: self.owner.clone(),
+            roles: self.role.iter().cloned().collect(),
+            permissions: self.permissions.clone(),
+        }
+    }
+}
+
+/// The stored token, only the SHA-256 digest of its secret is kept,
+/// a fast hash is enough for a random secret of 256 bits
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ApiTokenDocument {
+    #[serde(flatten)]
+    token: ApiToken,
+    secret: String,
+}
+
+#[derive(Clone)]
+pub struct ApiTokenRepository {
+    col: Arc<dyn DocumentStore>,
+}
+
+impl ApiTokenRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let col = storage.collection("api_tokens");
+
+        Self { col }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let by_owner = IndexDefinition::new("owner", doc! {"owner": 1});
+        self.col.ensure_indexes(&[by_owner]).await
+    }
+
+    /// Create a token, the returned value is the only copy of the secret
+    pub async fn create(
+        &self,
+        name: String,
+        owner: Email,
+        role: Option<RoleKey>,
+        permissions: Vec<Permission>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(ApiToken, String)> {
+        ensure!(
+            role.is_some() || !permissions.is_empty(),
+            "Expected a role or some permissions"
+        );
+        let now = Utc::now();
+        ensure!(expires_at > now, "Expected an expiry in the future");
+
+        let token = ApiToken {
+            id: Uuid::new_v4().to_string(),
+            name,
+            owner,
+            role,
+            permissions,
+            created_at: now,
+            expires_at,
+            last_used_at: None,
+        };
+        let secret = random_secret();
+        let document = ApiTokenDocument {
+            token: token.clone(),
+            secret: digest(secret.as_str()),
+        };
+        self.col.insert_one(to_document(&document)?).await?;
+
+        let value = format!("{}{}.{}", API_TOKEN_PREFIX, token.id, secret);
+        Ok((token, value))
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<ApiToken>> {
+        let docs = self.col.find(doc! {}, Some(doc! {"created_at": 1})).await?;
+        let documents: Vec<ApiTokenDocument> = from_documents(docs)?;
+
+        Ok(documents.into_iter().map(|it| it.token).collect())
+    }
+
+    /// The valid token, and remember its use
+    pub async fn authenticate(&self, value: &str) -> Result<Option<ApiToken>> {
+        let value = value.trim().trim_start_matches(API_TOKEN_PREFIX);
+        let mut parts = value.splitn(2, '.');
+        let id = parts.next().unwrap_or_default();
+        let secret = match parts.next() {
+            Some(secret) => secret,
+            None => return Ok(None),
+        };
+
+        let filter = doc! {"_id": id};
+        let document = match self.col.find_one(filter.clone()).await? {
+            Some(document) => from_document::<ApiTokenDocument>(document)?,
+            None => return Ok(None),
+        };
+        let now = Utc::now();
+        if document.token.expires_at <= now {
+            debug!("Expired API token {}", id);
+            return Ok(None);
+        }
+        if !verify_secret(document.secret.as_str(), secret) {
+            debug!("Invalid secret of API token {}", id);
+            return Ok(None);
+        }
+
+        let last_used_at = bson::to_bson(&now)?;
+        self.col
+            .update_one(filter, doc! {"$set": {"last_used_at": last_used_at}}, false)
+            .await?;
+        let token = ApiToken {
+            last_used_at: Some(now),
+            ..document.token
+        };
+
+        Ok(Some(token))
+    }
+
+    pub async fn revoke(&self, id: &str) -> Result<Option<ApiToken>> {
+        let result = self.col.delete_one(doc! {"_id": id}).await?;
+
+        result
+            .map(from_document::<ApiTokenDocument>)
+            .transpose()
+            .map(|it| it.map(|document| document.token))
+    }
+
+    /// Revoke the tokens of the owner, returning the count of revoked tokens
+    pub async fn revoke_all(&self, owner: &Email) -> Result<u64> {
+        let owner = bson::to_bson(owner)?;
+
+        self.col.delete_many(doc! {"owner": owner}).await
+    }
+}
+
+fn digest(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compare the digests in constant time
+fn verify_secret(stored: &str, secret: &str) -> bool {
+    let expected = digest(secret);
+    let difference = stored
+        .bytes()
+        .zip(expected.bytes())
+        .fold(0, |result, (a, b)| result | (a ^ b));
+
+    stored.len() == expected.len() && difference == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::Duration;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_authenticate_token() {
+        let repo = ApiTokenRepository::new(&MemoryStorage::new("test"));
+        let owner = Email::from_str("admin@devfest.fr").unwrap();
+        let expires_at = Utc::now() + Duration::days(30);
+        let (token, value) = repo
+            .create(
+                "ci".into(),
+                owner.clone(),
+                None,
+                vec![Permission::Generate],
+                expires_at,
+            )
+            .await
+            .unwrap();
+        assert!(is_api_token(value.as_str()));
+
+        let result = repo.authenticate(value.as_str()).await.unwrap().unwrap();
+        assert_eq!(result.id(), token.id());
+        assert!(result.last_used_at().is_some());
+        assert_eq!(
+            result.user(),
+            User::ApiClient {
+                token: token.id(),
+                owner: owner.clone(),
+                roles: vec![],
+                permissions: vec![Permission::Generate],
+            }
+        );
+        let stored = repo.find_all().await.unwrap();
+        assert!(stored[0].last_used_at().is_some());
+
+        let forged = format!("{}{}.{}", API_TOKEN_PREFIX, token.id(), random_secret());
+        assert!(repo.authenticate(forged.as_str()).await.unwrap().is_none());
+
+        assert_eq!(repo.revoke_all(&owner).await.unwrap(), 1);
+        assert!(repo.authenticate(value.as_str()).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn should_verify_secrets() {
+        let secret = random_secret();
+        let stored = digest(secret.as_str());
+        assert!(verify_secret(stored.as_str(), secret.as_str()));
+        let forged = random_secret();
+        assert!(!verify_secret(stored.as_str(), forged.as_str()));
+    }
+
+    #[tokio::test]
+    async fn should_reject_token_without_permission() {
+        let repo = ApiTokenRepository::new(&MemoryStorage::new("test"));
+        let owner = Email::from_str("admin@devfest.fr").unwrap();
+        let expires_at = Utc::now() + Duration::days(30);
+
+        let result = repo
+            .create("ci".into(), owner, None, vec![], expires_at)
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::migrations::{
+    migrate_all, save_schema_version, schema_version, METADATA, SCHEMA_VERSION,
+};
+use crate::staging::{is_staging, Staging};
+use crate::storage::{list_scopes, split_scope, DocumentStore, ScopedStorage, Storage};
+
+/// The version of the archive format
+pub const ARCHIVE_FORMAT: u32 = 1;
+
+/// The collection of users, their password hashes are only exported on demand
+const USERS: &str = "users";
+
+/// The collections holding secrets, only exported with the password hashes
+const SECRETS: [&str; 2] = ["api_tokens", "user_sessions"];
+
+/// A dump of every collection, the shared ones and the ones of each event,
+/// documents are stored as canonical extended JSON
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Archive {
+    format: u32,
+    /// The schema version of the shared collections
+    schema_version: u32,
+    /// The schema version of each event
+    event_versions: BTreeMap<String, u32>,
+    created_at: DateTime<Utc>,
+    with_passwords: bool,
+    collections: BTreeMap<String, Vec<Value>>,
+}
+
+impl Archive {
+    pub fn format(&self) -> u32 {
+        self.format
+    }
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+    pub fn event_versions(&self) -> &BTreeMap<String, u32> {
+        &self.event_versions
+    }
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+    pub fn with_passwords(&self) -> bool {
+        self.with_passwords
+    }
+    pub fn collections(&self) -> Vec<String> {
+        self.collections.keys().cloned().collect()
+    }
+    pub fn nb_documents(&self) -> usize {
+        self.collections.values().map(|it| it.len()).sum()
+    }
+}
+
+/// How an archive is restored
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    /// Remove the existing documents before restoring
+    Replace,
+    /// Insert or update the documents by `_id`, keeping the other documents
+    Merge,
+}
+
+impl FromStr for RestoreMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "replace" => Ok(RestoreMode::Replace),
+            "merge" => Ok(RestoreMode::Merge),
+            _ => bail!("Unknown restore mode '{}', expected replace or merge", s),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RestoreResult {
+    mode: RestoreMode,
+    schema_version: u32,
+    collections: BTreeMap<String, usize>,
+}
+
+impl RestoreResult {
+    pub fn mode(&self) -> RestoreMode {
+        self.mode
+    }
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+    pub fn collections(&self) -> &BTreeMap<String, usize> {
+        &self.collections
+    }
+}
+
+/// Dump every collection of the database, with the collections of every event
+pub(crate) async fn export_archive(
+    storage: &Arc<dyn Storage>,
+    with_passwords: bool,
+) -> Result<Archive> {
+    let schema_version = schema_version(storage.as_ref()).await?;
+    let mut event_versions = BTreeMap::new();
+    for event in list_scopes(storage.as_ref()).await? {
+        let version = schema_version(&ScopedStorage::new(storage, event.as_str())).await?;
+        event_versions.insert(event, version);
+    }
+    let mut collections = BTreeMap::new();
+
+    for name in storage.list_collections().await? {
+        if !is_archived(name.as_str()) || (is_secret(name.as_str()) && !with_passwords) {
+            continue;
+        }
+        debug!("Export the collection {}", name);
+        let col = storage.collection(name.as_str());
+        let mut documents = vec![];
+        for mut doc in col.find(doc! {}, None).await? {
+            if name == USERS && !with_passwords {
+                doc.remove("password");
+            }
+            documents.push(Bson::Document(doc).into_canonical_extjson());
+        }
+        collections.insert(name, documents);
+    }
+
+    Ok(Archive {
+        format: ARCHIVE_FORMAT,
+        schema_version,
+        event_versions,
+        created_at: Utc::now(),
+        with_passwords,
+        collections,
+    })
+}
+
+/// Restore an archive, then migrate the restored documents to the current schema
+///
+/// A replacement is staged then swapped, so a failure keeps the existing data.
+/// Without password hashes, users are always merged so existing accounts keep their password,
+/// and the existing API tokens and user sessions are kept.
+pub(crate) async fn import_archive(
+    storage: &Arc<dyn Storage>,
+    archive: &Archive,
+    mode: RestoreMode,
+) -> Result<RestoreResult> {
+    ensure!(
+        archive.format == ARCHIVE_FORMAT,
+        "Unsupported archive format {}, expected {}",
+        archive.format,
+        ARCHIVE_FORMAT
+    );
+    ensure!(
+        archive.schema_version <= SCHEMA_VERSION,
+        "The archive schema version {} is newer than the supported version {}",
+        archive.schema_version,
+        SCHEMA_VERSION
+    );
+    for (event, version) in archive.event_versions.iter() {
+        ensure!(
+            *version <= SCHEMA_VERSION,
+            "The archive schema version {} of {} is newer than the supported version {}",
+            version,
+            event,
+            SCHEMA_VERSION
+        );
+    }
+    let current = schema_version(storage.as_ref()).await?;
+    let mut current_events = BTreeMap::new();
+    for event in list_scopes(storage.as_ref()).await? {
+        let version = schema_version(&ScopedStorage::new(storage, event.as_str())).await?;
+        current_events.insert(event, version);
+    }
+
+    let collections = match mode {
+        RestoreMode::Replace => replace_collections(storage, archive).await?,
+        RestoreMode::Merge => {
+            let mut collections = BTreeMap::new();
+            for (name, values) in archive.collections.iter() {
+                info!("Merge {} document(s) into {}", values.len(), name);
+                let col = storage.collection(name.as_str());
+                for value in values.iter() {
+                    merge_document(col.as_ref(), to_document(value)?).await?;
+                }
+                collections.insert(name.clone(), values.len());
+            }
+            collections
+        }
+    };
+
+    // The restored documents could be older than the existing ones
+    save_schema_version(storage.as_ref(), archive.schema_version.min(current)).await?;
+    for (event, version) in archive.event_versions.iter() {
+        let current = current_events.get(event).copied().unwrap_or(*version);
+        let scoped = ScopedStorage::new(storage, event.as_str());
+        save_schema_version(&scoped, (*version).min(current)).await?;
+    }
+    let migrations = migrate_all(storage, false).await?;
+    let schema_version = migrations
+        .first()
+        .map(|it| it.to())
+        .unwrap_or(archive.schema_version);
+
+    Ok(RestoreResult {
+        mode,
+        schema_version,
+        collections,
+    })
+}
+
+/// Replace the archived collections and the existing ones with the archive, all or nothing
+async fn replace_collections(
+    storage: &Arc<dyn Storage>,
+    archive: &Archive,
+) -> Result<BTreeMap<String, usize>> {
+    let mut names: Vec<String> = archive.collections.keys().cloned().collect();
+    for name in storage.list_collections().await? {
+        let kept = is_secret(name.as_str()) && !archive.with_passwords;
+        if is_archived(name.as_str()) && !kept && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    let names: Vec<&str> = names.iter().map(|it| it.as_str()).collect();
+
+    let staging = Staging::new(storage, &names).await?;
+    match stage_collections(storage, &staging, archive).await {
+        Ok(result) => {
+            staging.commit().await?;
+            Ok(result)
+        }
+        Err(err) => {
+            if let Err(abort) = staging.abort().await {
+                warn!("Could not drop the staging collections: {:#}", abort);
+            }
+            Err(err)
+        }
+    }
+}
+
+async fn stage_collections(
+    storage: &Arc<dyn Storage>,
+    staging: &Staging,
+    archive: &Archive,
+) -> Result<BTreeMap<String, usize>> {
+    if !archive.with_passwords {
+        staging.copy(USERS).await?;
+    }
+    let mut collections = BTreeMap::new();
+    for (name, values) in archive.collections.iter() {
+        info!("Restore {} document(s) into {}", values.len(), name);
+        let col = storage.collection(staging.name(name).as_str());
+        if name == USERS && !archive.with_passwords {
+            for value in values.iter() {
+                merge_document(col.as_ref(), to_document(value)?).await?;
+            }
+        } else {
+            let docs = values.iter().map(to_document).collect::<Result<_>>()?;
+            col.insert_many(docs).await?;
+        }
+        collections.insert(name.clone(), values.len());
+    }
+
+    Ok(collections)
+}
+
+/// The metadata of the database and of the events, the leftovers of a staging,
+/// and the MongoDB system collections are not archived
+fn is_archived(name: &str) -> bool {
+    if is_staging(name) {
+        return false;
+    }
+    match split_scope(name) {
+        Some(("system", _)) => false,
+        Some((_, collection)) => collection != METADATA,
+        None => name != METADATA,
+    }
+}
+
+fn is_secret(name: &str) -> bool {
+    SECRETS.contains(&name)
+}
+
+async fn merge_document(col: &dyn DocumentStore, mut doc: Document) -> Result<()> {
+    let id = doc
+        .remove("_id")
+        .ok_or_else(|| anyhow!("Missing _id in {:?}", doc))?;
+    col.update_one(doc! {"_id": id}, doc! {"$set": doc}, true)
+        .await?;
+
+    Ok(())
+}
+
+fn to_document(value: &Value) -> Result<Document> {
+    match Bson::try_from(value.clone())? {
+        Bson::Document(doc) => Ok(doc),
+        other => bail!("Expected a document, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn should_parse_restore_mode() {
+        assert_eq!("merge".parse::<RestoreMode>().unwrap(), RestoreMode::Merge);
+        assert_eq!(
+            "Replace".parse::<RestoreMode>().unwrap(),
+            RestoreMode::Replace
+        );
+        assert!("drop".parse::<RestoreMode>().is_err());
+    }
+
+    #[test]
+    fn should_archive_the_collections_of_every_event() {
+        assert!(is_archived("users"));
+        assert!(is_archived("devfest-2020.sessions"));
+        assert!(!is_archived(METADATA));
+        assert!(!is_archived("devfest-2020.metadata"));
+        assert!(!is_archived("system.views"));
+        assert!(!is_archived("users__staging"));
+    }
+
+    #[tokio::test]
+    async fn should_backup_and_restore_every_event() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new("devfest"));
+        storage
+            .collection(USERS)
+            .insert_one(doc! {"_id": "u1", "email": "<jane@devfest.fr>", "password": "hash"})
+            .await
+            .unwrap();
+        storage
+            .collection("system.views")
+            .insert_one(doc! {"_id": "v1"})
+            .await
+            .unwrap();
+        for &(event, key) in [("devfest-2020", "rust"), ("devfest-2021", "java")].iter() {
+            ScopedStorage::new(&storage, event)
+                .collection("sessions")
+                .insert_one(doc! {"_id": key, "key": key, "session": {}, "patch": {}})
+                .await
+                .unwrap();
+        }
+        migrate_all(&storage, false).await.unwrap();
+
+        let archive = export_archive(&storage, false).await.unwrap();
+        assert_eq!(
+            archive.collections(),
+            vec!["devfest-2020.sessions", "devfest-2021.sessions", USERS]
+        );
+        assert_eq!(archive.schema_version(), SCHEMA_VERSION);
+        assert_eq!(archive.event_versions().len(), 2);
+        assert!(archive
+            .event_versions()
+            .values()
+            .all(|it| *it == SCHEMA_VERSION));
+
+        let restored: Arc<dyn Storage> = Arc::new(MemoryStorage::new("restored"));
+        let result = import_archive(&restored, &archive, RestoreMode::Replace)
+            .await
+            .unwrap();
+        assert_eq!(result.schema_version(), SCHEMA_VERSION);
+        assert_eq!(result.collections().len(), 3);
+        for &(event, key) in [("devfest-2020", "rust"), ("devfest-2021", "java")].iter() {
+            let scoped = ScopedStorage::new(&restored, event);
+            let sessions = scoped.collection("sessions").find(doc! {}, None).await;
+            assert_eq!(sessions.unwrap()[0].get_str("key").unwrap(), key);
+            assert_eq!(schema_version(&scoped).await.unwrap(), SCHEMA_VERSION);
+        }
+        let user = restored.collection(USERS).find_one(doc! {}).await.unwrap();
+        assert!(!user.unwrap().contains_key("password"));
+    }
+
+    #[tokio::test]
+    async fn should_replace_all_or_nothing() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new("devfest"));
+        let sessions = ScopedStorage::new(&storage, "devfest-2020").collection("sessions");
+        sessions
+            .insert_one(doc! {"_id": "rust", "key": "rust", "session": {}, "patch": {}})
+            .await
+            .unwrap();
+        for &name in SECRETS.iter() {
+            let col = storage.collection(name);
+            col.insert_one(doc! {"_id": "secret"}).await.unwrap();
+        }
+        migrate_all(&storage, false).await.unwrap();
+
+        let archive = export_archive(&storage, false).await.unwrap();
+        assert_eq!(archive.collections(), vec!["devfest-2020.sessions"]);
+
+        storage
+            .collection("rooms")
+            .insert_one(doc! {"_id": "hall"})
+            .await
+            .unwrap();
+        import_archive(&storage, &archive, RestoreMode::Replace)
+            .await
+            .unwrap();
+        assert_eq!(storage.collection("rooms").count(doc! {}).await.unwrap(), 0);
+        for &name in SECRETS.iter() {
+            assert_eq!(storage.collection(name).count(doc! {}).await.unwrap(), 1);
+        }
+
+        let mut broken = archive.clone();
+        let duplicated = archive.collections["devfest-2020.sessions"][0].clone();
+        broken.collections.insert(
+            "devfest-2020.sessions".into(),
+            vec![duplicated.clone(), duplicated],
+        );
+        assert!(import_archive(&storage, &broken, RestoreMode::Replace)
+            .await
+            .is_err());
+        assert_eq!(sessions.count(doc! {}).await.unwrap(), 1);
+        let names = storage.list_collections().await.unwrap();
+        assert!(!names.iter().any(|it| is_staging(it)));
+    }
+
+    #[test]
+    fn should_roundtrip_documents() {
+        let doc = doc! {
+            "_id": "s1",
+            "key": "rust",
+            "session": {"title": "Rust", "speakers": ["jane-doe"]},
+            "patch": {"title": Bson::Null},
+            "nb": 42_i32,
+        };
+        let value = Bson::Document(doc.clone()).into_canonical_extjson();
+
+        assert_eq!(to_document(&value).unwrap(), doc);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use dftk_common::acl::user::User;
+use dftk_common::new_id;
+
+use crate::indexes::IndexDefinition;
+use crate::storage::{DocumentStore, Storage};
+use crate::{from_document, from_documents, to_document};
+
+/// The collection storing the history entries
+const HISTORY: &str = "history";
+
+/// The kind of tracked entity
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Entity {
+    Session,
+    Speaker,
+    Sponsor,
+    TeamMember,
+    SiteInfo,
+    Schedule,
+}
+
+impl FromStr for Entity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let result = match s {
+            "session" => Entity::Session,
+            "speaker" => Entity::Speaker,
+            "sponsor" => Entity::Sponsor,
+            "team_member" => Entity::TeamMember,
+            "site_info" => Entity::SiteInfo,
+            "schedule" => Entity::Schedule,
+            _ => bail!("Unknown entity '{}'", s),
+        };
+
+        Ok(result)
+    }
+}
+
+impl Display for Entity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Entity::Session => "session",
+            Entity::Speaker => "speaker",
+            Entity::Sponsor => "sponsor",
+            Entity::TeamMember => "team_member",
+            Entity::SiteInfo => "site_info",
+            Entity::Schedule => "schedule",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A recorded mutation, `before` is missing for a creation and `after` for a deletion
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    #[serde(rename = "_id")]
+    id: String,
+    entity: Entity,
+    entity_id: String,
+    version: u32,
+    user: Option<User>,
+    at: DateTime<Utc>,
+    before: Option<Document>,
+    after: Option<Document>,
+}
+
+impl HistoryEntry {
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+    pub fn entity_id(&self) -> String {
+        self.entity_id.clone()
+    }
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    pub fn user(&self) -> Option<User> {
+        self.user.clone()
+    }
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+    pub fn before(&self) -> Option<Document> {
+        self.before.clone()
+    }
+    pub fn after(&self) -> Option<Document> {
+        self.after.clone()
+    }
+}
+
+/// A changed field between two versions, nested fields use a dotted path
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    path: String,
+    before: Option<Bson>,
+    after: Option<Bson>,
+}
+
+impl FieldChange {
+    pub fn path(&self) -> String {
+        self.path.clone()
+    }
+    pub fn before(&self) -> Option<Bson> {
+        self.before.clone()
+    }
+    pub fn after(&self) -> Option<Bson> {
+        self.after.clone()
+    }
+}
+
+/// Compute the changed fields between two documents
+pub fn diff(before: Option<&Document>, after: Option<&Document>) -> Vec<FieldChange> {
+    let empty = Document::new();
+    let mut result = vec![];
+    diff_documents(
+        "",
+        before.unwrap_or(&empty),
+        after.unwrap_or(&empty),
+        &mut result,
+    );
+
+    result
+}
+
+fn diff_documents(
+    prefix: &str,
+    before: &Document,
+    after: &Document,
+    result: &mut Vec<FieldChange>,
+) {
+    let keys: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for key in keys {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match (before.get(key), after.get(key)) {
+            (Some(Bson::Document(b)), Some(Bson::Document(a))) => {
+                diff_documents(path.as_str(), b, a, result)
+            }
+            (b, a) if b != a => result.push(FieldChange {
+                path,
+                before: b.cloned(),
+                after: a.cloned(),
+            }),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HistoryRepository {
+    col: Arc<dyn DocumentStore>,
+    user: Option<User>,
+}
+
+impl HistoryRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let col = storage.collection(HISTORY);
+
+        Self { col, user: None }
+    }
+
+    /// The same repository, recording the mutations on behalf of the user
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        Self {
+            col: self.col.clone(),
+            user,
+        }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let entity_version = IndexDefinition::new(
+            "entity_version_unique",
+            doc! {"entity": 1, "entity_id": 1, "version": 1},
+        )
+        .unique();
+        self.col.ensure_indexes(&[entity_version]).await
+    }
+
+    pub async fn record(
+        &self,
+        entity: Entity,
+        entity_id: &str,
+        before: Option<Document>,
+        after: Option<Document>,
+    ) -> Result<HistoryEntry> {
+        let version = self.last_version(entity, entity_id).await? + 1;
+        debug!("Record the version {} of {} {}", version, entity, entity_id);
+        let entry = HistoryEntry {
+            id: new_id().to_string(),
+            entity,
+            entity_id: entity_id.into(),
+            version,
+            user: self.user.clone(),
+            at: Utc::now(),
+            before,
+            after,
+        };
+        let doc = to_document(&entry)?;
+        self.col.insert_one(doc).await?;
+
+        Ok(entry)
+    }
+
+    /// The history of an entity, sorted by version
+    pub async fn find(&self, entity: Entity, entity_id: &str) -> Result<Vec<HistoryEntry>> {
+        let query = doc! {"entity": entity.to_string(), "entity_id": entity_id};
+        let docs = self.col.find(query, Some(doc! {"version": 1})).await?;
+
+        from_documents(docs)
+    }
+
+    pub async fn find_version(
+        &self,
+        entity: Entity,
+        entity_id: &str,
+        version: u32,
+    ) -> Result<Option<HistoryEntry>> {
+        let query = doc! {
+            "entity": entity.to_string(),
+            "entity_id": entity_id,
+            "version": version as i64,
+        };
+        let result = match self.col.find_one(query).await? {
+            Some(doc) => Some(from_document(doc)?),
+            None => None,
+        };
+
+        Ok(result)
+    }
+
+    /// Compare the states of an entity after two versions, the version 0 is the empty state
+    pub async fn diff(
+        &self,
+        entity: Entity,
+        entity_id: &str,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<FieldChange>> {
+        let before = self.state(entity, entity_id, from).await?;
+        let after = self.state(entity, entity_id, to).await?;
+
+        Ok(diff(before.as_ref(), after.as_ref()))
+    }
+
+    /// The state of an entity after the version
+    pub async fn state(
+        &self,
+        entity: Entity,
+        entity_id: &str,
+        version: u32,
+    ) -> Result<Option<Document>> {
+        if version == 0 {
+            return Ok(None);
+        }
+        let entry = self
+            .find_version(entity, entity_id, version)
+            .await?
+            .ok_or_else(|| anyhow!("No version {} for {} {}", version, entity, entity_id))?;
+
+        Ok(entry.after)
+    }
+
+    /// The last recorded version of an entity, `0` without history
+    pub async fn last_version(&self, entity: Entity, entity_id: &str) -> Result<u32> {
+        let query = doc! {"entity": entity.to_string(), "entity_id": entity_id};
+        let count = self.col.count(query).await?;
+
+        Ok(count as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_entity() {
+        for entity in [Entity::Session, Entity::TeamMember, Entity::SiteInfo].iter() {
+            assert_eq!(entity.to_string().parse::<Entity>().unwrap(), *entity);
+        }
+        assert!("room".parse::<Entity>().is_err());
+    }
+
+    #[test]
+    fn should_diff_nested_fields() {
+        let before = doc! {"key": "keynote", "patch": {"title": "Keynote", "description": "Hello"}};
+        let after = doc! {"key": "keynote", "patch": {"title": "Keynote", "draft": true}};
+        let result = diff(Some(&before), Some(&after));
+
+        assert_eq!(
+            result,
+            vec![
+                FieldChange {
+                    path: "patch.description".into(),
+                    before: Some(Bson::String("Hello".into())),
+                    after: None,
+                },
+                FieldChange {
+                    path: "patch.draft".into(),
+                    before: None,
+                    after: Some(Bson::Boolean(true)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn should_diff_creation() {
+        let after = doc! {"key": "keynote"};
+        let result = diff(None, Some(&after));
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].path(), "key");
+        assert_eq!(result[0].before(), None);
+    }
+}
@@ -0,0 +1,215 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+
+use anyhow::{anyhow, Result};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::{Error, ErrorKind, WriteFailure};
+use mongodb::Database;
+
+/// MongoDB error code for a duplicate key
+const DUPLICATE_KEY: i32 = 11000;
+
+/// MongoDB error code when the collection does not exist yet
+const NAMESPACE_NOT_FOUND: i32 = 26;
+
+/// The name of the default `_id` index, never reconciled
+const ID_INDEX: &str = "_id_";
+
+/// A write rejected because of existing data, e.g. a duplicated key or email
+#[derive(Debug, Clone)]
+pub struct Conflict(String);
+
+impl Conflict {
+    pub(crate) fn new(message: String) -> Self {
+        Self(message)
+    }
+
+    pub fn message(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Conflict: {}", self.0)
+    }
+}
+
+impl StdError for Conflict {}
+
+/// Translate a duplicate key error into a [`Conflict`]
+pub(crate) fn write_error(err: Error) -> anyhow::Error {
+    let message = match err.kind.as_ref() {
+        ErrorKind::WriteError(WriteFailure::WriteError(we)) if we.code == DUPLICATE_KEY => {
+            Some(we.message.clone())
+        }
+        ErrorKind::BulkWriteError(bwf) => bwf
+            .write_errors
+            .iter()
+            .flatten()
+            .find(|it| it.code == DUPLICATE_KEY)
+            .map(|it| it.message.clone()),
+        ErrorKind::CommandError(ce) if ce.code == DUPLICATE_KEY => Some(ce.message.clone()),
+        _ => None,
+    };
+
+    match message {
+        Some(message) => Conflict(message).into(),
+        None => err.into(),
+    }
+}
+
+/// Declarative definition of a MongoDB index
+#[derive(Debug, Clone)]
+pub struct IndexDefinition {
+    name: String,
+    keys: Document,
+    unique: bool,
+}
+
+impl IndexDefinition {
+    pub fn new(name: &str, keys: Document) -> Self {
+        let name = name.into();
+
+        Self {
+            name,
+            keys,
+            unique: false,
+        }
+    }
+
+    pub fn unique(self) -> Self {
+        Self {
+            unique: true,
+            ..self
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn keys(&self) -> Document {
+        self.keys.clone()
+    }
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    fn to_document(&self) -> Document {
+        doc! {
+            "name": self.name.clone(),
+            "key": self.keys.clone(),
+            "unique": self.unique,
+        }
+    }
+
+    fn matches(&self, index: &Document) -> bool {
+        let keys = index.get_document("key").ok();
+        let unique = index.get_bool("unique").unwrap_or(false);
+
+        keys == Some(&self.keys) && unique == self.unique
+    }
+}
+
+/// The unique index on the `key` field, shared by most collections
+pub fn unique_key() -> IndexDefinition {
+    IndexDefinition::new("key_unique", doc! {"key": 1}).unique()
+}
+
+/// Create the missing indexes, recreate the changed ones, and drop the undeclared ones
+pub(crate) async fn reconcile_indexes(
+    db: &Database,
+    collection: &str,
+    indexes: &[IndexDefinition],
+) -> Result<()> {
+    let existing = list_indexes(db, collection).await?;
+
+    for index in existing.iter() {
+        let name = index.get_str("name").unwrap_or_default();
+        if name == ID_INDEX {
+            continue;
+        }
+        if let Some(declared) = indexes.iter().find(|it| it.name == name) {
+            if declared.matches(index) {
+                continue;
+            }
+        }
+        info!("Drop the index {} of {}", name, collection);
+        db.run_command(doc! {"dropIndexes": collection, "index": name}, None)
+            .await?;
+    }
+
+    let to_create: Vec<Bson> = indexes
+        .iter()
+        .filter(|declared| {
+            !existing.iter().any(|index| {
+                index.get_str("name").ok() == Some(declared.name.as_str())
+                    && declared.matches(index)
+            })
+        })
+        .map(|it| {
+            info!("Create the index {} of {}", it.name, collection);
+            it.to_document().into()
+        })
+        .collect();
+    if !to_create.is_empty() {
+        db.run_command(
+            doc! {"createIndexes": collection, "indexes": to_create},
+            None,
+        )
+        .await
+        .map_err(|err| anyhow!("Could not create indexes of {}: {}", collection, err))?;
+    }
+
+    Ok(())
+}
+
+async fn list_indexes(db: &Database, collection: &str) -> Result<Vec<Document>> {
+    let result = match db.run_command(doc! {"listIndexes": collection}, None).await {
+        Ok(result) => result,
+        Err(err) => {
+            if let ErrorKind::CommandError(ce) = err.kind.as_ref() {
+                if ce.code == NAMESPACE_NOT_FOUND {
+                    return Ok(vec![]);
+                }
+            }
+            return Err(err.into());
+        }
+    };
+
+    let indexes = result
+        .get_document("cursor")?
+        .get_array("firstBatch")?
+        .iter()
+        .filter_map(|it| it.as_document().cloned())
+        .collect();
+
+    Ok(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod index_definition {
+        use super::*;
+
+        #[test]
+        fn should_match_same_index() {
+            let index = doc! {"v": 2, "name": "key_unique", "key": {"key": 1}, "unique": true};
+            assert!(unique_key().matches(&index));
+        }
+
+        #[test]
+        fn should_not_match_non_unique_index() {
+            let index = doc! {"v": 2, "name": "key_unique", "key": {"key": 1}};
+            assert!(!unique_key().matches(&index));
+        }
+
+        #[test]
+        fn should_not_match_other_keys() {
+            let index = doc! {"v": 2, "name": "key_unique", "key": {"slug": 1}, "unique": true};
+            assert!(!unique_key().matches(&index));
+        }
+    }
+}
@@ -1,52 +1,105 @@
 #[macro_use]
 extern crate log;
 
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
 
-use anyhow::{anyhow, Context, Result};
-use bson::Document;
-use mongodb::{Client, Cursor};
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bson::{doc, Document};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
-use tokio::stream::StreamExt;
+use serde::{Deserialize, Serialize};
 
 use dftk_common::acl::operation::Operation;
-use dftk_common::acl::user::User;
-use dftk_common::models::schedule::{Room, ScheduleDay, Slot};
-use dftk_common::models::site::{Site, SiteInfo};
-use dftk_common::models::speaker::SpeakerKey;
+use dftk_common::acl::role::{Permission, RoleKey};
+use dftk_common::acl::user::{Email, User};
+use dftk_common::models::schedule::placement::{find_placement, find_placements, SessionPlacement};
+use dftk_common::models::schedule::{Room, ScheduleDay};
+use dftk_common::models::session::{SessionId, SessionKey};
+use dftk_common::models::site::{EventId, Site, SiteInfo};
+use dftk_common::models::speaker::{Speaker, SpeakerId, SpeakerKey};
 
+use crate::api_tokens::ApiTokenRepository;
+use crate::backup::{export_archive, import_archive, Archive, RestoreMode, RestoreResult};
+use crate::history::{Entity, HistoryEntry, HistoryRepository};
+use crate::indexes::unique_key;
+use crate::login_attempts::LoginAttemptRepository;
+use crate::migrations::{migrate_all, migrate_database};
+use crate::oidc_states::OidcStateRepository;
+use crate::password_tokens::PasswordTokenRepository;
 use crate::repository::MongodbRepository;
-use crate::session_categories::SessionCategoryRepository;
-use crate::session_formats::SessionFormatRepository;
-use crate::sessions::SessionRepository;
-use crate::speakers::SpeakerRepository;
+use crate::revision::check_revision;
+use crate::roles::RoleRepository;
+use crate::session_categories::{SessionCategoryRepository, SESSION_CATEGORIES};
+use crate::session_formats::{SessionFormatRepository, SESSION_FORMATS};
+use crate::sessions::{SessionRepository, SESSIONS};
+use crate::slots::SlotRepository;
+use crate::speakers::{SpeakerRepository, SPEAKERS};
 use crate::sponsor_type::SponsorCategoryRepository;
 use crate::sponsors::SponsorRepository;
+use crate::staging::Staging;
+use crate::storage::{
+    list_scopes, open_storage, MemoryStorage, ScopedStorage, Storage, StorageKind,
+};
 use crate::team_member_types::MemberTypeRepository;
 use crate::team_members::TeamMemberRepository;
-use crate::user::UserRepository;
+use crate::user::{Escalation, UnknownKey, UserRepository};
+use crate::user_sessions::UserSessionRepository;
 
+pub mod api_tokens;
+pub mod backup;
+pub mod history;
+pub mod indexes;
+pub mod login_attempts;
+pub mod migrations;
+pub mod oidc_states;
+pub mod password_tokens;
+pub mod query;
 pub mod repository;
+pub mod revision;
+pub mod roles;
 pub mod session_categories;
 pub mod session_formats;
 pub mod sessions;
+pub mod slots;
 pub mod speakers;
 pub mod sponsor_type;
 pub mod sponsors;
+mod staging;
+pub mod storage;
 pub mod team_member_types;
 pub mod team_members;
 pub mod user;
+pub mod user_sessions;
 
 #[derive(Clone, Debug)]
 pub struct MongodbConfig {
     pub url: String,
     pub database: String,
+    pub storage: StorageKind,
+    /// The default event, `None` for the collections without event
+    pub event: Option<EventId>,
 }
 
 impl MongodbConfig {
+    /// The storage is deduced from the URL, e.g. `file://devfest.json` for the file storage
     pub fn new(url: String, database: String) -> Self {
-        Self { url, database }
+        let storage = StorageKind::from_url(url.as_str());
+
+        Self {
+            url,
+            database,
+            storage,
+            event: None,
+        }
+    }
+
+    pub fn with_storage(self, storage: StorageKind) -> Self {
+        Self { storage, ..self }
+    }
+
+    pub fn with_event(self, event: Option<EventId>) -> Self {
+        Self { event, ..self }
     }
 }
 
@@ -55,7 +108,7 @@ impl Default for MongodbConfig {
         let url = "mongodb://localhost:27017/".into();
         let database = "devfest".into();
 
-        Self { url, database }
+        Self::new(url, database)
     }
 }
 
@@ -82,9 +135,41 @@ impl SynchronizeResult {
     }
 }
 
+#[derive(Serialize, Debug, Clone)]
+pub struct CloneResult {
+    event: String,
+    collections: BTreeMap<String, usize>,
+}
+
+impl CloneResult {
+    pub fn event(&self) -> EventId {
+        EventId::new(self.event.clone())
+    }
+    pub fn collections(&self) -> &BTreeMap<String, usize> {
+        &self.collections
+    }
+}
+
+/// The scoped repositories of every opened event
+type EventCache = Arc<Mutex<BTreeMap<String, Repositories>>>;
+
 #[derive(Clone)]
 pub struct Repositories {
+    root: Arc<dyn Storage>,
+    storage: Arc<dyn Storage>,
+    event: Option<EventId>,
+    events: EventCache,
+    history: HistoryRepository,
+
+    /// The user doing the requests
+    current_user: User,
     user: UserRepository,
+    user_session: UserSessionRepository,
+    password_token: PasswordTokenRepository,
+    login_attempt: LoginAttemptRepository,
+    api_token: ApiTokenRepository,
+    oidc_state: OidcStateRepository,
+    role: RoleRepository,
 
     info: MongodbRepository<SiteInfo>,
 
@@ -101,43 +186,111 @@ pub struct Repositories {
     sponsor_category: SponsorCategoryRepository,
 
     room: MongodbRepository<Room>,
-    slot: MongodbRepository<Slot>,
+    slot: SlotRepository,
     schedule: MongodbRepository<ScheduleDay>,
 }
 
 impl Repositories {
     pub async fn build(config: &MongodbConfig) -> Result<Repositories> {
-        info!(
-            "Connection to mongodb {} using database {}",
-            config.url, config.database
-        );
+        let storage = open_storage(config).await?;
+        let result = Self::with_storage(storage).await?;
+
+        match &config.event {
+            Some(event) => result.for_event(event).await,
+            None => Ok(result),
+        }
+    }
+
+    /// Repositories without database, everything is lost when the process stops
+    pub async fn in_memory() -> Result<Repositories> {
+        let storage = Arc::new(MemoryStorage::new("devfest"));
+
+        Self::with_storage(storage).await
+    }
+
+    pub async fn with_storage(storage: Arc<dyn Storage>) -> Result<Repositories> {
+        migrate_all(&storage, false).await?;
+        let result = Self::scoped(storage, None, EventCache::default()).await?;
+        result.user.ensure_indexes().await?;
+        result.user_session.ensure_indexes().await?;
+        result.password_token.ensure_indexes().await?;
+        result.login_attempt.ensure_indexes().await?;
+        result.api_token.ensure_indexes().await?;
+        result.role.ensure_defaults().await?;
+
+        Ok(result)
+    }
 
-        let client = Client::with_uri_str(config.url.as_str()).await?;
-        let db = client.database(config.database.as_str());
-        let user = UserRepository::build(&db).await?;
+    /// Build the repositories of an event, the users with their sessions, login attempts and API tokens,
+    /// and the roles are shared by every event
+    async fn scoped(
+        root: Arc<dyn Storage>,
+        event: Option<EventId>,
+        events: EventCache,
+    ) -> Result<Repositories> {
+        let storage: Arc<dyn Storage> = match &event {
+            Some(event) => {
+                let event: String = event.clone().into();
+                Arc::new(ScopedStorage::new(&root, event.as_str()))
+            }
+            None => Arc::clone(&root),
+        };
+        let db = storage.as_ref();
 
-        let info = MongodbRepository::new(&db, "info");
+        let history = HistoryRepository::new(db);
+        let user = UserRepository::build(root.as_ref()).await?;
+        let user_session = UserSessionRepository::new(root.as_ref());
+        let password_token = PasswordTokenRepository::new(root.as_ref());
+        let login_attempt = LoginAttemptRepository::new(root.as_ref());
+        let api_token = ApiTokenRepository::new(root.as_ref());
+        let oidc_state = OidcStateRepository::new(root.as_ref());
+        let role = RoleRepository::new(root.as_ref());
 
-        let session_category = SessionCategoryRepository::new(&db);
-        let session_format = SessionFormatRepository::new(&db);
-        let session = SessionRepository::new(&db);
+        let info = MongodbRepository::new(db, INFO).with_history(Entity::SiteInfo, &history);
 
-        let speaker = SpeakerRepository::new(&db);
+        let session_category = SessionCategoryRepository::new(db);
+        let session_format = SessionFormatRepository::new(db);
+        let session = SessionRepository::new(db, &history);
 
-        let team = TeamMemberRepository::new(&db);
-        let member_type = MemberTypeRepository::new(&db);
+        let speaker = SpeakerRepository::new(db, &history);
 
-        let sponsor = SponsorRepository::new(&db);
-        let sponsor_category = SponsorCategoryRepository::new(&db);
+        let team = TeamMemberRepository::new(db, &history);
+        let member_type = MemberTypeRepository::new(db);
 
-        let room = MongodbRepository::new(&db, "rooms");
-        let slot = MongodbRepository::new(&db, "slots");
-        let schedule = MongodbRepository::new(&db, "schedule");
+        let sponsor = SponsorRepository::new(db, &history);
+        let sponsor_category = SponsorCategoryRepository::new(db);
 
-        // FIXME indexes
+        let room = MongodbRepository::new(db, "rooms");
+        let slot = SlotRepository::new(db);
+        let schedule = MongodbRepository::new(db, "schedule");
+
+        info!("Ensure indexes of database {}", db.name());
+        history.ensure_indexes().await?;
+        session_category.ensure_indexes().await?;
+        session_format.ensure_indexes().await?;
+        session.ensure_indexes().await?;
+        speaker.ensure_indexes().await?;
+        team.ensure_indexes().await?;
+        member_type.ensure_indexes().await?;
+        sponsor.ensure_indexes().await?;
+        sponsor_category.ensure_indexes().await?;
+        room.ensure_indexes(&[unique_key()]).await?;
+        slot.ensure_indexes().await?;
 
         let repositories = Repositories {
+            root,
+            storage,
+            event,
+            events,
+            history,
+            current_user: User::Guest,
             user,
+            user_session,
+            password_token,
+            login_attempt,
+            api_token,
+            oidc_state,
+            role,
             info,
             session_category,
             session_format,
@@ -154,9 +307,116 @@ impl Repositories {
 
         Ok(repositories)
     }
+    /// The repositories of an event, created on the first write
+    pub async fn for_event(&self, event: &EventId) -> Result<Repositories> {
+        let name: String = event.clone().into();
+        check_event_id(name.as_str())?;
+        let cached = self.events.lock().unwrap().get(&name).cloned();
+        if let Some(result) = cached {
+            return Ok(result);
+        }
+
+        info!("Open the event {}", name);
+        let root = Arc::clone(&self.root);
+        // A new event starts with the current schema version
+        migrate_database(&ScopedStorage::new(&root, name.as_str()), false).await?;
+        let events = Arc::clone(&self.events);
+        let result = Self::scoped(root, Some(event.clone()), events).await?;
+        self.events.lock().unwrap().insert(name, result.clone());
+
+        Ok(result)
+    }
+
+    /// The repositories of an existing event, `None` if the event has no data
+    pub async fn existing_event(&self, event: &EventId) -> Result<Option<Repositories>> {
+        let name: String = event.clone().into();
+        let cached = self.events.lock().unwrap().contains_key(&name);
+        if cached || self.events().await?.contains(event) {
+            Ok(Some(self.for_event(event).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The event of these repositories, `None` for the collections without event
+    pub fn event(&self) -> Option<EventId> {
+        self.event.clone()
+    }
+
+    /// The events having data
+    pub async fn events(&self) -> Result<Vec<EventId>> {
+        let result = list_scopes(self.root.as_ref())
+            .await?
+            .into_iter()
+            .map(EventId::new)
+            .collect();
+
+        Ok(result)
+    }
+
+    /// Start a new edition with the reusable data of this one:
+    /// team, member types, sponsor categories, rooms and formats
+    pub async fn clone_edition(&self, to: &EventId) -> Result<CloneResult> {
+        let event: String = to.clone().into();
+        ensure!(
+            self.event.as_ref() != Some(to) && !self.events().await?.contains(to),
+            "The event {} already exists",
+            event
+        );
+        let target = self.for_event(to).await?;
+
+        let mut collections = BTreeMap::new();
+        for name in REUSABLE.iter() {
+            let docs = self.storage.collection(name).find(doc! {}, None).await?;
+            info!("Copy {} document(s) of {} into {}", docs.len(), name, event);
+            collections.insert(String::from(*name), docs.len());
+            target.storage.collection(name).insert_many(docs).await?;
+        }
+
+        Ok(CloneResult { event, collections })
+    }
+
+    /// The same repositories, recording the mutations on behalf of the user
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        Self {
+            current_user: user.clone().unwrap_or(User::Guest),
+            history: self.history.as_user(user.clone()),
+            info: self.info.as_user(user.clone()),
+            session: self.session.as_user(user.clone()),
+            speaker: self.speaker.as_user(user.clone()),
+            team: self.team.as_user(user.clone()),
+            sponsor: self.sponsor.as_user(user),
+            ..self.clone()
+        }
+    }
+
+    pub fn current_user(&self) -> User {
+        self.current_user.clone()
+    }
+    pub fn history(&self) -> HistoryRepository {
+        self.history.clone()
+    }
     pub fn user(&self) -> UserRepository {
         self.user.clone()
     }
+    pub fn user_session(&self) -> UserSessionRepository {
+        self.user_session.clone()
+    }
+    pub fn password_token(&self) -> PasswordTokenRepository {
+        self.password_token.clone()
+    }
+    pub fn login_attempt(&self) -> LoginAttemptRepository {
+        self.login_attempt.clone()
+    }
+    pub fn api_token(&self) -> ApiTokenRepository {
+        self.api_token.clone()
+    }
+    pub fn oidc_state(&self) -> OidcStateRepository {
+        self.oidc_state.clone()
+    }
+    pub fn role(&self) -> RoleRepository {
+        self.role.clone()
+    }
     pub fn info(&self) -> MongodbRepository<SiteInfo> {
         self.info.clone()
     }
@@ -187,28 +447,311 @@ impl Repositories {
     pub fn room(&self) -> MongodbRepository<Room> {
         self.room.clone()
     }
-    pub fn slot(&self) -> MongodbRepository<Slot> {
+    pub fn slot(&self) -> SlotRepository {
         self.slot.clone()
     }
     pub fn schedule(&self) -> MongodbRepository<ScheduleDay> {
         self.schedule.clone()
     }
 
+    /// Backup the whole database, the shared collections and every event
+    pub async fn backup(&self, with_passwords: bool) -> Result<Archive> {
+        info!("Backup the database {}", self.root.name());
+        export_archive(&self.root, with_passwords).await
+    }
+
+    /// Restore a backup of the whole database
+    pub async fn restore(&self, archive: &Archive, mode: RestoreMode) -> Result<RestoreResult> {
+        info!("Restore the database {} with {:?}", self.root.name(), mode);
+        let result = import_archive(&self.root, archive, mode).await?;
+        // The swapped collections come without the indexes of the previous ones
+        Self::with_storage(Arc::clone(&self.root)).await?;
+        for event in self.events().await? {
+            let name: String = event.clone().into();
+            let events = Arc::clone(&self.events);
+            let repositories = Self::scoped(Arc::clone(&self.root), Some(event), events).await?;
+            self.events.lock().unwrap().insert(name, repositories);
+        }
+
+        Ok(result)
+    }
+
+    /// Replace the schedule, checking the expected revision if any
+    pub async fn update_schedule(
+        &self,
+        schedule: &[ScheduleDay],
+        expected: Option<u32>,
+    ) -> Result<usize> {
+        let current = self.schedule_revision().await?;
+        check_revision(expected, Some(current))?;
+        let (result, _entry) = self.save_schedule(schedule).await?;
+
+        Ok(result)
+    }
+
+    /// The schedule is saved as a whole, its revision is its last history version
+    pub async fn schedule_revision(&self) -> Result<u32> {
+        self.history
+            .last_version(Entity::Schedule, SCHEDULE_ID)
+            .await
+    }
+
+    /// The current revision of an entity, `None` if it does not exist
+    pub async fn revision(&self, entity: Entity, id: &str) -> Result<Option<u32>> {
+        let result = match entity {
+            Entity::Session => self.session.revision(SessionId::new(id.into())).await?,
+            Entity::Speaker => self.speaker.revision(SpeakerId::new(id.into())).await?,
+            Entity::Sponsor => self.sponsor.revision(id.parse()?).await?,
+            Entity::TeamMember => self.team.revision(id.parse()?).await?,
+            Entity::SiteInfo => self.info.revision(id).await?,
+            Entity::Schedule => Some(self.schedule_revision().await?),
+        };
+
+        Ok(result)
+    }
+
+    async fn save_schedule(&self, schedule: &[ScheduleDay]) -> Result<(usize, HistoryEntry)> {
+        info!("Update the schedule with {} days", schedule.len());
+        let before = self.schedule.find_all().await?;
+        let result = if schedule.is_empty() {
+            self.schedule.remove_all().await?;
+            0
+        } else {
+            self.schedule.update_all(schedule).await?
+        };
+        let entry = self
+            .history
+            .record(
+                Entity::Schedule,
+                SCHEDULE_ID,
+                Some(schedule_document(&before)?),
+                Some(schedule_document(schedule)?),
+            )
+            .await?;
+
+        Ok((result, entry))
+    }
+
+    /// Restore an entity to its state after a version of its history
+    pub async fn revert(&self, entity: Entity, id: &str, version: u32) -> Result<HistoryEntry> {
+        let result = match entity {
+            Entity::Session => {
+                self.session
+                    .revert(SessionId::new(id.into()), version)
+                    .await?
+            }
+            Entity::Speaker => {
+                self.speaker
+                    .revert(SpeakerId::new(id.into()), version)
+                    .await?
+            }
+            Entity::Sponsor => self.sponsor.revert(id.parse()?, version).await?,
+            Entity::TeamMember => self.team.revert(id.parse()?, version).await?,
+            Entity::SiteInfo => self.info.revert(id, version).await?,
+            Entity::Schedule => {
+                let state = self.history.state(entity, SCHEDULE_ID, version).await?;
+                let days = match state {
+                    Some(doc) => from_document::<ScheduleHistory>(doc)?.days,
+                    None => vec![],
+                };
+                let (_result, entry) = self.save_schedule(&days).await?;
+                entry
+            }
+        };
+
+        Ok(result)
+    }
+
+    pub async fn find_placements(&self) -> Result<Vec<SessionPlacement>> {
+        let schedule = self.schedule.find_all().await?;
+        let slots = self.slot.find_all().await?;
+
+        Ok(find_placements(&schedule, &slots))
+    }
+
+    pub async fn find_placement(&self, session: &SessionKey) -> Result<Option<SessionPlacement>> {
+        let schedule = self.schedule.find_all().await?;
+        let slots = self.slot.find_all().await?;
+
+        Ok(find_placement(&schedule, &slots, session))
+    }
+
+    /// Check the permissions of the user roles, an administrator is always allowed
+    /// and a team member can always view the site,
+    /// a speaker or a sponsor only gets its own resources in its event
     pub async fn is_allowed(&self, user: &User, operation: &Operation) -> Result<bool> {
+        match user {
+            User::Guest => return Ok(false),
+            User::Admin { .. } => return Ok(true),
+            User::Team { .. } if operation.is_view() => return Ok(true),
+            _ => {}
+        }
+        let permissions = self.user_permissions(user).await?;
+        if permissions.contains(&operation.permission()) {
+            return Ok(true);
+        }
+        let own = match operation.own_permission() {
+            Some(permission) => permissions.contains(&permission),
+            None => false,
+        };
+        if !own {
+            return Ok(false);
+        }
+
+        // The keys are only unique in an event
+        if user.event() != self.event {
+            return Ok(false);
+        }
         let allowed = match user {
-            User::Guest => false,
-            User::Admin { .. } => true,
-            User::Team { .. } => operation.is_view(),
             User::Speaker { key, .. } => self.operation_speakers(operation).await?.contains(key),
             User::Sponsor { key, .. } => operation
                 .sponsor() //
                 .map(|it| &it == key) //
                 .unwrap_or(false),
+            _ => false,
         };
 
         Ok(allowed)
     }
 
+    /// The permissions of the user roles, and the permissions granted without role
+    async fn user_permissions(&self, user: &User) -> Result<Vec<Permission>> {
+        let mut permissions = match user {
+            User::Guest => return Ok(vec![]),
+            User::Admin { .. } => return Ok(Permission::all()),
+            User::Team { .. } => vec![Permission::ViewSite],
+            _ => vec![],
+        };
+        permissions.extend(self.role.permissions(&user.roles()).await?);
+        permissions.extend(user.permissions());
+
+        Ok(permissions)
+    }
+
+    /// The user of a valid API token, its permissions are limited to the current ones of its owner
+    pub async fn api_client(&self, value: &str) -> Result<Option<User>> {
+        let token = match self.api_token.authenticate(value).await? {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        let owner = match self.user.find_by_email(&token.owner()).await? {
+            Some(owner) => owner,
+            None => {
+                debug!("API token {} without owner", token.id());
+                return Ok(None);
+            }
+        };
+
+        let held = self.user_permissions(&owner).await?;
+        let mut permissions = vec![];
+        for permission in self.user_permissions(&token.user()).await? {
+            if held.iter().any(|it| it.includes(permission)) && !permissions.contains(&permission) {
+                permissions.push(permission);
+            }
+        }
+
+        Ok(Some(User::ApiClient {
+            token: token.id(),
+            owner: token.owner(),
+            roles: vec![],
+            permissions,
+        }))
+    }
+
+    /// Check the current user holds every permission of the user,
+    /// only an administrator could give or take the administration
+    pub async fn check_grant(&self, user: &User) -> Result<()> {
+        let current = self.current_user();
+        if let User::Admin { .. } = current {
+            return Ok(());
+        }
+        if let User::Admin { .. } = user {
+            let message = format!("the administration to {:?}", user.email());
+            return Err(Escalation::new(message).into());
+        }
+        let held = self.user_permissions(&current).await?;
+        let missing: Vec<Permission> = self
+            .user_permissions(user)
+            .await?
+            .into_iter()
+            .filter(|it| !held.iter().any(|held| held.includes(*it)))
+            .collect();
+        if !missing.is_empty() {
+            let message = format!("{:?} to {:?}", missing, user.email());
+            return Err(Escalation::new(message).into());
+        }
+
+        Ok(())
+    }
+
+    /// Check the current user could issue the API token, only an administrator issues
+    /// the tokens of another owner, and the token cannot hold more than the current user
+    pub async fn check_api_token(
+        &self,
+        owner: &Email,
+        role: Option<RoleKey>,
+        permissions: &[Permission],
+    ) -> Result<()> {
+        let current = self.current_user();
+        let is_admin = matches!(current, User::Admin { .. });
+        if !is_admin && current.email().as_ref() != Some(owner) {
+            let message = format!("an API token of {:?}", owner);
+            return Err(Escalation::new(message).into());
+        }
+        let token = User::ApiClient {
+            token: String::new(),
+            owner: owner.clone(),
+            roles: role.into_iter().collect(),
+            permissions: permissions.to_vec(),
+        };
+
+        self.check_grant(&token).await
+    }
+
+    /// Check the speaker or the sponsor of the user exists in the event of the user,
+    /// otherwise the user would never be allowed anything
+    pub async fn check_user(&self, user: &User) -> Result<()> {
+        if user.event() != self.event {
+            let message = format!("{:?} is not in the event {:?}", user, self.event);
+            return Err(UnknownKey::new(message).into());
+        }
+        match user {
+            User::Speaker { key, .. } => {
+                if self.speaker.find_by_key(key.clone()).await?.is_none() {
+                    let message = format!("No speaker found for {:?}", key);
+                    return Err(UnknownKey::new(message).into());
+                }
+            }
+            User::Sponsor { key, .. } => {
+                if self.sponsor.find_by_key(key.clone()).await?.is_none() {
+                    let message = format!("No sponsor found for {:?}", key);
+                    return Err(UnknownKey::new(message).into());
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// The speakers of the sessions that are not drafts, i.e. accepted
+    pub async fn accepted_speakers(&self) -> Result<Vec<Speaker>> {
+        let mut keys: Vec<String> = vec![];
+        for session in self.session.find_all().await? {
+            if session.draft().unwrap_or_default() {
+                continue;
+            }
+            for key in session.speakers() {
+                let key: String = key.into();
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+
+        self.speaker.find_by_keys(&keys).await
+    }
+
     async fn operation_speakers(&self, operation: &Operation) -> Result<Vec<SpeakerKey>> {
         let result = match operation {
             Operation::ViewSpeaker(k) => vec![k.clone()],
@@ -238,6 +781,7 @@ impl Repositories {
 
         debug!("Load site slots");
         let slots = self.slot.find_all().await?;
+        let slot_layouts = self.slot.find_layouts().await?;
 
         debug!("Load site schedule");
         let schedule = self.schedule.find_all().await?;
@@ -259,6 +803,7 @@ impl Repositories {
             formats,
             rooms,
             slots,
+            slot_layouts,
             schedule,
             team,
             member_types,
@@ -269,29 +814,102 @@ impl Repositories {
         Ok(site)
     }
 
+    /// Synchronize into staging collections swapped at the end, a failure keeps the previous data
     pub async fn synchronize(&self, site: Site) -> Result<SynchronizeResult> {
+        let collections = [
+            INFO,
+            SESSION_CATEGORIES,
+            SESSION_FORMATS,
+            SESSIONS,
+            SPEAKERS,
+        ];
+        let staging = Staging::new(&self.storage, &collections).await?;
+        match self.stage_synchronization(&staging, site).await {
+            Ok(result) => {
+                staging.commit().await.map_err(failed_at("swap"))?;
+                Ok(result)
+            }
+            Err(err) => {
+                if let Err(abort) = staging.abort().await {
+                    warn!("Could not drop the staging collections: {:#}", abort);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    async fn stage_synchronization(
+        &self,
+        staging: &Staging,
+        site: Site,
+    ) -> Result<SynchronizeResult> {
+        let db = self.storage.as_ref();
+
         debug!("Synchronise site info");
-        self.info.remove_all().await?;
-        self.info.insert(&site.info()).await?;
+        // Conference Hall does not know the local time of the conference
+        let site_info = match self.info.find_first().await {
+            Ok(current) => site.info().with_utc_offset(current.utc_offset()),
+            Err(_) => site.info(),
+        };
+        let info = MongodbRepository::new(db, staging.name(INFO).as_str());
+        info.insert(&site_info).await.map_err(failed_at("info"))?;
 
         debug!("Synchronise site categories");
-        let nb_categories = self.session_category.update_all(site.categories()).await? as u32;
+        let session_category = SessionCategoryRepository::with_collection(
+            db,
+            staging.name(SESSION_CATEGORIES).as_str(),
+        );
+        session_category
+            .ensure_indexes()
+            .await
+            .map_err(failed_at("categories"))?;
+        let nb_categories = session_category
+            .update_all(site.categories())
+            .await
+            .map_err(failed_at("categories"))? as u32;
 
         debug!("Synchronise site formats");
-        let nb_formats = self.session_format.update_all(site.formats()).await? as u32;
+        let session_format =
+            SessionFormatRepository::with_collection(db, staging.name(SESSION_FORMATS).as_str());
+        session_format
+            .ensure_indexes()
+            .await
+            .map_err(failed_at("formats"))?;
+        let nb_formats = session_format
+            .update_all(site.formats())
+            .await
+            .map_err(failed_at("formats"))? as u32;
 
         debug!("Synchronise site sessions");
-        let nb_sessions = self
-            .session
+        staging
+            .copy(SESSIONS)
+            .await
+            .map_err(failed_at("sessions"))?;
+        let session = SessionRepository::with_collection(db, staging.name(SESSIONS).as_str());
+        session
+            .ensure_indexes()
+            .await
+            .map_err(failed_at("sessions"))?;
+        let nb_sessions = session
             .synchronize_sessions(site.sessions())
-            .await?
+            .await
+            .map_err(failed_at("sessions"))?
             .len() as u32;
 
         debug!("Synchronise site speakers");
-        let nb_speakers = self
-            .speaker
+        staging
+            .copy(SPEAKERS)
+            .await
+            .map_err(failed_at("speakers"))?;
+        let speaker = SpeakerRepository::with_collection(db, staging.name(SPEAKERS).as_str());
+        speaker
+            .ensure_indexes()
+            .await
+            .map_err(failed_at("speakers"))?;
+        let nb_speakers = speaker
             .synchronize_speakers(site.speakers())
-            .await?
+            .await
+            .map_err(failed_at("speakers"))?
             .len() as u32;
 
         Ok(SynchronizeResult {
@@ -303,6 +921,58 @@ impl Repositories {
     }
 }
 
+/// The collection of the site info
+const INFO: &str = "info";
+
+/// The collections copied into a new edition,
+/// note that the team members are stored in `team_member_types` and the member types in `team_members`
+const REUSABLE: [&str; 5] = [
+    "team_member_types",
+    "team_members",
+    "sponsor_categories",
+    "rooms",
+    SESSION_FORMATS,
+];
+
+/// An event is a prefix of collection names, e.g. `devfest-2021`
+fn check_event_id(event: &str) -> Result<()> {
+    let valid = event
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if event.is_empty() || !valid || event == "system" {
+        bail!(
+            "Invalid event '{}', expected letters, digits, '-' or '_'",
+            event
+        );
+    }
+
+    Ok(())
+}
+
+/// Report the failed step of a synchronization
+fn failed_at(step: &'static str) -> impl FnOnce(anyhow::Error) -> anyhow::Error {
+    move |err| {
+        anyhow!(
+            "Synchronization failed at the {} step, the previous data is kept: {:#}",
+            step,
+            err
+        )
+    }
+}
+
+/// The history identifier of the schedule, recorded as a whole
+const SCHEDULE_ID: &str = "schedule";
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ScheduleHistory {
+    days: Vec<ScheduleDay>,
+}
+
+fn schedule_document(days: &[ScheduleDay]) -> Result<Document> {
+    let days = days.to_vec();
+    to_document(&ScheduleHistory { days })
+}
+
 fn to_document<T>(element: &T) -> Result<Document>
 where
     T: Serialize + Debug,
@@ -325,16 +995,268 @@ where
     Ok(element)
 }
 
-async fn cursor_to_vec<T>(cursor: &mut Cursor) -> Result<Vec<T>>
+fn from_documents<T>(documents: Vec<Document>) -> Result<Vec<T>>
 where
     T: DeserializeOwned,
 {
-    let mut result = vec![];
-    while let Some(doc) = cursor.next().await {
-        let doc = doc.map_err(|err| anyhow!("Oops, {}", err))?;
-        let element = from_document::<T>(doc)?;
-        result.push(element);
+    documents.into_iter().map(from_document::<T>).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_check_event_id() {
+        assert!(check_event_id("devfest-2021").is_ok());
+        assert!(check_event_id("").is_err());
+        assert!(check_event_id("devfest.2021").is_err());
+        assert!(check_event_id("system").is_err());
+    }
+
+    #[tokio::test]
+    async fn should_not_grant_more_than_the_current_user() {
+        use dftk_common::acl::role::{Role, RoleKey};
+
+        let repos = Repositories::in_memory().await.unwrap();
+        let manager = RoleKey::new("user-manager");
+        let role = Role::new(
+            manager.clone(),
+            "User manager".into(),
+            vec![Permission::ViewSite, Permission::ManageUsers],
+        );
+        repos.role().save(&role).await.unwrap();
+        let team = |email: &str, roles: Vec<RoleKey>| User::Team {
+            email: email.parse().unwrap(),
+            roles,
+        };
+        let repos = repos.as_user(Some(team("manager@devfest.fr", vec![manager.clone()])));
+
+        let admin = User::Admin {
+            email: "admin@devfest.fr".parse().unwrap(),
+        };
+        assert!(repos.check_grant(&admin).await.is_err());
+        let committee = team(
+            "committee@devfest.fr",
+            vec![RoleKey::new("program-committee")],
+        );
+        let err = repos.check_grant(&committee).await.unwrap_err();
+        assert!(err.downcast_ref::<Escalation>().is_some());
+        assert!(repos
+            .check_grant(&team("jane@devfest.fr", vec![manager]))
+            .await
+            .is_ok());
+
+        let repos = repos.as_user(Some(admin.clone()));
+        assert!(repos.check_grant(&admin).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn should_limit_api_clients_to_their_owner() {
+        use chrono::{Duration, Utc};
+        use dftk_common::acl::role::RoleKey;
+
+        let repos = Repositories::in_memory().await.unwrap();
+        let owner = User::Team {
+            email: "committee@devfest.fr".parse().unwrap(),
+            roles: vec![RoleKey::new("program-committee")],
+        };
+        repos.user().new_user(owner.clone()).await.unwrap();
+        let (_, value) = repos
+            .api_token()
+            .create(
+                "ci".into(),
+                owner.email().unwrap(),
+                Some(RoleKey::admin()),
+                vec![],
+                Utc::now() + Duration::days(1),
+            )
+            .await
+            .unwrap();
+
+        let client = repos.api_client(value.as_str()).await.unwrap().unwrap();
+        assert!(repos
+            .is_allowed(&client, &Operation::EditSessions)
+            .await
+            .unwrap());
+        assert!(!repos
+            .is_allowed(&client, &Operation::ManageUsers)
+            .await
+            .unwrap());
+
+        repos
+            .user()
+            .delete_user(&owner.email().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(repos.api_client(value.as_str()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn should_clone_edition() {
+        let repos = Repositories::in_memory().await.unwrap();
+        let event = EventId::new("devfest-2020".into());
+        let previous = repos.for_event(&event).await.unwrap();
+        previous
+            .session_format()
+            .create("Quickie".into(), None)
+            .await
+            .unwrap();
+
+        let next = EventId::new("devfest-2021".into());
+        let result = previous.clone_edition(&next).await.unwrap();
+        assert_eq!(result.collections().get(SESSION_FORMATS), Some(&1));
+        assert_eq!(repos.events().await.unwrap(), vec![event, next.clone()]);
+
+        let formats = repos.for_event(&next).await.unwrap().session_format();
+        assert_eq!(formats.find().await.unwrap().len(), 1);
+        assert!(repos.session_format().find().await.unwrap().is_empty());
+        assert!(previous.clone_edition(&next).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_allow_with_role_permissions() {
+        use std::str::FromStr;
+
+        use dftk_common::acl::role::RoleKey;
+        use dftk_common::acl::user::Email;
+        use dftk_common::models::sponsor::SponsorKey;
+
+        let repos = Repositories::in_memory().await.unwrap();
+        let email = Email::from_str("plop@plop.org").unwrap();
+        let committee = User::Team {
+            email: email.clone(),
+            roles: vec![RoleKey::new("program-committee")],
+        };
+        let volunteer = User::Team {
+            email: email.clone(),
+            roles: vec![],
+        };
+        let sponsor = User::Sponsor {
+            email: email.clone(),
+            key: SponsorKey::new("acme"),
+            event: None,
+        };
+        let client = User::ApiClient {
+            token: "ci".into(),
+            owner: email,
+            roles: vec![RoleKey::new("volunteer-lead")],
+            permissions: vec![Permission::Generate],
+        };
+
+        let allowed = |user: User, operation: Operation| {
+            let repos = repos.clone();
+            async move { repos.is_allowed(&user, &operation).await.unwrap() }
+        };
+        assert!(allowed(committee.clone(), Operation::EditSchedule).await);
+        assert!(!allowed(committee, Operation::EditSponsors).await);
+        assert!(allowed(volunteer.clone(), Operation::ViewSite).await);
+        assert!(!allowed(volunteer, Operation::EditTeam).await);
+        let own = Operation::EditSponsor(SponsorKey::new("acme"));
+        assert!(allowed(sponsor.clone(), own).await);
+        let other = Operation::EditSponsor(SponsorKey::new("other"));
+        assert!(!allowed(sponsor, other).await);
+        assert!(allowed(client.clone(), Operation::Generate).await);
+        assert!(allowed(client.clone(), Operation::EditTeam).await);
+        assert!(!allowed(client, Operation::Synchronize).await);
     }
 
-    Ok(result)
+    #[tokio::test]
+    async fn should_check_speaker_users() {
+        use std::str::FromStr;
+
+        use dftk_common::acl::user::Email;
+        use dftk_common::models::language::Lang;
+        use dftk_common::models::session::category::CategoryKey;
+        use dftk_common::models::session::format::FormatKey;
+        use dftk_common::models::session::Session;
+
+        let repos = Repositories::in_memory().await.unwrap();
+        let speaker = |id: &str, name: &str| {
+            let id = SpeakerId::new(id.into());
+            Speaker::new(
+                id,
+                name.into(),
+                false,
+                None,
+                None,
+                None,
+                vec![],
+                None,
+                "".to_string().into(),
+            )
+        };
+        let speakers = vec![speaker("s1", "Jane Doe"), speaker("s2", "John Doe")];
+        repos
+            .speaker()
+            .synchronize_speakers(&speakers)
+            .await
+            .unwrap();
+        let session = |id: &str, speaker: &Speaker, draft: bool| {
+            Session::new(
+                SessionId::new(id.into()),
+                format!("Talk {}", id),
+                None,
+                FormatKey::new("talk"),
+                vec![speaker.key()],
+                CategoryKey::new("web"),
+                Lang::default(),
+                None,
+                None,
+                Some(draft),
+                None,
+                "".to_string().into(),
+            )
+        };
+        let sessions = vec![
+            session("t1", &speakers[0], false),
+            session("t2", &speakers[1], true),
+        ];
+        repos
+            .session()
+            .synchronize_sessions(&sessions)
+            .await
+            .unwrap();
+
+        let accepted = repos.accepted_speakers().await.unwrap();
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].key(), speakers[0].key());
+
+        let email = Email::from_str("jane@devfest.fr").unwrap();
+        let jane = User::Speaker {
+            email: email.clone(),
+            key: SpeakerKey::from_str("jane-doe").unwrap(),
+            event: None,
+        };
+        assert!(repos.check_user(&jane).await.is_ok());
+        let unknown = User::Speaker {
+            email,
+            key: SpeakerKey::from_str("Jane Doe").unwrap(),
+            event: None,
+        };
+        let err = repos.check_user(&unknown).await.unwrap_err();
+        assert!(err.downcast_ref::<UnknownKey>().is_some());
+    }
+
+    #[tokio::test]
+    async fn should_bind_speaker_users_to_their_event() {
+        use std::str::FromStr;
+
+        let repos = Repositories::in_memory().await.unwrap();
+        let first = repos.for_event(&EventId::new("2020".into())).await.unwrap();
+        let second = repos.for_event(&EventId::new("2021".into())).await.unwrap();
+        let jane = SpeakerKey::from_str("jane-doe").unwrap();
+        let user = User::Speaker {
+            email: "jane@devfest.fr".parse().unwrap(),
+            key: jane.clone(),
+            event: first.event(),
+        };
+
+        let operation = Operation::EditSpeaker(jane);
+        assert!(first.is_allowed(&user, &operation).await.unwrap());
+        // Another speaker with the same key
+        assert!(!second.is_allowed(&user, &operation).await.unwrap());
+        let err = second.check_user(&user).await.unwrap_err();
+        assert!(err.downcast_ref::<UnknownKey>().is_some());
+    }
 }
@@ -0,0 +1,366 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use bson::Document;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+
+use dftk_common::acl::user::Email;
+use dftk_common::new_id;
+
+use crate::indexes::IndexDefinition;
+use crate::query::{Page, PageRequest};
+use crate::storage::{DocumentStore, Storage};
+use crate::{from_document, from_documents, to_document};
+
+/// The limits of the failed logins, counted by e-mail and by IP address
+#[derive(Clone, Debug)]
+pub struct ThrottleConfig {
+    pub max_failures_per_email: u32,
+    pub max_failures_per_ip: u32,
+    /// The failures older than the window are forgotten
+    pub window: Duration,
+    pub lockout: Duration,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            max_failures_per_email: 5,
+            max_failures_per_ip: 20,
+            window: Duration::minutes(15),
+            lockout: Duration::minutes(15),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    /// Rejected without checking the password
+    Locked,
+}
+
+/// A login attempt, for the review of the admins
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoginEvent {
+    #[serde(rename = "_id")]
+    id: String,
+    email: String,
+    ip: Option<String>,
+    outcome: LoginOutcome,
+    at: DateTime<Utc>,
+}
+
+impl LoginEvent {
+    pub fn email(&self) -> String {
+        self.email.clone()
+    }
+    pub fn ip(&self) -> Option<String> {
+        self.ip.clone()
+    }
+    pub fn outcome(&self) -> LoginOutcome {
+        self.outcome
+    }
+    pub fn at(&self) -> DateTime<Utc> {
+        self.at
+    }
+}
+
+/// The recent attempts of an e-mail or of an IP address,
+/// its `attempts` and `window_start` are only changed by atomic updates
+#[derive(Deserialize, Debug, Clone)]
+struct Throttle {
+    attempts: i64,
+    locked_until: Option<i64>,
+}
+
+#[derive(Clone)]
+pub struct LoginAttemptRepository {
+    throttles: Arc<dyn DocumentStore>,
+    events: Arc<dyn DocumentStore>,
+}
+
+impl LoginAttemptRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let throttles = storage.collection("login_throttles");
+        let events = storage.collection("login_events");
+
+        Self { throttles, events }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let by_email = IndexDefinition::new("email_at", doc! {"email": 1, "at": -1});
+        self.events.ensure_indexes(&[by_email]).await
+    }
+
+    /// Reserve an attempt of the e-mail and of the IP address before checking the credentials,
+    /// returning the end of the lockout if a limit is exceeded
+    ///
+    /// The attempt is counted first, so concurrent attempts cannot check more credentials than the limits.
+    pub async fn reserve(
+        &self,
+        email: &Email,
+        ip: Option<IpAddr>,
+        config: &ThrottleConfig,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let mut result = None;
+        for (key, per_email) in throttle_keys(email, ip) {
+            let max = if per_email {
+                config.max_failures_per_email
+            } else {
+                config.max_failures_per_ip
+            };
+            let locked_until = self.reserve_attempt(key, max, config).await?;
+            result = result.max(locked_until);
+        }
+
+        Ok(result.map(|it| Utc.timestamp(it, 0)))
+    }
+
+    async fn reserve_attempt(
+        &self,
+        key: String,
+        max: u32,
+        config: &ThrottleConfig,
+    ) -> Result<Option<i64>> {
+        let now = Utc::now().timestamp();
+        let expired = now - config.window.num_seconds();
+        self.throttles
+            .update_one(
+                doc! {"_id": key.clone(), "window_start": {"$lte": expired}},
+                doc! {"$set": {"attempts": 0, "window_start": now}},
+                false,
+            )
+            .await?;
+        self.throttles
+            .update_one(
+                doc! {"_id": key.clone()},
+                doc! {"$inc": {"attempts": 1}, "$setOnInsert": {"window_start": now}},
+                true,
+            )
+            .await?;
+        // Read after the increment, the concurrent attempts could only raise the count
+        let throttle: Throttle = match self.throttles.find_one(doc! {"_id": key.clone()}).await? {
+            Some(document) => from_document(document)?,
+            None => return Ok(None),
+        };
+
+        if let Some(locked_until) = throttle.locked_until.filter(|it| *it > now) {
+            // Not counted in the window following the lockout
+            self.release(key).await?;
+            return Ok(Some(locked_until));
+        }
+        if throttle.attempts <= i64::from(max) {
+            return Ok(None);
+        }
+
+        // Only one of the concurrent attempts exceeding the maximum locks
+        let locked_until = now + config.lockout.num_seconds();
+        let lock = doc! {
+            "$set": {"attempts": 0, "window_start": now, "locked_until": locked_until}
+        };
+        let result = self
+            .throttles
+            .update_one(
+                doc! {"_id": key.clone(), "attempts": {"$gt": i64::from(max)}},
+                lock,
+                false,
+            )
+            .await?;
+        if result.matched > 0 {
+            warn!("Lock the login of {} after {} failures", key, max);
+        }
+
+        Ok(Some(locked_until))
+    }
+
+    /// Give back a reserved attempt
+    async fn release(&self, key: String) -> Result<()> {
+        self.throttles
+            .update_one(
+                doc! {"_id": key, "attempts": {"$gt": 0}},
+                doc! {"$inc": {"attempts": -1}},
+                false,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Record the outcome of a reserved attempt, a failure stays counted,
+    /// and a success forgets the attempts of the e-mail and gives back the attempt of the IP address
+    pub async fn record(
+        &self,
+        email: &Email,
+        ip: Option<IpAddr>,
+        outcome: LoginOutcome,
+    ) -> Result<()> {
+        let event = LoginEvent {
+            id: new_id().to_string(),
+            email: email.clone().into(),
+            ip: ip.map(|it| it.to_string()),
+            outcome,
+            at: Utc::now(),
+        };
+        self.events.insert_one(to_document(&event)?).await?;
+
+        if outcome == LoginOutcome::Success {
+            let (key, _) = email_key(email);
+            self.throttles.delete_one(doc! {"_id": key}).await?;
+            if let Some(ip) = ip {
+                self.release(ip_key(ip)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The login attempts, the most recent first
+    pub async fn find_events(
+        &self,
+        email: Option<&Email>,
+        page: &PageRequest,
+    ) -> Result<Page<LoginEvent>> {
+        let filter: Document = match email {
+            Some(email) => {
+                let email: String = email.clone().into();
+                doc! {"email": email}
+            }
+            None => doc! {},
+        };
+        let total = self.events.count(filter.clone()).await?;
+        let docs = self
+            .events
+            .find_range(filter, Some(doc! {"at": -1}), page.offset(), page.limit())
+            .await?;
+        let items = from_documents(docs)?;
+
+        Ok(Page::new(items, total, page.offset()))
+    }
+}
+
+fn email_key(email: &Email) -> (String, bool) {
+    let email: String = email.clone().into();
+    (format!("email:{}", email.to_lowercase()), true)
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+/// The keys of the throttles, with `true` for the e-mail
+fn throttle_keys(email: &Email, ip: Option<IpAddr>) -> Vec<(String, bool)> {
+    let mut result = vec![email_key(email)];
+    if let Some(ip) = ip {
+        result.push((ip_key(ip), false));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    /// A reserved attempt with a wrong password
+    async fn fail(
+        repo: &LoginAttemptRepository,
+        email: &Email,
+        ip: Option<IpAddr>,
+        config: &ThrottleConfig,
+    ) -> Option<DateTime<Utc>> {
+        let locked_until = repo.reserve(email, ip, config).await.unwrap();
+        let outcome = match locked_until {
+            Some(_) => LoginOutcome::Locked,
+            None => LoginOutcome::Failure,
+        };
+        repo.record(email, ip, outcome).await.unwrap();
+
+        locked_until
+    }
+
+    #[tokio::test]
+    async fn should_lock_after_failures() {
+        let repo = LoginAttemptRepository::new(&MemoryStorage::new("test"));
+        let config = ThrottleConfig {
+            max_failures_per_email: 3,
+            ..ThrottleConfig::default()
+        };
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        let other = Email::from_str("team@devfest.fr").unwrap();
+        let ip = IpAddr::from_str("10.0.0.1").ok();
+
+        for _ in 0..2 {
+            assert!(fail(&repo, &email, ip, &config).await.is_none());
+        }
+        assert!(repo.reserve(&email, ip, &config).await.unwrap().is_none());
+        repo.record(&email, ip, LoginOutcome::Success)
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            assert!(fail(&repo, &email, ip, &config).await.is_none());
+        }
+        assert!(fail(&repo, &email, None, &config).await.is_some());
+        assert!(fail(&repo, &email, None, &config).await.is_some());
+        assert!(fail(&repo, &other, None, &config).await.is_none());
+
+        let page = PageRequest::new(0, Some(2));
+        let events = repo.find_events(Some(&email), &page).await.unwrap();
+        assert_eq!(events.total(), 8);
+        assert_eq!(events.items().len(), 2);
+        assert_eq!(events.items()[0].outcome(), LoginOutcome::Locked);
+    }
+
+    #[tokio::test]
+    async fn should_reserve_concurrent_attempts() {
+        let repo = LoginAttemptRepository::new(&MemoryStorage::new("test"));
+        let config = ThrottleConfig {
+            max_failures_per_email: 5,
+            ..ThrottleConfig::default()
+        };
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+
+        let attempts: Vec<_> = (0..20)
+            .map(|_| {
+                let (repo, email, config) = (repo.clone(), email.clone(), config.clone());
+                tokio::spawn(async move { repo.reserve(&email, None, &config).await })
+            })
+            .collect();
+        let mut allowed = 0;
+        for attempt in attempts {
+            if attempt.await.unwrap().unwrap().is_none() {
+                allowed += 1;
+            }
+        }
+        assert!(allowed <= 5, "Expected at most 5 attempts, got {}", allowed);
+        assert!(repo.reserve(&email, None, &config).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn should_lock_ip() {
+        let repo = LoginAttemptRepository::new(&MemoryStorage::new("test"));
+        let config = ThrottleConfig {
+            max_failures_per_ip: 2,
+            ..ThrottleConfig::default()
+        };
+        let ip = IpAddr::from_str("10.0.0.1").ok();
+        for email in &["a@devfest.fr", "b@devfest.fr"] {
+            let email = Email::from_str(email).unwrap();
+            assert!(fail(&repo, &email, ip, &config).await.is_none());
+        }
+
+        let email = Email::from_str("c@devfest.fr").unwrap();
+        assert!(fail(&repo, &email, ip, &config).await.is_some());
+        assert!(fail(&repo, &email, None, &config).await.is_none());
+    }
+}
@@ -0,0 +1,426 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use mongodb::bson::{doc, Bson, Document};
+use serde::Serialize;
+
+use crate::query::{session_sort_values, speaker_sort_values, SORT_VALUES};
+use crate::storage::{list_scopes, open_storage, ScopedStorage, Storage};
+use crate::MongodbConfig;
+
+/// The schema version expected by this binary, i.e. the number of known migrations
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// The collection storing the database metadata
+pub(crate) const METADATA: &str = "metadata";
+
+/// The identifier of the schema version document
+const SCHEMA_ID: &str = "schema";
+
+/// Ordered migration steps, the version of a step is its position starting from 1
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [
+    Migration::SlotLayouts,
+    Migration::SessionDocuments,
+    Migration::SpeakerDocuments,
+    Migration::SortValues,
+];
+
+/// A migration step, it only touches the documents with the legacy shape,
+/// so running it again is harmless
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Migration {
+    SlotLayouts,
+    SessionDocuments,
+    SpeakerDocuments,
+    SortValues,
+}
+
+impl Migration {
+    fn version(&self) -> u32 {
+        let index = MIGRATIONS.iter().position(|it| it == self).unwrap();
+        index as u32 + 1
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Migration::SlotLayouts => "Move the slot rows into the slot_layouts collection",
+            Migration::SessionDocuments => "Wrap the legacy sessions with a session and a patch",
+            Migration::SpeakerDocuments => "Wrap the legacy speakers with a speaker and a patch",
+            Migration::SortValues => {
+                "Store the title of the sessions and the name of the speakers to sort on"
+            }
+        }
+    }
+
+    async fn apply(&self, storage: &dyn Storage, dry_run: bool) -> Result<usize> {
+        match self {
+            Migration::SlotLayouts => migrate_slot_rows(storage, dry_run).await,
+            Migration::SessionDocuments => {
+                wrap_documents(storage, "sessions", "session", dry_run).await
+            }
+            Migration::SpeakerDocuments => {
+                wrap_documents(storage, "speakers", "speaker", dry_run).await
+            }
+            Migration::SortValues => {
+                let sessions =
+                    store_sort_values(storage, "sessions", session_sort_values, dry_run).await?;
+                let speakers =
+                    store_sort_values(storage, "speakers", speaker_sort_values, dry_run).await?;
+                Ok(sessions + speakers)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MigrationStep {
+    version: u32,
+    description: String,
+    nb_documents: usize,
+}
+
+impl MigrationStep {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    pub fn description(&self) -> String {
+        self.description.clone()
+    }
+    pub fn nb_documents(&self) -> usize {
+        self.nb_documents
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MigrationResult {
+    /// The migrated event, none for the shared collections
+    event: Option<String>,
+    from: u32,
+    to: u32,
+    dry_run: bool,
+    steps: Vec<MigrationStep>,
+}
+
+impl MigrationResult {
+    pub fn event(&self) -> Option<String> {
+        self.event.clone()
+    }
+    pub fn from(&self) -> u32 {
+        self.from
+    }
+    pub fn to(&self) -> u32 {
+        self.to
+    }
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+    pub fn steps(&self) -> &[MigrationStep] {
+        self.steps.as_slice()
+    }
+}
+
+/// Migrate the database up to the [`SCHEMA_VERSION`], the shared collections and every event
+///
+/// With `dry_run`, the steps only count the documents to migrate.
+pub async fn migrate(config: &MongodbConfig, dry_run: bool) -> Result<Vec<MigrationResult>> {
+    let storage = open_storage(config).await?;
+
+    migrate_all(&storage, dry_run).await
+}
+
+/// Migrate the root collections, then the collections of each event, each one has its own version
+pub(crate) async fn migrate_all(
+    root: &Arc<dyn Storage>,
+    dry_run: bool,
+) -> Result<Vec<MigrationResult>> {
+    let mut result = vec![migrate_database(root.as_ref(), dry_run).await?];
+    for event in list_scopes(root.as_ref()).await? {
+        let storage = ScopedStorage::new(root, event.as_str());
+        let mut migration = migrate_database(&storage, dry_run).await?;
+        migration.event = Some(event);
+        result.push(migration);
+    }
+
+    Ok(result)
+}
+
+pub(crate) async fn migrate_database(
+    storage: &dyn Storage,
+    dry_run: bool,
+) -> Result<MigrationResult> {
+    let from = schema_version(storage).await?;
+    let pending = pending_migrations(from)?;
+
+    let mut steps = vec![];
+    let mut to = from;
+    for migration in pending {
+        let version = migration.version();
+        let description = migration.description().into();
+        info!("Migration {}: {}", version, description);
+        let nb_documents = migration.apply(storage, dry_run).await?;
+        if !dry_run {
+            save_schema_version(storage, version).await?;
+        }
+        debug!("...{} document(s) migrated", nb_documents);
+
+        steps.push(MigrationStep {
+            version,
+            description,
+            nb_documents,
+        });
+        to = version;
+    }
+
+    Ok(MigrationResult {
+        event: None,
+        from,
+        to,
+        dry_run,
+        steps,
+    })
+}
+
+/// The schema version of the database, a database without metadata has the version 0
+pub async fn schema_version(storage: &dyn Storage) -> Result<u32> {
+    let metadata = storage.collection(METADATA);
+    let document = metadata.find_one(doc! {"_id": SCHEMA_ID}).await?;
+    let version = match document {
+        Some(document) => document.get_i32("version")? as u32,
+        None => 0,
+    };
+
+    Ok(version)
+}
+
+pub(crate) async fn save_schema_version(storage: &dyn Storage, version: u32) -> Result<()> {
+    let metadata = storage.collection(METADATA);
+    metadata
+        .update_one(
+            doc! {"_id": SCHEMA_ID},
+            doc! {"$set": {"version": version as i32}},
+            true,
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn pending_migrations(current: u32) -> Result<Vec<Migration>> {
+    if current > SCHEMA_VERSION {
+        bail!(
+            "The database schema version {} is newer than the supported version {}, please upgrade",
+            current,
+            SCHEMA_VERSION
+        );
+    }
+    let result = MIGRATIONS
+        .iter()
+        .filter(|it| it.version() > current)
+        .copied()
+        .collect();
+
+    Ok(result)
+}
+
+/// Move the legacy `row` of slot documents into the `slot_layouts` collection
+///
+/// The layout is upserted by slot before the row is removed,
+/// so an interrupted run is completed by the next one without duplicated layouts.
+async fn migrate_slot_rows(storage: &dyn Storage, dry_run: bool) -> Result<usize> {
+    let slots = storage.collection("slots");
+    let layouts = storage.collection("slot_layouts");
+
+    let query = doc! {"row": {"$exists": true}};
+    if dry_run {
+        let count = slots.count(query).await?;
+        return Ok(count as usize);
+    }
+
+    let mut count = 0;
+    for doc in slots.find(query, None).await? {
+        let key = doc.get_str("key")?;
+        let row = doc
+            .get("row")
+            .cloned()
+            .ok_or_else(|| anyhow!("Missing row for slot {}", key))?;
+
+        debug!("Migrate the row of slot {}", key);
+        layouts
+            .replace_one(doc! {"slot": key}, doc! {"slot": key, "row": row}, true)
+            .await?;
+        slots
+            .update_one(doc! {"key": key}, doc! {"$unset": {"row": ""}}, false)
+            .await?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Wrap flat legacy documents into `{ _id, key, <field>, patch }`
+async fn wrap_documents(
+    storage: &dyn Storage,
+    collection: &str,
+    field: &str,
+    dry_run: bool,
+) -> Result<usize> {
+    let col = storage.collection(collection);
+
+    let query = doc! {"patch": {"$exists": false}};
+    if dry_run {
+        let count = col.count(query).await?;
+        return Ok(count as usize);
+    }
+
+    let mut count = 0;
+    for doc in col.find(query, None).await? {
+        let id = doc
+            .get("_id")
+            .cloned()
+            .ok_or_else(|| anyhow!("Missing _id in {}", collection))?;
+
+        debug!("Wrap the {} {}", field, id);
+        let wrapped = wrap_document(doc, field)?;
+        col.replace_one(doc! {"_id": id}, wrapped, false).await?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Add the values to sort on to the documents written before them
+async fn store_sort_values(
+    storage: &dyn Storage,
+    collection: &str,
+    sort_values: fn(&mut Document),
+    dry_run: bool,
+) -> Result<usize> {
+    let col = storage.collection(collection);
+
+    let query = doc! {SORT_VALUES: {"$exists": false}};
+    if dry_run {
+        let count = col.count(query).await?;
+        return Ok(count as usize);
+    }
+
+    let mut count = 0;
+    for mut doc in col.find(query, None).await? {
+        let id = doc
+            .get("_id")
+            .cloned()
+            .ok_or_else(|| anyhow!("Missing _id in {}", collection))?;
+
+        debug!("Store the sort values of the {} {}", collection, id);
+        sort_values(&mut doc);
+        col.replace_one(doc! {"_id": id}, doc, false).await?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn wrap_document(mut doc: Document, field: &str) -> Result<Document> {
+    let id = doc
+        .remove("_id")
+        .ok_or_else(|| anyhow!("Missing _id in {:?}", doc))?;
+    let key = doc.get_str("key")?.to_string();
+    if !doc.contains_key("id") {
+        doc.insert("id", id.clone());
+    }
+
+    let mut result = doc! {"_id": id, "key": key};
+    result.insert(field, Bson::Document(doc));
+    result.insert("patch", Document::new());
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn should_have_ordered_versions() {
+        let versions: Vec<u32> = MIGRATIONS.iter().map(|it| it.version()).collect();
+        assert_eq!(versions, (1..=SCHEMA_VERSION).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_find_pending_migrations() {
+        assert_eq!(pending_migrations(0).unwrap(), MIGRATIONS.to_vec());
+        assert_eq!(
+            pending_migrations(1).unwrap(),
+            vec![
+                Migration::SessionDocuments,
+                Migration::SpeakerDocuments,
+                Migration::SortValues
+            ]
+        );
+        assert!(pending_migrations(SCHEMA_VERSION).unwrap().is_empty());
+    }
+
+    #[test]
+    fn should_refuse_newer_database() {
+        assert!(pending_migrations(SCHEMA_VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn should_wrap_legacy_document() {
+        let legacy = doc! {"_id": "s1", "key": "rust", "title": "Rust"};
+        let result = wrap_document(legacy, "session").unwrap();
+
+        assert_eq!(result.get_str("_id").unwrap(), "s1");
+        assert_eq!(result.get_str("key").unwrap(), "rust");
+        let session = result.get_document("session").unwrap();
+        assert_eq!(session.get_str("id").unwrap(), "s1");
+        assert_eq!(session.get_str("title").unwrap(), "Rust");
+        assert!(!session.contains_key("_id"));
+        assert!(result.get_document("patch").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_complete_interrupted_slot_migration() {
+        let storage = MemoryStorage::new("test");
+        let row = doc! {"start": 1, "end": 2};
+        let slots = storage.collection("slots");
+        slots
+            .insert_one(doc! {"key": "morning", "row": row.clone()})
+            .await
+            .unwrap();
+        // Interrupted after the layout insertion
+        let layouts = storage.collection("slot_layouts");
+        layouts
+            .insert_one(doc! {"slot": "morning", "row": row})
+            .await
+            .unwrap();
+
+        assert_eq!(migrate_slot_rows(&storage, false).await.unwrap(), 1);
+        assert_eq!(migrate_slot_rows(&storage, false).await.unwrap(), 0);
+        assert_eq!(layouts.count(doc! {}).await.unwrap(), 1);
+        assert_eq!(
+            slots.count(doc! {"row": {"$exists": true}}).await.unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn should_migrate_every_event() {
+        let root: Arc<dyn Storage> = Arc::new(MemoryStorage::new("test"));
+        let event = ScopedStorage::new(&root, "devfest-2020");
+        event
+            .collection("sessions")
+            .insert_one(doc! {"_id": "s1", "key": "rust", "title": "Rust"})
+            .await
+            .unwrap();
+
+        let results = migrate_all(&root, false).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].event(), Some("devfest-2020".into()));
+        assert_eq!(results[1].steps()[1].nb_documents(), 1);
+        assert_eq!(results[1].steps()[3].nb_documents(), 1);
+        assert_eq!(schema_version(&event).await.unwrap(), SCHEMA_VERSION);
+        assert_eq!(schema_version(root.as_ref()).await.unwrap(), SCHEMA_VERSION);
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{DocumentStore, Storage};
+use crate::{from_document, to_document};
+
+/// A pending login with the identity provider, the state is sent back to the callback
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct OidcState {
+    #[serde(rename = "_id")]
+    state: String,
+    nonce: String,
+    expires_at: i64,
+}
+
+#[derive(Clone)]
+pub struct OidcStateRepository {
+    col: Arc<dyn DocumentStore>,
+}
+
+impl OidcStateRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let col = storage.collection("oidc_states");
+
+        Self { col }
+    }
+
+    /// Remember the nonce of the state, and forget the expired states
+    pub async fn create(&self, state: &str, nonce: &str, ttl: Duration) -> Result<()> {
+        let now = Utc::now();
+        self.col
+            .delete_many(doc! {"expires_at": {"$lte": now.timestamp()}})
+            .await?;
+        let document = OidcState {
+            state: state.into(),
+            nonce: nonce.into(),
+            expires_at: (now + ttl).timestamp(),
+        };
+
+        self.col.insert_one(to_document(&document)?).await
+    }
+
+    /// The nonce of a pending state, a state can only be consumed once
+    pub async fn consume(&self, state: &str) -> Result<Option<String>> {
+        let result = self.col.delete_one(doc! {"_id": state}).await?;
+        let document = match result {
+            Some(document) => from_document::<OidcState>(document)?,
+            None => return Ok(None),
+        };
+        if document.expires_at <= Utc::now().timestamp() {
+            return Ok(None);
+        }
+
+        Ok(Some(document.nonce))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_consume_state_once() {
+        let repo = OidcStateRepository::new(&MemoryStorage::new("test"));
+        repo.create("s1", "n1", Duration::minutes(10))
+            .await
+            .unwrap();
+        repo.create("s2", "n2", Duration::seconds(-1))
+            .await
+            .unwrap();
+
+        assert_eq!(repo.consume("s1").await.unwrap(), Some("n1".into()));
+        assert_eq!(repo.consume("s1").await.unwrap(), None);
+        assert_eq!(repo.consume("s2").await.unwrap(), None);
+        assert_eq!(repo.consume("unknown").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, ensure, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use mongodb::bson::doc;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use dftk_common::acl::user::Email;
+
+use crate::indexes::IndexDefinition;
+use crate::storage::{DocumentStore, Storage};
+use crate::user::Password;
+use crate::{from_document, to_document};
+
+/// Why a password token was issued
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordTokenKind {
+    /// A new user choosing its first password
+    Invitation,
+    /// A user that forgot its password
+    Reset,
+}
+
+/// A single-use token allowing to set the password of a user,
+/// only the hash of its secret is stored
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PasswordTokenDocument {
+    #[serde(rename = "_id")]
+    id: String,
+    secret: String,
+    email: String,
+    kind: PasswordTokenKind,
+    issued_at: i64,
+    expires_at: i64,
+}
+
+/// The redeemed token
+#[derive(Debug, Clone, PartialEq)]
+pub struct PasswordToken {
+    pub email: Email,
+    pub kind: PasswordTokenKind,
+}
+
+#[derive(Clone)]
+pub struct PasswordTokenRepository {
+    col: Arc<dyn DocumentStore>,
+}
+
+impl PasswordTokenRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let col = storage.collection("password_tokens");
+
+        Self { col }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let by_email = IndexDefinition::new("email", doc! {"email": 1});
+        self.col.ensure_indexes(&[by_email]).await
+    }
+
+    /// Issue a token for the user, replacing its previous tokens of the same kind,
+    /// the returned value is the only copy of the secret
+    pub async fn issue(
+        &self,
+        email: &Email,
+        kind: PasswordTokenKind,
+        ttl: Duration,
+    ) -> Result<String> {
+        let address: String = email.clone().into();
+        let kind_value = bson::to_bson(&kind)?;
+        self.col
+            .delete_many(doc! {"email": address, "kind": kind_value})
+            .await?;
+        let now = Utc::now().timestamp();
+        self.col
+            .delete_many(doc! {"expires_at": {"$lte": now}})
+            .await?;
+
+        let id = Uuid::new_v4().to_string();
+        let secret = random_secret();
+        let document = PasswordTokenDocument {
+            id: id.clone(),
+            secret: Password::hash_blocking(secret.as_bytes()).await?.hash(),
+            email: email.clone().into(),
+            kind,
+            issued_at: now,
+            expires_at: (Utc::now() + ttl).timestamp(),
+        };
+        self.col.insert_one(to_document(&document)?).await?;
+
+        Ok(format!("{}.{}", id, secret))
+    }
+
+    /// Consume the token, it can only be redeemed once
+    pub async fn redeem(&self, token: &str) -> Result<PasswordToken> {
+        let mut parts = token.trim().splitn(2, '.');
+        let id = parts.next().unwrap_or_default();
+        let secret = parts.next().ok_or_else(|| anyhow!("Invalid token"))?;
+
+        let document = self
+            .col
+            .find_one(doc! {"_id": id})
+            .await?
+            .ok_or_else(|| anyhow!("Invalid token"))?;
+        let document: PasswordTokenDocument = from_document(document)?;
+        let verified = Password::from_hash(document.secret)
+            .verify_blocking(secret.as_bytes())
+            .await?;
+        ensure!(verified, "Invalid token");
+        // Only the first redeem removes the token
+        self.col
+            .delete_one(doc! {"_id": id})
+            .await?
+            .ok_or_else(|| anyhow!("Invalid token"))?;
+        ensure!(
+            document.expires_at > Utc::now().timestamp(),
+            "Expired token"
+        );
+
+        let email = Email::from_str(document.email.as_str())?;
+        Ok(PasswordToken {
+            email,
+            kind: document.kind,
+        })
+    }
+
+    /// The last issue of a pending token of the user
+    pub async fn last_issued(&self, email: &Email) -> Result<Option<DateTime<Utc>>> {
+        let email: String = email.clone().into();
+        let now = Utc::now().timestamp();
+        let filter = doc! {"email": email, "expires_at": {"$gt": now}};
+        let mut result = None;
+        for document in self.col.find(filter, None).await? {
+            let document: PasswordTokenDocument = from_document(document)?;
+            result = result.max(Some(document.issued_at));
+        }
+
+        Ok(result.map(|it| Utc.timestamp(it, 0)))
+    }
+
+    /// Whether the user has a pending invitation
+    pub async fn is_invited(&self, email: &Email) -> Result<bool> {
+        let email: String = email.clone().into();
+        let kind = bson::to_bson(&PasswordTokenKind::Invitation)?;
+        let now = Utc::now().timestamp();
+        let filter = doc! {"email": email, "kind": kind, "expires_at": {"$gt": now}};
+        let count = self.col.count(filter).await?;
+
+        Ok(count > 0)
+    }
+
+    pub async fn revoke_all(&self, email: &Email) -> Result<u64> {
+        let email: String = email.clone().into();
+
+        self.col.delete_many(doc! {"email": email}).await
+    }
+}
+
+/// A random secret of 256 bits, as hexadecimal
+pub(crate) fn random_secret() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 32]>();
+    let mut result = String::with_capacity(bytes.len() * 2);
+    for byte in bytes.iter() {
+        write!(result, "{:02x}", byte).unwrap();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_redeem_token_once() {
+        let repo = PasswordTokenRepository::new(&MemoryStorage::new("test"));
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        let token = repo
+            .issue(&email, PasswordTokenKind::Invitation, Duration::days(1))
+            .await
+            .unwrap();
+
+        let (id, _) = token.split_at(token.find('.').unwrap());
+        let forged = format!("{}.{}", id, random_secret());
+        assert!(repo.redeem(forged.as_str()).await.is_err());
+
+        let result = repo.redeem(token.as_str()).await.unwrap();
+        assert_eq!(result.email, email);
+        assert_eq!(result.kind, PasswordTokenKind::Invitation);
+        assert!(repo.redeem(token.as_str()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn should_replace_previous_token_of_the_same_kind() {
+        let repo = PasswordTokenRepository::new(&MemoryStorage::new("test"));
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        assert_eq!(repo.last_issued(&email).await.unwrap(), None);
+        let invitation = repo
+            .issue(&email, PasswordTokenKind::Invitation, Duration::days(1))
+            .await
+            .unwrap();
+        let first = repo
+            .issue(&email, PasswordTokenKind::Reset, Duration::days(1))
+            .await
+            .unwrap();
+        let second = repo
+            .issue(&email, PasswordTokenKind::Reset, Duration::days(1))
+            .await
+            .unwrap();
+        assert!(repo.last_issued(&email).await.unwrap().is_some());
+
+        assert!(repo.redeem(first.as_str()).await.is_err());
+        let result = repo.redeem(second.as_str()).await.unwrap();
+        assert_eq!(result.kind, PasswordTokenKind::Reset);
+        let result = repo.redeem(invitation.as_str()).await.unwrap();
+        assert_eq!(result.kind, PasswordTokenKind::Invitation);
+    }
+
+    #[tokio::test]
+    async fn should_find_pending_invitation() {
+        let repo = PasswordTokenRepository::new(&MemoryStorage::new("test"));
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        repo.issue(&email, PasswordTokenKind::Reset, Duration::days(1))
+            .await
+            .unwrap();
+        assert!(!repo.is_invited(&email).await.unwrap());
+
+        repo.issue(&email, PasswordTokenKind::Invitation, Duration::seconds(-1))
+            .await
+            .unwrap();
+        assert!(!repo.is_invited(&email).await.unwrap());
+
+        repo.issue(&email, PasswordTokenKind::Invitation, Duration::days(1))
+            .await
+            .unwrap();
+        assert!(repo.is_invited(&email).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_reject_expired_token() {
+        let repo = PasswordTokenRepository::new(&MemoryStorage::new("test"));
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        let token = repo
+            .issue(&email, PasswordTokenKind::Reset, Duration::seconds(-1))
+            .await
+            .unwrap();
+
+        assert!(repo.redeem(token.as_str()).await.is_err());
+    }
+}
@@ -0,0 +1,458 @@
+//! A typed query model: filters, sort and offset pagination, pushed down into the storage
+
+use anyhow::Result;
+use mongodb::bson::{doc, to_bson, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use dftk_common::models::language::Lang;
+use dftk_common::models::session::category::CategoryKey;
+use dftk_common::models::session::format::FormatKey;
+use dftk_common::models::session::SessionLevel;
+use dftk_common::models::speaker::SpeakerKey;
+use dftk_common::models::sponsor::category::SponsorCategoryKey;
+
+use crate::revision::UPDATED_AT;
+
+/// Build the MongoDB filter of a typed filter
+pub trait QueryFilter {
+    fn to_filter(&self) -> Result<Document>;
+}
+
+/// A sortable field of a collection
+pub trait SortField {
+    /// The path of the field in the stored document
+    fn path(&self) -> &'static str;
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Asc,
+    Desc,
+}
+
+impl Default for Direction {
+    fn default() -> Self {
+        Direction::Asc
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sort<S> {
+    field: S,
+    direction: Direction,
+}
+
+impl<S> Sort<S>
+where
+    S: SortField,
+{
+    pub fn new(field: S, direction: Direction) -> Self {
+        Self { field, direction }
+    }
+
+    /// The sort document, the `_id` breaks ties so pages are stable
+    pub(crate) fn to_document(&self) -> Document {
+        let order = match self.direction {
+            Direction::Asc => 1,
+            Direction::Desc => -1,
+        };
+        let mut result = doc! { self.field.path(): order };
+        if !result.contains_key("_id") {
+            result.insert("_id", order);
+        }
+
+        result
+    }
+}
+
+/// The requested page, without a limit every element after the offset is returned
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq)]
+pub struct PageRequest {
+    offset: u64,
+    limit: Option<u64>,
+}
+
+impl PageRequest {
+    pub fn new(offset: u64, limit: Option<u64>) -> Self {
+        Self { offset, limit }
+    }
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    pub fn limit(&self) -> Option<u64> {
+        self.limit
+    }
+}
+
+/// A filter, a sort, and a page
+#[derive(Debug, Clone)]
+pub struct Query<F, S> {
+    filter: F,
+    sort: Option<Sort<S>>,
+    page: PageRequest,
+}
+
+impl<F, S> Query<F, S>
+where
+    F: QueryFilter,
+    S: SortField,
+{
+    pub fn new(filter: F, sort: Option<Sort<S>>, page: PageRequest) -> Self {
+        Self { filter, sort, page }
+    }
+
+    pub(crate) fn filter(&self) -> Result<Document> {
+        self.filter.to_filter()
+    }
+    pub(crate) fn sort(&self) -> Option<Document> {
+        self.sort.as_ref().map(|it| it.to_document())
+    }
+    pub(crate) fn page(&self) -> PageRequest {
+        self.page
+    }
+}
+
+/// The elements of a page, with the total count of matching elements
+#[derive(Serialize, Debug, Clone)]
+pub struct Page<T> {
+    items: Vec<T>,
+    total: u64,
+    offset: u64,
+}
+
+impl<T> Page<T> {
+    pub(crate) fn new(items: Vec<T>, total: u64, offset: u64) -> Self {
+        Self {
+            items,
+            total,
+            offset,
+        }
+    }
+
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    pub fn has_next(&self) -> bool {
+        self.offset + (self.items.len() as u64) < self.total
+    }
+
+    pub fn map<U, M>(self, mapper: M) -> Page<U>
+    where
+        M: FnMut(T) -> U,
+    {
+        let items = self.items.into_iter().map(mapper).collect();
+
+        Page::new(items, self.total, self.offset)
+    }
+}
+
+/// Filter the sessions, on their patched values
+#[derive(Debug, Clone, Default)]
+pub struct SessionFilter {
+    pub category: Option<CategoryKey>,
+    pub format: Option<FormatKey>,
+    pub language: Option<Lang>,
+    pub level: Option<SessionLevel>,
+    pub draft: Option<bool>,
+    pub speaker: Option<SpeakerKey>,
+}
+
+impl QueryFilter for SessionFilter {
+    fn to_filter(&self) -> Result<Document> {
+        let mut conditions = vec![];
+        if let Some(category) = &self.category {
+            conditions.push(patched("session", "category", to_bson(category)?));
+        }
+        if let Some(format) = &self.format {
+            conditions.push(patched("session", "format", to_bson(format)?));
+        }
+        if let Some(language) = &self.language {
+            conditions.push(patched("session", "language", to_bson(language)?));
+        }
+        if let Some(level) = &self.level {
+            conditions.push(patched("session", "level", to_bson(level)?));
+        }
+        if let Some(speaker) = &self.speaker {
+            conditions.push(patched("session", "speakers", to_bson(speaker)?));
+        }
+        if let Some(draft) = self.draft {
+            conditions.push(patched_flag("session", "draft", draft));
+        }
+
+        Ok(all_of(conditions))
+    }
+}
+
+/// The document field holding the values only stored to sort on,
+/// e.g. the patched title of a session, see [`sort_values`]
+pub(crate) const SORT_VALUES: &str = "_sort";
+
+/// Sort the sessions on their key, their patched title, or their last change
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionSort {
+    Key,
+    Title,
+    Date,
+}
+
+impl SortField for SessionSort {
+    fn path(&self) -> &'static str {
+        match self {
+            SessionSort::Key => "key",
+            SessionSort::Title => "_sort.title",
+            SessionSort::Date => UPDATED_AT,
+        }
+    }
+}
+
+/// Sort the speakers on their key, their patched name, or their last change
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeakerSort {
+    Key,
+    Name,
+    Date,
+}
+
+impl SortField for SpeakerSort {
+    fn path(&self) -> &'static str {
+        match self {
+            SpeakerSort::Key => "key",
+            SpeakerSort::Name => "_sort.name",
+            SpeakerSort::Date => UPDATED_AT,
+        }
+    }
+}
+
+/// Store the patched values sorted on, in lower case so the order ignores the case
+fn sort_values(doc: &mut Document, original: &str, fields: &[&str]) {
+    let mut values = Document::new();
+    for field in fields.iter() {
+        let value = ["patch", original]
+            .iter()
+            .filter_map(|it| doc.get_document(it).ok())
+            .filter_map(|it| it.get_str(field).ok())
+            .next();
+        if let Some(value) = value {
+            values.insert(*field, value.to_lowercase());
+        }
+    }
+    doc.insert(SORT_VALUES, values);
+}
+
+/// The values of a stored session to sort on
+pub(crate) fn session_sort_values(doc: &mut Document) {
+    sort_values(doc, "session", &["title"]);
+}
+
+/// The values of a stored speaker to sort on
+pub(crate) fn speaker_sort_values(doc: &mut Document) {
+    sort_values(doc, "speaker", &["name"]);
+}
+
+/// Filter the speakers, on their patched values
+#[derive(Debug, Clone, Default)]
+pub struct SpeakerFilter {
+    pub featured: Option<bool>,
+    pub company: Option<String>,
+    pub city: Option<String>,
+    pub draft: Option<bool>,
+}
+
+impl QueryFilter for SpeakerFilter {
+    fn to_filter(&self) -> Result<Document> {
+        let mut conditions = vec![];
+        if let Some(featured) = self.featured {
+            conditions.push(patched_flag("speaker", "featured", featured));
+        }
+        if let Some(company) = &self.company {
+            conditions.push(patched("speaker", "company", company.into()));
+        }
+        if let Some(city) = &self.city {
+            conditions.push(patched("speaker", "city", city.into()));
+        }
+        if let Some(draft) = self.draft {
+            conditions.push(patched_flag("speaker", "draft", draft));
+        }
+
+        Ok(all_of(conditions))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SponsorFilter {
+    pub category: Option<SponsorCategoryKey>,
+}
+
+impl QueryFilter for SponsorFilter {
+    fn to_filter(&self) -> Result<Document> {
+        let result = match &self.category {
+            Some(category) => doc! {"category": to_bson(category)?},
+            None => doc! {},
+        };
+
+        Ok(result)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SponsorSort {
+    Key,
+    Title,
+    Category,
+    Order,
+}
+
+impl SortField for SponsorSort {
+    fn path(&self) -> &'static str {
+        match self {
+            SponsorSort::Key => "key",
+            SponsorSort::Title => "title",
+            SponsorSort::Category => "category",
+            SponsorSort::Order => "order",
+        }
+    }
+}
+
+/// The patch value if any, the original value otherwise
+fn patched(original: &str, field: &str, value: Bson) -> Document {
+    let patch = format!("patch.{}", field);
+    let original = format!("{}.{}", original, field);
+
+    doc! {
+        "$or": [
+            { patch.as_str(): value.clone() },
+            { patch.as_str(): Bson::Null, original: value },
+        ]
+    }
+}
+
+/// Like [`patched`], a missing flag is `false`
+fn patched_flag(original: &str, field: &str, value: bool) -> Document {
+    let patch = format!("patch.{}", field);
+    let original = format!("{}.{}", original, field);
+    let condition = if value {
+        Bson::Boolean(true)
+    } else {
+        Bson::Document(doc! {"$ne": true})
+    };
+
+    doc! {
+        "$or": [
+            { patch.as_str(): value },
+            { patch.as_str(): Bson::Null, original: condition },
+        ]
+    }
+}
+
+fn all_of(conditions: Vec<Document>) -> Document {
+    match conditions.len() {
+        0 => doc! {},
+        1 => conditions.into_iter().next().unwrap_or_default(),
+        _ => doc! {"$and": conditions},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use chrono::{TimeZone, Utc};
+
+    use crate::storage::filter::{compare, matches};
+
+    use super::*;
+
+    fn session(patch: Document) -> Document {
+        doc! {
+            "_id": "s1",
+            "key": "rust",
+            "session": {"category": "backend", "speakers": ["alice"], "draft": false},
+            "patch": patch,
+        }
+    }
+
+    #[test]
+    fn should_filter_patched_values() {
+        let filter = SessionFilter {
+            category: Some(CategoryKey::new("backend")),
+            ..SessionFilter::default()
+        };
+        let filter = filter.to_filter().unwrap();
+        assert!(matches(&session(doc! {}), &filter));
+        assert!(matches(&session(doc! {"category": null}), &filter));
+        assert!(!matches(&session(doc! {"category": "frontend"}), &filter));
+
+        let filter = SessionFilter {
+            speaker: Some(SpeakerKey::new("alice")),
+            draft: Some(false),
+            ..SessionFilter::default()
+        };
+        let filter = filter.to_filter().unwrap();
+        assert!(matches(&session(doc! {}), &filter));
+        assert!(!matches(&session(doc! {"draft": true}), &filter));
+        assert!(!matches(&session(doc! {"speakers": ["bob"]}), &filter));
+    }
+
+    #[test]
+    fn should_sort_with_tie_breaker() {
+        let sort = Sort::new(SponsorSort::Order, Direction::Desc);
+        assert_eq!(sort.to_document(), doc! {"order": -1, "_id": -1});
+    }
+
+    fn ordering<S: SortField>(sort: S, a: &Document, b: &Document) -> Ordering {
+        let sort = Sort::new(sort, Direction::Asc).to_document();
+        compare(a, b, &sort)
+    }
+
+    #[test]
+    fn should_sort_sessions_by_patched_title() {
+        let mut rust = doc! {"_id": "s1", "key": "rust", "session": {"title": "Rust"}, "patch": {"title": "Async Rust"}};
+        let mut java = doc! {"_id": "s2", "key": "java", "session": {"title": "Java"}, "patch": {"title": null}};
+        session_sort_values(&mut rust);
+        session_sort_values(&mut java);
+
+        assert_eq!(
+            rust.get_document(SORT_VALUES).unwrap(),
+            &doc! {"title": "async rust"}
+        );
+        assert_eq!(ordering(SessionSort::Title, &rust, &java), Ordering::Less);
+        assert_eq!(ordering(SessionSort::Key, &rust, &java), Ordering::Greater);
+    }
+
+    #[test]
+    fn should_sort_speakers_by_patched_name() {
+        let mut alice =
+            doc! {"_id": "a", "key": "zed", "speaker": {"name": "Zed"}, "patch": {"name": "alice"}};
+        let mut bob = doc! {"_id": "b", "key": "bob", "speaker": {"name": "Bob"}, "patch": {}};
+        speaker_sort_values(&mut alice);
+        speaker_sort_values(&mut bob);
+
+        assert_eq!(ordering(SpeakerSort::Name, &alice, &bob), Ordering::Less);
+        assert_eq!(ordering(SpeakerSort::Key, &alice, &bob), Ordering::Greater);
+    }
+
+    #[test]
+    fn should_sort_by_last_change() {
+        let before = doc! {"_id": "b", UPDATED_AT: Utc.ymd(2020, 10, 15).and_hms(9, 0, 0)};
+        let after = doc! {"_id": "a", UPDATED_AT: Utc.ymd(2020, 10, 16).and_hms(9, 0, 0)};
+
+        assert_eq!(ordering(SessionSort::Date, &before, &after), Ordering::Less);
+        assert_eq!(
+            ordering(SpeakerSort::Date, &after, &before),
+            Ordering::Greater
+        );
+    }
+}
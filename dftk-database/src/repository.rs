@@ -1,20 +1,34 @@
 use std::marker::PhantomData;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
-use mongodb::bson::doc;
-use mongodb::options::UpdateOptions;
-use mongodb::{Collection, Database};
+use mongodb::bson::{doc, Document};
 use serde::de::DeserializeOwned;
 use serde::export::fmt::Debug;
 use serde::Serialize;
 
-use crate::{cursor_to_vec, from_document, to_document};
+use dftk_common::acl::user::User;
+
+use crate::history::{Entity, HistoryEntry, HistoryRepository};
+use crate::indexes::IndexDefinition;
+use crate::query::{Page, Query, QueryFilter, SortField};
+use crate::revision::{check_revision, revision_filter, revision_of, with_revision, StaleRevision};
+use crate::storage::{DocumentStore, Storage};
+use crate::{from_document, from_documents, to_document};
+
+/// The outcome of a write
+struct Written {
+    revision: u32,
+    created: bool,
+}
 
 #[derive(Clone)]
 pub struct MongodbRepository<T> {
     db_name: String,
     col_name: String,
-    col: Collection,
+    col: Arc<dyn DocumentStore>,
+    history: Option<(Entity, HistoryRepository)>,
+    sort_values: Option<fn(&mut Document)>,
     resource_type: PhantomData<T>,
 }
 
@@ -22,19 +36,59 @@ impl<T> MongodbRepository<T>
 where
     T: Serialize + DeserializeOwned + Debug,
 {
-    pub fn new(db: &Database, col_name: &str) -> Self {
-        let db_name = db.name().into();
-        let col = db.collection(col_name);
+    pub fn new(storage: &dyn Storage, col_name: &str) -> Self {
+        let db_name = storage.name();
+        let col = storage.collection(col_name);
         let col_name = String::from(col_name);
 
         Self {
             db_name,
             col_name,
             col,
+            history: None,
+            sort_values: None,
             resource_type: PhantomData,
         }
     }
 
+    /// Record every mutation of this repository into the history
+    pub fn with_history(self, entity: Entity, history: &HistoryRepository) -> Self {
+        Self {
+            history: Some((entity, history.clone())),
+            ..self
+        }
+    }
+
+    /// Store the values to sort on with every written document, e.g. the patched title of a session
+    pub fn with_sort_values(self, sort_values: fn(&mut Document)) -> Self {
+        Self {
+            sort_values: Some(sort_values),
+            ..self
+        }
+    }
+
+    /// The same repository, recording the mutations on behalf of the user
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        let history = self
+            .history
+            .as_ref()
+            .map(|(entity, history)| (*entity, history.as_user(user)));
+
+        Self {
+            db_name: self.db_name.clone(),
+            col_name: self.col_name.clone(),
+            col: self.col.clone(),
+            history,
+            sort_values: self.sort_values,
+            resource_type: PhantomData,
+        }
+    }
+
+    pub async fn ensure_indexes(&self, indexes: &[IndexDefinition]) -> Result<()> {
+        debug!("Ensure indexes of {} from {}", self.col_name, self.db_name);
+        self.col.ensure_indexes(indexes).await
+    }
+
     pub async fn insert(&self, element: &T) -> Result<bool> {
         let MongodbRepository {
             col_name,
@@ -43,49 +97,96 @@ where
             ..
         } = self;
         info!("Insert a {} from {}", col_name, db_name);
-        let doc = to_document(element)?;
-        let result = col.insert_one(doc, None).await?;
-        debug!("...inserted {:?}", result);
+        let doc = with_revision(self.to_stored(element)?, 1);
+        col.insert_one(doc.clone()).await?;
+        debug!("...inserted {:?}", doc);
+        if let Ok(id) = doc.get_str("_id") {
+            self.record(id, None, Some(doc.clone())).await?;
+        }
 
         Ok(true)
     }
 
     pub async fn save_or_update(&self, id: &str, element: &T) -> Result<bool> {
-        let MongodbRepository {
-            col_name,
-            db_name,
-            col,
-            ..
-        } = self;
-        debug!("Save or update a {} [{}] from {}", col_name, id, db_name);
-        let doc = to_document(element)?;
-        let option = UpdateOptions::builder().upsert(true).build();
-        let result = col.update_one(doc! {"_id": id}, doc, option).await?;
-        debug!("...saved {:?}", result);
-        let result = if let Some(new_id) = result.upserted_id {
-            debug!("...saved a {} with id [{}]", col_name, new_id);
-            true
-        } else {
-            debug!("...updated a {} with id [{}]", col_name, id);
-            false
+        debug!(
+            "Save or update a {} [{}] from {}",
+            self.col_name, id, self.db_name
+        );
+        let written = self.write(id, element, None, true).await?;
+        let result = match written {
+            Some(Written { created: true, .. }) => {
+                debug!("...saved a {} with id [{}]", self.col_name, id);
+                true
+            }
+            _ => {
+                debug!("...updated a {} with id [{}]", self.col_name, id);
+                false
+            }
         };
 
         Ok(result)
     }
 
     pub async fn update(&self, id: &str, element: &T) -> Result<bool> {
-        let MongodbRepository {
-            col_name,
-            db_name,
-            col,
-            ..
-        } = self;
-        debug!("Update a {} [{}] from {}", col_name, id, db_name);
-        let doc = to_document(element)?;
-        let result = col.update_one(doc! {"_id": id}, doc, None).await?;
-        debug!("...updated {:?}", result);
+        debug!("Update a {} [{}] from {}", self.col_name, id, self.db_name);
+        let written = self.write(id, element, None, false).await?;
+
+        Ok(written.is_some())
+    }
+
+    /// Save a document if it still has the expected revision, returns the new revision
+    pub async fn save(&self, id: &str, element: &T, expected: Option<u32>) -> Result<u32> {
+        debug!(
+            "Save a {} [{}] with revision {:?} from {}",
+            self.col_name, id, expected, self.db_name
+        );
+        let written = self
+            .write(id, element, expected, expected.is_none())
+            .await?
+            .ok_or_else(|| anyhow!("No {} found with id {}", self.col_name, id))?;
 
-        Ok(result.modified_count == 1)
+        Ok(written.revision)
+    }
+
+    /// The current revision of a document
+    pub async fn revision(&self, id: &str) -> Result<Option<u32>> {
+        let result = self.find_document(id).await?;
+
+        Ok(result.as_ref().map(revision_of))
+    }
+
+    async fn write(
+        &self,
+        id: &str,
+        element: &T,
+        expected: Option<u32>,
+        upsert: bool,
+    ) -> Result<Option<Written>> {
+        let before = self.find_document(id).await?;
+        let current = before.as_ref().map(revision_of);
+        check_revision(expected, current)?;
+        let (filter, revision) = match current {
+            Some(rev) => (revision_filter(id, rev), rev + 1),
+            None if upsert => (doc! {"_id": id}, 1),
+            None => return Ok(None),
+        };
+        let doc = with_revision(self.to_stored(element)?, revision);
+        let result = self
+            .col
+            .replace_one(filter, doc.clone(), current.is_none())
+            .await?;
+        debug!("...written {:?}", result);
+        if let Some(rev) = current {
+            if result.matched == 0 {
+                // Modified in the meantime
+                let current = self.revision(id).await?;
+                return Err(StaleRevision::new(rev, current).into());
+            }
+        }
+        self.record(id, before, Some(doc)).await?;
+        let created = current.is_none();
+
+        Ok(Some(Written { revision, created }))
     }
 
     pub async fn find_first(&self) -> Result<T> {
@@ -96,7 +197,7 @@ where
             ..
         } = self;
         info!("Find first {} from {}", col_name, db_name);
-        let result = col.find_one(doc! {}, None).await?;
+        let result = col.find_one(doc! {}).await?;
         debug!("...found first {:?}", result);
         let doc = result.ok_or_else(|| anyhow!("Missing {} in database", col_name))?;
         let result = from_document(doc)?;
@@ -112,7 +213,7 @@ where
             ..
         } = self;
         debug!("Find {} by id [{}] from {}", col_name, id, db_name);
-        let result = col.find_one(doc! {"_id": &id}, None).await?;
+        let result = col.find_one(doc! {"_id": &id}).await?;
         debug!("...found {}: {:?}", id, result);
         let option = match result {
             Some(doc) => Some(from_document(doc)?),
@@ -130,7 +231,7 @@ where
             ..
         } = self;
         debug!("Find {} by key '{}' from {}", col_name, key, db_name);
-        let result = col.find_one(doc! {"key": &key}, None).await?;
+        let result = col.find_one(doc! {"key": &key}).await?;
         debug!("...found {}: {:?}", key, result);
         let option = match result {
             Some(doc) => Some(from_document::<T>(doc)?),
@@ -148,13 +249,48 @@ where
             ..
         } = self;
         debug!("Find {} by keys {:?} from {}", col_name, keys, db_name);
-        let mut cursor = col.find(doc! {"key": {"$in": keys}}, None).await?;
-        let result = cursor_to_vec(&mut cursor).await?;
+        let docs = col.find(doc! {"key": {"$in": keys}}, None).await?;
+        let result = from_documents(docs)?;
         debug!("...found {} {}", result.len(), col_name);
 
         Ok(result)
     }
 
+    pub async fn find_by_query(&self, query: Document) -> Result<Vec<T>> {
+        debug!(
+            "Find {} by {:?} from {}",
+            self.col_name, query, self.db_name
+        );
+        let docs = self.col.find(query, None).await?;
+        let result = from_documents(docs)?;
+        debug!("...found {} {}", result.len(), self.col_name);
+
+        Ok(result)
+    }
+
+    /// Find a page of the matching elements, with their total count
+    pub async fn find_page<F, S>(&self, query: &Query<F, S>) -> Result<Page<T>>
+    where
+        F: QueryFilter,
+        S: SortField,
+    {
+        let filter = query.filter()?;
+        let page = query.page();
+        debug!(
+            "Find {} by {:?} with {:?} from {}",
+            self.col_name, filter, page, self.db_name
+        );
+        let total = self.col.count(filter.clone()).await?;
+        let docs = self
+            .col
+            .find_range(filter, query.sort(), page.offset(), page.limit())
+            .await?;
+        let items = from_documents(docs)?;
+        debug!("...found {}/{} {}", items.len(), total, self.col_name);
+
+        Ok(Page::new(items, total, page.offset()))
+    }
+
     pub async fn find_all(&self) -> Result<Vec<T>> {
         let MongodbRepository {
             col_name,
@@ -163,8 +299,8 @@ where
             ..
         } = self;
         info!("Find all {} from {}", col_name, db_name);
-        let mut cursor = col.find(doc! {}, None).await?;
-        let result = cursor_to_vec(&mut cursor).await?;
+        let docs = col.find(doc! {}, None).await?;
+        let result = from_documents(docs)?;
         debug!("...found {} {}", result.len(), col_name);
 
         Ok(result)
@@ -179,9 +315,12 @@ where
         } = self;
         self.remove_all().await?;
         info!("Update all {} from {}", col_name, db_name);
-        let documents = elements.iter().filter_map(|elt| to_document::<T>(elt).ok());
-        let result = col.insert_many(documents, None).await?;
-        debug!("...updated {:?}", result);
+        let documents = elements
+            .iter()
+            .filter_map(|elt| self.to_stored(elt).ok())
+            .collect();
+        col.insert_many(documents).await?;
+        debug!("...updated {} {}", elements.len(), col_name);
 
         Ok(elements.len())
     }
@@ -194,10 +333,10 @@ where
             ..
         } = self;
         info!("Remove all {} from {}", col_name, db_name);
-        let result = col.delete_many(doc! {}, None).await?;
+        let result = col.delete_many(doc! {}).await?;
         debug!("...deleted {:?}", result);
 
-        Ok(result.deleted_count as usize)
+        Ok(result as usize)
     }
 
     pub async fn remove_by_id(&self, id: &str) -> Result<Option<T>> {
@@ -208,13 +347,173 @@ where
             ..
         } = self;
         debug!("Remove {} with id [{}] from {}", col_name, id, db_name);
-        let result = col.find_one_and_delete(doc! {"_id": &id}, None).await?;
+        let result = col.delete_one(doc! {"_id": &id}).await?;
         debug!("...deleted {}: {:?}", id, result);
         let result = match result {
-            Some(t) => Some(from_document::<T>(t)?),
+            Some(t) => {
+                self.record(id, Some(t.clone()), None).await?;
+                Some(from_document::<T>(t)?)
+            }
             None => None,
         };
 
         Ok(result)
     }
+
+    /// Restore the state of a document after a version of its history
+    pub async fn revert(&self, id: &str, version: u32) -> Result<HistoryEntry> {
+        let (entity, history) = self
+            .history
+            .as_ref()
+            .ok_or_else(|| anyhow!("No history for {}", self.col_name))?;
+        info!("Revert {} [{}] to the version {}", entity, id, version);
+        let target = history.state(*entity, id, version).await?;
+        let before = self.find_document(id).await?;
+        let revision = before.as_ref().map(revision_of).unwrap_or_default() + 1;
+        let target = target.map(|mut doc| {
+            if let Some(sort_values) = self.sort_values {
+                sort_values(&mut doc);
+            }
+            with_revision(doc, revision)
+        });
+        match target.clone() {
+            Some(doc) => {
+                self.col.replace_one(doc! {"_id": id}, doc, true).await?;
+            }
+            None => {
+                self.col.delete_one(doc! {"_id": id}).await?;
+            }
+        }
+
+        history.record(*entity, id, before, target).await
+    }
+
+    /// The stored document of an element, with its values to sort on
+    fn to_stored(&self, element: &T) -> Result<Document> {
+        let mut result = to_document(element)?;
+        if let Some(sort_values) = self.sort_values {
+            sort_values(&mut result);
+        }
+
+        Ok(result)
+    }
+
+    async fn find_document(&self, id: &str) -> Result<Option<Document>> {
+        let result = self.col.find_one(doc! {"_id": id}).await?;
+
+        Ok(result)
+    }
+
+    async fn record(
+        &self,
+        id: &str,
+        before: Option<Document>,
+        after: Option<Document>,
+    ) -> Result<()> {
+        if let Some((entity, history)) = &self.history {
+            history.record(*entity, id, before, after).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::indexes::{unique_key, Conflict};
+    use crate::query::{Direction, PageRequest, Sort, SponsorFilter, SponsorSort};
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Room {
+        #[serde(rename = "_id")]
+        id: String,
+        key: String,
+    }
+
+    fn room(id: &str, key: &str) -> Room {
+        let id = id.into();
+        let key = key.into();
+        Room { id, key }
+    }
+
+    async fn repository() -> MongodbRepository<Room> {
+        let storage = MemoryStorage::new("test");
+        let history = HistoryRepository::new(&storage);
+        let result =
+            MongodbRepository::new(&storage, "rooms").with_history(Entity::Session, &history);
+        result.ensure_indexes(&[unique_key()]).await.unwrap();
+
+        result
+    }
+
+    #[tokio::test]
+    async fn should_check_revisions() {
+        let repo = repository().await;
+        assert_eq!(repo.save("a", &room("a", "amphi"), None).await.unwrap(), 1);
+        assert_eq!(
+            repo.save("a", &room("a", "hall"), Some(1)).await.unwrap(),
+            2
+        );
+
+        let err = repo
+            .save("a", &room("a", "lab"), Some(1))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<StaleRevision>(),
+            Some(&StaleRevision::new(1, Some(2)))
+        );
+        assert_eq!(
+            repo.find_by_key("hall").await.unwrap(),
+            Some(room("a", "hall"))
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_duplicated_key() {
+        let repo = repository().await;
+        repo.insert(&room("a", "amphi")).await.unwrap();
+
+        let err = repo.insert(&room("b", "amphi")).await.unwrap_err();
+        assert!(err.downcast_ref::<Conflict>().is_some());
+    }
+
+    #[tokio::test]
+    async fn should_revert() {
+        let repo = repository().await;
+        repo.insert(&room("a", "amphi")).await.unwrap();
+        repo.update("a", &room("a", "hall")).await.unwrap();
+
+        let entry = repo.revert("a", 1).await.unwrap();
+        assert_eq!(entry.version(), 3);
+        assert_eq!(
+            repo.find_by_id("a").await.unwrap(),
+            Some(room("a", "amphi"))
+        );
+        assert_eq!(repo.revision("a").await.unwrap(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn should_find_page() {
+        let repo = repository().await;
+        for (id, key) in [("a", "amphi"), ("b", "hall"), ("c", "lab")].iter() {
+            repo.insert(&room(id, key)).await.unwrap();
+        }
+
+        let sort = Sort::new(SponsorSort::Key, Direction::Desc);
+        let query = Query::new(
+            SponsorFilter::default(),
+            Some(sort),
+            PageRequest::new(1, Some(1)),
+        );
+        let page = repo.find_page(&query).await.unwrap();
+        assert_eq!(page.items(), &[room("b", "hall")]);
+        assert_eq!(page.total(), 3);
+        assert!(page.has_next());
+    }
 }
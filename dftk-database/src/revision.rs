@@ -0,0 +1,111 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+
+use chrono::Utc;
+use mongodb::bson::{doc, Document};
+
+/// The field holding the revision of a document, bumped by every write
+pub const REVISION: &str = "_rev";
+
+/// The field holding the date of the last write, to sort on the recent changes
+pub const UPDATED_AT: &str = "_updated_at";
+
+/// A write rejected because the document changed since the expected revision
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleRevision {
+    expected: u32,
+    current: Option<u32>,
+}
+
+impl StaleRevision {
+    pub fn new(expected: u32, current: Option<u32>) -> Self {
+        Self { expected, current }
+    }
+
+    pub fn expected(&self) -> u32 {
+        self.expected
+    }
+    /// The current revision, `None` if the document does not exist anymore
+    pub fn current(&self) -> Option<u32> {
+        self.current
+    }
+}
+
+impl Display for StaleRevision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.current {
+            Some(current) => write!(
+                f,
+                "Stale revision: expected {} but current is {}",
+                self.expected, current
+            ),
+            None => write!(
+                f,
+                "Stale revision: expected {} but the document does not exist",
+                self.expected
+            ),
+        }
+    }
+}
+
+impl StdError for StaleRevision {}
+
+/// Fail if the current revision is not the expected one
+pub(crate) fn check_revision(
+    expected: Option<u32>,
+    current: Option<u32>,
+) -> Result<(), StaleRevision> {
+    match expected {
+        Some(expected) if current != Some(expected) => Err(StaleRevision::new(expected, current)),
+        _ => Ok(()),
+    }
+}
+
+/// The revision of a document, `0` for documents written before revisions
+pub(crate) fn revision_of(doc: &Document) -> u32 {
+    doc.get_i32(REVISION)
+        .map(|it| it as u32)
+        .unwrap_or_default()
+}
+
+/// Stamp a written document with its revision and the date of the write
+pub(crate) fn with_revision(mut doc: Document, revision: u32) -> Document {
+    doc.insert(REVISION, revision as i32);
+    doc.insert(UPDATED_AT, Utc::now());
+
+    doc
+}
+
+/// Select the document only if it still has the revision
+pub(crate) fn revision_filter(id: &str, revision: u32) -> Document {
+    if revision == 0 {
+        doc! {"_id": id, REVISION: {"$exists": false}}
+    } else {
+        doc! {"_id": id, REVISION: revision as i32}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_read_revision() {
+        assert_eq!(revision_of(&doc! {"_id": "a"}), 0);
+        assert_eq!(revision_of(&with_revision(doc! {"_id": "a"}, 3)), 3);
+    }
+
+    #[test]
+    fn should_check_revision() {
+        assert!(check_revision(None, Some(2)).is_ok());
+        assert!(check_revision(Some(2), Some(2)).is_ok());
+        assert_eq!(
+            check_revision(Some(1), Some(2)),
+            Err(StaleRevision::new(1, Some(2)))
+        );
+        assert_eq!(
+            check_revision(Some(1), None),
+            Err(StaleRevision::new(1, None))
+        );
+    }
+}
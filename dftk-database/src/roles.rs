@@ -0,0 +1,95 @@
+use anyhow::Result;
+
+use dftk_common::acl::role::{Permission, Role, RoleKey};
+
+use crate::repository::MongodbRepository;
+use crate::storage::Storage;
+
+/// The roles, shared by every event like the users
+#[derive(Clone)]
+pub struct RoleRepository {
+    repo: MongodbRepository<Role>,
+}
+
+impl RoleRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let repo = MongodbRepository::new(storage, "roles");
+        Self { repo }
+    }
+
+    /// Create the missing default roles, the existing ones are kept as configured
+    pub async fn ensure_defaults(&self) -> Result<()> {
+        for role in Role::defaults() {
+            let key: String = role.key().into();
+            if self.repo.find_by_id(key.as_str()).await?.is_none() {
+                info!("Create the default role {}", key);
+                self.repo.insert(&role).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn find(&self) -> Result<Vec<Role>> {
+        self.repo.find_all().await
+    }
+
+    pub async fn find_by_key(&self, key: RoleKey) -> Result<Option<Role>> {
+        let key: String = key.into();
+        self.repo.find_by_id(key.as_str()).await
+    }
+
+    /// Create or replace a role
+    pub async fn save(&self, role: &Role) -> Result<Role> {
+        let key: String = role.key().into();
+        self.repo.save_or_update(key.as_str(), role).await?;
+
+        Ok(role.clone())
+    }
+
+    pub async fn delete(&self, key: RoleKey) -> Result<Option<Role>> {
+        let key: String = key.into();
+        self.repo.remove_by_id(key.as_str()).await
+    }
+
+    /// The permissions granted by the roles, an unknown role grants nothing
+    pub async fn permissions(&self, keys: &[RoleKey]) -> Result<Vec<Permission>> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut result: Vec<Permission> = vec![];
+        for role in self.find().await? {
+            if keys.contains(&role.key()) {
+                result.extend_from_slice(role.permissions());
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_keep_configured_roles() {
+        let storage = MemoryStorage::new("test");
+        let roles = RoleRepository::new(&storage);
+        roles.ensure_defaults().await.unwrap();
+
+        let sponsor = Role::new(RoleKey::sponsor(), "Sponsor".into(), vec![]);
+        roles.save(&sponsor).await.unwrap();
+        roles.ensure_defaults().await.unwrap();
+
+        let result = roles.permissions(&[RoleKey::sponsor()]).await.unwrap();
+        assert!(result.is_empty());
+        let result = roles
+            .permissions(&[RoleKey::new("volunteer-lead")])
+            .await
+            .unwrap();
+        assert_eq!(result, vec![Permission::ViewSite, Permission::EditTeam]);
+    }
+}
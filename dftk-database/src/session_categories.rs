@@ -1,11 +1,14 @@
 use anyhow::{bail, Result};
-use mongodb::Database;
 use uuid::Uuid;
 
 use dftk_common::models::session::category::{CategoryKey, SessionCategory};
 use dftk_common::new_id;
 
+use crate::indexes::unique_key;
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
+
+pub(crate) const SESSION_CATEGORIES: &str = "session_categories";
 
 #[derive(Clone)]
 pub struct SessionCategoryRepository {
@@ -13,11 +16,20 @@ pub struct SessionCategoryRepository {
 }
 
 impl SessionCategoryRepository {
-    pub fn new(db: &Database) -> Self {
-        let repo = MongodbRepository::new(&db, "session_categories");
+    pub fn new(storage: &dyn Storage) -> Self {
+        Self::with_collection(storage, SESSION_CATEGORIES)
+    }
+
+    /// A repository on another collection, e.g. to stage a synchronization
+    pub(crate) fn with_collection(storage: &dyn Storage, col_name: &str) -> Self {
+        let repo = MongodbRepository::new(storage, col_name);
         Self { repo }
     }
 
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn create(
         &self,
         name: String,
@@ -1,11 +1,14 @@
 use anyhow::{bail, Result};
-use mongodb::Database;
 use uuid::Uuid;
 
 use dftk_common::models::session::format::{FormatKey, SessionFormat};
 use dftk_common::new_id;
 
+use crate::indexes::unique_key;
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
+
+pub(crate) const SESSION_FORMATS: &str = "session_formats";
 
 #[derive(Clone)]
 pub struct SessionFormatRepository {
@@ -13,11 +16,20 @@ pub struct SessionFormatRepository {
 }
 
 impl SessionFormatRepository {
-    pub fn new(db: &Database) -> Self {
-        let repo = MongodbRepository::new(&db, "session_formats");
+    pub fn new(storage: &dyn Storage) -> Self {
+        Self::with_collection(storage, SESSION_FORMATS)
+    }
+
+    /// A repository on another collection, e.g. to stage a synchronization
+    pub(crate) fn with_collection(storage: &dyn Storage, col_name: &str) -> Self {
+        let repo = MongodbRepository::new(storage, col_name);
         Self { repo }
     }
 
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn create(&self, name: String, description: Option<String>) -> Result<SessionFormat> {
         // check key not exists
         let key = FormatKey::new(name.as_str());
@@ -1,8 +1,8 @@
 use anyhow::{bail, ensure, Result};
 use mongodb::bson::doc;
-use mongodb::{Collection, Database};
 use serde::{Deserialize, Serialize};
 
+use dftk_common::acl::user::User;
 use dftk_common::models::language::Lang;
 use dftk_common::models::session::category::CategoryKey;
 use dftk_common::models::session::format::FormatKey;
@@ -11,8 +11,11 @@ use dftk_common::models::speaker::SpeakerKey;
 use dftk_common::models::Markdown;
 use dftk_common::new_id;
 
-use crate::cursor_to_vec;
+use crate::history::{Entity, HistoryEntry, HistoryRepository};
+use crate::indexes::{unique_key, IndexDefinition};
+use crate::query::{session_sort_values, Page, Query, SessionFilter, SessionSort};
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SessionDocument {
@@ -53,6 +56,9 @@ impl SessionDocument {
     pub fn id(&self) -> SessionId {
         self.id.clone()
     }
+    pub fn key(&self) -> SessionKey {
+        self.key.clone()
+    }
     pub fn session(&self) -> Option<Session> {
         self.session.clone()
     }
@@ -245,19 +251,42 @@ impl From<PartialSession> for SessionPatch {
     }
 }
 
+pub(crate) const SESSIONS: &str = "sessions";
+
 #[derive(Clone)]
 pub struct SessionRepository {
-    col: Collection,
     repo: MongodbRepository<SessionDocument>,
 }
 
 impl SessionRepository {
-    pub fn new(db: &Database) -> Self {
-        let col_name = "sessions";
-        let col = db.collection(col_name);
-        let repo = MongodbRepository::new(db, col_name);
+    pub fn new(storage: &dyn Storage, history: &HistoryRepository) -> Self {
+        let repo = MongodbRepository::new(storage, SESSIONS)
+            .with_history(Entity::Session, history)
+            .with_sort_values(session_sort_values);
+
+        Self { repo }
+    }
+
+    /// A repository on another collection, without history, e.g. to stage a synchronization
+    pub(crate) fn with_collection(storage: &dyn Storage, col_name: &str) -> Self {
+        let repo = MongodbRepository::new(storage, col_name).with_sort_values(session_sort_values);
+
+        Self { repo }
+    }
+
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        let repo = self.repo.as_user(user);
+
+        Self { repo }
+    }
 
-        Self { col, repo }
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let indexes = [
+            unique_key(),
+            IndexDefinition::new("session_speakers", doc! {"session.speakers": 1}),
+            IndexDefinition::new("patch_speakers", doc! {"patch.speakers": 1}),
+        ];
+        self.repo.ensure_indexes(&indexes).await
     }
 
     pub async fn find_all(&self) -> Result<Vec<Session>> {
@@ -273,6 +302,16 @@ impl SessionRepository {
         Ok(result)
     }
 
+    /// Find a page of sessions, filtered on their patched values
+    pub async fn find_page(
+        &self,
+        query: &Query<SessionFilter, SessionSort>,
+    ) -> Result<Page<Session>> {
+        let result = self.repo.find_page(query).await?;
+
+        Ok(result.map(|it| it.into()))
+    }
+
     pub async fn find_by_id(&self, id: SessionId) -> Result<Option<SessionDocument>> {
         let sid: String = id.into();
         self.repo.find_by_id(sid.as_str()).await
@@ -320,8 +359,7 @@ impl SessionRepository {
                { "session.speakers": &s }
             ]
         };
-        let mut cursor = self.col.find(query, None).await?;
-        let result = cursor_to_vec::<SessionDocument>(&mut cursor).await?;
+        let result = self.repo.find_by_query(query).await?;
         let result = result
             .iter()
             .cloned()
@@ -363,10 +401,12 @@ impl SessionRepository {
         Ok(session)
     }
 
+    /// Update the patch of a session, checking the expected revision if any
     pub async fn update_session(
         &self,
         id: SessionId,
         patch: SessionPatch,
+        expected: Option<u32>,
     ) -> Result<SessionDocument> {
         let sid: String = id.clone().into();
         let option = self.repo.find_by_id(sid.as_str()).await?;
@@ -382,7 +422,7 @@ impl SessionRepository {
             patch,
         };
         updated.validate()?;
-        self.repo.save_or_update(sid.as_str(), &updated).await?;
+        self.repo.save(sid.as_str(), &updated, expected).await?;
 
         Ok(updated)
     }
@@ -393,4 +433,14 @@ impl SessionRepository {
 
         Ok(result)
     }
+
+    pub async fn revision(&self, id: SessionId) -> Result<Option<u32>> {
+        let sid: String = id.into();
+        self.repo.revision(sid.as_str()).await
+    }
+
+    pub async fn revert(&self, id: SessionId, version: u32) -> Result<HistoryEntry> {
+        let sid: String = id.into();
+        self.repo.revert(sid.as_str(), version).await
+    }
 }
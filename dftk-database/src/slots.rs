@@ -0,0 +1,41 @@
+use anyhow::Result;
+use mongodb::bson::doc;
+
+use dftk_common::models::schedule::{Slot, SlotLayout};
+
+use crate::indexes::{unique_key, IndexDefinition};
+use crate::repository::MongodbRepository;
+use crate::storage::Storage;
+
+#[derive(Clone)]
+pub struct SlotRepository {
+    repo: MongodbRepository<Slot>,
+    layout_repo: MongodbRepository<SlotLayout>,
+}
+
+impl SlotRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let repo = MongodbRepository::new(storage, "slots");
+        let layout_repo = MongodbRepository::new(storage, "slot_layouts");
+
+        Self { repo, layout_repo }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await?;
+        let slot_unique = IndexDefinition::new("slot_unique", doc! {"slot": 1}).unique();
+        self.layout_repo.ensure_indexes(&[slot_unique]).await
+    }
+
+    pub async fn find_all(&self) -> Result<Vec<Slot>> {
+        self.repo.find_all().await
+    }
+
+    pub async fn find_by_key(&self, key: &str) -> Result<Option<Slot>> {
+        self.repo.find_by_key(key).await
+    }
+
+    pub async fn find_layouts(&self) -> Result<Vec<SlotLayout>> {
+        self.layout_repo.find_all().await
+    }
+}
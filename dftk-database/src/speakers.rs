@@ -1,14 +1,18 @@
 use anyhow::{bail, ensure, Result};
 use mongodb::bson::doc;
-use mongodb::Database;
 use serde::{Deserialize, Serialize};
 
+use dftk_common::acl::user::User;
 use dftk_common::models::socials::Social;
 use dftk_common::models::speaker::{PartialSpeaker, Speaker, SpeakerId, SpeakerKey};
 use dftk_common::models::Markdown;
 use dftk_common::new_id;
 
+use crate::history::{Entity, HistoryEntry, HistoryRepository};
+use crate::indexes::unique_key;
+use crate::query::{speaker_sort_values, Page, Query, SpeakerFilter, SpeakerSort};
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SpeakerDocument {
@@ -37,6 +41,9 @@ impl SpeakerDocument {
     pub fn id(&self) -> SpeakerId {
         self.id.clone()
     }
+    pub fn key(&self) -> SpeakerKey {
+        self.key.clone()
+    }
     pub fn speaker(&self) -> Option<Speaker> {
         self.speaker.clone()
     }
@@ -196,19 +203,39 @@ impl From<PartialSpeaker> for SpeakerPatch {
     }
 }
 
+pub(crate) const SPEAKERS: &str = "speakers";
+
 #[derive(Clone)]
 pub struct SpeakerRepository {
     repo: MongodbRepository<SpeakerDocument>,
 }
 
 impl SpeakerRepository {
-    pub fn new(db: &Database) -> Self {
-        let col_name = "speakers";
-        let repo = MongodbRepository::new(db, col_name);
+    pub fn new(storage: &dyn Storage, history: &HistoryRepository) -> Self {
+        let repo = MongodbRepository::new(storage, SPEAKERS)
+            .with_history(Entity::Speaker, history)
+            .with_sort_values(speaker_sort_values);
+
+        Self { repo }
+    }
+
+    /// A repository on another collection, without history, e.g. to stage a synchronization
+    pub(crate) fn with_collection(storage: &dyn Storage, col_name: &str) -> Self {
+        let repo = MongodbRepository::new(storage, col_name).with_sort_values(speaker_sort_values);
 
         Self { repo }
     }
 
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        let repo = self.repo.as_user(user);
+
+        Self { repo }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn find_all(&self) -> Result<Vec<Speaker>> {
         let result = self
             .repo
@@ -221,6 +248,16 @@ impl SpeakerRepository {
 
         Ok(result)
     }
+    /// Find a page of speakers, filtered on their patched values
+    pub async fn find_page(
+        &self,
+        query: &Query<SpeakerFilter, SpeakerSort>,
+    ) -> Result<Page<Speaker>> {
+        let result = self.repo.find_page(query).await?;
+
+        Ok(result.map(|it| it.into()))
+    }
+
     pub async fn find_by_id(&self, id: SpeakerId) -> Result<Option<SpeakerDocument>> {
         let sid: String = id.into();
         self.repo.find_by_id(sid.as_str()).await
@@ -288,10 +325,12 @@ impl SpeakerRepository {
         Ok(speaker)
     }
 
+    /// Update the patch of a speaker, checking the expected revision if any
     pub async fn update_speaker(
         &self,
         id: SpeakerId,
         patch: SpeakerPatch,
+        expected: Option<u32>,
     ) -> Result<SpeakerDocument> {
         let sid: String = id.clone().into();
         let option = self.repo.find_by_id(sid.as_str()).await?;
@@ -308,7 +347,7 @@ impl SpeakerRepository {
         };
         updated.validate()?;
         let sid: String = speaker.id().into();
-        self.repo.save_or_update(sid.as_str(), &updated).await?;
+        self.repo.save(sid.as_str(), &updated, expected).await?;
 
         Ok(updated)
     }
@@ -319,4 +358,14 @@ impl SpeakerRepository {
 
         Ok(result)
     }
+
+    pub async fn revision(&self, id: SpeakerId) -> Result<Option<u32>> {
+        let sid = id.to_string();
+        self.repo.revision(sid.as_str()).await
+    }
+
+    pub async fn revert(&self, id: SpeakerId, version: u32) -> Result<HistoryEntry> {
+        let sid = id.to_string();
+        self.repo.revert(sid.as_str(), version).await
+    }
 }
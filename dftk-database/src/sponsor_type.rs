@@ -1,11 +1,12 @@
 use anyhow::{bail, Result};
-use mongodb::Database;
 use uuid::Uuid;
 
 use dftk_common::models::sponsor::category::{SponsorCategory, SponsorCategoryKey};
 use dftk_common::new_id;
 
+use crate::indexes::unique_key;
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
 
 #[derive(Clone)]
 pub struct SponsorCategoryRepository {
@@ -13,11 +14,15 @@ pub struct SponsorCategoryRepository {
 }
 
 impl SponsorCategoryRepository {
-    pub fn new(db: &Database) -> Self {
-        let repo = MongodbRepository::new(&db, "sponsor_categories");
+    pub fn new(storage: &dyn Storage) -> Self {
+        let repo = MongodbRepository::new(storage, "sponsor_categories");
         Self { repo }
     }
 
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn create(&self, name: String) -> Result<SponsorCategory> {
         // check key not exists
         let key = SponsorCategoryKey::new(name.as_str());
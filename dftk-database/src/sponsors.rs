@@ -1,10 +1,14 @@
 use anyhow::{bail, Result};
-use mongodb::Database;
 use uuid::Uuid;
 
+use dftk_common::acl::user::User;
 use dftk_common::models::sponsor::{PartialSponsor, Sponsor, SponsorKey};
 
+use crate::history::{Entity, HistoryEntry, HistoryRepository};
+use crate::indexes::unique_key;
+use crate::query::{Page, Query, SponsorFilter, SponsorSort};
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
 
 #[derive(Clone)]
 pub struct SponsorRepository {
@@ -12,11 +16,22 @@ pub struct SponsorRepository {
 }
 
 impl SponsorRepository {
-    pub fn new(db: &Database) -> Self {
-        let repo = MongodbRepository::new(&db, "sponsors");
+    pub fn new(storage: &dyn Storage, history: &HistoryRepository) -> Self {
+        let repo =
+            MongodbRepository::new(storage, "sponsors").with_history(Entity::Sponsor, history);
         Self { repo }
     }
 
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        let repo = self.repo.as_user(user);
+
+        Self { repo }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn create(&self, element: PartialSponsor) -> Result<Sponsor> {
         let element: Sponsor = element.into();
         // check key not exists
@@ -34,12 +49,30 @@ impl SponsorRepository {
         self.repo.find_all().await
     }
 
+    pub async fn find_page(
+        &self,
+        query: &Query<SponsorFilter, SponsorSort>,
+    ) -> Result<Page<Sponsor>> {
+        self.repo.find_page(query).await
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<Sponsor>> {
+        let id = id.to_string();
+        self.repo.find_by_id(id.as_str()).await
+    }
+
     pub async fn find_by_key(&self, key: SponsorKey) -> Result<Option<Sponsor>> {
         let k: String = key.into();
         self.repo.find_by_key(k.as_str()).await
     }
 
-    pub async fn update(&self, id: Uuid, element: PartialSponsor) -> Result<Sponsor> {
+    /// Update an element, checking the expected revision if any
+    pub async fn update(
+        &self,
+        id: Uuid,
+        element: PartialSponsor,
+        expected: Option<u32>,
+    ) -> Result<Sponsor> {
         let sid = id.to_string();
         let option = self.repo.find_by_id(sid.as_str()).await?;
         if let Some(mt) = option {
@@ -57,7 +90,7 @@ impl SponsorRepository {
                 element.description(),
             );
             self.repo
-                .save_or_update(id.to_string().as_str(), &result)
+                .save(id.to_string().as_str(), &result, expected)
                 .await?;
             Ok(result)
         } else {
@@ -71,4 +104,14 @@ impl SponsorRepository {
 
         Ok(result)
     }
+
+    pub async fn revision(&self, id: Uuid) -> Result<Option<u32>> {
+        let id = id.to_string();
+        self.repo.revision(id.as_str()).await
+    }
+
+    pub async fn revert(&self, id: Uuid, version: u32) -> Result<HistoryEntry> {
+        let id = id.to_string();
+        self.repo.revert(id.as_str(), version).await
+    }
 }
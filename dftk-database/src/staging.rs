@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use mongodb::bson::doc;
+
+use crate::storage::Storage;
+
+/// Suffix of the collections written during a synchronization
+const STAGING: &str = "__staging";
+
+/// Suffix of the collections kept during the swap, to roll back
+const PREVIOUS: &str = "__previous";
+
+/// Whether the collection is a leftover of a staging, not live data
+pub(crate) fn is_staging(name: &str) -> bool {
+    name.ends_with(STAGING) || name.ends_with(PREVIOUS)
+}
+
+/// Write into shadow collections, then swap them with the live ones.
+///
+/// Until the commit the live collections are untouched,
+/// and a failed swap puts the previous collections back.
+/// A staging collection never written empties the live one.
+pub(crate) struct Staging {
+    storage: Arc<dyn Storage>,
+    collections: Vec<String>,
+}
+
+impl Staging {
+    /// Prepare empty staging collections, dropping the leftovers of a previous failure
+    pub(crate) async fn new(storage: &Arc<dyn Storage>, collections: &[&str]) -> Result<Self> {
+        let storage = Arc::clone(storage);
+        let collections = collections.iter().map(|it| String::from(*it)).collect();
+        let result = Self {
+            storage,
+            collections,
+        };
+        result.drop_all(STAGING).await?;
+
+        Ok(result)
+    }
+
+    /// The name of the staging collection
+    pub(crate) fn name(&self, collection: &str) -> String {
+        format!("{}{}", collection, STAGING)
+    }
+
+    /// Start the staging collection from a copy of the live one
+    pub(crate) async fn copy(&self, collection: &str) -> Result<()> {
+        debug!("Copy {} into {}", collection, self.name(collection));
+        let docs = self
+            .storage
+            .collection(collection)
+            .find(doc! {}, None)
+            .await?;
+        let name = self.name(collection);
+
+        self.storage
+            .collection(name.as_str())
+            .insert_many(docs)
+            .await
+    }
+
+    /// Swap every staging collection with the live one, rolling back on failure
+    pub(crate) async fn commit(&self) -> Result<()> {
+        info!("Swap the staging collections {:?}", self.collections);
+        let existing = self.storage.list_collections().await?;
+        let mut swapped = vec![];
+        for collection in self.collections.iter() {
+            let exists = existing.contains(collection);
+            let staged = existing.contains(&self.name(collection));
+            if let Err(err) = self.swap(collection, exists, staged).await {
+                error!("Failed to swap {}, rolling back: {:#}", collection, err);
+                for (collection, exists) in swapped.iter().rev() {
+                    self.restore(collection, *exists).await?;
+                }
+                self.drop_all(STAGING).await?;
+                return Err(err);
+            }
+            swapped.push((collection.as_str(), exists));
+        }
+        self.drop_all(PREVIOUS).await?;
+
+        Ok(())
+    }
+
+    /// Drop the staging collections, the live ones are untouched
+    pub(crate) async fn abort(&self) -> Result<()> {
+        info!("Drop the staging collections {:?}", self.collections);
+        self.drop_all(STAGING).await
+    }
+
+    async fn swap(&self, collection: &str, exists: bool, staged: bool) -> Result<()> {
+        let previous = format!("{}{}", collection, PREVIOUS);
+        if exists {
+            self.rename(collection, previous.as_str()).await?;
+        }
+        if !staged {
+            return Ok(());
+        }
+        if let Err(err) = self
+            .rename(self.name(collection).as_str(), collection)
+            .await
+        {
+            if exists {
+                self.rename(previous.as_str(), collection).await?;
+            }
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    async fn restore(&self, collection: &str, exists: bool) -> Result<()> {
+        warn!("Restore the previous {}", collection);
+        if exists {
+            let previous = format!("{}{}", collection, PREVIOUS);
+            self.rename(previous.as_str(), collection).await
+        } else {
+            self.storage.drop_collection(collection).await
+        }
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        debug!("Rename {} to {}", from, to);
+        self.storage.rename_collection(from, to).await
+    }
+
+    async fn drop_all(&self, suffix: &str) -> Result<()> {
+        let existing = self.storage.list_collections().await?;
+        for collection in self.collections.iter() {
+            let name = format!("{}{}", collection, suffix);
+            if existing.contains(&name) {
+                debug!("Drop {}", name);
+                self.storage.drop_collection(name.as_str()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
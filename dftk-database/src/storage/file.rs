@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use mongodb::bson::{Bson, Document};
+use serde_json::Value;
+use tokio::sync::RwLock;
+use tokio::task;
+
+use crate::indexes::IndexDefinition;
+use crate::storage::{DocumentStore, MemoryStorage, Storage, WriteResult};
+
+/// The scheme of the file storage URL, e.g. `file://data/devfest.json`
+pub(crate) const FILE_SCHEME: &str = "file://";
+
+/// The embedded adapter: the documents live in memory and every write is saved
+/// into a single JSON file, for small events without MongoDB
+///
+/// The writes are serialized, a write whose file could not be saved is undone in memory.
+/// The reads wait for the pending write, so a change is only visible once saved.
+#[derive(Clone)]
+pub struct FileStorage {
+    memory: MemoryStorage,
+    file: Arc<DataFile>,
+}
+
+impl FileStorage {
+    /// Open the file storage from a `file://` URL, the file is created on the first write
+    pub fn open(url: &str) -> Result<Self> {
+        let path = url.strip_prefix(FILE_SCHEME).unwrap_or(url);
+        let path = PathBuf::from(path);
+        info!("Using the file storage {}", path.display());
+        let memory = MemoryStorage::new(path.to_string_lossy().as_ref());
+        if path.exists() {
+            for (name, docs) in read_file(&path)? {
+                debug!("Load {} document(s) into {}", docs.len(), name);
+                memory.load(name.as_str(), docs);
+            }
+        }
+        let lock = RwLock::new(());
+        let file = Arc::new(DataFile { path, lock });
+
+        Ok(Self { memory, file })
+    }
+
+    /// Read the collections once the pending write is saved or undone
+    async fn read<T, F>(&self, query: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let _guard = self.file.lock.read().await;
+        query.await
+    }
+
+    /// Apply the change to the collections, then save the file,
+    /// the collections are restored if the file could not be saved
+    async fn write<T, F>(&self, names: &[&str], change: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>> + Send,
+        T: Send,
+    {
+        let _guard = self.file.lock.write().await;
+        let checkpoint = self.memory.checkpoint(names);
+        let result = change.await?;
+        if let Err(err) = self.file.save(&self.memory).await {
+            warn!("Undo the write of {:?}: {:#}", names, err);
+            checkpoint.restore();
+            return Err(err);
+        }
+
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    fn name(&self) -> String {
+        self.memory.name()
+    }
+
+    fn collection(&self, name: &str) -> Arc<dyn DocumentStore> {
+        let inner = self.memory.collection(name);
+        let name = name.into();
+        let storage = self.clone();
+
+        Arc::new(FileCollection {
+            name,
+            inner,
+            storage,
+        })
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        self.read(self.memory.list_collections()).await
+    }
+
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<()> {
+        let change = self.memory.rename_collection(from, to);
+        self.write(&[from, to], change).await
+    }
+
+    async fn drop_collection(&self, name: &str) -> Result<()> {
+        let change = self.memory.drop_collection(name);
+        self.write(&[name], change).await
+    }
+}
+
+/// The file holding every collection, as canonical extended JSON like the backups
+struct DataFile {
+    path: PathBuf,
+    /// Held by the writes from the change of the collections until the file is saved,
+    /// and shared by the reads
+    lock: RwLock<()>,
+}
+
+impl DataFile {
+    /// Save a snapshot of the collections, out of the async executor
+    async fn save(&self, memory: &MemoryStorage) -> Result<()> {
+        let path = self.path.clone();
+        let memory = memory.clone();
+
+        task::spawn_blocking(move || write_file(&path, &memory)).await?
+    }
+}
+
+/// Write a snapshot into a temporary file, then rename it,
+/// so a crash never leaves a truncated file
+fn write_file(path: &Path, memory: &MemoryStorage) -> Result<()> {
+    let collections: BTreeMap<String, Vec<Value>> = memory
+        .snapshot()
+        .into_iter()
+        .map(|(name, docs)| {
+            let values = docs
+                .into_iter()
+                .map(|doc| Bson::Document(doc).into_canonical_extjson())
+                .collect();
+            (name, values)
+        })
+        .collect();
+    let content = serde_json::to_string(&collections)?;
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, content).with_context(|| format!("Could not write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Could not replace {}", path.display()))?;
+
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<BTreeMap<String, Vec<Document>>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    let collections: BTreeMap<String, Vec<Value>> = serde_json::from_str(content.as_str())
+        .with_context(|| format!("Invalid storage file {}", path.display()))?;
+
+    let mut result = BTreeMap::new();
+    for (name, values) in collections.into_iter() {
+        let mut docs = vec![];
+        for value in values.into_iter() {
+            match Bson::try_from(value)? {
+                Bson::Document(doc) => docs.push(doc),
+                other => bail!("Expected a document in {}, got {:?}", name, other),
+            }
+        }
+        result.insert(name, docs);
+    }
+
+    Ok(result)
+}
+
+/// Save the file after every write of the collection, the reads wait for the pending write
+struct FileCollection {
+    name: String,
+    inner: Arc<dyn DocumentStore>,
+    storage: FileStorage,
+}
+
+#[async_trait]
+impl DocumentStore for FileCollection {
+    async fn ensure_indexes(&self, indexes: &[IndexDefinition]) -> Result<()> {
+        self.inner.ensure_indexes(indexes).await
+    }
+
+    async fn find(&self, filter: Document, sort: Option<Document>) -> Result<Vec<Document>> {
+        self.storage.read(self.inner.find(filter, sort)).await
+    }
+
+    async fn find_range(
+        &self,
+        filter: Document,
+        sort: Option<Document>,
+        skip: u64,
+        limit: Option<u64>,
+    ) -> Result<Vec<Document>> {
+        let query = self.inner.find_range(filter, sort, skip, limit);
+        self.storage.read(query).await
+    }
+
+    async fn find_one(&self, filter: Document) -> Result<Option<Document>> {
+        self.storage.read(self.inner.find_one(filter)).await
+    }
+
+    async fn count(&self, filter: Document) -> Result<u64> {
+        self.storage.read(self.inner.count(filter)).await
+    }
+
+    async fn insert_one(&self, doc: Document) -> Result<()> {
+        let change = self.inner.insert_one(doc);
+        self.storage.write(&[self.name.as_str()], change).await
+    }
+
+    async fn insert_many(&self, docs: Vec<Document>) -> Result<()> {
+        let change = self.inner.insert_many(docs);
+        self.storage.write(&[self.name.as_str()], change).await
+    }
+
+    async fn replace_one(
+        &self,
+        filter: Document,
+        doc: Document,
+        upsert: bool,
+    ) -> Result<WriteResult> {
+        let change = self.inner.replace_one(filter, doc, upsert);
+        self.storage.write(&[self.name.as_str()], change).await
+    }
+
+    async fn update_one(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<WriteResult> {
+        let change = self.inner.update_one(filter, update, upsert);
+        self.storage.write(&[self.name.as_str()], change).await
+    }
+
+    async fn delete_one(&self, filter: Document) -> Result<Option<Document>> {
+        let change = self.inner.delete_one(filter);
+        self.storage.write(&[self.name.as_str()], change).await
+    }
+
+    async fn delete_many(&self, filter: Document) -> Result<u64> {
+        let change = self.inner.delete_many(filter);
+        self.storage.write(&[self.name.as_str()], change).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_reload_saved_documents() {
+        let path = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let url = format!("{}{}", FILE_SCHEME, path.display());
+
+        let storage = FileStorage::open(url.as_str()).unwrap();
+        let sessions = storage.collection("sessions");
+        sessions
+            .insert_many(vec![doc! {"_id": "s1", "key": "rust", "_rev": 1}])
+            .await
+            .unwrap();
+        storage
+            .collection("users")
+            .insert_one(doc! {"_id": "u1", "password": "$argon2i$..."})
+            .await
+            .unwrap();
+        storage
+            .collection("users")
+            .delete_many(doc! {})
+            .await
+            .unwrap();
+
+        let storage = FileStorage::open(url.as_str()).unwrap();
+        let result = storage.collection("sessions").find(doc! {}, None).await;
+        assert_eq!(
+            result.unwrap(),
+            vec![doc! {"_id": "s1", "key": "rust", "_rev": 1}]
+        );
+        assert_eq!(storage.list_collections().await.unwrap(), vec!["sessions"]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_undo_unsaved_writes() {
+        let path = std::env::temp_dir()
+            .join(uuid::Uuid::new_v4().to_string())
+            .join("devfest.json");
+        let url = format!("{}{}", FILE_SCHEME, path.display());
+
+        let storage = FileStorage::open(url.as_str()).unwrap();
+        let sessions = storage.collection("sessions");
+        let result = sessions.insert_one(doc! {"_id": "s1", "key": "rust"}).await;
+        assert!(result.is_err());
+        assert!(sessions.find(doc! {}, None).await.unwrap().is_empty());
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        sessions
+            .insert_one(doc! {"_id": "s1", "key": "rust"})
+            .await
+            .unwrap();
+        assert_eq!(sessions.count(doc! {}).await.unwrap(), 1);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn should_not_read_unsaved_writes() {
+        let path = std::env::temp_dir()
+            .join(uuid::Uuid::new_v4().to_string())
+            .join("devfest.json");
+        let url = format!("{}{}", FILE_SCHEME, path.display());
+
+        let storage = FileStorage::open(url.as_str()).unwrap();
+        let reader = storage.collection("sessions");
+        let reader = tokio::spawn(async move {
+            let mut seen = 0;
+            for _ in 0..100 {
+                seen += reader.count(doc! {}).await.unwrap();
+                task::yield_now().await;
+            }
+            seen
+        });
+        let sessions = storage.collection("sessions");
+        for _ in 0..10 {
+            let result = sessions.insert_one(doc! {"_id": "s1", "key": "rust"}).await;
+            assert!(result.is_err());
+        }
+
+        assert_eq!(reader.await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,448 @@
+//! Evaluate the subset of the MongoDB query and update syntax used by the repositories
+//!
+//! This is not a query engine: an operator is only added here with the repository issuing it,
+//! and anything else is rejected instead of approximated.
+
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Result};
+use mongodb::bson::{Bson, Document};
+
+/// The comparison operators evaluated by [`matches`]
+const QUERY_OPERATORS: [&str; 7] = ["$ne", "$in", "$exists", "$gt", "$gte", "$lt", "$lte"];
+
+/// Check a document against a filter, e.g. `{"key": {"$in": ["a", "b"]}}`
+pub(crate) fn matches(doc: &Document, filter: &Document) -> bool {
+    filter.iter().all(|(key, condition)| match key.as_str() {
+        "$or" => sub_filters(condition).iter().any(|it| matches(doc, it)),
+        "$and" => sub_filters(condition).iter().all(|it| matches(doc, it)),
+        path => matches_condition(&values(doc, path), condition),
+    })
+}
+
+/// Reject the filters using an operator not evaluated by [`matches`],
+/// otherwise a typo would silently match nothing
+pub(crate) fn check_filter(filter: &Document) -> Result<()> {
+    for (key, condition) in filter.iter() {
+        match key.as_str() {
+            "$or" | "$and" => {
+                let filters = condition
+                    .as_array()
+                    .ok_or_else(|| anyhow!("Expected an array for {}", key))?;
+                for filter in filters.iter() {
+                    let filter = filter
+                        .as_document()
+                        .ok_or_else(|| anyhow!("Expected documents for {}", key))?;
+                    check_filter(filter)?;
+                }
+            }
+            operator if operator.starts_with('$') => {
+                bail!("Unsupported query operator {}", operator)
+            }
+            _ => match condition {
+                Bson::Document(operators) if is_operator(operators) => {
+                    for operator in operators.keys() {
+                        if !QUERY_OPERATORS.contains(&operator.as_str()) {
+                            bail!("Unsupported query operator {}", operator);
+                        }
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Reject the sort specifications other than ascending or descending fields
+pub(crate) fn check_sort(sort: &Document) -> Result<()> {
+    for (path, direction) in sort.iter() {
+        if is_descending(direction).is_none() {
+            bail!("Unsupported sort {:?} for {}", direction, path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Order two documents with a sort specification, e.g. `{"version": 1}`
+pub(crate) fn compare(a: &Document, b: &Document, sort: &Document) -> Ordering {
+    for (path, direction) in sort.iter() {
+        let left = values(a, path).first().copied();
+        let right = values(b, path).first().copied();
+        let ordering = match (left, right) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            (Some(l), Some(r)) => compare_bson(l, r).unwrap_or(Ordering::Equal),
+        };
+        let ordering = if is_descending(direction).unwrap_or(false) {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Apply the `$set`, `$unset` and `$inc` operators,
+/// and the `$setOnInsert` operator to an upserted document
+pub(crate) fn apply_update(doc: &mut Document, update: &Document, inserted: bool) -> Result<()> {
+    for (operator, fields) in update.iter() {
+        let fields = fields
+            .as_document()
+            .ok_or_else(|| anyhow!("Expected a document for {}", operator))?;
+        match operator.as_str() {
+            "$set" => {
+                for (path, value) in fields.iter() {
+                    set_path(doc, path, value.clone());
+                }
+            }
+            "$unset" => {
+                for path in fields.keys() {
+                    unset_path(doc, path);
+                }
+            }
+            "$inc" => {
+                for (path, value) in fields.iter() {
+                    let current = values(doc, path).first().map(|it| (*it).clone());
+                    set_path(doc, path, increment(current, value)?);
+                }
+            }
+            "$setOnInsert" if inserted => {
+                for (path, value) in fields.iter() {
+                    set_path(doc, path, value.clone());
+                }
+            }
+            "$setOnInsert" => {}
+            _ => bail!("Unsupported update operator {}", operator),
+        }
+    }
+
+    Ok(())
+}
+
+/// The document created by an upsert: the equality conditions of the filter
+pub(crate) fn upsert_seed(filter: &Document) -> Document {
+    let mut result = Document::new();
+    for (path, condition) in filter.iter() {
+        match condition {
+            _ if path.starts_with('$') => {}
+            Bson::Document(doc) if is_operator(doc) => {}
+            _ => set_path(&mut result, path, condition.clone()),
+        }
+    }
+
+    result
+}
+
+/// The values at a dotted path, looking into arrays of documents
+pub(crate) fn values<'a>(doc: &'a Document, path: &str) -> Vec<&'a Bson> {
+    let path: Vec<&str> = path.split('.').collect();
+    let mut result = vec![];
+    if let Some((head, tail)) = path.split_first() {
+        if let Some(value) = doc.get(head) {
+            collect_values(value, tail, &mut result);
+        }
+    }
+
+    result
+}
+
+fn collect_values<'a>(value: &'a Bson, path: &[&str], result: &mut Vec<&'a Bson>) {
+    match path.split_first() {
+        None => result.push(value),
+        Some((head, tail)) => match value {
+            Bson::Document(doc) => {
+                if let Some(value) = doc.get(head) {
+                    collect_values(value, tail, result);
+                }
+            }
+            Bson::Array(items) => {
+                for item in items.iter() {
+                    if let Bson::Document(_) = item {
+                        collect_values(item, path, result);
+                    }
+                }
+            }
+            _ => {}
+        },
+    }
+}
+
+/// `1` sorts ascending, `-1` descending
+fn is_descending(direction: &Bson) -> Option<bool> {
+    match as_number(direction) {
+        Some(it) if (it - 1.0).abs() < f64::EPSILON => Some(false),
+        Some(it) if (it + 1.0).abs() < f64::EPSILON => Some(true),
+        _ => None,
+    }
+}
+
+fn sub_filters(condition: &Bson) -> Vec<&Document> {
+    match condition {
+        Bson::Array(items) => items.iter().filter_map(|it| it.as_document()).collect(),
+        _ => vec![],
+    }
+}
+
+fn is_operator(doc: &Document) -> bool {
+    doc.keys()
+        .next()
+        .map(|it| it.starts_with('$'))
+        .unwrap_or(false)
+}
+
+fn matches_condition(values: &[&Bson], condition: &Bson) -> bool {
+    match condition {
+        Bson::Document(operators) if is_operator(operators) => operators
+            .iter()
+            .all(|(operator, argument)| matches_operator(values, operator, argument)),
+        _ => matches_eq(values, condition),
+    }
+}
+
+fn matches_operator(values: &[&Bson], operator: &str, argument: &Bson) -> bool {
+    match operator {
+        "$ne" => !matches_eq(values, argument),
+        "$in" => in_array(values, argument),
+        "$exists" => values.is_empty() != argument.as_bool().unwrap_or(true),
+        "$gt" => matches_ordering(values, argument, |it| it == Ordering::Greater),
+        "$gte" => matches_ordering(values, argument, |it| it != Ordering::Less),
+        "$lt" => matches_ordering(values, argument, |it| it == Ordering::Less),
+        "$lte" => matches_ordering(values, argument, |it| it != Ordering::Greater),
+        // Rejected by `check_filter`
+        _ => false,
+    }
+}
+
+/// A field equals a value, or an array field contains it, a missing field equals `null`
+fn matches_eq(values: &[&Bson], expected: &Bson) -> bool {
+    if values.is_empty() {
+        return expected == &Bson::Null;
+    }
+    values.iter().any(|value| match value {
+        Bson::Array(items) => {
+            bson_eq(value, expected) || items.iter().any(|it| bson_eq(it, expected))
+        }
+        _ => bson_eq(value, expected),
+    })
+}
+
+fn in_array(values: &[&Bson], argument: &Bson) -> bool {
+    argument
+        .as_array()
+        .map(|items| items.iter().any(|it| matches_eq(values, it)))
+        .unwrap_or(false)
+}
+
+fn matches_ordering<F>(values: &[&Bson], argument: &Bson, accept: F) -> bool
+where
+    F: Fn(Ordering) -> bool,
+{
+    values
+        .iter()
+        .filter_map(|it| compare_bson(it, argument))
+        .any(accept)
+}
+
+fn bson_eq(a: &Bson, b: &Bson) -> bool {
+    match (as_number(a), as_number(b)) {
+        (Some(x), Some(y)) => (x - y).abs() < f64::EPSILON,
+        _ => a == b,
+    }
+}
+
+fn compare_bson(a: &Bson, b: &Bson) -> Option<Ordering> {
+    if let (Some(x), Some(y)) = (as_number(a), as_number(b)) {
+        return x.partial_cmp(&y);
+    }
+    match (a, b) {
+        (Bson::String(x), Bson::String(y)) => Some(x.cmp(y)),
+        (Bson::DateTime(x), Bson::DateTime(y)) => Some(x.cmp(y)),
+        (Bson::Boolean(x), Bson::Boolean(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+/// The sum of the numbers, a missing value counts as zero
+fn increment(current: Option<Bson>, by: &Bson) -> Result<Bson> {
+    let result = match (current.unwrap_or(Bson::Int32(0)), by) {
+        (Bson::Int32(a), Bson::Int32(b)) => Bson::Int32(a + b),
+        (Bson::Int32(a), Bson::Int64(b)) => Bson::Int64(i64::from(a) + b),
+        (Bson::Int64(a), Bson::Int32(b)) => Bson::Int64(a + i64::from(*b)),
+        (Bson::Int64(a), Bson::Int64(b)) => Bson::Int64(a + b),
+        (a, b) => match (as_number(&a), as_number(b)) {
+            (Some(x), Some(y)) => Bson::Double(x + y),
+            _ => bail!("Cannot increment {:?} by {:?}", a, b),
+        },
+    };
+
+    Ok(result)
+}
+
+fn as_number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(it) => Some(*it as f64),
+        Bson::Int64(it) => Some(*it as f64),
+        Bson::Double(it) => Some(*it),
+        _ => None,
+    }
+}
+
+fn set_path(doc: &mut Document, path: &str, value: Bson) {
+    let mut parts = path.splitn(2, '.');
+    let head = parts.next().unwrap_or_default();
+    match parts.next() {
+        None => {
+            doc.insert(head, value);
+        }
+        Some(tail) => {
+            let entry = doc
+                .entry(head.into())
+                .or_insert_with(|| Bson::Document(Document::new()));
+            if let Bson::Document(sub) = entry {
+                set_path(sub, tail, value);
+            }
+        }
+    }
+}
+
+fn unset_path(doc: &mut Document, path: &str) {
+    let mut parts = path.splitn(2, '.');
+    let head = parts.next().unwrap_or_default();
+    match parts.next() {
+        None => {
+            doc.remove(head);
+        }
+        Some(tail) => {
+            if let Some(Bson::Document(sub)) = doc.get_mut(head) {
+                unset_path(sub, tail);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    fn session() -> Document {
+        doc! {
+            "_id": "s1",
+            "key": "rust",
+            "_rev": 2_i32,
+            "session": {"title": "Rust", "speakers": ["jane-doe", "john-doe"]},
+            "patch": {},
+        }
+    }
+
+    #[test]
+    fn should_match_equality() {
+        assert!(matches(&session(), &doc! {}));
+        assert!(matches(&session(), &doc! {"_id": "s1", "key": "rust"}));
+        assert!(matches(&session(), &doc! {"_rev": 2_i64}));
+        assert!(!matches(&session(), &doc! {"key": "java"}));
+    }
+
+    #[test]
+    fn should_match_nested_arrays() {
+        assert!(matches(&session(), &doc! {"session.speakers": "jane-doe"}));
+        assert!(matches(
+            &session(),
+            &doc! {"$or": [{"patch.speakers": "john-doe"}, {"session.speakers": "john-doe"}]}
+        ));
+        assert!(!matches(&session(), &doc! {"patch.speakers": "jane-doe"}));
+    }
+
+    #[test]
+    fn should_match_operators() {
+        assert!(matches(
+            &session(),
+            &doc! {"key": {"$in": ["java", "rust"]}}
+        ));
+        assert!(matches(&session(), &doc! {"row": {"$exists": false}}));
+        assert!(!matches(&session(), &doc! {"patch": {"$exists": false}}));
+        assert!(matches(&session(), &doc! {"_rev": {"$gte": 2, "$lt": 3}}));
+    }
+
+    #[test]
+    fn should_reject_unsupported_operators() {
+        assert!(check_filter(&doc! {"key": {"$in": ["rust"]}, "_rev": {"$gte": 2}}).is_ok());
+        assert!(check_filter(&doc! {"$or": [{"key": "rust"}, {"key": {"$ne": "java"}}]}).is_ok());
+        assert!(check_filter(&doc! {"key": {"$regex": "^ru"}}).is_err());
+        assert!(check_filter(&doc! {"$or": [{"key": {"$inn": ["rust"]}}]}).is_err());
+        assert!(check_filter(&doc! {"$where": "this.key == 'rust'"}).is_err());
+        assert!(check_filter(&doc! {"$or": {"key": "rust"}}).is_err());
+        assert!(check_filter(&doc! {"$nor": [{"key": "rust"}]}).is_err());
+        assert!(check_filter(&doc! {"key": {"$nin": ["rust"]}}).is_err());
+    }
+
+    #[test]
+    fn should_reject_unsupported_sorts() {
+        assert!(check_sort(&doc! {"day": 1, "start": -1_i64}).is_ok());
+        assert!(check_sort(&doc! {"score": {"$meta": "textScore"}}).is_err());
+        assert!(check_sort(&doc! {"day": 2}).is_err());
+    }
+
+    #[test]
+    fn should_sort() {
+        let first = doc! {"version": 1_i64};
+        let second = doc! {"version": 2_i64};
+
+        assert_eq!(
+            compare(&first, &second, &doc! {"version": 1}),
+            Ordering::Less
+        );
+        assert_eq!(
+            compare(&first, &second, &doc! {"version": -1}),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn should_apply_update() {
+        let mut doc = session();
+        let update = doc! {
+            "$set": {"need_change_password": false, "patch.title": "Rust 2021"},
+            "$unset": {"_rev": ""},
+        };
+        apply_update(&mut doc, &update, false).unwrap();
+
+        assert!(!doc.get_bool("need_change_password").unwrap());
+        assert_eq!(
+            doc.get_document("patch").unwrap().get_str("title").unwrap(),
+            "Rust 2021"
+        );
+        assert!(!doc.contains_key("_rev"));
+    }
+
+    #[test]
+    fn should_increment() {
+        let update = doc! {"$inc": {"failures": 1}, "$setOnInsert": {"window_start": 42}};
+        let mut doc = doc! {"_id": "ip"};
+        apply_update(&mut doc, &update, true).unwrap();
+        assert_eq!(doc, doc! {"_id": "ip", "failures": 1, "window_start": 42});
+
+        let mut doc = doc! {"failures": 2_i64, "window_start": 12};
+        apply_update(&mut doc, &update, false).unwrap();
+        assert_eq!(doc, doc! {"failures": 3_i64, "window_start": 12});
+
+        let mut doc = doc! {"failures": "many"};
+        assert!(apply_update(&mut doc, &update, false).is_err());
+    }
+
+    #[test]
+    fn should_seed_upsert() {
+        let filter = doc! {"_id": "schema", "_rev": {"$exists": false}};
+
+        assert_eq!(upsert_seed(&filter), doc! {"_id": "schema"});
+    }
+}
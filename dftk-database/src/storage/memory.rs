@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use mongodb::bson::{Bson, Document};
+
+use crate::indexes::{Conflict, IndexDefinition};
+use crate::storage::filter::{
+    apply_update, check_filter, check_sort, compare, matches, upsert_seed, values,
+};
+use crate::storage::{DocumentStore, Storage, WriteResult};
+
+/// The in-memory adapter, everything is lost when the process stops
+#[derive(Clone)]
+pub struct MemoryStorage {
+    name: String,
+    collections: Arc<Mutex<BTreeMap<String, Arc<MemoryCollection>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new(name: &str) -> Self {
+        info!("Using the in-memory storage {}", name);
+        let name = name.into();
+        let collections = Arc::default();
+
+        Self { name, collections }
+    }
+
+    /// The documents of every non-empty collection
+    pub(crate) fn snapshot(&self) -> BTreeMap<String, Vec<Document>> {
+        let collections = self.collections.lock().unwrap();
+        collections
+            .iter()
+            .map(|(name, col)| (name.clone(), col.docs.read().unwrap().clone()))
+            .filter(|(_, docs)| !docs.is_empty())
+            .collect()
+    }
+
+    /// Replace the documents of a collection, e.g. with the content of a file
+    pub(crate) fn load(&self, name: &str, docs: Vec<Document>) {
+        self.get(name).put(docs, vec![]);
+    }
+
+    /// The current documents and indexes of the collections, to undo a failed write
+    pub(crate) fn checkpoint(&self, names: &[&str]) -> Checkpoint {
+        let collections = names
+            .iter()
+            .map(|name| {
+                let col = self.get(name);
+                let docs = col.docs.read().unwrap().clone();
+                let indexes = col.indexes.read().unwrap().clone();
+                (col, docs, indexes)
+            })
+            .collect();
+
+        Checkpoint(collections)
+    }
+
+    fn get(&self, name: &str) -> Arc<MemoryCollection> {
+        let mut collections = self.collections.lock().unwrap();
+        let result = collections.entry(name.into()).or_default();
+
+        Arc::clone(result)
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn collection(&self, name: &str) -> Arc<dyn DocumentStore> {
+        self.get(name)
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let collections = self.collections.lock().unwrap();
+        let result = collections
+            .iter()
+            .filter(|(_, col)| col.exists())
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        Ok(result)
+    }
+
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<()> {
+        let source = self.get(from);
+        if !source.exists() {
+            return Err(anyhow!("Could not rename {} to {}: not found", from, to));
+        }
+        let target = self.get(to);
+        let (docs, indexes) = source.take();
+        target.put(docs, indexes);
+
+        Ok(())
+    }
+
+    async fn drop_collection(&self, name: &str) -> Result<()> {
+        self.get(name).take();
+
+        Ok(())
+    }
+}
+
+/// The state of some collections, see [`MemoryStorage::checkpoint`]
+pub(crate) struct Checkpoint(Vec<(Arc<MemoryCollection>, Vec<Document>, Vec<IndexDefinition>)>);
+
+impl Checkpoint {
+    /// Put back the documents and the indexes of the checkpoint
+    pub(crate) fn restore(self) {
+        for (col, docs, indexes) in self.0.into_iter() {
+            col.put(docs, indexes);
+        }
+    }
+}
+
+/// The collections are kept by the storage, so a renamed collection
+/// is visible from every repository holding it
+#[derive(Default)]
+pub(crate) struct MemoryCollection {
+    docs: RwLock<Vec<Document>>,
+    indexes: RwLock<Vec<IndexDefinition>>,
+}
+
+impl MemoryCollection {
+    fn exists(&self) -> bool {
+        !self.docs.read().unwrap().is_empty() || !self.indexes.read().unwrap().is_empty()
+    }
+
+    fn take(&self) -> (Vec<Document>, Vec<IndexDefinition>) {
+        let docs = std::mem::take(&mut *self.docs.write().unwrap());
+        let indexes = std::mem::take(&mut *self.indexes.write().unwrap());
+
+        (docs, indexes)
+    }
+
+    fn put(&self, docs: Vec<Document>, indexes: Vec<IndexDefinition>) {
+        *self.docs.write().unwrap() = docs;
+        *self.indexes.write().unwrap() = indexes;
+    }
+
+    fn find_sorted(&self, filter: &Document, sort: Option<&Document>) -> Result<Vec<Document>> {
+        check_filter(filter)?;
+        let docs = self.docs.read().unwrap();
+        let mut result: Vec<Document> = docs
+            .iter()
+            .filter(|it| matches(it, filter))
+            .cloned()
+            .collect();
+        if let Some(sort) = sort {
+            check_sort(sort)?;
+            result.sort_by(|a, b| compare(a, b, sort));
+        }
+
+        Ok(result)
+    }
+
+    fn insert(&self, docs: Vec<Document>) -> Result<()> {
+        let mut current = self.docs.write().unwrap();
+        let mut added: Vec<Document> = vec![];
+        for doc in docs.into_iter() {
+            let doc = with_id(doc);
+            let others = current.iter().chain(added.iter());
+            self.check_unique(&doc, others)?;
+            added.push(doc);
+        }
+        current.extend(added);
+
+        Ok(())
+    }
+
+    /// Replace or update the first matching document, the change computes the new document
+    fn write<F>(&self, filter: &Document, upsert: bool, change: F) -> Result<WriteResult>
+    where
+        F: Fn(&Document, bool) -> Result<Document>,
+    {
+        check_filter(filter)?;
+        let mut docs = self.docs.write().unwrap();
+        match docs.iter().position(|it| matches(it, filter)) {
+            Some(index) => {
+                let mut doc = change(&docs[index], false)?;
+                if let Some(id) = docs[index].get("_id") {
+                    doc.insert("_id", id.clone());
+                }
+                let others = docs
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != index)
+                    .map(|(_, it)| it);
+                self.check_unique(&doc, others)?;
+                docs[index] = doc;
+
+                Ok(WriteResult {
+                    matched: 1,
+                    upserted: false,
+                })
+            }
+            None if upsert => {
+                let doc = with_id(change(&upsert_seed(filter), true)?);
+                self.check_unique(&doc, docs.iter())?;
+                docs.push(doc);
+
+                Ok(WriteResult {
+                    matched: 0,
+                    upserted: true,
+                })
+            }
+            None => Ok(WriteResult::default()),
+        }
+    }
+
+    fn delete(&self, filter: &Document, limit: Option<usize>) -> Result<Vec<Document>> {
+        check_filter(filter)?;
+        let mut docs = self.docs.write().unwrap();
+        let mut result = vec![];
+        let mut index = 0;
+        while index < docs.len() && limit.map(|it| result.len() < it).unwrap_or(true) {
+            if matches(&docs[index], filter) {
+                result.push(docs.remove(index));
+            } else {
+                index += 1;
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Reject a document colliding with another one on `_id` or on a unique index
+    fn check_unique<'a, I>(&self, doc: &Document, others: I) -> Result<()>
+    where
+        I: Iterator<Item = &'a Document>,
+    {
+        let indexes = self.indexes.read().unwrap();
+        let mut unique_keys: Vec<(String, Vec<String>)> = indexes
+            .iter()
+            .filter(|it| it.is_unique())
+            .map(|it| (it.name(), it.keys().keys().cloned().collect()))
+            .collect();
+        unique_keys.push(("_id_".into(), vec!["_id".into()]));
+
+        let others: Vec<&Document> = others.collect();
+        for (name, keys) in unique_keys.iter() {
+            let key = index_key(doc, keys);
+            if others.iter().any(|other| index_key(other, keys) == key) {
+                let message = format!("duplicate key error index: {} dup key: {:?}", name, key);
+                return Err(Conflict::new(message).into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DocumentStore for MemoryCollection {
+    async fn ensure_indexes(&self, indexes: &[IndexDefinition]) -> Result<()> {
+        // Like MongoDB, refuse a unique index on duplicated data
+        let docs = self.docs.read().unwrap().clone();
+        let previous = std::mem::replace(&mut *self.indexes.write().unwrap(), indexes.to_vec());
+        for (index, doc) in docs.iter().enumerate() {
+            if let Err(err) = self.check_unique(doc, docs.iter().skip(index + 1)) {
+                *self.indexes.write().unwrap() = previous;
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn find(&self, filter: Document, sort: Option<Document>) -> Result<Vec<Document>> {
+        self.find_sorted(&filter, sort.as_ref())
+    }
+
+    async fn find_range(
+        &self,
+        filter: Document,
+        sort: Option<Document>,
+        skip: u64,
+        limit: Option<u64>,
+    ) -> Result<Vec<Document>> {
+        let result = self
+            .find_sorted(&filter, sort.as_ref())?
+            .into_iter()
+            .skip(skip as usize)
+            .take(limit.map(|it| it as usize).unwrap_or(usize::MAX))
+            .collect();
+
+        Ok(result)
+    }
+
+    async fn find_one(&self, filter: Document) -> Result<Option<Document>> {
+        check_filter(&filter)?;
+        let docs = self.docs.read().unwrap();
+        let result = docs.iter().find(|it| matches(it, &filter)).cloned();
+
+        Ok(result)
+    }
+
+    async fn count(&self, filter: Document) -> Result<u64> {
+        check_filter(&filter)?;
+        let docs = self.docs.read().unwrap();
+        let result = docs.iter().filter(|it| matches(it, &filter)).count();
+
+        Ok(result as u64)
+    }
+
+    async fn insert_one(&self, doc: Document) -> Result<()> {
+        self.insert(vec![doc])
+    }
+
+    async fn insert_many(&self, docs: Vec<Document>) -> Result<()> {
+        self.insert(docs)
+    }
+
+    async fn replace_one(
+        &self,
+        filter: Document,
+        doc: Document,
+        upsert: bool,
+    ) -> Result<WriteResult> {
+        self.write(&filter, upsert, |current, _| {
+            let mut result = doc.clone();
+            if !result.contains_key("_id") {
+                if let Some(id) = current.get("_id") {
+                    result.insert("_id", id.clone());
+                }
+            }
+            Ok(result)
+        })
+    }
+
+    async fn update_one(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<WriteResult> {
+        self.write(&filter, upsert, |current, inserted| {
+            let mut result = current.clone();
+            apply_update(&mut result, &update, inserted)?;
+            Ok(result)
+        })
+    }
+
+    async fn delete_one(&self, filter: Document) -> Result<Option<Document>> {
+        Ok(self.delete(&filter, Some(1))?.into_iter().next())
+    }
+
+    async fn delete_many(&self, filter: Document) -> Result<u64> {
+        Ok(self.delete(&filter, None)?.len() as u64)
+    }
+}
+
+/// Documents inserted without `_id` get a generated one, like with MongoDB
+fn with_id(mut doc: Document) -> Document {
+    if !doc.contains_key("_id") {
+        let id = uuid::Uuid::new_v4().to_string();
+        doc.insert("_id", id);
+    }
+
+    doc
+}
+
+/// The values of the index keys, a missing field is `null` like with MongoDB
+fn index_key(doc: &Document, keys: &[String]) -> Vec<Bson> {
+    keys.iter()
+        .map(|key| values(doc, key).first().map(|it| (*it).clone()))
+        .map(|it| it.unwrap_or(Bson::Null))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use super::*;
+
+    fn collection() -> MemoryCollection {
+        let result = MemoryCollection::default();
+        result.put(
+            vec![],
+            vec![IndexDefinition::new("key", doc! {"key": 1}).unique()],
+        );
+
+        result
+    }
+
+    #[test]
+    fn should_reject_duplicated_keys() {
+        let col = collection();
+        col.insert(vec![doc! {"_id": "a", "key": "rust"}]).unwrap();
+
+        let err = col
+            .insert(vec![doc! {"_id": "b", "key": "rust"}])
+            .unwrap_err();
+        assert!(err.downcast_ref::<Conflict>().is_some());
+
+        let err = col.insert(vec![doc! {"_id": "a"}]).unwrap_err();
+        assert!(err.downcast_ref::<Conflict>().is_some());
+    }
+
+    #[test]
+    fn should_upsert_with_filter_id() {
+        let col = collection();
+        let result = col
+            .write(&doc! {"_id": "schema"}, true, |current, inserted| {
+                let mut result = current.clone();
+                apply_update(&mut result, &doc! {"$set": {"version": 2}}, inserted)?;
+                Ok(result)
+            })
+            .unwrap();
+
+        assert!(result.upserted);
+        assert_eq!(
+            col.find_sorted(&doc! {}, None).unwrap(),
+            vec![doc! {"_id": "schema", "version": 2}]
+        );
+    }
+
+    #[test]
+    fn should_replace_and_delete() {
+        let col = collection();
+        col.insert(vec![
+            doc! {"_id": "a", "key": "a"},
+            doc! {"_id": "b", "key": "b"},
+        ])
+        .unwrap();
+
+        let result = col
+            .write(&doc! {"_id": "b"}, false, |_| Ok(doc! {"key": "a"}))
+            .unwrap_err();
+        assert!(result.downcast_ref::<Conflict>().is_some());
+
+        let result = col
+            .write(&doc! {"_id": "b"}, false, |_| Ok(doc! {"key": "c"}))
+            .unwrap();
+        assert_eq!(result.matched, 1);
+
+        let sort = doc! {"key": -1};
+        let keys: Vec<String> = col
+            .find_sorted(&doc! {}, Some(&sort))
+            .unwrap()
+            .iter()
+            .map(|it| it.get_str("key").unwrap().into())
+            .collect();
+        assert_eq!(keys, vec!["c", "a"]);
+
+        assert_eq!(
+            col.delete(&doc! {"key": {"$in": ["a", "c"]}}, None)
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(col.find_sorted(&doc! {}, None).unwrap().is_empty());
+        assert!(col.delete(&doc! {"key": {"$regex": "a"}}, None).is_err());
+    }
+}
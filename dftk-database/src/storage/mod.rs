@@ -0,0 +1,197 @@
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use mongodb::bson::Document;
+
+use crate::indexes::IndexDefinition;
+use crate::MongodbConfig;
+
+pub use crate::storage::file::FileStorage;
+pub use crate::storage::memory::MemoryStorage;
+pub use crate::storage::mongo::MongoStorage;
+pub use crate::storage::scoped::ScopedStorage;
+
+use crate::storage::file::FILE_SCHEME;
+pub(crate) use crate::storage::scoped::{list_scopes, split_scope};
+
+mod file;
+pub(crate) mod filter;
+mod memory;
+mod mongo;
+mod scoped;
+
+/// The storage backend
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StorageKind {
+    Mongodb,
+    /// A single JSON file, for small events
+    File,
+    /// Everything is lost when the process stops, for demos and tests
+    Memory,
+}
+
+impl StorageKind {
+    /// The storage matching the scheme of a database URL
+    pub fn from_url(url: &str) -> Self {
+        if url.starts_with(FILE_SCHEME) {
+            StorageKind::File
+        } else {
+            StorageKind::Mongodb
+        }
+    }
+}
+
+impl Default for StorageKind {
+    fn default() -> Self {
+        StorageKind::Mongodb
+    }
+}
+
+impl FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let result = match s.to_lowercase().as_str() {
+            "mongodb" | "mongo" => StorageKind::Mongodb,
+            "file" => StorageKind::File,
+            "memory" => StorageKind::Memory,
+            _ => bail!("Unknown storage '{}', expected mongodb, file or memory", s),
+        };
+
+        Ok(result)
+    }
+}
+
+impl Display for StorageKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageKind::Mongodb => write!(f, "mongodb"),
+            StorageKind::File => write!(f, "file"),
+            StorageKind::Memory => write!(f, "memory"),
+        }
+    }
+}
+
+/// Open the storage selected by the configuration
+pub async fn open_storage(config: &MongodbConfig) -> Result<Arc<dyn Storage>> {
+    let result: Arc<dyn Storage> = match config.storage {
+        StorageKind::Mongodb => Arc::new(MongoStorage::connect(config).await?),
+        StorageKind::File => Arc::new(FileStorage::open(config.url.as_str())?),
+        StorageKind::Memory => Arc::new(MemoryStorage::new(config.database.as_str())),
+    };
+
+    Ok(result)
+}
+
+/// The storage port: named collections of documents
+///
+/// The port sits under the repositories rather than beside them: the sessions, speakers,
+/// users and the other aggregates keep a single implementation, with their revisions,
+/// history and unique keys, whatever the adapter, so the memory and file adapters
+/// run the code MongoDB runs instead of a second copy per aggregate.
+///
+/// Queries and updates use the MongoDB syntax, the adapters other than MongoDB
+/// only evaluate the operators the repositories issue, see the `filter` module.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// The name of the database, for logs
+    fn name(&self) -> String;
+
+    fn collection(&self, name: &str) -> Arc<dyn DocumentStore>;
+
+    async fn list_collections(&self) -> Result<Vec<String>>;
+
+    /// Rename a collection, replacing the target if it exists
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<()>;
+
+    async fn drop_collection(&self, name: &str) -> Result<()>;
+}
+
+/// The outcome of a replace or an update
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct WriteResult {
+    pub matched: u64,
+    pub upserted: bool,
+}
+
+/// A collection of documents
+///
+/// Writes rejected by a unique index fail with a [`Conflict`](crate::indexes::Conflict).
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    /// Create the missing indexes, recreate the changed ones, and drop the undeclared ones
+    async fn ensure_indexes(&self, indexes: &[IndexDefinition]) -> Result<()>;
+
+    async fn find(&self, filter: Document, sort: Option<Document>) -> Result<Vec<Document>>;
+
+    /// Find a page of the sorted documents
+    async fn find_range(
+        &self,
+        filter: Document,
+        sort: Option<Document>,
+        skip: u64,
+        limit: Option<u64>,
+    ) -> Result<Vec<Document>>;
+
+    async fn find_one(&self, filter: Document) -> Result<Option<Document>>;
+
+    async fn count(&self, filter: Document) -> Result<u64>;
+
+    async fn insert_one(&self, doc: Document) -> Result<()>;
+
+    async fn insert_many(&self, docs: Vec<Document>) -> Result<()>;
+
+    async fn replace_one(
+        &self,
+        filter: Document,
+        doc: Document,
+        upsert: bool,
+    ) -> Result<WriteResult>;
+
+    /// Update the first matching document with `$set`, `$unset`, `$inc` and `$setOnInsert` operators,
+    /// applied atomically
+    async fn update_one(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<WriteResult>;
+
+    /// Delete the first matching document, and return it
+    async fn delete_one(&self, filter: Document) -> Result<Option<Document>>;
+
+    async fn delete_many(&self, filter: Document) -> Result<u64>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_storage_kind() {
+        assert_eq!(
+            "memory".parse::<StorageKind>().unwrap(),
+            StorageKind::Memory
+        );
+        assert_eq!(
+            "MongoDB".parse::<StorageKind>().unwrap(),
+            StorageKind::Mongodb
+        );
+        assert!("sqlite".parse::<StorageKind>().is_err());
+    }
+
+    #[test]
+    fn should_find_storage_from_url() {
+        assert_eq!(
+            StorageKind::from_url("file://data/devfest.json"),
+            StorageKind::File
+        );
+        assert_eq!(
+            StorageKind::from_url("mongodb://localhost:27017/"),
+            StorageKind::Mongodb
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOptions, ReplaceOptions, UpdateOptions};
+use mongodb::{Client, Collection, Cursor, Database};
+use tokio::stream::StreamExt;
+
+use crate::indexes::{reconcile_indexes, write_error, IndexDefinition};
+use crate::storage::{DocumentStore, Storage, WriteResult};
+use crate::MongodbConfig;
+
+/// The MongoDB adapter
+#[derive(Clone)]
+pub struct MongoStorage {
+    client: Client,
+    db: Database,
+}
+
+impl MongoStorage {
+    pub async fn connect(config: &MongodbConfig) -> Result<Self> {
+        info!(
+            "Connection to mongodb {} using database {}",
+            config.url, config.database
+        );
+        let client = Client::with_uri_str(config.url.as_str()).await?;
+        let db = client.database(config.database.as_str());
+
+        Ok(Self { client, db })
+    }
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    fn name(&self) -> String {
+        self.db.name().into()
+    }
+
+    fn collection(&self, name: &str) -> Arc<dyn DocumentStore> {
+        let db = self.db.clone();
+        let col = self.db.collection(name);
+        let name = name.into();
+
+        Arc::new(MongoCollection { db, col, name })
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let result = self.db.list_collection_names(None).await?;
+
+        Ok(result)
+    }
+
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<()> {
+        let db_name = self.db.name();
+        let command = doc! {
+            "renameCollection": format!("{}.{}", db_name, from),
+            "to": format!("{}.{}", db_name, to),
+            "dropTarget": true,
+        };
+        self.client
+            .database("admin")
+            .run_command(command, None)
+            .await
+            .with_context(|| format!("Could not rename {} to {}", from, to))?;
+
+        Ok(())
+    }
+
+    async fn drop_collection(&self, name: &str) -> Result<()> {
+        self.db.collection(name).drop(None).await?;
+
+        Ok(())
+    }
+}
+
+struct MongoCollection {
+    db: Database,
+    col: Collection,
+    name: String,
+}
+
+#[async_trait]
+impl DocumentStore for MongoCollection {
+    async fn ensure_indexes(&self, indexes: &[IndexDefinition]) -> Result<()> {
+        reconcile_indexes(&self.db, self.name.as_str(), indexes).await
+    }
+
+    async fn find(&self, filter: Document, sort: Option<Document>) -> Result<Vec<Document>> {
+        let mut options = FindOptions::default();
+        options.sort = sort;
+        let mut cursor = self.col.find(filter, options).await?;
+
+        cursor_to_vec(&mut cursor).await
+    }
+
+    async fn find_range(
+        &self,
+        filter: Document,
+        sort: Option<Document>,
+        skip: u64,
+        limit: Option<u64>,
+    ) -> Result<Vec<Document>> {
+        let mut options = FindOptions::default();
+        options.sort = sort;
+        options.skip = Some(skip as i64);
+        options.limit = limit.map(|it| it as i64);
+        let mut cursor = self.col.find(filter, options).await?;
+
+        cursor_to_vec(&mut cursor).await
+    }
+
+    async fn find_one(&self, filter: Document) -> Result<Option<Document>> {
+        let result = self.col.find_one(filter, None).await?;
+
+        Ok(result)
+    }
+
+    async fn count(&self, filter: Document) -> Result<u64> {
+        let result = self.col.count_documents(filter, None).await?;
+
+        Ok(result as u64)
+    }
+
+    async fn insert_one(&self, doc: Document) -> Result<()> {
+        self.col.insert_one(doc, None).await.map_err(write_error)?;
+
+        Ok(())
+    }
+
+    async fn insert_many(&self, docs: Vec<Document>) -> Result<()> {
+        if !docs.is_empty() {
+            self.col
+                .insert_many(docs, None)
+                .await
+                .map_err(write_error)?;
+        }
+
+        Ok(())
+    }
+
+    async fn replace_one(
+        &self,
+        filter: Document,
+        doc: Document,
+        upsert: bool,
+    ) -> Result<WriteResult> {
+        let option = ReplaceOptions::builder().upsert(upsert).build();
+        let result = self
+            .col
+            .replace_one(filter, doc, option)
+            .await
+            .map_err(write_error)?;
+
+        Ok(WriteResult {
+            matched: result.matched_count as u64,
+            upserted: result.upserted_id.is_some(),
+        })
+    }
+
+    async fn update_one(
+        &self,
+        filter: Document,
+        update: Document,
+        upsert: bool,
+    ) -> Result<WriteResult> {
+        let option = UpdateOptions::builder().upsert(upsert).build();
+        let result = self
+            .col
+            .update_one(filter, update, option)
+            .await
+            .map_err(write_error)?;
+
+        Ok(WriteResult {
+            matched: result.matched_count as u64,
+            upserted: result.upserted_id.is_some(),
+        })
+    }
+
+    async fn delete_one(&self, filter: Document) -> Result<Option<Document>> {
+        let result = self.col.find_one_and_delete(filter, None).await?;
+
+        Ok(result)
+    }
+
+    async fn delete_many(&self, filter: Document) -> Result<u64> {
+        let result = self.col.delete_many(filter, None).await?;
+
+        Ok(result.deleted_count as u64)
+    }
+}
+
+async fn cursor_to_vec(cursor: &mut Cursor) -> Result<Vec<Document>> {
+    let mut result = vec![];
+    while let Some(doc) = cursor.next().await {
+        let doc = doc.map_err(|err| anyhow!("Oops, {}", err))?;
+        result.push(doc);
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::storage::{DocumentStore, Storage};
+
+/// Separate the event from the collection name, e.g. `devfest-2021.sessions`
+pub(crate) const EVENT_SEPARATOR: char = '.';
+
+/// The collections of an event, stored next to the other events with a prefix
+#[derive(Clone)]
+pub struct ScopedStorage {
+    inner: Arc<dyn Storage>,
+    event: String,
+}
+
+impl ScopedStorage {
+    pub fn new(inner: &Arc<dyn Storage>, event: &str) -> Self {
+        let inner = Arc::clone(inner);
+        let event = event.into();
+
+        Self { inner, event }
+    }
+
+    fn scoped(&self, name: &str) -> String {
+        format!("{}{}{}", self.event, EVENT_SEPARATOR, name)
+    }
+}
+
+/// The event and the collection of a scoped collection name
+pub(crate) fn split_scope(name: &str) -> Option<(&str, &str)> {
+    let index = name.find(EVENT_SEPARATOR)?;
+    let (event, collection) = name.split_at(index);
+
+    Some((event, &collection[1..]))
+}
+
+/// The events having collections, MongoDB `system.` collections are not an event
+pub(crate) async fn list_scopes(storage: &dyn Storage) -> Result<Vec<String>> {
+    let mut result: Vec<String> = vec![];
+    for name in storage.list_collections().await? {
+        if let Some((event, _)) = split_scope(name.as_str()) {
+            if event != "system" && !result.iter().any(|it| it == event) {
+                result.push(event.into());
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[async_trait]
+impl Storage for ScopedStorage {
+    fn name(&self) -> String {
+        format!("{}/{}", self.inner.name(), self.event)
+    }
+
+    fn collection(&self, name: &str) -> Arc<dyn DocumentStore> {
+        self.inner.collection(self.scoped(name).as_str())
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let result = self
+            .inner
+            .list_collections()
+            .await?
+            .iter()
+            .filter_map(|name| match split_scope(name) {
+                Some((event, collection)) if event == self.event => Some(collection.into()),
+                _ => None,
+            })
+            .collect();
+
+        Ok(result)
+    }
+
+    async fn rename_collection(&self, from: &str, to: &str) -> Result<()> {
+        let from = self.scoped(from);
+        let to = self.scoped(to);
+
+        self.inner
+            .rename_collection(from.as_str(), to.as_str())
+            .await
+    }
+
+    async fn drop_collection(&self, name: &str) -> Result<()> {
+        self.inner.drop_collection(self.scoped(name).as_str()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::doc;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn should_split_scope() {
+        assert_eq!(
+            split_scope("devfest-2021.sessions"),
+            Some(("devfest-2021", "sessions"))
+        );
+        assert_eq!(split_scope("sessions"), None);
+    }
+
+    #[tokio::test]
+    async fn should_isolate_events() {
+        let root: Arc<dyn Storage> = Arc::new(MemoryStorage::new("test"));
+        let event = ScopedStorage::new(&root, "devfest-2021");
+        event
+            .collection("rooms")
+            .insert_one(doc! {"_id": "amphi"})
+            .await
+            .unwrap();
+
+        assert!(root
+            .collection("rooms")
+            .find_one(doc! {})
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(event.list_collections().await.unwrap(), vec!["rooms"]);
+        assert_eq!(
+            root.list_collections().await.unwrap(),
+            vec!["devfest-2021.rooms"]
+        );
+    }
+}
@@ -1,11 +1,12 @@
 use anyhow::{bail, Result};
-use mongodb::Database;
 use uuid::Uuid;
 
 use dftk_common::models::team::member_type::{MemberType, MemberTypeKey};
 use dftk_common::new_id;
 
+use crate::indexes::unique_key;
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
 
 #[derive(Clone)]
 pub struct MemberTypeRepository {
@@ -13,11 +14,15 @@ pub struct MemberTypeRepository {
 }
 
 impl MemberTypeRepository {
-    pub fn new(db: &Database) -> Self {
-        let repo = MongodbRepository::new(&db, "team_members");
+    pub fn new(storage: &dyn Storage) -> Self {
+        let repo = MongodbRepository::new(storage, "team_members");
         Self { repo }
     }
 
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn create(&self, name: String) -> Result<MemberType> {
         // check key not exists
         let key = MemberTypeKey::new(name.as_str());
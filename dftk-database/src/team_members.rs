@@ -1,10 +1,13 @@
 use anyhow::{bail, Result};
-use mongodb::Database;
 use uuid::Uuid;
 
+use dftk_common::acl::user::User;
 use dftk_common::models::team::{PartialTeamMember, TeamMember};
 
+use crate::history::{Entity, HistoryEntry, HistoryRepository};
+use crate::indexes::unique_key;
 use crate::repository::MongodbRepository;
+use crate::storage::Storage;
 
 #[derive(Clone)]
 pub struct TeamMemberRepository {
@@ -12,11 +15,22 @@ pub struct TeamMemberRepository {
 }
 
 impl TeamMemberRepository {
-    pub fn new(db: &Database) -> Self {
-        let repo = MongodbRepository::new(&db, "team_member_types");
+    pub fn new(storage: &dyn Storage, history: &HistoryRepository) -> Self {
+        let repo = MongodbRepository::new(storage, "team_member_types")
+            .with_history(Entity::TeamMember, history);
         Self { repo }
     }
 
+    pub fn as_user(&self, user: Option<User>) -> Self {
+        let repo = self.repo.as_user(user);
+
+        Self { repo }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        self.repo.ensure_indexes(&[unique_key()]).await
+    }
+
     pub async fn create(&self, element: PartialTeamMember) -> Result<TeamMember> {
         let element: TeamMember = element.into();
         // check key not exists
@@ -38,7 +52,13 @@ impl TeamMemberRepository {
         self.repo.find_by_key(key).await
     }
 
-    pub async fn update(&self, id: Uuid, element: PartialTeamMember) -> Result<TeamMember> {
+    /// Update an element, checking the expected revision if any
+    pub async fn update(
+        &self,
+        id: Uuid,
+        element: PartialTeamMember,
+        expected: Option<u32>,
+    ) -> Result<TeamMember> {
         let sid = id.to_string();
         let option = self.repo.find_by_id(sid.as_str()).await?;
         if let Some(mt) = option {
@@ -53,7 +73,7 @@ impl TeamMemberRepository {
                 element.description(),
             );
             self.repo
-                .save_or_update(id.to_string().as_str(), &result)
+                .save(id.to_string().as_str(), &result, expected)
                 .await?;
             Ok(result)
         } else {
@@ -67,4 +87,14 @@ impl TeamMemberRepository {
 
         Ok(result)
     }
+
+    pub async fn revision(&self, id: Uuid) -> Result<Option<u32>> {
+        let id = id.to_string();
+        self.repo.revision(id.as_str()).await
+    }
+
+    pub async fn revert(&self, id: Uuid, version: u32) -> Result<HistoryEntry> {
+        let id = id.to_string();
+        self.repo.revert(id.as_str(), version).await
+    }
 }
@@ -1,34 +1,137 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+use std::sync::Arc;
+
 use anyhow::{anyhow, Result};
 use argon2::Config;
 use bson::Document;
 use chbs::passphrase;
 use mongodb::bson::doc;
-use mongodb::options::{FindOneAndUpdateOptions, FindOptions};
-use mongodb::{Collection, Database};
 use rand::Rng;
 use serde::Serialize;
-use tokio::stream::StreamExt;
+use tokio::task;
 
 use dftk_common::acl::user::{Email, User, UserInfo};
 
 use crate::from_document;
+use crate::indexes::IndexDefinition;
+use crate::storage::{DocumentStore, Storage};
 
+/// An argon2 hash
 #[derive(Clone)]
-struct Password(String);
+pub(crate) struct Password(String);
 
 impl Password {
-    fn new(s: &[u8]) -> Self {
+    pub(crate) fn new(s: &[u8]) -> Self {
         let salt = rand::thread_rng().gen::<[u8; 32]>();
         let config = Config::default();
         let hash = argon2::hash_encoded(s, &salt, &config).unwrap();
         Password(hash)
     }
 
-    fn verify(&self, password: &[u8]) -> bool {
+    pub(crate) fn from_hash(hash: String) -> Self {
+        Password(hash)
+    }
+
+    pub(crate) fn hash(&self) -> String {
+        self.0.clone()
+    }
+
+    pub(crate) fn verify(&self, password: &[u8]) -> bool {
         argon2::verify_encoded(self.0.as_str(), password).unwrap_or(false)
     }
+
+    /// Hash off the executor, argon2 is slow on purpose
+    pub(crate) async fn hash_blocking(password: &[u8]) -> Result<Self> {
+        let password = password.to_vec();
+        let result = task::spawn_blocking(move || Password::new(&password)).await?;
+
+        Ok(result)
+    }
+
+    /// Verify off the executor
+    pub(crate) async fn verify_blocking(&self, password: &[u8]) -> Result<bool> {
+        let (hash, password) = (self.clone(), password.to_vec());
+        let result = task::spawn_blocking(move || hash.verify(&password)).await?;
+
+        Ok(result)
+    }
+}
+
+/// The rules of a new password
+///
+/// The current password cannot be reused, this includes the generated password of a new user.
+#[derive(Clone, Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
 }
 
+impl PasswordPolicy {
+    /// Check the password alone, the reuse is checked when the password is set
+    pub fn check(&self, password: &[u8]) -> Result<()> {
+        if password.len() < self.min_length {
+            let message = format!("Expected at least {} characters", self.min_length);
+            return Err(PasswordRejected(message).into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self { min_length: 10 }
+    }
+}
+
+/// A new password rejected by the [`PasswordPolicy`]
+#[derive(Debug, Clone)]
+pub struct PasswordRejected(String);
+
+impl Display for PasswordRejected {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Password rejected: {}", self.0)
+    }
+}
+
+impl StdError for PasswordRejected {}
+
+/// A speaker or a sponsor user bound to a missing speaker or sponsor
+#[derive(Debug, Clone)]
+pub struct UnknownKey(String);
+
+impl UnknownKey {
+    pub(crate) fn new(message: String) -> Self {
+        UnknownKey(message)
+    }
+}
+
+impl Display for UnknownKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown key: {}", self.0)
+    }
+}
+
+impl StdError for UnknownKey {}
+
+/// A user granted more than the current user holds
+#[derive(Debug, Clone)]
+pub struct Escalation(String);
+
+impl Escalation {
+    pub(crate) fn new(message: String) -> Self {
+        Escalation(message)
+    }
+}
+
+impl Display for Escalation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Not allowed to grant {}", self.0)
+    }
+}
+
+impl StdError for Escalation {}
+
 #[derive(Serialize)]
 struct UserDocument {
     user: User,
@@ -38,54 +141,65 @@ struct UserDocument {
 
 #[derive(Clone)]
 pub struct UserRepository {
-    col: Collection,
+    col: Arc<dyn DocumentStore>,
+    /// Verified for the unknown e-mails, they take as long as the known ones
+    dummy: Password,
 }
 
 impl UserRepository {
-    pub async fn build(db: &Database) -> Result<Self> {
-        let col = db.collection("users");
-        let result = Self { col };
+    pub async fn build(storage: &dyn Storage) -> Result<Self> {
+        let col = storage.collection("users");
+        let dummy = Password::hash_blocking(passphrase().as_bytes()).await?;
+        let result = Self { col, dummy };
 
         Ok(result)
     }
 
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let email_unique = IndexDefinition::new("email_unique", doc! {"user.email": 1}).unique();
+        self.col.ensure_indexes(&[email_unique]).await
+    }
+
     pub async fn find_all(&self) -> Result<Vec<User>> {
-        let doc = FindOptions::builder()
-            .projection(Some(user_projection()))
-            .build();
-        let mut cursor = self.col.find(doc! {}, Some(doc)).await?;
-        let mut result = vec![];
-        while let Some(doc) = cursor.next().await {
-            let doc = doc.map_err(|err| anyhow!("Oops, {}", err))?;
-            let user = get_user(&doc)?;
-            result.push(user);
-        }
+        let docs = self.col.find(doc! {}, None).await?;
 
-        Ok(result)
+        docs.iter().map(get_user).collect()
     }
 
     pub async fn authenticate(&self, email: &Email, password: &[u8]) -> Result<UserInfo> {
         info!("Try authenticate {:?}", email);
         let query = query_by_email(email);
         debug!("authenticate query: {:#?}", query);
-        let result = self.col.find_one(query, None).await?;
-
-        if let Some(doc) = result {
-            let db_password = get_password(&doc)?;
-            if db_password.verify(password) {
-                let user_info = get_user_info(&doc)?;
-                Ok(user_info)
-            } else {
-                Err(anyhow!("No user found this e-mail & password"))
-            }
-        } else {
-            Err(anyhow!("No user found this e-mail & password"))
+        let result = self.col.find_one(query).await?;
+
+        let (db_password, user_info) = match &result {
+            Some(doc) => (get_password(doc)?, Some(get_user_info(doc)?)),
+            None => (self.dummy.clone(), None),
+        };
+        let verified = db_password.verify_blocking(password).await?;
+        match user_info {
+            Some(user_info) if verified => Ok(user_info),
+            _ => Err(anyhow!("No user found this e-mail & password")),
         }
     }
 
-    pub async fn new_user(&self, user: User) -> Result<String> {
+    pub async fn find_by_email(&self, email: &Email) -> Result<Option<User>> {
+        let result = self.col.find_one(query_by_email(email)).await?;
+
+        result.as_ref().map(get_user).transpose()
+    }
+
+    /// The user, and whether it still has to choose its password
+    pub async fn find_info_by_email(&self, email: &Email) -> Result<Option<UserInfo>> {
+        let result = self.col.find_one(query_by_email(email)).await?;
+
+        result.as_ref().map(get_user_info).transpose()
+    }
+
+    /// Create a user with an unknown password, it should redeem an invitation to choose one
+    pub async fn new_user(&self, user: User) -> Result<()> {
         let generated = passphrase();
-        let new_password = Password::new(generated.as_bytes());
+        let new_password = Password::hash_blocking(generated.as_bytes()).await?;
         let need_change_password = true;
         let user_doc = UserDocument {
             user,
@@ -94,15 +208,30 @@ impl UserRepository {
         };
         let bson = bson::to_bson(&user_doc)?;
         let doc = bson.as_document().cloned().unwrap();
-        self.col.insert_one(doc, None).await?;
-        Ok(generated)
+        self.col.insert_one(doc).await?;
+        Ok(())
+    }
+
+    /// Replace the kind, the key or the roles of the user, keeping its e-mail and its password
+    pub async fn update_user(&self, email: &Email, user: User) -> Result<Option<User>> {
+        if user.email().as_ref() != Some(email) {
+            return Err(anyhow!("Cannot change the e-mail of {:?}", email));
+        }
+        let filter = query_by_email(email);
+        let update = doc! {"$set": {"user": bson::to_bson(&user)?}};
+        let result = self.col.update_one(filter, update, false).await?;
+        if result.matched == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(user))
     }
 
     pub async fn delete_user(&self, email: &Email) -> Result<i64> {
         let query = query_by_email(email);
-        let result = self.col.delete_one(query, None).await?;
+        let result = self.col.delete_one(query).await?;
 
-        Ok(result.deleted_count)
+        Ok(result.map(|_| 1).unwrap_or_default())
     }
 
     pub async fn change_password(
@@ -110,26 +239,39 @@ impl UserRepository {
         email: &Email,
         old_password: &[u8],
         new_password: &[u8],
+        policy: &PasswordPolicy,
     ) -> Result<User> {
         // Check old password
         self.authenticate(email, old_password).await?;
 
+        self.set_password(email, new_password, policy).await
+    }
+
+    /// Set the password without checking the old one, e.g. after redeeming a password token
+    pub async fn set_password(
+        &self,
+        email: &Email,
+        new_password: &[u8],
+        policy: &PasswordPolicy,
+    ) -> Result<User> {
+        policy.check(new_password)?;
         let filter = query_by_email(email);
+        if let Some(doc) = self.col.find_one(filter.clone()).await? {
+            if get_password(&doc)?.verify_blocking(new_password).await? {
+                let message = "Expected a password different from the current one".into();
+                return Err(PasswordRejected(message).into());
+            }
+        }
 
-        let new_password = Password::new(new_password);
+        let new_password = Password::hash_blocking(new_password).await?;
         let update: Document = doc! {
             "$set": {
                 "password" : new_password.0,
                 "need_change_password" : false,
             }
         };
-        let options = FindOneAndUpdateOptions::builder()
-            .projection(Some(user_projection()))
-            .build();
-        let result = self
-            .col
-            .find_one_and_update(filter, update, Some(options))
-            .await?;
+        self.col.update_one(filter.clone(), update, false).await?;
+        let result = self.col.find_one(filter).await?;
 
         match result {
             None => Err(anyhow!("No user found this e-mail or old password")),
@@ -143,13 +285,6 @@ fn query_by_email(email: &Email) -> Document {
     doc! { "user.email": email }
 }
 
-fn user_projection() -> Document {
-    doc! {
-        "_id": 0,
-        "user": 1,
-    }
-}
-
 fn get_user(doc: &Document) -> Result<User> {
     let doc = doc.get_document("user")?;
     let user = from_document::<User>(doc.clone())?;
@@ -195,4 +330,122 @@ mod tests {
             assert_eq!(result, false)
         }
     }
+
+    mod repository {
+        use crate::indexes::Conflict;
+        use crate::storage::MemoryStorage;
+
+        use super::*;
+
+        fn admin() -> User {
+            let email = "admin@devfest.fr".parse().unwrap();
+            User::Admin { email }
+        }
+
+        #[tokio::test]
+        async fn should_change_password() {
+            let repo = UserRepository::build(&MemoryStorage::new("test"))
+                .await
+                .unwrap();
+            repo.ensure_indexes().await.unwrap();
+            let email = "admin@devfest.fr".parse().unwrap();
+            let policy = PasswordPolicy::default();
+
+            repo.new_user(admin()).await.unwrap();
+            assert_eq!(repo.find_by_email(&email).await.unwrap(), Some(admin()));
+
+            let user = repo
+                .set_password(&email, b"f1rst-passw0rd", &policy)
+                .await
+                .unwrap();
+            assert_eq!(user, admin());
+            let info = repo.authenticate(&email, b"f1rst-passw0rd").await.unwrap();
+            assert!(!info.need_change_password());
+
+            let user = repo
+                .change_password(&email, b"f1rst-passw0rd", b"s3cr3t-passw0rd", &policy)
+                .await
+                .unwrap();
+            assert_eq!(user, admin());
+            assert!(repo.authenticate(&email, b"s3cr3t-passw0rd").await.is_ok());
+            assert!(repo.authenticate(&email, b"f1rst-passw0rd").await.is_err());
+            let unknown = "other@devfest.fr".parse().unwrap();
+            assert!(repo
+                .authenticate(&unknown, b"s3cr3t-passw0rd")
+                .await
+                .is_err());
+            assert!(repo
+                .change_password(&email, b"f1rst-passw0rd", b"other-passw0rd", &policy)
+                .await
+                .is_err());
+        }
+
+        #[tokio::test]
+        async fn should_reject_password_by_policy() {
+            let repo = UserRepository::build(&MemoryStorage::new("test"))
+                .await
+                .unwrap();
+            let email = "admin@devfest.fr".parse().unwrap();
+            let policy = PasswordPolicy::default();
+            repo.new_user(admin()).await.unwrap();
+
+            let err = repo.set_password(&email, b"", &policy).await.unwrap_err();
+            assert!(err.downcast_ref::<PasswordRejected>().is_some());
+            let err = repo
+                .set_password(&email, b"short", &policy)
+                .await
+                .unwrap_err();
+            assert!(err.downcast_ref::<PasswordRejected>().is_some());
+
+            repo.set_password(&email, b"s3cr3t-passw0rd", &policy)
+                .await
+                .unwrap();
+            let err = repo
+                .change_password(&email, b"s3cr3t-passw0rd", b"s3cr3t-passw0rd", &policy)
+                .await
+                .unwrap_err();
+            assert!(err.downcast_ref::<PasswordRejected>().is_some());
+        }
+
+        #[tokio::test]
+        async fn should_update_user() {
+            let repo = UserRepository::build(&MemoryStorage::new("test"))
+                .await
+                .unwrap();
+            let email: Email = "admin@devfest.fr".parse().unwrap();
+            let policy = PasswordPolicy::default();
+            repo.new_user(admin()).await.unwrap();
+            repo.set_password(&email, b"s3cr3t-passw0rd", &policy)
+                .await
+                .unwrap();
+
+            let team = User::Team {
+                email: email.clone(),
+                roles: vec![],
+            };
+            let result = repo.update_user(&email, team.clone()).await.unwrap();
+            assert_eq!(result, Some(team.clone()));
+            let info = repo.authenticate(&email, b"s3cr3t-passw0rd").await.unwrap();
+            assert_eq!(info.user(), team);
+
+            let other = "other@devfest.fr".parse().unwrap();
+            assert!(repo.update_user(&other, team).await.is_err());
+            let unknown = User::Admin {
+                email: other.clone(),
+            };
+            assert_eq!(repo.update_user(&other, unknown).await.unwrap(), None);
+        }
+
+        #[tokio::test]
+        async fn should_reject_duplicated_email() {
+            let repo = UserRepository::build(&MemoryStorage::new("test"))
+                .await
+                .unwrap();
+            repo.ensure_indexes().await.unwrap();
+            repo.new_user(admin()).await.unwrap();
+
+            let err = repo.new_user(admin()).await.unwrap_err();
+            assert!(err.downcast_ref::<Conflict>().is_some());
+        }
+    }
 }
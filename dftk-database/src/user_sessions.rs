@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb::bson::doc;
+use serde::{Deserialize, Serialize};
+
+use dftk_common::acl::user::Email;
+
+use crate::indexes::IndexDefinition;
+use crate::storage::{DocumentStore, Storage};
+use crate::{from_document, from_documents, to_document};
+
+/// A signed-in session, identified by the id of its token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserSession {
+    #[serde(rename = "_id")]
+    id: String,
+    email: String,
+    issued_at: i64,
+    expires_at: i64,
+}
+
+impl UserSession {
+    pub fn new(
+        id: String,
+        email: &Email,
+        issued_at: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id,
+            email: email.clone().into(),
+            issued_at: issued_at.timestamp(),
+            expires_at: expires_at.timestamp(),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+    pub fn email(&self) -> String {
+        self.email.clone()
+    }
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.issued_at, 0)
+    }
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.expires_at, 0)
+    }
+}
+
+/// The active sessions of the users, a token is only accepted while its session exists
+#[derive(Clone)]
+pub struct UserSessionRepository {
+    col: Arc<dyn DocumentStore>,
+}
+
+impl UserSessionRepository {
+    pub fn new(storage: &dyn Storage) -> Self {
+        let col = storage.collection("user_sessions");
+
+        Self { col }
+    }
+
+    pub async fn ensure_indexes(&self) -> Result<()> {
+        let by_email = IndexDefinition::new("email", doc! {"email": 1});
+        self.col.ensure_indexes(&[by_email]).await
+    }
+
+    /// Record a new session, and forget the expired ones
+    pub async fn create(&self, session: &UserSession) -> Result<()> {
+        let now = Utc::now().timestamp();
+        self.col
+            .delete_many(doc! {"expires_at": {"$lte": now}})
+            .await?;
+        self.col.insert_one(to_document(session)?).await
+    }
+
+    /// Check the session exists and is not expired
+    pub async fn is_active(&self, id: &str) -> Result<bool> {
+        let now = Utc::now().timestamp();
+        let filter = doc! {"_id": id, "expires_at": {"$gt": now}};
+        let result = self.col.find_one(filter).await?;
+
+        Ok(result.is_some())
+    }
+
+    /// The active sessions of a user
+    pub async fn find_by_email(&self, email: &Email) -> Result<Vec<UserSession>> {
+        let email: String = email.clone().into();
+        let now = Utc::now().timestamp();
+        let filter = doc! {"email": email, "expires_at": {"$gt": now}};
+        let docs = self.col.find(filter, Some(doc! {"issued_at": 1})).await?;
+
+        from_documents(docs)
+    }
+
+    pub async fn revoke(&self, id: &str) -> Result<Option<UserSession>> {
+        let result = self.col.delete_one(doc! {"_id": id}).await?;
+
+        result.map(from_document).transpose()
+    }
+
+    /// Revoke every session of a user, returning the count of revoked sessions
+    pub async fn revoke_all(&self, email: &Email) -> Result<u64> {
+        let email: String = email.clone().into();
+
+        self.col.delete_many(doc! {"email": email}).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::Duration;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn should_revoke_sessions() {
+        let repo = UserSessionRepository::new(&MemoryStorage::new("test"));
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        let now = Utc::now();
+        let expired = UserSession::new("t0".into(), &email, now - Duration::days(2), now);
+        repo.create(&expired).await.unwrap();
+        for id in &["t1", "t2"] {
+            let session = UserSession::new(id.to_string(), &email, now, now + Duration::hours(1));
+            repo.create(&session).await.unwrap();
+        }
+
+        assert!(!repo.is_active("t0").await.unwrap());
+        assert!(repo.is_active("t1").await.unwrap());
+        assert_eq!(repo.find_by_email(&email).await.unwrap().len(), 2);
+
+        assert!(repo.revoke("t1").await.unwrap().is_some());
+        assert!(!repo.is_active("t1").await.unwrap());
+        assert_eq!(repo.revoke_all(&email).await.unwrap(), 1);
+        assert!(!repo.is_active("t2").await.unwrap());
+    }
+}
@@ -0,0 +1,538 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use uuid::Uuid;
+
+use dftk_common::models::schedule::{ScheduleDay, ScheduleRoom, Slot};
+use dftk_common::models::session::Session;
+use dftk_common::models::site::Site;
+
+use crate::frab::xml_writer::write_schedule;
+
+mod xml_writer;
+
+///
+/// Export of the schedule with the frab format (also used by pretalx and the C3VOC tooling)
+///
+/// See <https://github.com/voc/schedule/tree/master/validator>
+///
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrabFormat {
+    Xml,
+    Json,
+}
+
+impl FromStr for FrabFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "xml" => Ok(FrabFormat::Xml),
+            "json" => Ok(FrabFormat::Json),
+            _ => Err(anyhow!(
+                "Unknown schedule format '{}', expected xml or json",
+                s
+            )),
+        }
+    }
+}
+
+impl FrabFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FrabFormat::Xml => "xml",
+            FrabFormat::Json => "json",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            FrabFormat::Xml => "application/xml",
+            FrabFormat::Json => "application/json",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FrabSchedule {
+    version: String,
+    base_url: Option<String>,
+    conference: FrabConference,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FrabConference {
+    acronym: String,
+    title: String,
+    start: String,
+    end: String,
+    #[serde(rename = "daysCount")]
+    days_count: usize,
+    timeslot_duration: String,
+    days: Vec<FrabDay>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrabDay {
+    index: usize,
+    date: String,
+    day_start: String,
+    day_end: String,
+    rooms: Vec<FrabRoom>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FrabRoom {
+    name: String,
+    events: Vec<FrabEvent>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FrabEvent {
+    id: usize,
+    guid: Uuid,
+    date: String,
+    start: String,
+    duration: String,
+    room: String,
+    slug: String,
+    url: Option<String>,
+    title: String,
+    subtitle: String,
+    track: String,
+    #[serde(rename = "type")]
+    event_type: String,
+    language: String,
+    #[serde(rename = "abstract")]
+    event_abstract: String,
+    description: String,
+    persons: Vec<FrabPerson>,
+    links: Vec<String>,
+    attachments: Vec<String>,
+    #[serde(skip)]
+    end: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FrabPerson {
+    id: usize,
+    public_name: String,
+}
+
+impl Serialize for FrabDay {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // rooms are a JSON object keyed by the room name, keep the rooms order
+        let rooms = FrabRooms(self.rooms.as_slice());
+        let mut map = serializer.serialize_map(Some(5))?;
+        map.serialize_entry("index", &self.index)?;
+        map.serialize_entry("date", &self.date)?;
+        map.serialize_entry("day_start", &self.day_start)?;
+        map.serialize_entry("day_end", &self.day_end)?;
+        map.serialize_entry("rooms", &rooms)?;
+        map.end()
+    }
+}
+
+struct FrabRooms<'a>(&'a [FrabRoom]);
+
+impl<'a> Serialize for FrabRooms<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|room| (&room.name, &room.events)))
+    }
+}
+
+impl FrabSchedule {
+    pub fn new(site: &Site, version: String) -> Self {
+        let info = site.info();
+        let acronym: String = info.id().into();
+        let ids = FrabIds::new(site);
+        let offset = info.local_offset();
+        let days = site
+            .schedule()
+            .iter()
+            .enumerate()
+            .map(|(index, day)| FrabDay::new(index + 1, day, site, &ids))
+            .collect::<Vec<_>>();
+
+        let conference = FrabConference {
+            acronym,
+            title: info.name(),
+            start: local(info.dates().start(), &offset)
+                .format("%Y-%m-%d")
+                .to_string(),
+            end: local(info.dates().end(), &offset)
+                .format("%Y-%m-%d")
+                .to_string(),
+            days_count: days.len(),
+            timeslot_duration: format_duration(timeslot_duration(site.slots())),
+            days,
+        };
+
+        Self {
+            version,
+            base_url: None,
+            conference,
+        }
+    }
+
+    pub fn conference(&self) -> &FrabConference {
+        &self.conference
+    }
+
+    pub fn to_xml(&self) -> Result<String> {
+        write_schedule(self)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Wrapper<'a> {
+            schedule: &'a FrabSchedule,
+        }
+        let result = serde_json::to_string_pretty(&Wrapper { schedule: self })?;
+
+        Ok(result)
+    }
+
+    pub fn export(&self, format: FrabFormat) -> Result<String> {
+        match format {
+            FrabFormat::Xml => self.to_xml(),
+            FrabFormat::Json => self.to_json(),
+        }
+    }
+}
+
+impl FrabConference {
+    pub fn days(&self) -> &[FrabDay] {
+        self.days.as_slice()
+    }
+}
+
+impl FrabDay {
+    fn new(index: usize, day: &ScheduleDay, site: &Site, ids: &FrabIds) -> Self {
+        let rooms = day
+            .rooms()
+            .iter()
+            .map(|room| FrabRoom::new(room, site, ids))
+            .collect::<Vec<_>>();
+
+        let day_end = rooms
+            .iter()
+            .flat_map(|room| room.events.iter())
+            .map(|event| event.end)
+            .max()
+            .unwrap_or_else(|| day.start());
+        let offset = ids.offset;
+
+        Self {
+            index,
+            date: local(day.start(), &offset).format("%Y-%m-%d").to_string(),
+            day_start: local(day.start(), &offset).to_rfc3339(),
+            day_end: local(day_end, &offset).to_rfc3339(),
+            rooms,
+        }
+    }
+
+    pub fn rooms(&self) -> &[FrabRoom] {
+        self.rooms.as_slice()
+    }
+}
+
+impl FrabRoom {
+    fn new(schedule_room: &ScheduleRoom, site: &Site, ids: &FrabIds) -> Self {
+        let key = schedule_room.room();
+        let name = site
+            .rooms()
+            .iter()
+            .find(|it| it.key() == key)
+            .map(|it| it.label())
+            .unwrap_or_else(|| key.clone().into());
+
+        let mut scheduled = vec![];
+        for room_slot in schedule_room.slots() {
+            let slot = site.slots().iter().find(|it| it.key() == room_slot.slot());
+            let session = site
+                .sessions()
+                .iter()
+                .find(|it| it.key() == room_slot.session());
+            match (slot, session) {
+                (Some(slot), Some(session)) => scheduled.push((slot, session)),
+                _ => warn!("Skip the unresolved schedule entry {:?}", room_slot),
+            }
+        }
+        scheduled.sort_by_key(|(slot, _)| slot.start());
+
+        let events = scheduled
+            .into_iter()
+            .map(|(slot, session)| FrabEvent::new(slot, session, name.as_str(), site, ids))
+            .collect();
+
+        Self { name, events }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn events(&self) -> &[FrabEvent] {
+        self.events.as_slice()
+    }
+}
+
+impl FrabEvent {
+    fn new(slot: &Slot, session: &Session, room: &str, site: &Site, ids: &FrabIds) -> Self {
+        let key: String = session.key().into();
+        let track = site
+            .categories()
+            .iter()
+            .find(|it| it.key() == session.category())
+            .map(|it| it.name())
+            .unwrap_or_else(|| session.category().into());
+        let event_type = site
+            .formats()
+            .iter()
+            .find(|it| it.key() == session.format())
+            .map(|it| it.name())
+            .unwrap_or_else(|| session.format().into());
+        let persons = session
+            .speakers()
+            .iter()
+            .filter_map(|speaker_key| {
+                site.speakers()
+                    .iter()
+                    .find(|it| &it.key() == speaker_key)
+                    .map(|speaker| {
+                        let id: String = speaker.id().into();
+                        FrabPerson {
+                            id: ids.speaker(&id),
+                            public_name: speaker.name(),
+                        }
+                    })
+            })
+            .collect();
+
+        let id: String = session.id().into();
+        let start = local(slot.start(), &ids.offset);
+
+        Self {
+            id: ids.session(&id),
+            guid: ids.guid(session),
+            date: start.to_rfc3339(),
+            start: start.format("%H:%M").to_string(),
+            duration: format_duration(slot.duration().into()),
+            room: room.into(),
+            slug: key,
+            url: None,
+            title: session.title(),
+            subtitle: String::new(),
+            track,
+            event_type,
+            language: session.language().code(),
+            event_abstract: session.description().into(),
+            description: String::new(),
+            persons,
+            links: vec![],
+            attachments: vec![],
+            end: slot.start() + Duration::minutes(slot.duration().into()),
+        }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    pub fn guid(&self) -> Uuid {
+        self.guid
+    }
+    pub fn title(&self) -> String {
+        self.title.clone()
+    }
+    pub fn duration(&self) -> String {
+        self.duration.clone()
+    }
+    pub fn persons(&self) -> &[FrabPerson] {
+        self.persons.as_slice()
+    }
+}
+
+/// Frab expect numeric ids for events and persons, and a stable GUID for events
+///
+/// The numeric ids are derived from the ids of the sessions and of the speakers,
+/// so they do not change between two exports when a session or a speaker is added or removed.
+struct FrabIds {
+    event_id: String,
+    offset: FixedOffset,
+}
+
+impl FrabIds {
+    fn new(site: &Site) -> Self {
+        let info = site.info();
+        let event_id = info.id().into();
+        let offset = info.local_offset();
+
+        Self { event_id, offset }
+    }
+
+    fn session(&self, id: &str) -> usize {
+        numeric_id("session", id)
+    }
+
+    fn speaker(&self, id: &str) -> usize {
+        numeric_id("speaker", id)
+    }
+
+    fn guid(&self, session: &Session) -> Uuid {
+        let id: String = session.id().into();
+        let name = format!("{}/{}", self.event_id, id);
+
+        Uuid::new_v5(&Uuid::NAMESPACE_URL, name.as_bytes())
+    }
+}
+
+/// A positive 31 bits number hashed from the id, to fit the integer of any consumer
+fn numeric_id(kind: &str, id: &str) -> usize {
+    let name = format!("{}/{}", kind, id);
+    let hash = Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes());
+    let bytes = hash.as_bytes();
+    let value = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) & 0x7fff_ffff;
+
+    value as usize
+}
+
+fn local(date: DateTime<Utc>, offset: &FixedOffset) -> DateTime<FixedOffset> {
+    date.with_timezone(offset)
+}
+
+/// The greatest common divisor of slot durations, in minutes
+fn timeslot_duration(slots: &[Slot]) -> i64 {
+    fn gcd(a: i64, b: i64) -> i64 {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+
+    let result = slots
+        .iter()
+        .map(|it| i64::from(it.duration().minutes()))
+        .fold(0, gcd);
+
+    if result == 0 {
+        5
+    } else {
+        result
+    }
+}
+
+fn format_duration(minutes: i64) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+pub fn frab_schedule(site: &Site) -> FrabSchedule {
+    let version = Utc::now().format("%Y-%m-%d %H:%M").to_string();
+
+    FrabSchedule::new(site, version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> Site {
+        let json = include_str!("../../tests/resources/frab_site.json");
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn site_event(site: &Site) -> FrabEvent {
+        let schedule = FrabSchedule::new(site, "1".into());
+
+        schedule.conference().days()[0].rooms()[0].events()[0].clone()
+    }
+
+    #[test]
+    fn should_parse_format() {
+        assert_eq!(FrabFormat::from_str("XML").unwrap(), FrabFormat::Xml);
+        assert_eq!(FrabFormat::from_str("json").unwrap(), FrabFormat::Json);
+        assert!(FrabFormat::from_str("yaml").is_err());
+    }
+
+    #[test]
+    fn should_format_duration() {
+        assert_eq!(format_duration(45), "00:45");
+        assert_eq!(format_duration(480), "08:00");
+    }
+
+    #[test]
+    fn should_build_events() {
+        let schedule = FrabSchedule::new(&site(), "1".into());
+        let day = schedule.conference().days().first().cloned().unwrap();
+        assert_eq!(day.date, "2020-10-15");
+        assert_eq!(day.day_end, "2020-10-15T11:45:00+02:00");
+
+        let room = day.rooms().first().cloned().unwrap();
+        assert_eq!(room.name(), "Amphi A");
+
+        let event = room.events().first().cloned().unwrap();
+        assert_eq!(event.id(), numeric_id("session", "s1"));
+        assert_eq!(event.date, "2020-10-15T11:00:00+02:00");
+        assert_eq!(event.start, "11:00");
+        assert_eq!(event.duration(), "00:45");
+        assert_eq!(event.persons().len(), 1);
+    }
+
+    #[test]
+    fn should_have_stable_guid() {
+        let first = FrabSchedule::new(&site(), "1".into());
+        let second = FrabSchedule::new(&site(), "2".into());
+        let guid = |s: &FrabSchedule| s.conference().days()[0].rooms()[0].events()[0].guid();
+        assert_eq!(guid(&first), guid(&second));
+    }
+
+    #[test]
+    fn should_have_stable_ids() {
+        let id = |site: &Site| site_event(site).id();
+        let person = |site: &Site| site_event(site).persons()[0].id;
+        let mut value: serde_json::Value =
+            serde_json::from_str(include_str!("../../tests/resources/frab_site.json")).unwrap();
+        let first = site();
+
+        let mut session = value["sessions"][0].clone();
+        session["id"] = "s0".into();
+        session["key"] = "an-earlier-session".into();
+        value["sessions"].as_array_mut().unwrap().insert(0, session);
+        let mut speaker = value["speakers"][0].clone();
+        speaker["_id"] = "sp0".into();
+        speaker["key"] = "john-doe".into();
+        value["speakers"].as_array_mut().unwrap().insert(0, speaker);
+        let second: Site = serde_json::from_value(value).unwrap();
+
+        assert_eq!(id(&first), id(&second));
+        assert_eq!(person(&first), person(&second));
+        assert_ne!(numeric_id("session", "s0"), numeric_id("session", "s1"));
+    }
+
+    #[test]
+    fn should_write_escaped_xml() {
+        let xml = FrabSchedule::new(&site(), "1".into()).to_xml().unwrap();
+        assert!(xml.contains("<title>Rust for the &lt;win&gt;</title>"));
+        assert!(xml.contains(r#"<room name="Amphi A">"#));
+        assert!(xml.contains("<track>Languages</track>"));
+    }
+
+    #[test]
+    fn should_write_json_rooms_by_name() {
+        let json = FrabSchedule::new(&site(), "1".into()).to_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(json.as_str()).unwrap();
+        let events = &value["schedule"]["conference"]["days"][0]["rooms"]["Amphi A"];
+        assert_eq!(events[0]["type"], "Talk");
+        assert_eq!(events[0]["persons"][0]["public_name"], "Jane Doe");
+    }
+}
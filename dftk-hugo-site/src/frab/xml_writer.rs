@@ -0,0 +1,133 @@
+use std::io::{Cursor, Write};
+
+use anyhow::Result;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
+
+use crate::frab::{FrabConference, FrabDay, FrabEvent, FrabRoom, FrabSchedule};
+
+type XmlWriter = Writer<Cursor<Vec<u8>>>;
+
+pub(crate) fn write_schedule(schedule: &FrabSchedule) -> Result<String> {
+    let mut writer = Writer::new_with_indent(Cursor::new(vec![]), b' ', 2);
+    writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+
+    start(&mut writer, BytesStart::borrowed_name(b"schedule"))?;
+    text(&mut writer, "version", schedule.version.as_str())?;
+    if let Some(base_url) = &schedule.base_url {
+        text(&mut writer, "base_url", base_url.as_str())?;
+    }
+    write_conference(&mut writer, &schedule.conference)?;
+    for day in schedule.conference.days.iter() {
+        write_day(&mut writer, day)?;
+    }
+    end(&mut writer, "schedule")?;
+
+    let mut bytes = writer.into_inner().into_inner();
+    bytes.write_all(b"\n")?;
+    let result = String::from_utf8(bytes)?;
+
+    Ok(result)
+}
+
+fn write_conference(writer: &mut XmlWriter, conference: &FrabConference) -> Result<()> {
+    start(writer, BytesStart::borrowed_name(b"conference"))?;
+    text(writer, "acronym", conference.acronym.as_str())?;
+    text(writer, "title", conference.title.as_str())?;
+    text(writer, "start", conference.start.as_str())?;
+    text(writer, "end", conference.end.as_str())?;
+    text(writer, "days", conference.days_count.to_string().as_str())?;
+    text(
+        writer,
+        "timeslot_duration",
+        conference.timeslot_duration.as_str(),
+    )?;
+    end(writer, "conference")
+}
+
+fn write_day(writer: &mut XmlWriter, day: &FrabDay) -> Result<()> {
+    let index = day.index.to_string();
+    let element = BytesStart::borrowed_name(b"day").with_attributes(vec![
+        ("index", index.as_str()),
+        ("date", day.date.as_str()),
+        ("start", day.day_start.as_str()),
+        ("end", day.day_end.as_str()),
+    ]);
+    start(writer, element)?;
+    for room in day.rooms.iter() {
+        write_room(writer, room)?;
+    }
+    end(writer, "day")
+}
+
+fn write_room(writer: &mut XmlWriter, room: &FrabRoom) -> Result<()> {
+    let element =
+        BytesStart::borrowed_name(b"room").with_attributes(vec![("name", room.name.as_str())]);
+    start(writer, element)?;
+    for event in room.events.iter() {
+        write_event(writer, event)?;
+    }
+    end(writer, "room")
+}
+
+fn write_event(writer: &mut XmlWriter, event: &FrabEvent) -> Result<()> {
+    let id = event.id.to_string();
+    let guid = event.guid.to_string();
+    let element = BytesStart::borrowed_name(b"event")
+        .with_attributes(vec![("id", id.as_str()), ("guid", guid.as_str())]);
+    start(writer, element)?;
+    text(writer, "date", event.date.as_str())?;
+    text(writer, "start", event.start.as_str())?;
+    text(writer, "duration", event.duration.as_str())?;
+    text(writer, "room", event.room.as_str())?;
+    text(writer, "slug", event.slug.as_str())?;
+    text(writer, "url", event.url.as_deref().unwrap_or_default())?;
+    text(writer, "title", event.title.as_str())?;
+    text(writer, "subtitle", event.subtitle.as_str())?;
+    text(writer, "track", event.track.as_str())?;
+    text(writer, "type", event.event_type.as_str())?;
+    text(writer, "language", event.language.as_str())?;
+    text(writer, "abstract", event.event_abstract.as_str())?;
+    text(writer, "description", event.description.as_str())?;
+
+    start(writer, BytesStart::borrowed_name(b"persons"))?;
+    for person in event.persons.iter() {
+        let id = person.id.to_string();
+        let element =
+            BytesStart::borrowed_name(b"person").with_attributes(vec![("id", id.as_str())]);
+        writer.write_event(Event::Start(element))?;
+        writer.write_event(Event::Text(BytesText::from_plain_str(
+            person.public_name.as_str(),
+        )))?;
+        end(writer, "person")?;
+    }
+    end(writer, "persons")?;
+
+    writer.write_event(Event::Empty(BytesStart::borrowed_name(b"links")))?;
+    writer.write_event(Event::Empty(BytesStart::borrowed_name(b"attachments")))?;
+    end(writer, "event")
+}
+
+fn start(writer: &mut XmlWriter, element: BytesStart) -> Result<()> {
+    writer.write_event(Event::Start(element))?;
+
+    Ok(())
+}
+
+fn end(writer: &mut XmlWriter, name: &str) -> Result<()> {
+    writer.write_event(Event::End(BytesEnd::borrowed(name.as_bytes())))?;
+
+    Ok(())
+}
+
+fn text(writer: &mut XmlWriter, name: &str, value: &str) -> Result<()> {
+    if value.is_empty() {
+        writer.write_event(Event::Empty(BytesStart::borrowed_name(name.as_bytes())))?;
+    } else {
+        writer.write_event(Event::Start(BytesStart::borrowed_name(name.as_bytes())))?;
+        writer.write_event(Event::Text(BytesText::from_plain_str(value)))?;
+        end(writer, name)?;
+    }
+
+    Ok(())
+}
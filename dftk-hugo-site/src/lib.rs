@@ -12,6 +12,7 @@ use crate::markdown_writer::FrontMatterMarkdownWriter;
 use crate::site_writer::{GenerateResult, SiteWriter};
 
 pub mod data_writer;
+pub mod frab;
 pub mod markdown_writer;
 pub mod models;
 pub mod site_writer;
@@ -88,6 +89,8 @@ pub async fn clean_site_dir(site_dir: PathBuf) -> Result<()> {
         "data/schedule.yml",
         "data/slots.yml",
         "static/site.json",
+        "static/schedule.xml",
+        "static/schedule.json",
     ];
 
     for g in globs {
@@ -1,4 +1,5 @@
 mod session;
+pub(crate) mod slot;
 mod speaker;
 mod sponsor;
 mod team;
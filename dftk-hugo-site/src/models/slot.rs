@@ -0,0 +1,41 @@
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use dftk_common::models::schedule::{Slot, SlotKey};
+use dftk_common::models::site::Site;
+
+/// A slot as expected by the `data/slots.yml` consumers, with its grid row
+#[derive(Serialize, Debug, Clone)]
+pub(crate) struct SlotData {
+    key: SlotKey,
+    start: DateTime<Utc>,
+    duration: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row: Option<Range<u32>>,
+}
+
+impl SlotData {
+    fn new(slot: &Slot, site: &Site) -> Self {
+        let row = site
+            .slot_layouts()
+            .iter()
+            .find(|it| it.slot() == slot.key())
+            .map(|it| it.row());
+
+        Self {
+            key: slot.key(),
+            start: slot.start(),
+            duration: slot.duration().minutes(),
+            row,
+        }
+    }
+}
+
+pub(crate) fn slots_data(site: &Site) -> Vec<SlotData> {
+    site.slots()
+        .iter()
+        .map(|it| SlotData::new(it, site))
+        .collect()
+}
@@ -1,4 +1,5 @@
 use std::fs::{create_dir_all, File};
+use std::io::Write;
 
 use anyhow::Result;
 use serde::Serialize;
@@ -6,7 +7,9 @@ use serde::Serialize;
 use dftk_common::models::site::Site;
 
 use crate::data_writer::DataWriter;
+use crate::frab::{frab_schedule, FrabFormat};
 use crate::markdown_writer::FrontMatterMarkdownWriter;
+use crate::models::slot::slots_data;
 use crate::{
     new_session_writer, new_speaker_writer, new_sponsor_writer, new_team_writer, SiteConfig,
 };
@@ -91,6 +94,25 @@ impl SiteWriter {
         Ok(())
     }
 
+    async fn write_frab_schedule(&self, site: &Site) -> Result<()> {
+        let mut static_path = self.config.site_dir.clone();
+        static_path.push("static");
+        create_dir_all(static_path.clone())?;
+
+        let schedule = frab_schedule(site);
+        for format in [FrabFormat::Xml, FrabFormat::Json].iter() {
+            let mut file_path = static_path.clone();
+            file_path.push(format!("schedule.{}", format.extension()));
+            info!("Write the schedule to {:?}", file_path);
+
+            let content = schedule.export(*format)?;
+            let mut file = File::create(file_path)?;
+            file.write_all(content.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
     pub async fn write_site(&self, site: &Site) -> Result<GenerateResult> {
         info!("Write site");
 
@@ -108,11 +130,16 @@ impl SiteWriter {
         // Write schedule data/
         self.schedule_data_writer.write_all(site.schedule()).await?;
         self.room_data_writer.write_all(site.rooms()).await?;
-        self.slot_data_writer.write_all(site.slots()).await?;
+        self.slot_data_writer
+            .write_all(slots_data(site).as_slice())
+            .await?;
 
         // Write json
         self.write_site_json(site).await?;
 
+        // Write frab schedule (xml & json)
+        self.write_frab_schedule(site).await?;
+
         // FIXME write iCal
 
         let result = GenerateResult {
@@ -0,0 +1,132 @@
+//! A stand-in OpenID Connect provider, to try the login locally
+//!
+//! Every authorization is approved, for the `login_hint` e-mail or the `OIDC_EMAIL` one.
+//!
+//! ```shell
+//! cargo run -p dftk-server --example oidc_provider
+//! OIDC_ISSUER=http://localhost:9090 OIDC_CLIENT_ID=dftk OIDC_CLIENT_SECRET=secret cargo run -- serve
+//! ```
+//!
+//! Then open `http://localhost:8080/auth/oidc/login`
+
+use std::collections::HashMap;
+use std::env;
+
+use chrono::Utc;
+use reqwest::Url;
+use serde_json::{json, Value};
+use warp::filters::BoxedFilter;
+use warp::http::{Response, StatusCode};
+use warp::{Filter, Reply};
+
+const ISSUER: &str = "http://localhost:9090";
+
+#[tokio::main]
+async fn main() {
+    println!("Stand-in OpenID Connect provider on {}", ISSUER);
+    warp::serve(routes(ISSUER.into()))
+        .run(([127, 0, 0, 1], 9090))
+        .await;
+}
+
+/// The discovery, the authorization and the token endpoints of the issuer
+pub fn routes(issuer: String) -> BoxedFilter<(impl Reply,)> {
+    let token_issuer = issuer.clone();
+    let discovery = warp::path!(".well-known" / "openid-configuration").map(move || {
+        warp::reply::json(&json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "response_types_supported": ["code"],
+            "id_token_signing_alg_values_supported": ["none"],
+        }))
+    });
+
+    let authorize = warp::path("authorize")
+        .and(warp::get())
+        .and(warp::query::<HashMap<String, String>>())
+        .map(authorize);
+
+    let token = warp::path("token")
+        .and(warp::post())
+        .and(warp::body::form::<HashMap<String, String>>())
+        .map(move |form| token(&token_issuer, form));
+
+    discovery.or(authorize).or(token).boxed()
+}
+
+/// Approve, the code carries the claims of the ID token
+fn authorize(query: HashMap<String, String>) -> Response<String> {
+    let email = query
+        .get("login_hint")
+        .cloned()
+        .or_else(|| env::var("OIDC_EMAIL").ok())
+        .unwrap_or_else(|| "admin@devfest.fr".into());
+    let code = json!({
+        "email": email,
+        "nonce": query.get("nonce"),
+        "client_id": query.get("client_id"),
+    });
+    let code = base64::encode_config(code.to_string(), base64::URL_SAFE_NO_PAD);
+
+    let redirect_uri = query.get("redirect_uri").cloned().unwrap_or_default();
+    let state = query.get("state").cloned().unwrap_or_default();
+    let location = Url::parse_with_params(
+        redirect_uri.as_str(),
+        &[("code", code.as_str()), ("state", state.as_str())],
+    );
+    match location {
+        Ok(location) => Response::builder()
+            .status(StatusCode::SEE_OTHER)
+            .header("Location", location.as_str())
+            .body(String::new())
+            .unwrap(),
+        Err(err) => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(format!("Invalid redirect_uri: {}", err))
+            .unwrap(),
+    }
+}
+
+/// An unsigned ID token, the client reads it from the token endpoint
+fn token(issuer: &str, form: HashMap<String, String>) -> Response<String> {
+    let code = form.get("code").cloned().unwrap_or_default();
+    let claims = base64::decode_config(code.as_str(), base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|it| serde_json::from_slice::<Value>(&it).ok());
+    let claims = match claims {
+        Some(claims) => claims,
+        None => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .header("Content-Type", "application/json")
+                .body(json!({"error": "invalid_grant"}).to_string())
+                .unwrap()
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let id_token = json!({
+        "iss": issuer,
+        "sub": claims["email"],
+        "aud": claims["client_id"],
+        "iat": now,
+        "exp": now + 300,
+        "nonce": claims["nonce"],
+        "email": claims["email"],
+        "email_verified": true,
+    });
+    let header = base64::encode_config(r#"{"alg":"none"}"#, base64::URL_SAFE_NO_PAD);
+    let payload = base64::encode_config(id_token.to_string(), base64::URL_SAFE_NO_PAD);
+    let body = json!({
+        "access_token": "stand-in",
+        "token_type": "Bearer",
+        "expires_in": 300,
+        "id_token": format!("{}.{}.", header, payload),
+    });
+
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .unwrap()
+}
@@ -0,0 +1,354 @@
+//! Create the accounts of the speakers and of the sponsors, from a mapping of their keys to e-mails
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use dftk_common::acl::user::{Email, User, UserInfo};
+use dftk_common::models::speaker::SpeakerKey;
+use dftk_common::models::sponsor::SponsorKey;
+use dftk_database::Repositories;
+
+use crate::password::{invite_user, send_invitation};
+use crate::ServerContext;
+
+/// A speaker or a sponsor key, with the e-mail of its account
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AccountEntry {
+    pub key: String,
+    pub email: String,
+}
+
+impl AccountEntry {
+    fn new(key: &str, email: &Email) -> Self {
+        Self {
+            key: key.into(),
+            email: email.clone().into(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct AccountFailure {
+    pub key: String,
+    pub email: String,
+    pub error: String,
+}
+
+/// The outcome of a bulk creation, nothing is created twice
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct AccountReport {
+    /// The invited users
+    pub created: Vec<AccountEntry>,
+    /// The users without password nor pending invitation, invited again
+    pub reinvited: Vec<AccountEntry>,
+    /// The e-mails already used by a user, skipped
+    pub existing: Vec<AccountEntry>,
+    /// The speakers or sponsors without e-mail in the mapping
+    pub without_email: Vec<String>,
+    /// The keys of the mapping not matching an accepted speaker or a sponsor
+    pub unknown_keys: Vec<String>,
+    pub failed: Vec<AccountFailure>,
+}
+
+/// Read a mapping file, a `key,email` line by account
+///
+/// The separator could also be a `;` or a tab, the empty lines and the `#` comments are ignored,
+/// and the first line is a header if its e-mail is invalid.
+pub(crate) fn parse_mapping(content: &str) -> Result<Vec<(String, Email)>> {
+    let mut result = vec![];
+    let lines = content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    for (position, (number, line)) in lines.enumerate() {
+        let mut parts = line.splitn(2, |c| c == ',' || c == ';' || c == '\t');
+        let key = parts.next().unwrap_or_default().trim();
+        let email = parts.next().unwrap_or_default().trim();
+        let email = match Email::from_str(email) {
+            Ok(email) => email,
+            Err(_) if position == 0 => continue,
+            Err(err) => return Err(anyhow!("Invalid line {}: {}", number, err)),
+        };
+        if key.is_empty() {
+            return Err(anyhow!("Invalid line {}: missing the key", number));
+        }
+        result.push((key.to_string(), email));
+    }
+
+    Ok(result)
+}
+
+/// Invite the speakers of the accepted sessions
+pub(crate) async fn create_speaker_accounts(
+    context: &ServerContext,
+    repos: &Repositories,
+    mapping: &[(String, Email)],
+) -> Result<AccountReport> {
+    let keys = repos
+        .accepted_speakers()
+        .await?
+        .iter()
+        .map(|it| it.key().into())
+        .collect();
+
+    create_accounts(context, repos, keys, mapping, |email, key| {
+        let key = SpeakerKey::from_str(key)?;
+        Ok(User::Speaker {
+            email,
+            key,
+            event: None,
+        })
+    })
+    .await
+}
+
+/// Invite the sponsors
+pub(crate) async fn create_sponsor_accounts(
+    context: &ServerContext,
+    repos: &Repositories,
+    mapping: &[(String, Email)],
+) -> Result<AccountReport> {
+    let keys = repos
+        .sponsor()
+        .find()
+        .await?
+        .iter()
+        .map(|it| it.key().into())
+        .collect();
+
+    create_accounts(context, repos, keys, mapping, |email, key| {
+        let key = SponsorKey::from_str(key)?;
+        Ok(User::Sponsor {
+            email,
+            key,
+            event: None,
+        })
+    })
+    .await
+}
+
+async fn create_accounts<F>(
+    context: &ServerContext,
+    repos: &Repositories,
+    keys: Vec<String>,
+    mapping: &[(String, Email)],
+    to_user: F,
+) -> Result<AccountReport>
+where
+    F: Fn(Email, &str) -> Result<User>,
+{
+    let mut report = AccountReport::default();
+    for (key, _) in mapping {
+        if !keys.contains(key) && !report.unknown_keys.contains(key) {
+            report.unknown_keys.push(key.clone());
+        }
+    }
+
+    for key in keys {
+        let emails: Vec<&Email> = mapping
+            .iter()
+            .filter(|(it, _)| it == &key)
+            .map(|(_, email)| email)
+            .collect();
+        if emails.is_empty() {
+            report.without_email.push(key);
+            continue;
+        }
+        for email in emails {
+            let entry = AccountEntry::new(key.as_str(), email);
+            let result = match repos.user().find_info_by_email(email).await? {
+                Some(info) => reinvite_account(context, repos, email, &info).await,
+                None => {
+                    let user = to_user(email.clone(), key.as_str())?;
+                    create_account(context, repos, user).await
+                }
+            };
+            match result {
+                Ok(AccountOutcome::Created) => report.created.push(entry),
+                Ok(AccountOutcome::Reinvited) => report.reinvited.push(entry),
+                Ok(AccountOutcome::Existing) => report.existing.push(entry),
+                Err(err) => {
+                    warn!("Cannot create the account of {:?}: {}", entry, err);
+                    report.failed.push(AccountFailure {
+                        key: entry.key,
+                        email: entry.email,
+                        error: err.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    info!(
+        "Created {} account(s), invited again {}, skipped {} existing",
+        report.created.len(),
+        report.reinvited.len(),
+        report.existing.len()
+    );
+
+    Ok(report)
+}
+
+enum AccountOutcome {
+    Created,
+    Reinvited,
+    Existing,
+}
+
+/// The current user should hold the permissions of the created user
+async fn create_account(
+    context: &ServerContext,
+    repos: &Repositories,
+    user: User,
+) -> Result<AccountOutcome> {
+    repos.check_grant(&user).await?;
+    invite_user(context, repos, user).await?;
+
+    Ok(AccountOutcome::Created)
+}
+
+/// A user without password nor pending invitation is invited again,
+/// e.g. when its invitation was lost or has expired
+async fn reinvite_account(
+    context: &ServerContext,
+    repos: &Repositories,
+    email: &Email,
+    info: &UserInfo,
+) -> Result<AccountOutcome> {
+    if !info.need_change_password() || repos.password_token().is_invited(email).await? {
+        return Ok(AccountOutcome::Existing);
+    }
+    repos.check_grant(&info.user()).await?;
+    send_invitation(context, repos, email).await?;
+
+    Ok(AccountOutcome::Reinvited)
+}
+
+/// Update the user, a speaker or a sponsor is bound to the current event,
+/// its previous sessions are revoked as the session tokens hold the user,
+/// the current user should hold the permissions of the user, before and after the update
+pub(crate) async fn update_user(
+    repos: &Repositories,
+    email: &Email,
+    user: User,
+) -> Result<Option<User>> {
+    let user = user.in_event(repos.event());
+    repos.check_user(&user).await?;
+    repos.check_grant(&user).await?;
+    check_managed_user(repos, email).await?;
+    let result = repos.user().update_user(email, user).await?;
+    if result.is_some() {
+        let revoked = repos.user_session().revoke_all(email).await?;
+        info!("Revoked {} session(s) of {:?}", revoked, email);
+    }
+
+    Ok(result)
+}
+
+/// Check the current user holds the permissions of the existing user,
+/// before deleting it or revoking its sessions
+pub(crate) async fn check_managed_user(repos: &Repositories, email: &Email) -> Result<()> {
+    if let Some(user) = repos.user().find_by_email(email).await? {
+        repos.check_grant(&user).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::tests::{context_with_mails, mail_dir};
+
+    fn mapping() -> Vec<(String, Email)> {
+        vec![("jane".into(), Email::from_str("jane@devfest.fr").unwrap())]
+    }
+
+    async fn create(context: &ServerContext, repos: &Repositories) -> AccountReport {
+        let keys = vec!["jane".into()];
+        create_accounts(context, repos, keys, &mapping(), |email, _| {
+            Ok(User::Admin { email })
+        })
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn should_parse_mapping() {
+        let content = "key;email\n\
+                       jane-doe;jane@devfest.fr\n\
+                       \n\
+                       # a comment\n\
+                       acme,sponsor@acme.com\n";
+        let result = parse_mapping(content).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (
+                    "jane-doe".to_string(),
+                    Email::from_str("jane@devfest.fr").unwrap()
+                ),
+                (
+                    "acme".to_string(),
+                    Email::from_str("sponsor@acme.com").unwrap()
+                ),
+            ]
+        );
+
+        assert!(parse_mapping("jane-doe,jane@devfest.fr\njohn-doe,invalid").is_err());
+        assert!(parse_mapping("jane-doe,jane@devfest.fr\n,john@devfest.fr").is_err());
+    }
+
+    #[tokio::test]
+    async fn should_invite_again_the_users_without_password() {
+        let dir = mail_dir();
+        let context = context_with_mails(dir.clone()).await;
+        let admin = User::Admin {
+            email: Email::from_str("admin@devfest.fr").unwrap(),
+        };
+        let repos = context.repos().as_user(Some(admin));
+        // The invitation was lost
+        let jane = User::Admin {
+            email: Email::from_str("jane@devfest.fr").unwrap(),
+        };
+        repos.user().new_user(jane).await.unwrap();
+
+        let report = create(&context, &repos).await;
+        assert_eq!(report.reinvited.len(), 1);
+        let report = create(&context, &repos).await;
+        assert_eq!(report.existing.len(), 1);
+
+        let mails = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(mails, 1, "Expected a single invitation");
+    }
+
+    #[tokio::test]
+    async fn should_not_grant_more_than_the_current_user() {
+        let context = context_with_mails(PathBuf::from("/dev/null/mails")).await;
+        let manager = User::Team {
+            email: Email::from_str("manager@devfest.fr").unwrap(),
+            roles: vec![],
+        };
+        let repos = context.repos().as_user(Some(manager));
+
+        let report = create(&context, &repos).await;
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].error.contains("Not allowed"));
+        let jane = Email::from_str("jane@devfest.fr").unwrap();
+        assert_eq!(repos.user().find_by_email(&jane).await.unwrap(), None);
+
+        repos
+            .user()
+            .new_user(User::Admin { email: jane })
+            .await
+            .unwrap();
+        let report = create(&context, &repos).await;
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].error.contains("Not allowed"));
+    }
+}
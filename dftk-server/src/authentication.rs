@@ -1,24 +1,37 @@
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 
+use chrono::{DateTime, Utc};
 use cookie::{Cookie, SameSite};
+use serde::Serialize;
 use time::Duration;
 use warp::filters::BoxedFilter;
-use warp::http::Response;
-use warp::{Filter, Reply};
+use warp::http::{Response, StatusCode};
+use warp::{Filter, Rejection, Reply};
 
-use dftk_common::acl::user::Email;
+use dftk_common::acl::operation::Operation;
+use dftk_common::acl::user::{Email, User};
+use dftk_database::api_tokens::is_api_token;
+use dftk_database::login_attempts::LoginOutcome;
+use dftk_database::user_sessions::UserSession;
 use dftk_database::Repositories;
 
+use crate::oidc::build_oidc_routes;
+use crate::password::build_password_routes;
 use crate::rejection::Oops;
-use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::token::Claims;
+use crate::{with_context, with_repo, ServerContext, MAX_BODY_LENGTH};
 
-// FIXME see https://blog.joco.dev/posts/warp_auth_server_tutorial
+/// The cookie holding the session token
+const AUTH_COOKIE: &str = "auth";
 
 pub fn build_auth_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let login = warp::path("login").and(
         warp::post()
-            .and(with_repo(context.repos()))
+            .and(with_repo(context))
+            .and(with_context(context.clone()))
+            .and(with_client_ip(context))
             .and(warp::body::form())
             .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
             .and_then(do_login),
@@ -26,16 +39,33 @@ pub fn build_auth_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)>
 
     let logout = warp::path("logout").and(
         warp::post()
-            .and(with_repo(context.repos()))
+            .and(with_repo(context))
+            .and(with_claims(context))
             .and(warp::body::content_length_limit(0))
             .and_then(do_logout),
     );
 
-    login.or(logout).boxed()
+    login
+        .or(logout)
+        .or(build_password_routes(context))
+        .or(build_oidc_routes(context))
+        .boxed()
 }
 
+#[derive(Serialize, Debug)]
+struct LoginResponse {
+    token: String,
+    expires_at: DateTime<Utc>,
+    user: User,
+    need_change_password: bool,
+}
+
+/// Authenticate the user, the attempt is reserved before checking the password, and recorded,
+/// the e-mail or the IP address is locked after too many failures
 async fn do_login(
     repo: Repositories,
+    context: ServerContext,
+    ip: Option<IpAddr>,
     form: HashMap<String, String>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let email = form.get("email").ok_or_else(|| Oops::missing("email"))?;
@@ -44,40 +74,275 @@ async fn do_login(
         .get("password")
         .ok_or_else(|| Oops::missing("password"))?;
 
-    let user_info = repo
-        .user()
-        .authenticate(&email, password.as_bytes())
+    let throttle = context.server_config().throttle;
+    let attempts = repo.login_attempt();
+    let locked_until = attempts
+        .reserve(&email, ip, &throttle)
+        .await
+        .map_err(Oops::db)?;
+    if let Some(until) = locked_until {
+        attempts
+            .record(&email, ip, LoginOutcome::Locked)
+            .await
+            .map_err(Oops::db)?;
+        return Err(Oops::locked(until));
+    }
+
+    let result = repo.user().authenticate(&email, password.as_bytes()).await;
+    let outcome = if result.is_ok() {
+        LoginOutcome::Success
+    } else {
+        LoginOutcome::Failure
+    };
+    attempts
+        .record(&email, ip, outcome)
         .await
-        .map_err(Oops::auth)?;
+        .map_err(Oops::db)?;
+    let user_info = result.map_err(Oops::auth)?;
     info!("User authenticated {:?}", user_info);
-    let json = serde_json::to_string(&user_info).map_err(|err| Oops::other(err.into()))?;
-    let value = base64::encode(json);
+    let (token, claims, cookie) = open_session(&repo, &context, &user_info.user(), &email).await?;
+
+    let body = LoginResponse {
+        token,
+        expires_at: claims.expires_at(),
+        user: claims.user(),
+        need_change_password: user_info.need_change_password(),
+    };
+    let body = serde_json::to_string(&body).map_err(|err| Oops::other(err.into()))?;
+    let result = Response::builder()
+        // Set cookie
+        .header("Set-Cookie", cookie.to_string())
+        .header("Content-Type", "application/json")
+        .body(body)
+        .map_err(|err| Oops::other(err.into()))?;
+
+    Ok(result)
+}
 
-    let cookie: Cookie = Cookie::build("auth", value)
+/// Issue the session token of the user, with the cookie holding it
+pub(crate) async fn open_session(
+    repo: &Repositories,
+    context: &ServerContext,
+    user: &User,
+    email: &Email,
+) -> Result<(String, Claims, Cookie<'static>), Rejection> {
+    let tokens = context.tokens();
+    let (token, claims) = tokens.issue(user).map_err(Oops::other)?;
+    let session = UserSession::new(claims.id(), email, claims.issued_at(), claims.expires_at());
+    repo.user_session()
+        .create(&session)
+        .await
+        .map_err(Oops::db)?;
+
+    let max_age = Duration::seconds(tokens.ttl().num_seconds());
+    let cookie = Cookie::build(AUTH_COOKIE, token.clone())
         .path("/")
         .secure(true)
         .http_only(true)
         .same_site(SameSite::Strict)
-        .max_age(Duration::days(1))
+        .max_age(max_age)
         .finish();
 
+    Ok((token, claims, cookie))
+}
+
+/// The user of the token, an API client limited to the permissions of its owner for an API token,
+/// a guest without a valid token
+pub(crate) fn with_user(
+    context: &ServerContext,
+) -> impl Filter<Extract = (User,), Error = Rejection> + Clone {
+    let repos = context.repos();
+    with_token().and(with_claims(context)).and_then(
+        move |token: Option<String>, claims: Option<Claims>| {
+            let repos = repos.clone();
+            async move {
+                if let Some(claims) = claims {
+                    return Ok::<_, Rejection>(claims.user());
+                }
+                let token = match token {
+                    Some(token) if is_api_token(token.as_str()) => token,
+                    _ => return Ok(User::Guest),
+                };
+                let client = repos.api_client(token.as_str()).await.map_err(Oops::db)?;
+
+                Ok(client.unwrap_or(User::Guest))
+            }
+        },
+    )
+}
+
+/// The claims of the session token, a missing, invalid, expired or revoked token has no claims
+pub(crate) fn with_claims(
+    context: &ServerContext,
+) -> impl Filter<Extract = (Option<Claims>,), Error = Rejection> + Clone {
+    let tokens = context.tokens();
+    let sessions = context.repos().user_session();
+    with_token().and_then(move |token: Option<String>| {
+        let tokens = tokens.clone();
+        let sessions = sessions.clone();
+        async move {
+            let token = token.filter(|it| !is_api_token(it.as_str()));
+            let claims = match token.map(|it| tokens.verify(it.as_str())) {
+                Some(Ok(claims)) => claims,
+                Some(Err(err)) => {
+                    debug!("Rejected token: {}", err);
+                    return Ok::<_, Rejection>(None);
+                }
+                None => return Ok(None),
+            };
+            let active = sessions
+                .is_active(claims.id().as_str())
+                .await
+                .map_err(Oops::db)?;
+            if !active {
+                debug!("Rejected revoked session {}", claims.id());
+                return Ok(None);
+            }
+
+            Ok(Some(claims))
+        }
+    })
+}
+
+/// The address of the client, appended to the `X-Forwarded-For` header by the trusted proxies,
+/// or the remote address without proxy
+pub(crate) fn with_client_ip(
+    context: &ServerContext,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Rejection> + Clone {
+    let trusted_proxies = context.server_config().trusted_proxies;
+    warp::addr::remote()
+        .and(warp::header::optional::<String>("x-forwarded-for"))
+        .map(
+            move |remote: Option<SocketAddr>, forwarded_for: Option<String>| {
+                client_ip(remote, forwarded_for.as_deref(), trusted_proxies)
+            },
+        )
+}
+
+/// Each proxy appends the address of its peer, the previous addresses could be forged
+fn client_ip(
+    remote: Option<SocketAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: usize,
+) -> Option<IpAddr> {
+    if trusted_proxies == 0 {
+        return remote.map(|it| it.ip());
+    }
+    let addresses: Vec<&str> = forwarded_for
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|it| !it.is_empty())
+        .collect();
+    let index = addresses.len().checked_sub(trusted_proxies)?;
+
+    addresses[index].parse().ok()
+}
+
+/// The token from the `Authorization: Bearer` header or the `auth` cookie
+fn with_token() -> impl Filter<Extract = (Option<String>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::cookie::optional(AUTH_COOKIE))
+        .map(|authorization: Option<String>, cookie: Option<String>| {
+            authorization.as_deref().and_then(bearer).or(cookie)
+        })
+}
+
+fn bearer(authorization: &str) -> Option<String> {
+    let mut parts = authorization.trim().splitn(2, ' ');
+    let scheme = parts.next()?;
+    let token = parts.next()?.trim();
+    if scheme.eq_ignore_ascii_case("bearer") && !token.is_empty() {
+        Some(token.into())
+    } else {
+        None
+    }
+}
+
+/// Revoke the session of the token, and clear the cookie
+async fn do_logout(
+    repo: Repositories,
+    claims: Option<Claims>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(claims) = claims {
+        info!("Logout {:?}", claims.user());
+        repo.user_session()
+            .revoke(claims.id().as_str())
+            .await
+            .map_err(Oops::db)?;
+    }
+
+    let cookie: Cookie = Cookie::build(AUTH_COOKIE, "")
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::zero())
+        .finish();
     let result = Response::builder()
-        // Set cookie
+        .status(StatusCode::NO_CONTENT)
         .header("Set-Cookie", cookie.to_string())
-        .body("Welcome")
+        .body("")
         .map_err(|err| Oops::other(err.into()))?;
 
     Ok(result)
 }
 
-async fn do_logout(_repo: Repositories) -> Result<impl warp::Reply, warp::Rejection> {
-    Ok("TODO logout")
+/// Reject unless the current user is allowed to do the operation,
+/// with a `401` for a guest and a `403` for an authenticated user
+pub(crate) async fn authorize(
+    repos: &Repositories,
+    operation: &Operation,
+) -> Result<(), Rejection> {
+    let user = repos.current_user();
+    let allowed = repos.is_allowed(&user, operation).await.map_err(Oops::db)?;
+    if allowed {
+        return Ok(());
+    }
+    debug!("{:?} is not allowed to {:?}", user, operation);
+
+    match user {
+        User::Guest => Err(Oops::unauthorized()),
+        _ => Err(Oops::forbidden(operation)),
+    }
 }
 
-// FIXME filter authenticated
-// check cookie
-// parse JWT
-// verify JWT
-// check not rejected
+/// The repositories of the current user, if allowed to do the operation
+pub(crate) fn with_permission(
+    context: &ServerContext,
+    operation: Operation,
+) -> impl Filter<Extract = (Repositories,), Error = Rejection> + Clone {
+    with_repo(context).and_then(move |repos: Repositories| {
+        let operation = operation.clone();
+        async move {
+            authorize(&repos, &operation).await?;
+            Ok::<_, Rejection>(repos)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_bearer() {
+        assert_eq!(bearer("Bearer abc.def.ghi"), Some("abc.def.ghi".into()));
+        assert_eq!(bearer("bearer  abc"), Some("abc".into()));
+        assert_eq!(bearer("Basic dXNlcg=="), None);
+        assert_eq!(bearer("Bearer "), None);
+    }
 
-// FIXME filter authorization with Operation
+    #[test]
+    fn should_read_client_ip() {
+        let remote: Option<SocketAddr> = "10.0.0.1:4242".parse().ok();
+        let forwarded_for = Some("1.2.3.4, 192.168.1.1,172.16.0.1");
+        let ip = |it: &str| it.parse::<IpAddr>().ok();
+
+        assert_eq!(client_ip(remote, forwarded_for, 0), ip("10.0.0.1"));
+        assert_eq!(client_ip(remote, forwarded_for, 1), ip("172.16.0.1"));
+        assert_eq!(client_ip(remote, forwarded_for, 2), ip("192.168.1.1"));
+        assert_eq!(client_ip(remote, forwarded_for, 4), None);
+        assert_eq!(client_ip(remote, None, 1), None);
+    }
+}
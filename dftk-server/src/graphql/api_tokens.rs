@@ -0,0 +1,109 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use async_graphql::{InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use dftk_common::acl::role::{Permission, RoleKey};
+use dftk_common::acl::user::Email;
+use dftk_database::api_tokens::ApiToken;
+
+/// An API token, without its secret
+#[SimpleObject]
+pub struct ApiTokenOutputType {
+    id: String,
+    name: String,
+    owner: String,
+    role: Option<String>,
+    permissions: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiToken> for ApiTokenOutputType {
+    fn from(token: ApiToken) -> Self {
+        Self {
+            id: token.id(),
+            name: token.name(),
+            owner: token.owner().into(),
+            role: token.role().map(|it| it.into()),
+            permissions: token.permissions().iter().map(permission_name).collect(),
+            created_at: token.created_at(),
+            expires_at: token.expires_at(),
+            last_used_at: token.last_used_at(),
+        }
+    }
+}
+
+/// The created token, the only copy of its value
+#[SimpleObject]
+pub struct ApiTokenCreateOutput {
+    token: String,
+    api_token: ApiTokenOutputType,
+}
+
+impl ApiTokenCreateOutput {
+    pub fn new(token: String, api_token: ApiToken) -> Self {
+        Self {
+            token,
+            api_token: api_token.into(),
+        }
+    }
+}
+
+#[InputObject]
+pub struct ApiTokenInput {
+    name: String,
+    owner: String,
+    role: Option<String>,
+    /// The permissions, e.g. `generate` or `view_site`
+    permissions: Option<Vec<String>>,
+    expires_at: DateTime<Utc>,
+}
+
+pub struct ApiTokenRequest {
+    pub name: String,
+    pub owner: Email,
+    pub role: Option<RoleKey>,
+    pub permissions: Vec<Permission>,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub fn to_api_token_request(input: ApiTokenInput) -> Result<ApiTokenRequest> {
+    let ApiTokenInput {
+        name,
+        owner,
+        role,
+        permissions,
+        expires_at,
+    } = input;
+    let owner = Email::from_str(owner.as_str())?;
+    let role = role.map(|it| RoleKey::new(it.as_str()));
+    let permissions = permissions
+        .unwrap_or_default()
+        .into_iter()
+        .map(to_permission)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(ApiTokenRequest {
+        name,
+        owner,
+        role,
+        permissions,
+        expires_at,
+    })
+}
+
+fn permission_name(permission: &Permission) -> String {
+    match serde_json::to_value(permission) {
+        Ok(Value::String(name)) => name,
+        _ => format!("{:?}", permission),
+    }
+}
+
+fn to_permission(name: String) -> Result<Permission> {
+    serde_json::from_value(Value::String(name.clone()))
+        .map_err(|_| anyhow!("Unknown permission '{}'", name))
+}
@@ -0,0 +1,41 @@
+use async_graphql::FieldError;
+use serde_json::json;
+
+use dftk_common::acl::operation::Operation;
+use dftk_database::indexes::Conflict;
+use dftk_database::revision::StaleRevision;
+use dftk_database::user::{Escalation, UnknownKey};
+
+/// Add a `CONFLICT` code to the extensions of duplicate key errors,
+/// a `STALE_REVISION` code with the `currentRevision` to stale updates,
+/// an `UNKNOWN_KEY` code to users bound to a missing speaker or sponsor,
+/// and a `FORBIDDEN` code to users granted more than the current user holds
+pub(crate) fn db_error(err: anyhow::Error) -> FieldError {
+    if let Some(conflict) = err.downcast_ref::<Conflict>() {
+        FieldError(conflict.to_string(), Some(json!({ "code": "CONFLICT" })))
+    } else if let Some(stale) = err.downcast_ref::<StaleRevision>() {
+        let extensions = json!({
+            "code": "STALE_REVISION",
+            "currentRevision": stale.current(),
+        });
+        FieldError(stale.to_string(), Some(extensions))
+    } else if let Some(unknown) = err.downcast_ref::<UnknownKey>() {
+        FieldError(unknown.to_string(), Some(json!({ "code": "UNKNOWN_KEY" })))
+    } else if let Some(escalation) = err.downcast_ref::<Escalation>() {
+        FieldError(escalation.to_string(), Some(json!({ "code": "FORBIDDEN" })))
+    } else {
+        err.into()
+    }
+}
+
+/// A guest trying a protected field, with the `UNAUTHENTICATED` code
+pub(crate) fn unauthenticated() -> FieldError {
+    let extensions = json!({ "code": "UNAUTHENTICATED" });
+    FieldError("Authentication required".into(), Some(extensions))
+}
+
+/// An authenticated user without the permission, with the `FORBIDDEN` code
+pub(crate) fn forbidden(operation: &Operation) -> FieldError {
+    let extensions = json!({ "code": "FORBIDDEN" });
+    FieldError(format!("Not allowed to {:?}", operation), Some(extensions))
+}
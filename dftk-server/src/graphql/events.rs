@@ -0,0 +1,34 @@
+use async_graphql::SimpleObject;
+
+use dftk_common::models::site::EventId;
+use dftk_database::CloneResult;
+
+#[SimpleObject]
+pub struct CollectionCountOutputType {
+    name: String,
+    count: u32,
+}
+
+#[SimpleObject]
+pub struct CloneResultOutputType {
+    event: EventId,
+    collections: Vec<CollectionCountOutputType>,
+}
+
+impl From<CloneResult> for CloneResultOutputType {
+    fn from(result: CloneResult) -> Self {
+        let collections = result
+            .collections()
+            .iter()
+            .map(|(name, count)| CollectionCountOutputType {
+                name: name.clone(),
+                count: *count as u32,
+            })
+            .collect();
+
+        Self {
+            event: result.event(),
+            collections,
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use async_graphql::async_trait::async_trait;
+use async_graphql::guard::Guard;
+use async_graphql::{Context, FieldResult};
+
+use dftk_common::acl::operation::Operation;
+use dftk_common::acl::user::User;
+use dftk_database::Repositories;
+
+use crate::graphql::errors::{forbidden, unauthenticated};
+
+/// The user of the request, a guest without a valid token
+pub(crate) fn current_user(ctx: &Context<'_>) -> User {
+    ctx.data_opt::<User>().cloned().unwrap_or(User::Guest)
+}
+
+/// Reject unless the current user is allowed to do the operation
+pub(crate) async fn check_permission(ctx: &Context<'_>, operation: &Operation) -> FieldResult<()> {
+    let repos = ctx.data_unchecked::<Repositories>();
+    let user = current_user(ctx);
+    if repos.is_allowed(&user, operation).await? {
+        return Ok(());
+    }
+    debug!("{:?} is not allowed to {:?}", user, operation);
+
+    match user {
+        User::Guest => Err(unauthenticated()),
+        _ => Err(forbidden(operation)),
+    }
+}
+
+/// Guard a field with an operation without key,
+/// e.g. `#[field(guard(PermissionGuard(operation = "Operation::EditSite")))]`
+pub(crate) struct PermissionGuard {
+    pub operation: Operation,
+}
+
+#[async_trait]
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> FieldResult<()> {
+        check_permission(ctx, &self.operation).await
+    }
+}
+
+/// Reject a subscription of a connection without the user and the event scope of its handshake,
+/// the scope is only bound by the `connection_init` message
+pub(crate) struct ConnectionGuard;
+
+#[async_trait]
+impl Guard for ConnectionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> FieldResult<()> {
+        if ctx.data_opt::<User>().is_some() {
+            return Ok(());
+        }
+        debug!("Subscription without connection_init payload");
+
+        Err(unauthenticated())
+    }
+}
+
+/// The drafts are hidden from the guests
+pub(crate) fn is_visible(ctx: &Context<'_>, draft: Option<bool>) -> bool {
+    draft != Some(true) || current_user(ctx) != User::Guest
+}
+
+/// The draft filter of a query, the guests only get the published elements
+pub(crate) fn visible_draft(ctx: &Context<'_>, draft: Option<bool>) -> Option<bool> {
+    if current_user(ctx) == User::Guest {
+        Some(false)
+    } else {
+        draft
+    }
+}
@@ -0,0 +1,92 @@
+use async_graphql::{Enum, Object, SimpleObject};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use dftk_database::history::{Entity, FieldChange, HistoryEntry};
+
+#[Enum]
+pub enum EntityKind {
+    Session,
+    Speaker,
+    Sponsor,
+    TeamMember,
+    SiteInfo,
+    Schedule,
+}
+
+impl Into<Entity> for EntityKind {
+    fn into(self) -> Entity {
+        match self {
+            EntityKind::Session => Entity::Session,
+            EntityKind::Speaker => Entity::Speaker,
+            EntityKind::Sponsor => Entity::Sponsor,
+            EntityKind::TeamMember => Entity::TeamMember,
+            EntityKind::SiteInfo => Entity::SiteInfo,
+            EntityKind::Schedule => Entity::Schedule,
+        }
+    }
+}
+
+pub struct HistoryEntryOutputType {
+    entry: HistoryEntry,
+}
+
+/// A recorded mutation, documents are rendered as JSON
+#[Object]
+impl HistoryEntryOutputType {
+    async fn id(&self) -> String {
+        self.entry.id()
+    }
+    async fn entity_id(&self) -> String {
+        self.entry.entity_id()
+    }
+    async fn version(&self) -> u32 {
+        self.entry.version()
+    }
+    async fn user(&self) -> Option<String> {
+        self.entry.user().and_then(to_json)
+    }
+    async fn at(&self) -> DateTime<Utc> {
+        self.entry.at()
+    }
+    async fn before(&self) -> Option<String> {
+        self.entry.before().and_then(to_json)
+    }
+    async fn after(&self) -> Option<String> {
+        self.entry.after().and_then(to_json)
+    }
+}
+
+impl From<HistoryEntry> for HistoryEntryOutputType {
+    fn from(entry: HistoryEntry) -> Self {
+        Self { entry }
+    }
+}
+
+#[SimpleObject]
+pub struct FieldChangeOutputType {
+    path: String,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+impl From<FieldChange> for FieldChangeOutputType {
+    fn from(change: FieldChange) -> Self {
+        let path = change.path();
+        let before = change.before().and_then(to_json);
+        let after = change.after().and_then(to_json);
+
+        Self {
+            path,
+            before,
+            after,
+        }
+    }
+}
+
+fn to_json<T>(value: T) -> Option<String>
+where
+    T: Serialize,
+{
+    serde_json::to_string(&value).ok()
+}
@@ -12,6 +12,8 @@ pub struct SiteInfoInputType {
     address: AddressInputType,
     languages: LanguagesInputType,
     dates: DateRangeInputType,
+    /// The offset of the conference local time, in minutes east of UTC
+    utc_offset: Option<i32>,
 }
 
 impl SiteInfoInputType {
@@ -22,6 +24,7 @@ impl SiteInfoInputType {
         let dates = (&self.dates).into();
 
         SiteInfo::new(event_id.clone(), name, address, languages, dates)
+            .with_utc_offset(self.utc_offset.unwrap_or_default())
     }
 }
 
@@ -96,6 +99,8 @@ pub struct SiteInfoOutputType {
     address: AddressOutputType,
     languages: LanguagesOutputType,
     dates: DateRangeOutputType,
+    /// The offset of the conference local time, in minutes east of UTC
+    utc_offset: i32,
 }
 
 impl From<SiteInfo> for SiteInfoOutputType {
@@ -105,6 +110,7 @@ impl From<SiteInfo> for SiteInfoOutputType {
         let address = info.address().into();
         let languages = info.languages().into();
         let dates = info.dates().into();
+        let utc_offset = info.utc_offset();
 
         Self {
             id,
@@ -112,6 +118,7 @@ impl From<SiteInfo> for SiteInfoOutputType {
             address,
             languages,
             dates,
+            utc_offset,
         }
     }
 }
@@ -1,25 +1,38 @@
 #[cfg(feature = "graphql")]
-use std::convert::Infallible;
-
 use async_graphql::extensions::ApolloTracing;
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use async_graphql::{EmptySubscription, QueryBuilder, Schema};
+use async_graphql::{Data, QueryBuilder, Schema, WebSocketTransport};
 use async_graphql_warp::GQLResponse;
+use bytes::Bytes;
+use futures::{select, SinkExt, StreamExt};
+use warp::filters::ws::{Message, WebSocket, Ws};
 use warp::filters::BoxedFilter;
 use warp::http::Response;
-use warp::{Filter, Reply};
+use warp::{Filter, Rejection, Reply};
+
+use dftk_common::acl::user::User;
+use dftk_database::Repositories;
 
+use crate::authentication::with_user;
 use crate::graphql::mutation::MutationSite;
 use crate::graphql::query::QuerySite;
-use crate::ServerContext;
+use crate::graphql::subscription::SubscriptionSite;
+use crate::{event_repositories, with_event, ServerContext};
 
 mod mutation;
 mod query;
+mod subscription;
 
+mod api_tokens;
 mod categories;
+mod errors;
+mod events;
 mod formats;
+mod guards;
+mod history;
 mod info;
 mod languages;
+mod page;
 mod schedule;
 mod sessions;
 mod socials;
@@ -28,10 +41,10 @@ mod sponsors;
 mod teams;
 mod user;
 
-pub type SiteSchema = Schema<QuerySite, MutationSite, EmptySubscription>;
+pub type SiteSchema = Schema<QuerySite, MutationSite, SubscriptionSite>;
 
 pub fn build_schema(context: &ServerContext) -> SiteSchema {
-    Schema::build(QuerySite, MutationSite, EmptySubscription)
+    Schema::build(QuerySite, MutationSite, SubscriptionSite)
         .data(context.clone())
         .data(context.ch_config())
         .data(context.site_config())
@@ -48,21 +61,187 @@ pub fn build_graphql_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,
     let graphql_playground = warp::path::end() // End
         .and(warp::get()) // Get
         .map(move || {
-            let playground = GraphQLPlaygroundConfig::new(graphql_path.as_str());
+            let playground = GraphQLPlaygroundConfig::new(graphql_path.as_str())
+                .subscription_endpoint(graphql_path.as_str());
             Response::builder()
                 .header("content-type", "text/html")
                 .body(playground_source(playground))
         });
 
-    let graphql_post = async_graphql_warp::graphql(schema).and_then(
-        |(schema, builder): (_, QueryBuilder)| async move {
-            let resp = builder.execute(&schema).await;
-            Ok::<_, Infallible>(GQLResponse::from(resp))
-        },
-    );
+    let repos = context.repos();
+    let subscription_schema = schema.clone();
+    let graphql_subscription = warp::ws()
+        .and(with_user(context))
+        .and(with_event())
+        .and_then(move |ws: Ws, user: User, event| {
+            let repos = repos.clone();
+            let schema = subscription_schema.clone();
+            async move {
+                let repos = event_repositories(repos, event).await?;
+                let repos = repos.as_user(Some(user.clone()));
+                let reply = ws.on_upgrade(move |websocket| {
+                    subscription_connection(schema, websocket, user, repos)
+                });
+                let reply = warp::reply::with_header(reply, "Sec-WebSocket-Protocol", "graphql-ws");
+                Ok::<_, Rejection>(reply)
+            }
+        });
+
+    let repos = context.repos();
+    let graphql_post = async_graphql_warp::graphql(schema)
+        .and(with_user(context))
+        .and(with_event())
+        .and_then(move |(schema, builder): (_, QueryBuilder), user, event| {
+            let repos = repos.clone();
+            async move {
+                let repos = event_repositories(repos, event).await?;
+                let builder = builder.data(user.clone()).data(repos.as_user(Some(user)));
+                let resp = builder.execute(&schema).await;
+                Ok::<_, Rejection>(GQLResponse::from(resp))
+            }
+        });
 
-    graphql_playground
+    graphql_subscription
+        .or(graphql_playground)
         .or(graphql_post)
         .with(warp::log("graphql::api"))
         .boxed()
 }
+
+/// Forward the messages between the websocket and the subscriptions of the connection,
+/// the user and the event scope come from the handshake, like the `X-Event` header
+/// and the `Authorization` header or the `auth` cookie of a query
+async fn subscription_connection(
+    schema: SiteSchema,
+    websocket: WebSocket,
+    user: User,
+    repos: Repositories,
+) {
+    let transport = WebSocketTransport::new(move |_payload| {
+        let mut data = Data::default();
+        data.insert(user.clone());
+        data.insert(repos.clone());
+        Ok(data)
+    });
+    let (mut tx, rx) = websocket.split();
+    let (mut stx, srx) = schema.subscription_connection(transport);
+    let mut rx = rx.fuse();
+    let mut srx = srx.fuse();
+
+    loop {
+        select! {
+            bytes = srx.next() => {
+                let text = match bytes.map(|it| String::from_utf8(it.to_vec())) {
+                    Some(Ok(text)) => text,
+                    Some(Err(_)) => continue,
+                    None => return,
+                };
+                if tx.send(Message::text(text)).await.is_err() {
+                    return;
+                }
+            }
+            msg = rx.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => return,
+                };
+                if !msg.is_text() {
+                    continue;
+                }
+                if stx.send(Bytes::copy_from_slice(msg.as_bytes())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dftk_common::models::language::Lang;
+    use dftk_common::models::session::category::CategoryKey;
+    use dftk_common::models::session::format::FormatKey;
+    use dftk_common::models::session::PartialSession;
+    use serde_json::{json, Value};
+
+    use crate::routes;
+    use crate::tests::{admin_token, memory_context};
+
+    fn session(title: &str, draft: bool) -> PartialSession {
+        PartialSession::new(
+            title.into(),
+            None,
+            FormatKey::new("quickie"),
+            vec![],
+            CategoryKey::new("backend"),
+            Lang::default(),
+            None,
+            None,
+            Some(draft),
+            None,
+            "A talk".to_string().into(),
+        )
+    }
+
+    async fn session_keys(context: &crate::ServerContext, authorization: Option<&str>) -> Value {
+        let mut request = warp::test::request()
+            .method("POST")
+            .path("/graphql")
+            .json(&json!({"query": "{ sessions { key } }"}));
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+        let response = request.reply(&routes(context)).await;
+        let body: Value = serde_json::from_slice(response.body()).unwrap();
+
+        body["data"]["sessions"].clone()
+    }
+
+    #[tokio::test]
+    async fn should_scope_the_subscriptions_per_connection() {
+        let context = memory_context().await;
+
+        let unknown_event = warp::test::ws()
+            .path("/graphql")
+            .header("x-event", "unknown")
+            .handshake(routes(&context))
+            .await;
+        assert!(unknown_event.is_err());
+
+        let mut client = warp::test::ws()
+            .path("/graphql")
+            .handshake(routes(&context))
+            .await
+            .unwrap();
+        let start = json!({
+            "type": "start",
+            "id": "1",
+            "payload": {"query": "subscription { scheduleNow { room { key } } }"}
+        });
+        client.send_text(start.to_string()).await;
+        let message = client.recv().await.unwrap();
+        let message: Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+        assert_eq!(message["type"], "error");
+    }
+
+    #[tokio::test]
+    async fn should_query_sessions_in_memory() {
+        let context = memory_context().await;
+        let sessions = context.repos().session();
+        sessions
+            .insert_session(session("Rust", false))
+            .await
+            .unwrap();
+        sessions
+            .insert_session(session("Java", true))
+            .await
+            .unwrap();
+
+        let guest = session_keys(&context, None).await;
+        assert_eq!(guest, json!([{"key": "rust"}]));
+
+        let authorization = format!("Bearer {}", admin_token(&context).await);
+        let admin = session_keys(&context, Some(authorization.as_str())).await;
+        assert_eq!(admin.as_array().map(|it| it.len()), Some(2));
+    }
+}
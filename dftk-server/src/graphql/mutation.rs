@@ -1,13 +1,25 @@
+use anyhow::anyhow;
 #[cfg(feature = "graphql")]
 use async_graphql::{Context, FieldResult, Object};
 use uuid::Uuid;
 
+use dftk_common::acl::operation::Operation;
 use dftk_common::models::session::SessionId;
 use dftk_common::models::speaker::SpeakerId;
 use dftk_conference_hall::{read_event, ConferenceHallConfig};
 use dftk_database::Repositories;
 use dftk_hugo_site::{generate, SiteConfig};
 
+use crate::accounts::{
+    create_speaker_accounts, create_sponsor_accounts, parse_mapping, update_user,
+};
+use crate::graphql::api_tokens::{
+    to_api_token_request, ApiTokenCreateOutput, ApiTokenInput, ApiTokenOutputType,
+};
+use crate::graphql::errors::db_error;
+use crate::graphql::events::CloneResultOutputType;
+use crate::graphql::guards::{check_permission, PermissionGuard};
+use crate::graphql::history::{EntityKind, HistoryEntryOutputType};
 use crate::graphql::info::{SiteInfoInputType, SiteInfoOutputType};
 use crate::graphql::sessions::{
     GenerateResultOutputType, SessionCategoryOutputType, SessionCreateInput,
@@ -17,79 +29,210 @@ use crate::graphql::sessions::{
 use crate::graphql::speakers::{SpeakerCreateInput, SpeakerDocumentOutputType, SpeakerPatchInput};
 use crate::graphql::sponsors::{SponsorCategoryOutputType, SponsorInputType, SponsorOutputType};
 use crate::graphql::teams::{MemberTypeOutputType, TeamMemberInputType, TeamMemberOutputType};
-use crate::graphql::user::{to_user, UserCreateInput, UserCreateOutput};
+use crate::graphql::user::{to_user, AccountReportOutputType, UserCreateInput, UserCreateOutput};
+use crate::password::invite_user;
+use crate::ServerContext;
 use dftk_common::models::site::EventId;
 
 pub struct MutationSite;
 
 #[Object]
 impl MutationSite {
-    /// Insert a new user
+    /// Insert a new user, and send the invitation to choose its password,
+    /// the user cannot be granted more than the permissions of the current user
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
     async fn new_user(
         &self,
         ctx: &Context<'_>,
         user: UserCreateInput,
     ) -> FieldResult<UserCreateOutput> {
+        let context = ctx.data_unchecked::<ServerContext>();
         let repos = ctx.data_unchecked::<Repositories>();
         let user = to_user(&user)?;
-        // FIXME check speaker / sponsor Key
-        let password = repos.user().new_user(user).await?;
-        let result = UserCreateOutput::new(password);
+        repos.check_grant(&user).await.map_err(db_error)?;
+        let email: String = user.email().map(Into::into).unwrap_or_default();
+        let expires_at = invite_user(context, repos, user).await.map_err(db_error)?;
+        let result = UserCreateOutput::new(email, expires_at);
 
         Ok(result)
     }
 
+    /// Update the kind, the key or the roles of a user, and revoke its sessions,
+    /// returning the e-mail of the updated user if any
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
+    async fn update_user(
+        &self,
+        ctx: &Context<'_>,
+        user: UserCreateInput,
+    ) -> FieldResult<Option<String>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let user = to_user(&user)?;
+        let email = user
+            .email()
+            .ok_or_else(|| anyhow!("Expected the e-mail of the user"))?;
+        let result = update_user(repos, &email, user).await.map_err(db_error)?;
+
+        Ok(result.and_then(|it| it.email()).map(Into::into))
+    }
+
+    /// Create the accounts of the accepted speakers from a mapping file content,
+    /// a `key,email` line by account, the existing users are skipped
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
+    async fn create_speaker_accounts(
+        &self,
+        ctx: &Context<'_>,
+        mapping: String,
+    ) -> FieldResult<AccountReportOutputType> {
+        let context = ctx.data_unchecked::<ServerContext>();
+        let repos = ctx.data_unchecked::<Repositories>();
+        let mapping = parse_mapping(mapping.as_str())?;
+        let report = create_speaker_accounts(context, repos, &mapping)
+            .await
+            .map_err(db_error)?;
+
+        Ok(report.into())
+    }
+
+    /// Create the accounts of the sponsors from a mapping file content,
+    /// a `key,email` line by account, the existing users are skipped
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
+    async fn create_sponsor_accounts(
+        &self,
+        ctx: &Context<'_>,
+        mapping: String,
+    ) -> FieldResult<AccountReportOutputType> {
+        let context = ctx.data_unchecked::<ServerContext>();
+        let repos = ctx.data_unchecked::<Repositories>();
+        let mapping = parse_mapping(mapping.as_str())?;
+        let report = create_sponsor_accounts(context, repos, &mapping)
+            .await
+            .map_err(db_error)?;
+
+        Ok(report.into())
+    }
+
+    /// Create an API token, the output holds the only copy of the token,
+    /// it cannot hold more than the permissions of the current user
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
+    async fn create_api_token(
+        &self,
+        ctx: &Context<'_>,
+        input: ApiTokenInput,
+    ) -> FieldResult<ApiTokenCreateOutput> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let request = to_api_token_request(input)?;
+        repos
+            .check_api_token(&request.owner, request.role.clone(), &request.permissions)
+            .await
+            .map_err(db_error)?;
+        let (api_token, token) = repos
+            .api_token()
+            .create(
+                request.name,
+                request.owner,
+                request.role,
+                request.permissions,
+                request.expires_at,
+            )
+            .await?;
+
+        Ok(ApiTokenCreateOutput::new(token, api_token))
+    }
+
+    /// Revoke an API token
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
+    async fn revoke_api_token(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> FieldResult<Option<ApiTokenOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let result = repos.api_token().revoke(id.as_str()).await?;
+
+        Ok(result.map(|it| it.into()))
+    }
+
     /// Fetch site info, talks and speakers from Conference Hall and update the database
+    #[field(guard(PermissionGuard(operation = "Operation::Synchronize")))]
     async fn synchronize(&self, ctx: &Context<'_>) -> FieldResult<SynchronizeResultOutputType> {
         let ch_config = ctx.data_unchecked::<ConferenceHallConfig>();
         let repos = ctx.data_unchecked::<Repositories>();
         let site = read_event(ch_config).await?;
-        let result = repos.synchronize(site).await?;
+        let result = repos.synchronize(site).await.map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Generate Hugo Site files (speaker, sessions, team, sponsors, schedule)
+    #[field(guard(PermissionGuard(operation = "Operation::Generate")))]
     async fn generate(&self, ctx: &Context<'_>) -> FieldResult<GenerateResultOutputType> {
         let site_config = ctx.data_unchecked::<SiteConfig>();
         let repos = ctx.data_unchecked::<Repositories>();
-        let site = repos.load_site().await?;
+        let site = repos.load_site().await.map_err(db_error)?;
         let result = generate(&site_config, site).await?;
 
         Ok(result.into())
     }
 
-    /// Update general conference information
+    /// Start a new event with the team, the sponsor categories, the rooms and the formats of the current one
+    #[field(guard(PermissionGuard(operation = "Operation::Administration")))]
+    async fn clone_edition(
+        &self,
+        ctx: &Context<'_>,
+        to: EventId,
+    ) -> FieldResult<CloneResultOutputType> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let result = repos.clone_edition(&to).await.map_err(db_error)?;
+
+        Ok(result.into())
+    }
+
+    /// Update general conference information, rejected if the expected revision is stale
+    #[field(guard(PermissionGuard(operation = "Operation::EditSite")))]
     async fn update_site_info(
         &self,
         ctx: &Context<'_>,
         id: EventId,
         info: SiteInfoInputType,
+        expected_revision: Option<u32>,
     ) -> FieldResult<SiteInfoOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
         let site_info = info.to_site_info(&id);
         let id: String = id.into();
-        let _result = repos.info().save_or_update(id.as_str(), &site_info).await?;
+        let _result = repos
+            .info()
+            .save(id.as_str(), &site_info, expected_revision)
+            .await
+            .map_err(db_error)?;
 
         Ok(site_info.into())
     }
 
-    /// Patching a session
+    /// Patching a session, rejected if the expected revision is stale
     async fn patch_session(
         &self,
         ctx: &Context<'_>,
         id: SessionId,
         patch: SessionPatchInput,
+        expected_revision: Option<u32>,
     ) -> FieldResult<SessionDocumentOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
+        let session = repos.session().find_by_id(id.clone()).await?;
+        let session = session.ok_or_else(|| anyhow!("No session found for id {:?}", id))?;
+        check_permission(ctx, &Operation::EditSession(session.key())).await?;
         let patch = patch.to_session_patch()?;
         // FIXME check speaker key / category key / level key
-        let result = repos.session().update_session(id, patch).await?;
+        let result = repos
+            .session()
+            .update_session(id, patch, expected_revision)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Create a new session
+    #[field(guard(PermissionGuard(operation = "Operation::EditSessions")))]
     async fn create_session(
         &self,
         ctx: &Context<'_>,
@@ -97,25 +240,31 @@ impl MutationSite {
     ) -> FieldResult<SessionDocumentOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
         // FIXME check speaker key / category key / level key
-        let result = repos.session().insert_session(patch.into()).await?;
+        let result = repos
+            .session()
+            .insert_session(patch.into())
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a session
+    #[field(guard(PermissionGuard(operation = "Operation::EditSessions")))]
     async fn delete_session(
         &self,
         ctx: &Context<'_>,
         id: SessionId,
     ) -> FieldResult<Option<SessionDocumentOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session().delete_session(id).await?;
+        let result = repos.session().delete_session(id).await.map_err(db_error)?;
         let result: Option<SessionDocumentOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
     /// Create a session category
+    #[field(guard(PermissionGuard(operation = "Operation::EditProgram")))]
     async fn create_session_category(
         &self,
         ctx: &Context<'_>,
@@ -123,12 +272,17 @@ impl MutationSite {
         description: Option<String>,
     ) -> FieldResult<SessionCategoryOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session_category().create(name, description).await?;
+        let result = repos
+            .session_category()
+            .create(name, description)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Update a session category
+    #[field(guard(PermissionGuard(operation = "Operation::EditProgram")))]
     async fn update_session_category(
         &self,
         ctx: &Context<'_>,
@@ -140,25 +294,32 @@ impl MutationSite {
         let result = repos
             .session_category()
             .update(id, name, description)
-            .await?;
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a session category
+    #[field(guard(PermissionGuard(operation = "Operation::EditProgram")))]
     async fn delete_session_category(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
     ) -> FieldResult<Option<SessionCategoryOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session_category().delete(id).await?;
+        let result = repos
+            .session_category()
+            .delete(id)
+            .await
+            .map_err(db_error)?;
         let result: Option<SessionCategoryOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
     /// Create a session format
+    #[field(guard(PermissionGuard(operation = "Operation::EditProgram")))]
     async fn create_session_format(
         &self,
         ctx: &Context<'_>,
@@ -166,12 +327,17 @@ impl MutationSite {
         description: Option<String>,
     ) -> FieldResult<SessionFormatOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session_format().create(name, description).await?;
+        let result = repos
+            .session_format()
+            .create(name, description)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Update a session format
+    #[field(guard(PermissionGuard(operation = "Operation::EditProgram")))]
     async fn update_session_format(
         &self,
         ctx: &Context<'_>,
@@ -180,76 +346,97 @@ impl MutationSite {
         description: Option<String>,
     ) -> FieldResult<SessionFormatOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session_format().update(id, name, description).await?;
+        let result = repos
+            .session_format()
+            .update(id, name, description)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a session format
+    #[field(guard(PermissionGuard(operation = "Operation::EditProgram")))]
     async fn delete_session_format(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
     ) -> FieldResult<Option<SessionFormatOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session_format().delete(id).await?;
+        let result = repos.session_format().delete(id).await.map_err(db_error)?;
         let result: Option<SessionFormatOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
-    /// Patching a speaker
+    /// Patching a speaker, rejected if the expected revision is stale
     async fn patch_speaker(
         &self,
         ctx: &Context<'_>,
         id: SpeakerId,
         patch: SpeakerPatchInput,
+        expected_revision: Option<u32>,
     ) -> FieldResult<SpeakerDocumentOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
+        let speaker = repos.speaker().find_by_id(id.clone()).await?;
+        let speaker = speaker.ok_or_else(|| anyhow!("No speaker found for id {:?}", id))?;
+        check_permission(ctx, &Operation::EditSpeaker(speaker.key())).await?;
         let patch = patch.to_speaker_patch()?;
-        let result = repos.speaker().update_speaker(id, patch).await?;
+        let result = repos
+            .speaker()
+            .update_speaker(id, patch, expected_revision)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Create a new speaker
+    #[field(guard(PermissionGuard(operation = "Operation::EditSpeakers")))]
     async fn create_speaker(
         &self,
         ctx: &Context<'_>,
         input: SpeakerCreateInput,
     ) -> FieldResult<SpeakerDocumentOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.speaker().insert_speaker(input.into()).await?;
+        let result = repos
+            .speaker()
+            .insert_speaker(input.into())
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a speaker
+    #[field(guard(PermissionGuard(operation = "Operation::EditSpeakers")))]
     async fn delete_speaker(
         &self,
         ctx: &Context<'_>,
         id: SpeakerId,
     ) -> FieldResult<Option<SpeakerDocumentOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.speaker().delete_speaker(id).await?;
+        let result = repos.speaker().delete_speaker(id).await.map_err(db_error)?;
         let result: Option<SpeakerDocumentOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
     /// Create a team member type
+    #[field(guard(PermissionGuard(operation = "Operation::EditTeam")))]
     async fn create_member_type(
         &self,
         ctx: &Context<'_>,
         name: String,
     ) -> FieldResult<MemberTypeOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.member_type().create(name).await?;
+        let result = repos.member_type().create(name).await.map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Update a member type
+    #[field(guard(PermissionGuard(operation = "Operation::EditTeam")))]
     async fn update_member_type(
         &self,
         ctx: &Context<'_>,
@@ -257,75 +444,94 @@ impl MutationSite {
         name: String,
     ) -> FieldResult<MemberTypeOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.member_type().update(id, name).await?;
+        let result = repos
+            .member_type()
+            .update(id, name)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a member type
+    #[field(guard(PermissionGuard(operation = "Operation::EditTeam")))]
     async fn delete_member_type(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
     ) -> FieldResult<Option<MemberTypeOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.member_type().delete(id).await?;
+        let result = repos.member_type().delete(id).await.map_err(db_error)?;
         let result: Option<MemberTypeOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
     /// Create a team member
+    #[field(guard(PermissionGuard(operation = "Operation::EditTeam")))]
     async fn create_team_member(
         &self,
         ctx: &Context<'_>,
         input: TeamMemberInputType,
     ) -> FieldResult<TeamMemberOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.team().create(input.into()).await?;
+        let result = repos.team().create(input.into()).await.map_err(db_error)?;
 
         Ok(result.into())
     }
 
-    /// Update a team member
+    /// Update a team member, rejected if the expected revision is stale
+    #[field(guard(PermissionGuard(operation = "Operation::EditTeam")))]
     async fn update_team_member(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
         input: TeamMemberInputType,
+        expected_revision: Option<u32>,
     ) -> FieldResult<TeamMemberOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.team().update(id, input.into()).await?;
+        let result = repos
+            .team()
+            .update(id, input.into(), expected_revision)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a team member
+    #[field(guard(PermissionGuard(operation = "Operation::EditTeam")))]
     async fn delete_team_member(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
     ) -> FieldResult<Option<TeamMemberOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.team().delete(id).await?;
+        let result = repos.team().delete(id).await.map_err(db_error)?;
         let result: Option<TeamMemberOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
     /// Create a sponsor category
+    #[field(guard(PermissionGuard(operation = "Operation::EditSponsors")))]
     async fn create_sponsor_category(
         &self,
         ctx: &Context<'_>,
         name: String,
     ) -> FieldResult<SponsorCategoryOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.sponsor_category().create(name).await?;
+        let result = repos
+            .sponsor_category()
+            .create(name)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Update a sponsor category
+    #[field(guard(PermissionGuard(operation = "Operation::EditSponsors")))]
     async fn update_sponsor_category(
         &self,
         ctx: &Context<'_>,
@@ -333,61 +539,102 @@ impl MutationSite {
         name: String,
     ) -> FieldResult<SponsorCategoryOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.sponsor_category().update(id, name).await?;
+        let result = repos
+            .sponsor_category()
+            .update(id, name)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a sponsor category
+    #[field(guard(PermissionGuard(operation = "Operation::EditSponsors")))]
     async fn delete_sponsor_category(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
     ) -> FieldResult<Option<SponsorCategoryOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.sponsor_category().delete(id).await?;
+        let result = repos
+            .sponsor_category()
+            .delete(id)
+            .await
+            .map_err(db_error)?;
         let result: Option<SponsorCategoryOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
     /// Create a sponsor
+    #[field(guard(PermissionGuard(operation = "Operation::EditSponsors")))]
     async fn create_sponsor(
         &self,
         ctx: &Context<'_>,
         input: SponsorInputType,
     ) -> FieldResult<SponsorOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.sponsor().create(input.into()).await?;
+        let result = repos
+            .sponsor()
+            .create(input.into())
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
-    /// Update a sponsor
+    /// Update a sponsor, rejected if the expected revision is stale
     async fn update_sponsor(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
         input: SponsorInputType,
+        expected_revision: Option<u32>,
     ) -> FieldResult<SponsorOutputType> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.sponsor().update(id, input.into()).await?;
+        let sponsor = repos.sponsor().find_by_id(id).await?;
+        let sponsor = sponsor.ok_or_else(|| anyhow!("No sponsor found for id {}", id))?;
+        check_permission(ctx, &Operation::EditSponsor(sponsor.key())).await?;
+        let result = repos
+            .sponsor()
+            .update(id, input.into(), expected_revision)
+            .await
+            .map_err(db_error)?;
 
         Ok(result.into())
     }
 
     /// Delete a sponsor
+    #[field(guard(PermissionGuard(operation = "Operation::EditSponsors")))]
     async fn delete_sponsor(
         &self,
         ctx: &Context<'_>,
         id: Uuid,
     ) -> FieldResult<Option<SponsorOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.sponsor().delete(id).await?;
+        let result = repos.sponsor().delete(id).await.map_err(db_error)?;
         let result: Option<SponsorOutputType> = result.map(|it| it.into());
 
         Ok(result)
     }
 
+    /// Restore an entity to its state after a version of its history
+    #[field(guard(PermissionGuard(operation = "Operation::Administration")))]
+    async fn revert(
+        &self,
+        ctx: &Context<'_>,
+        entity: EntityKind,
+        id: String,
+        version: u32,
+    ) -> FieldResult<HistoryEntryOutputType> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let result = repos
+            .revert(entity.into(), id.as_str(), version)
+            .await
+            .map_err(db_error)?;
+
+        Ok(result.into())
+    }
+
     // FIXME schedule
 }
@@ -0,0 +1,79 @@
+use async_graphql::{Enum, FieldResult, SimpleObject};
+
+use dftk_database::query::{Direction, Page, PageRequest, Sort, SortField};
+
+#[Enum]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl Into<Direction> for SortDirection {
+    fn into(self) -> Direction {
+        match self {
+            SortDirection::Asc => Direction::Asc,
+            SortDirection::Desc => Direction::Desc,
+        }
+    }
+}
+
+/// The cursors are the positions of the elements in the sorted list
+#[SimpleObject]
+pub struct PageInfoOutputType {
+    has_next_page: bool,
+    has_previous_page: bool,
+    start_cursor: Option<String>,
+    end_cursor: Option<String>,
+}
+
+impl<T> From<&Page<T>> for PageInfoOutputType {
+    fn from(page: &Page<T>) -> Self {
+        let count = page.items().len() as u64;
+        let (start_cursor, end_cursor) = if count > 0 {
+            let start = page.offset();
+            (
+                Some(start.to_string()),
+                Some((start + count - 1).to_string()),
+            )
+        } else {
+            (None, None)
+        };
+
+        Self {
+            has_next_page: page.has_next(),
+            has_previous_page: page.offset() > 0,
+            start_cursor,
+            end_cursor,
+        }
+    }
+}
+
+/// The page of the `first` elements `after` a cursor
+pub fn page_request(first: Option<i32>, after: Option<String>) -> FieldResult<PageRequest> {
+    let offset = match after {
+        Some(cursor) => cursor.parse::<u64>()? + 1,
+        None => 0,
+    };
+    let limit = first.map(|it| it.max(0) as u64);
+
+    Ok(PageRequest::new(offset, limit))
+}
+
+/// The requested sort, on the `default` field when only the direction is given
+pub fn sort_by<F, S>(
+    field: Option<F>,
+    direction: Option<SortDirection>,
+    default: F,
+) -> Option<Sort<S>>
+where
+    F: Into<S>,
+    S: SortField,
+{
+    if field.is_none() && direction.is_none() {
+        return None;
+    }
+    let field = field.unwrap_or(default).into();
+    let direction = direction.map(|it| it.into()).unwrap_or_default();
+
+    Some(Sort::new(field, direction))
+}
@@ -1,16 +1,33 @@
 use async_graphql::{Context, FieldResult, Object};
 
-use dftk_common::models::session::SessionId;
-use dftk_common::models::speaker::SpeakerId;
+use dftk_common::acl::operation::Operation;
+use dftk_common::models::language::Lang;
+use dftk_common::models::session::category::CategoryKey;
+use dftk_common::models::session::format::FormatKey;
+use dftk_common::models::session::{SessionId, SessionLevel};
+use dftk_common::models::site::EventId;
+use dftk_common::models::speaker::{SpeakerId, SpeakerKey};
+use dftk_common::models::sponsor::category::SponsorCategoryKey;
+use dftk_database::query::{Query, SessionFilter, Sort, SpeakerFilter, SponsorFilter};
 use dftk_database::Repositories;
 
+use crate::graphql::api_tokens::ApiTokenOutputType;
 use crate::graphql::categories::CategoryOutputType;
 use crate::graphql::formats::FormatOutputType;
+use crate::graphql::guards::{check_permission, is_visible, visible_draft, PermissionGuard};
+use crate::graphql::history::{EntityKind, FieldChangeOutputType, HistoryEntryOutputType};
 use crate::graphql::info::SiteInfoOutputType;
+use crate::graphql::page::{page_request, sort_by, SortDirection};
 use crate::graphql::schedule::ScheduleOutputType;
-use crate::graphql::sessions::{SessionDocumentOutputType, SessionOutputType};
-use crate::graphql::speakers::{SpeakerDocumentOutputType, SpeakerOutputType};
-use crate::graphql::sponsors::{SponsorCategoryOutputType, SponsorOutputType};
+use crate::graphql::sessions::{
+    SessionConnectionOutputType, SessionDocumentOutputType, SessionOutputType, SessionSortField,
+};
+use crate::graphql::speakers::{
+    SpeakerConnectionOutputType, SpeakerDocumentOutputType, SpeakerOutputType, SpeakerSortField,
+};
+use crate::graphql::sponsors::{
+    SponsorCategoryOutputType, SponsorConnectionOutputType, SponsorOutputType, SponsorSortField,
+};
 use crate::graphql::teams::{MemberTypeOutputType, TeamMemberOutputType};
 
 pub struct QuerySite;
@@ -26,6 +43,25 @@ impl QuerySite {
         Ok(info)
     }
 
+    /// Getting the events, the current one is selected by the `X-Event` header
+    #[field(guard(PermissionGuard(operation = "Operation::Administration")))]
+    async fn events(&self, ctx: &Context<'_>) -> FieldResult<Vec<EventId>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let events = repos.events().await?;
+
+        Ok(events)
+    }
+
+    /// Getting the API tokens, without their secret
+    #[field(guard(PermissionGuard(operation = "Operation::ManageUsers")))]
+    async fn api_tokens(&self, ctx: &Context<'_>) -> FieldResult<Vec<ApiTokenOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let tokens = repos.api_token().find_all().await?;
+        let tokens = tokens.into_iter().map(|it| it.into()).collect();
+
+        Ok(tokens)
+    }
+
     /// Getting session categories
     async fn categories(&self, ctx: &Context<'_>) -> FieldResult<Vec<CategoryOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
@@ -52,23 +88,60 @@ impl QuerySite {
     ) -> FieldResult<Option<SessionDocumentOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
         let session = repos.session().find_by_id(id).await?;
+        if let Some(session) = &session {
+            check_permission(ctx, &Operation::ViewSession(session.key())).await?;
+        }
         let session: Option<SessionDocumentOutputType> = session.map(|it| it.into());
 
         Ok(session)
     }
 
-    /// Getting all sessions
+    /// Getting all sessions, without the drafts for a guest
     async fn sessions(&self, ctx: &Context<'_>) -> FieldResult<Vec<SessionOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
         let sessions = repos.session().find_all().await?;
         let sessions = sessions
             .iter()
+            .filter(|it| is_visible(ctx, it.draft()))
             .map(move |it| it.into())
             .collect::<Vec<SessionOutputType>>();
 
         Ok(sessions)
     }
 
+    /// Getting a page of sessions, filtered on their patched values and sorted by key,
+    /// title or last change, without the drafts for a guest
+    #[allow(clippy::too_many_arguments)]
+    async fn sessions_connection(
+        &self,
+        ctx: &Context<'_>,
+        category: Option<CategoryKey>,
+        format: Option<FormatKey>,
+        language: Option<Lang>,
+        level: Option<String>,
+        draft: Option<bool>,
+        speaker: Option<SpeakerKey>,
+        sort: Option<SessionSortField>,
+        direction: Option<SortDirection>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<SessionConnectionOutputType> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let filter = SessionFilter {
+            category,
+            format,
+            language,
+            level: level.map(SessionLevel::from),
+            draft: visible_draft(ctx, draft),
+            speaker,
+        };
+        let sort = sort_by(sort, direction, SessionSortField::Key);
+        let query = Query::new(filter, sort, page_request(first, after)?);
+        let page = repos.session().find_page(&query).await?;
+
+        Ok(page.into())
+    }
+
     /// Getting a speaker detail
     async fn speaker_by_id(
         &self,
@@ -77,20 +150,56 @@ impl QuerySite {
     ) -> FieldResult<Option<SpeakerDocumentOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
         let speaker = repos.speaker().find_by_id(id).await?;
+        if let Some(speaker) = &speaker {
+            check_permission(ctx, &Operation::ViewSpeaker(speaker.key())).await?;
+        }
         let speaker: Option<SpeakerDocumentOutputType> = speaker.map(|it| it.into());
 
         Ok(speaker)
     }
 
-    /// Getting all speakers
+    /// Getting all speakers, without the drafts for a guest
     async fn speakers(&self, ctx: &Context<'_>) -> FieldResult<Vec<SpeakerOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
         let speakers = repos.speaker().find_all().await?;
-        let speakers = speakers.iter().map(|it| it.into()).collect();
+        let speakers = speakers
+            .iter()
+            .filter(|it| is_visible(ctx, it.draft()))
+            .map(|it| it.into())
+            .collect();
 
         Ok(speakers)
     }
 
+    /// Getting a page of speakers, filtered on their patched values and sorted by key,
+    /// name or last change, without the drafts for a guest
+    #[allow(clippy::too_many_arguments)]
+    async fn speakers_connection(
+        &self,
+        ctx: &Context<'_>,
+        featured: Option<bool>,
+        company: Option<String>,
+        city: Option<String>,
+        draft: Option<bool>,
+        sort: Option<SpeakerSortField>,
+        direction: Option<SortDirection>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<SpeakerConnectionOutputType> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let filter = SpeakerFilter {
+            featured,
+            company,
+            city,
+            draft: visible_draft(ctx, draft),
+        };
+        let sort = sort_by(sort, direction, SpeakerSortField::Key);
+        let query = Query::new(filter, sort, page_request(first, after)?);
+        let page = repos.speaker().find_page(&query).await?;
+
+        Ok(page.into())
+    }
+
     /// Getting schedule
     async fn schedule(&self) -> FieldResult<ScheduleOutputType> {
         Ok(ScheduleOutputType)
@@ -123,6 +232,28 @@ impl QuerySite {
         Ok(sponsors)
     }
 
+    /// Getting a page of sponsors, filtered by category
+    async fn sponsors_connection(
+        &self,
+        ctx: &Context<'_>,
+        category: Option<SponsorCategoryKey>,
+        sort: Option<SponsorSortField>,
+        direction: Option<SortDirection>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> FieldResult<SponsorConnectionOutputType> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let filter = SponsorFilter { category };
+        let sort = sort.map(|it| {
+            let direction = direction.map(|it| it.into()).unwrap_or_default();
+            Sort::new(it.into(), direction)
+        });
+        let query = Query::new(filter, sort, page_request(first, after)?);
+        let page = repos.sponsor().find_page(&query).await?;
+
+        Ok(page.into())
+    }
+
     /// Getting sponsor categories
     async fn sponsor_categories(
         &self,
@@ -137,4 +268,52 @@ impl QuerySite {
 
         Ok(sponsor_categories)
     }
+
+    /// Getting the history of an entity, sorted by version
+    #[field(guard(PermissionGuard(operation = "Operation::Administration")))]
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        entity: EntityKind,
+        id: String,
+    ) -> FieldResult<Vec<HistoryEntryOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let history = repos.history().find(entity.into(), id.as_str()).await?;
+        let history = history.into_iter().map(|it| it.into()).collect();
+
+        Ok(history)
+    }
+
+    /// Getting the current revision of an entity, expected by the updates
+    async fn revision(
+        &self,
+        ctx: &Context<'_>,
+        entity: EntityKind,
+        id: String,
+    ) -> FieldResult<Option<u32>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let revision = repos.revision(entity.into(), id.as_str()).await?;
+
+        Ok(revision)
+    }
+
+    /// Compare two versions of an entity, the version 0 is the empty state
+    #[field(guard(PermissionGuard(operation = "Operation::Administration")))]
+    async fn history_diff(
+        &self,
+        ctx: &Context<'_>,
+        entity: EntityKind,
+        id: String,
+        from: u32,
+        to: u32,
+    ) -> FieldResult<Vec<FieldChangeOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let changes = repos
+            .history()
+            .diff(entity.into(), id.as_str(), from, to)
+            .await?;
+        let changes = changes.into_iter().map(|it| it.into()).collect();
+
+        Ok(changes)
+    }
 }
@@ -3,12 +3,16 @@ use std::ops::Range;
 use async_graphql::{Context, FieldResult, Object, SimpleObject};
 use chrono::{DateTime, Utc};
 
+use dftk_common::models::schedule::now::{now_next, RoomNowNext, TimedSession};
 use dftk_common::models::schedule::{
-    Room, RoomKey, ScheduleDay, ScheduleRoom, ScheduleRoomSlot, Slot, SlotKey,
+    Room, RoomKey, ScheduleDay, ScheduleRoom, ScheduleRoomSlot, Slot, SlotKey, SlotLayout,
 };
 use dftk_common::models::session::SessionKey;
 use dftk_database::Repositories;
 
+use crate::graphql::sessions::SessionOutputType;
+use crate::graphql::speakers::SpeakerOutputType;
+
 pub struct ScheduleOutputType;
 
 #[Object]
@@ -24,7 +28,14 @@ impl ScheduleOutputType {
     async fn slots(&self, ctx: &Context<'_>) -> FieldResult<Vec<SlotOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
         let result = repos.slot().find_all().await?;
-        let result = result.iter().map(|it| it.into()).collect();
+        let layouts = repos.slot().find_layouts().await?;
+        let result = result
+            .iter()
+            .map(|slot| {
+                let layout = layouts.iter().find(|it| it.slot() == slot.key());
+                SlotOutputType::new(slot, layout)
+            })
+            .collect();
 
         Ok(result)
     }
@@ -36,6 +47,22 @@ impl ScheduleOutputType {
 
         Ok(result)
     }
+
+    /// Current and next session per room, `at` default to now
+    async fn now(
+        &self,
+        ctx: &Context<'_>,
+        at: Option<DateTime<Utc>>,
+        room: Option<RoomKey>,
+    ) -> FieldResult<Vec<RoomNowNextOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let site = repos.load_site().await?;
+        let at = at.unwrap_or_else(Utc::now);
+        let result = now_next(&site, at, room);
+        let result = result.iter().map(|it| it.into()).collect();
+
+        Ok(result)
+    }
 }
 
 #[SimpleObject]
@@ -72,20 +99,35 @@ impl From<&ScheduleRoom> for ScheduleRoomOutputType {
     }
 }
 
-#[SimpleObject]
 pub struct ScheduleRoomSlotOutputType {
-    slot: SlotKey,
-    session: SessionKey,
+    room_slot: ScheduleRoomSlot,
+}
+
+#[Object]
+impl ScheduleRoomSlotOutputType {
+    async fn slot(&self) -> SlotKey {
+        self.room_slot.slot()
+    }
+    async fn session_key(&self) -> SessionKey {
+        self.room_slot.session()
+    }
+    async fn session(&self, ctx: &Context<'_>) -> FieldResult<Option<SessionOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let session = repos
+            .session()
+            .find_by_key(self.room_slot.session())
+            .await?;
+        let session = session.as_ref().map(|it| it.into());
+
+        Ok(session)
+    }
 }
 
 impl From<&ScheduleRoomSlot> for ScheduleRoomSlotOutputType {
     fn from(rs: &ScheduleRoomSlot) -> Self {
-        let rs = rs.clone();
+        let room_slot = rs.clone();
 
-        Self {
-            slot: rs.slot(),
-            session: rs.session(),
-        }
+        Self { room_slot }
     }
 }
 
@@ -93,19 +135,18 @@ impl From<&ScheduleRoomSlot> for ScheduleRoomSlotOutputType {
 pub struct SlotOutputType {
     key: SlotKey,
     start: DateTime<Utc>,
-    duration: u8,
-    row: SlotRangeOutputType,
+    /// Duration in minutes
+    duration: u32,
+    row: Option<SlotRangeOutputType>,
 }
 
-impl From<&Slot> for SlotOutputType {
-    fn from(slot: &Slot) -> Self {
-        let slot = slot.clone();
-
+impl SlotOutputType {
+    fn new(slot: &Slot, layout: Option<&SlotLayout>) -> Self {
         Self {
             key: slot.key(),
             start: slot.start(),
-            duration: slot.duration(),
-            row: slot.row().into(),
+            duration: slot.duration().minutes(),
+            row: layout.map(|it| it.row().into()),
         }
     }
 }
@@ -145,3 +186,47 @@ impl From<&Room> for RoomOutputType {
         }
     }
 }
+
+#[SimpleObject]
+pub struct RoomNowNextOutputType {
+    room: RoomOutputType,
+    current: Option<TimedSessionOutputType>,
+    next: Option<TimedSessionOutputType>,
+}
+
+impl From<&RoomNowNext> for RoomNowNextOutputType {
+    fn from(now_next: &RoomNowNext) -> Self {
+        Self {
+            room: (&now_next.room()).into(),
+            current: now_next.current().as_ref().map(|it| it.into()),
+            next: now_next.next().as_ref().map(|it| it.into()),
+        }
+    }
+}
+
+#[SimpleObject]
+pub struct TimedSessionOutputType {
+    session: SessionOutputType,
+    speakers: Vec<SpeakerOutputType>,
+    slot: SlotKey,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    /// Minutes before the session start
+    starts_in: i64,
+    /// Minutes before the session end
+    remaining: i64,
+}
+
+impl From<&TimedSession> for TimedSessionOutputType {
+    fn from(timed: &TimedSession) -> Self {
+        Self {
+            session: (&timed.session()).into(),
+            speakers: timed.speakers().iter().map(|it| it.into()).collect(),
+            slot: timed.slot(),
+            start: timed.start(),
+            end: timed.end(),
+            starts_in: timed.starts_in(),
+            remaining: timed.remaining(),
+        }
+    }
+}
@@ -1,24 +1,38 @@
 use anyhow::{anyhow, Result};
-use async_graphql::{Context, FieldResult, InputObject, Object, SimpleObject};
+use async_graphql::{Context, Enum, FieldResult, InputObject, Object, SimpleObject};
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
 use dftk_common::models::language::Lang;
+use dftk_common::models::schedule::placement::SessionPlacement;
+use dftk_common::models::schedule::SlotKey;
 use dftk_common::models::session::category::{CategoryKey, SessionCategory};
 use dftk_common::models::session::format::{FormatKey, SessionFormat};
 use dftk_common::models::session::{PartialSession, Session, SessionId, SessionKey};
 use dftk_common::models::speaker::SpeakerKey;
+use dftk_database::query::{Page, SessionSort};
 use dftk_database::sessions::{SessionDocument, SessionPatch};
 use dftk_database::{Repositories, SynchronizeResult};
 use dftk_hugo_site::site_writer::GenerateResult;
 
 use crate::graphql::categories::CategoryOutputType;
 use crate::graphql::formats::FormatOutputType;
+use crate::graphql::guards::is_visible;
+use crate::graphql::page::PageInfoOutputType;
+use crate::graphql::schedule::RoomOutputType;
 use crate::graphql::speakers::SpeakerOutputType;
 
 pub struct SessionOutputType {
     session: Session,
 }
 
+impl SessionOutputType {
+    async fn placement(&self, ctx: &Context<'_>) -> Result<Option<SessionPlacement>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        repos.find_placement(&self.session.key()).await
+    }
+}
+
 #[Object]
 impl SessionOutputType {
     async fn id(&self) -> SessionId {
@@ -83,10 +97,45 @@ impl SessionOutputType {
             .map(|it| it.clone().into())
             .collect::<Vec<String>>();
         let speakers = repos.speaker().find_by_keys(keys.as_slice()).await?;
-        let speakers = speakers.iter().map(|it| it.into()).collect();
+        let speakers = speakers
+            .iter()
+            .filter(|it| is_visible(ctx, it.draft()))
+            .map(|it| it.into())
+            .collect();
 
         Ok(speakers)
     }
+
+    /// The slot of the session, if scheduled
+    async fn slot(&self, ctx: &Context<'_>) -> FieldResult<Option<SlotKey>> {
+        let placement = self.placement(ctx).await?;
+
+        Ok(placement.map(|it| it.slot()))
+    }
+    /// The room of the session, if scheduled
+    async fn room(&self, ctx: &Context<'_>) -> FieldResult<Option<RoomOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>();
+        let room = if let Some(placement) = self.placement(ctx).await? {
+            let key: String = placement.room().into();
+            repos.room().find_by_key(key.as_str()).await?
+        } else {
+            None
+        };
+
+        Ok(room.as_ref().map(|it| it.into()))
+    }
+    /// The start of the session, if scheduled
+    async fn start(&self, ctx: &Context<'_>) -> FieldResult<Option<DateTime<Utc>>> {
+        let placement = self.placement(ctx).await?;
+
+        Ok(placement.map(|it| it.start()))
+    }
+    /// The end of the session, if scheduled
+    async fn end(&self, ctx: &Context<'_>) -> FieldResult<Option<DateTime<Utc>>> {
+        let placement = self.placement(ctx).await?;
+
+        Ok(placement.map(|it| it.end()))
+    }
 }
 
 impl From<&SessionDocument> for SessionOutputType {
@@ -307,3 +356,37 @@ impl Into<PartialSession> for SessionCreateInput {
         )
     }
 }
+
+#[Enum]
+pub enum SessionSortField {
+    Key,
+    Title,
+    Date,
+}
+
+impl Into<SessionSort> for SessionSortField {
+    fn into(self) -> SessionSort {
+        match self {
+            SessionSortField::Key => SessionSort::Key,
+            SessionSortField::Title => SessionSort::Title,
+            SessionSortField::Date => SessionSort::Date,
+        }
+    }
+}
+
+#[SimpleObject]
+pub struct SessionConnectionOutputType {
+    total_count: u64,
+    nodes: Vec<SessionOutputType>,
+    page_info: PageInfoOutputType,
+}
+
+impl From<Page<Session>> for SessionConnectionOutputType {
+    fn from(page: Page<Session>) -> Self {
+        Self {
+            total_count: page.total(),
+            page_info: (&page).into(),
+            nodes: page.items().iter().map(|it| it.into()).collect(),
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use anyhow::Result;
-use async_graphql::{Context, FieldResult, InputObject, Object, SimpleObject};
+use async_graphql::{Context, Enum, FieldResult, InputObject, Object, SimpleObject};
 
 use dftk_common::models::speaker::{PartialSpeaker, Speaker, SpeakerId, SpeakerKey};
+use dftk_database::query::{Page, SpeakerSort};
 use dftk_database::speakers::{SpeakerDocument, SpeakerPatch};
 use dftk_database::Repositories;
 
+use crate::graphql::guards::is_visible;
+use crate::graphql::page::PageInfoOutputType;
 use crate::graphql::sessions::SessionOutputType;
 use crate::graphql::socials::{SocialInputType, SocialOutputType};
 
@@ -45,9 +48,19 @@ impl SpeakerOutputType {
         self.speaker.content().into()
     }
 
+    /// Sessions of the speaker, scheduled sessions first, sorted by start, without the drafts for a guest
     async fn sessions(&self, ctx: &Context<'_>) -> FieldResult<Vec<SessionOutputType>> {
         let repos = ctx.data_unchecked::<Repositories>();
-        let result = repos.session().find_by_speaker(&self.speaker.key()).await?;
+        let mut result = repos.session().find_by_speaker(&self.speaker.key()).await?;
+        result.retain(|it| is_visible(ctx, it.draft()));
+        let placements = repos.find_placements().await?;
+        result.sort_by_key(|session| {
+            let start = placements
+                .iter()
+                .find(|it| it.session() == session.key())
+                .map(|it| it.start());
+            (start.is_none(), start)
+        });
         let result = result.iter().map(|it| it.into()).collect();
 
         Ok(result)
@@ -163,3 +176,37 @@ impl SpeakerPatchInput {
         Ok(result)
     }
 }
+
+#[Enum]
+pub enum SpeakerSortField {
+    Key,
+    Name,
+    Date,
+}
+
+impl Into<SpeakerSort> for SpeakerSortField {
+    fn into(self) -> SpeakerSort {
+        match self {
+            SpeakerSortField::Key => SpeakerSort::Key,
+            SpeakerSortField::Name => SpeakerSort::Name,
+            SpeakerSortField::Date => SpeakerSort::Date,
+        }
+    }
+}
+
+#[SimpleObject]
+pub struct SpeakerConnectionOutputType {
+    total_count: u64,
+    nodes: Vec<SpeakerOutputType>,
+    page_info: PageInfoOutputType,
+}
+
+impl From<Page<Speaker>> for SpeakerConnectionOutputType {
+    fn from(page: Page<Speaker>) -> Self {
+        Self {
+            total_count: page.total(),
+            page_info: (&page).into(),
+            nodes: page.items().iter().map(|it| it.into()).collect(),
+        }
+    }
+}
@@ -1,10 +1,12 @@
-use async_graphql::{InputObject, SimpleObject};
+use async_graphql::{Enum, InputObject, SimpleObject};
 use uuid::Uuid;
 
 use dftk_common::models::language::Lang;
 use dftk_common::models::sponsor::category::{SponsorCategory, SponsorCategoryKey};
 use dftk_common::models::sponsor::{PartialSponsor, Sponsor, SponsorKey};
+use dftk_database::query::{Page, SponsorSort};
 
+use crate::graphql::page::PageInfoOutputType;
 use crate::graphql::socials::{SocialInputType, SocialOutputType};
 
 #[SimpleObject]
@@ -83,3 +85,39 @@ impl Into<PartialSponsor> for SponsorInputType {
         )
     }
 }
+
+#[Enum]
+pub enum SponsorSortField {
+    Key,
+    Title,
+    Category,
+    Order,
+}
+
+impl Into<SponsorSort> for SponsorSortField {
+    fn into(self) -> SponsorSort {
+        match self {
+            SponsorSortField::Key => SponsorSort::Key,
+            SponsorSortField::Title => SponsorSort::Title,
+            SponsorSortField::Category => SponsorSort::Category,
+            SponsorSortField::Order => SponsorSort::Order,
+        }
+    }
+}
+
+#[SimpleObject]
+pub struct SponsorConnectionOutputType {
+    total_count: u64,
+    nodes: Vec<SponsorOutputType>,
+    page_info: PageInfoOutputType,
+}
+
+impl From<Page<Sponsor>> for SponsorConnectionOutputType {
+    fn from(page: Page<Sponsor>) -> Self {
+        Self {
+            total_count: page.total(),
+            page_info: (&page).into(),
+            nodes: page.items().iter().map(|it| it.clone().into()).collect(),
+        }
+    }
+}
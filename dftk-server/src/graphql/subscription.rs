@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use async_graphql::{Context, Subscription};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use tokio::time::delay_for;
+
+use dftk_common::models::schedule::now::{next_boundary, now_next};
+use dftk_common::models::schedule::RoomKey;
+use dftk_database::Repositories;
+
+use crate::graphql::guards::ConnectionGuard;
+use crate::graphql::schedule::RoomNowNextOutputType;
+
+/// How often the feed looks for a change of the schedule
+const SCHEDULE_POLL: Duration = Duration::from_secs(30);
+
+pub struct SubscriptionSite;
+
+#[Subscription]
+impl SubscriptionSite {
+    /// Current and next session per room, pushed at each slot boundary and when the schedule changes,
+    /// `at` default to now, the feed then follows the clock from it for rehearsals,
+    /// public like the `now` query, in the event of the connection
+    #[field(guard(ConnectionGuard()))]
+    async fn schedule_now(
+        &self,
+        ctx: &Context<'_>,
+        at: Option<DateTime<Utc>>,
+        room: Option<RoomKey>,
+    ) -> impl Stream<Item = Vec<RoomNowNextOutputType>> {
+        let repos = ctx.data_unchecked::<Repositories>().clone();
+        let feed = ScheduleFeed::new(repos, at, room);
+
+        stream::unfold(feed, |mut feed| async move {
+            let result = feed.next().await?;
+            Some((result, feed))
+        })
+    }
+}
+
+/// The state of a `scheduleNow` subscription
+struct ScheduleFeed {
+    repos: Repositories,
+    room: Option<RoomKey>,
+    /// The shift of the clock of a rehearsal
+    offset: chrono::Duration,
+    /// The schedule revision of the last push, `None` before the first one
+    revision: Option<u32>,
+    /// The next slot boundary, `None` after the last slot
+    boundary: Option<DateTime<Utc>>,
+}
+
+impl ScheduleFeed {
+    fn new(repos: Repositories, at: Option<DateTime<Utc>>, room: Option<RoomKey>) -> Self {
+        let offset = at
+            .map(|it| it - Utc::now())
+            .unwrap_or_else(chrono::Duration::zero);
+
+        Self {
+            repos,
+            room,
+            offset,
+            revision: None,
+            boundary: None,
+        }
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now() + self.offset
+    }
+
+    /// Wait for the next slot boundary or a change of the schedule,
+    /// `None` stops the feed when the schedule cannot be read
+    async fn next(&mut self) -> Option<Vec<RoomNowNextOutputType>> {
+        loop {
+            let revision = match self.repos.schedule_revision().await {
+                Ok(revision) => revision,
+                Err(err) => {
+                    warn!("Cannot read the schedule, stop the schedule feed: {}", err);
+                    return None;
+                }
+            };
+            let at = self.now();
+            let reached = self.boundary.map(|it| it <= at).unwrap_or(false);
+            if reached || self.revision != Some(revision) {
+                let site = match self.repos.load_site().await {
+                    Ok(site) => site,
+                    Err(err) => {
+                        warn!("Cannot load the site, stop the schedule feed: {}", err);
+                        return None;
+                    }
+                };
+                self.revision = Some(revision);
+                self.boundary = next_boundary(&site, at);
+                let result = now_next(&site, at, self.room.clone());
+                debug!("Schedule feed updated at {}", at);
+
+                return Some(result.iter().map(|it| it.into()).collect());
+            }
+
+            let wait = self
+                .boundary
+                .and_then(|it| (it - at).to_std().ok())
+                .map(|it| it.min(SCHEDULE_POLL))
+                .unwrap_or(SCHEDULE_POLL);
+            delay_for(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::time::timeout;
+
+    use dftk_common::models::schedule::ScheduleDay;
+    use dftk_common::models::site::SiteInfo;
+
+    use super::*;
+    use crate::tests::memory_context;
+
+    #[tokio::test]
+    async fn should_push_the_schedule_changes_after_the_last_slot() {
+        let repos = memory_context().await.repos();
+        let info: SiteInfo = serde_json::from_value(json!({
+            "_id": "devfest-2020",
+            "name": "DevFest Toulouse",
+            "address": {
+                "locality": {"long_name": "Toulouse", "short_name": "Toulouse"},
+                "country": {"long_name": "France", "short_name": "FR"},
+                "lat_lng": {"lat": 43.6, "lng": 1.44}
+            },
+            "languages": {"main": "fr", "others": ["en"]},
+            "dates": {"start": "2020-10-15T07:00:00Z", "end": "2020-10-15T18:00:00Z"}
+        }))
+        .unwrap();
+        repos.info().insert(&info).await.unwrap();
+        let mut feed = ScheduleFeed::new(repos.clone(), None, None);
+
+        assert!(feed.next().await.is_some());
+        let pending = timeout(Duration::from_millis(100), feed.next()).await;
+        assert!(pending.is_err());
+
+        let day = ScheduleDay::new(Utc::now(), vec![]);
+        repos.update_schedule(&[day], None).await.unwrap();
+        assert!(feed.next().await.is_some());
+    }
+}
@@ -2,11 +2,15 @@ use std::str::FromStr;
 
 use anyhow::{anyhow, Result};
 use async_graphql::{Enum, InputObject, SimpleObject};
+use chrono::{DateTime, Utc};
 
+use dftk_common::acl::role::RoleKey;
 use dftk_common::acl::user::{Email, User};
 use dftk_common::models::speaker::SpeakerKey;
 use dftk_common::models::sponsor::SponsorKey;
 
+use crate::accounts::{AccountEntry, AccountReport};
+
 #[Enum]
 pub enum UserKind {
     Admin,
@@ -15,14 +19,80 @@ pub enum UserKind {
     Sponsor,
 }
 
+/// The created user, invited to choose its password
 #[SimpleObject]
 pub struct UserCreateOutput {
-    new_password: String,
+    email: String,
+    invitation_expires_at: DateTime<Utc>,
 }
 
 impl UserCreateOutput {
-    pub fn new(new_password: String) -> Self {
-        Self { new_password }
+    pub fn new(email: String, invitation_expires_at: DateTime<Utc>) -> Self {
+        Self {
+            email,
+            invitation_expires_at,
+        }
+    }
+}
+
+/// A speaker or a sponsor key, with the e-mail of its account
+#[SimpleObject]
+pub struct AccountEntryOutputType {
+    key: String,
+    email: String,
+}
+
+impl From<AccountEntry> for AccountEntryOutputType {
+    fn from(entry: AccountEntry) -> Self {
+        let AccountEntry { key, email } = entry;
+
+        Self { key, email }
+    }
+}
+
+#[SimpleObject]
+pub struct AccountFailureOutputType {
+    key: String,
+    email: String,
+    error: String,
+}
+
+/// The outcome of a bulk creation of accounts, the existing users are skipped
+#[SimpleObject]
+pub struct AccountReportOutputType {
+    /// The invited users
+    created: Vec<AccountEntryOutputType>,
+    /// The users without password nor pending invitation, invited again
+    reinvited: Vec<AccountEntryOutputType>,
+    /// The e-mails already used by a user
+    existing: Vec<AccountEntryOutputType>,
+    /// The speakers or sponsors without e-mail in the mapping
+    without_email: Vec<String>,
+    /// The keys of the mapping not matching an accepted speaker or a sponsor
+    unknown_keys: Vec<String>,
+    failed: Vec<AccountFailureOutputType>,
+}
+
+impl From<AccountReport> for AccountReportOutputType {
+    fn from(report: AccountReport) -> Self {
+        let failed = report
+            .failed
+            .into_iter()
+            .map(|it| AccountFailureOutputType {
+                key: it.key,
+                email: it.email,
+                error: it.error,
+            })
+            .collect();
+
+        Self {
+            created: report.created.into_iter().map(Into::into).collect(),
+            reinvited: report.reinvited.into_iter().map(Into::into).collect(),
+            existing: report.existing.into_iter().map(Into::into).collect(),
+            without_email: report.without_email,
+            unknown_keys: report.unknown_keys,
+            failed,
+        }
     }
 }
 
@@ -31,27 +101,50 @@ pub struct UserCreateInput {
     kind: UserKind,
     email: String,
     key: Option<String>,
+    /// The roles of a team member
+    roles: Option<Vec<String>>,
 }
 
 pub fn to_user(user: &UserCreateInput) -> Result<User> {
-    let UserCreateInput { kind, email, key } = user;
+    let UserCreateInput {
+        kind,
+        email,
+        key,
+        roles,
+    } = user;
     let email = Email::from_str(email.as_str())?;
     let user = match kind {
         UserKind::Admin => User::Admin { email },
-        UserKind::Team => User::Team { email },
+        UserKind::Team => {
+            let roles = roles
+                .clone()
+                .unwrap_or_default()
+                .iter()
+                .map(|it| RoleKey::new(it.as_str()))
+                .collect();
+            User::Team { email, roles }
+        }
         UserKind::Speaker => {
             let key = key
                 .clone()
                 .ok_or_else(|| anyhow!("Expected a the speaker key"))?;
-            let key = SpeakerKey::new(key.as_str());
-            User::Speaker { email, key }
+            let key = SpeakerKey::from_str(key.as_str())?;
+            User::Speaker {
+                email,
+                key,
+                event: None,
+            }
         }
         UserKind::Sponsor => {
             let key = key
                 .clone()
                 .ok_or_else(|| anyhow!("Expected a the sponsor key"))?;
-            let key = SponsorKey::new(key.as_str());
-            User::Sponsor { email, key }
+            let key = SponsorKey::from_str(key.as_str())?;
+            User::Sponsor {
+                email,
+                key,
+                event: None,
+            }
         }
     };
 
@@ -3,20 +3,32 @@ extern crate log;
 
 use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use anyhow::Result;
 use warp::filters::BoxedFilter;
-use warp::{Filter, Reply};
+use warp::{Filter, Rejection, Reply};
 
+use dftk_common::models::site::EventId;
 use dftk_conference_hall::ConferenceHallConfig;
+use dftk_database::login_attempts::ThrottleConfig;
+use dftk_database::user::PasswordPolicy;
 use dftk_database::{MongodbConfig, Repositories};
 use dftk_hugo_site::SiteConfig;
 
-use crate::authentication::build_auth_routes;
-use crate::rejection::handle_rejection;
+use crate::authentication::{build_auth_routes, with_user};
+use crate::mailer::{Mailer, MailerConfig};
+use crate::oidc::OidcConfig;
+use crate::rejection::{handle_rejection, Oops};
+use crate::token::{TokenConfig, Tokens};
 
+mod accounts;
 pub mod authentication;
+pub mod mailer;
+pub mod oidc;
+mod password;
 pub mod rejection;
+pub mod token;
 
 #[cfg(feature = "rest")]
 pub mod rest;
@@ -32,15 +44,75 @@ pub struct ServerConfig {
     pub port: u32,
     pub graphql_path: String,
     pub rest_path: String,
+    pub token: TokenConfig,
+    pub mailer: MailerConfig,
+    pub password_policy: PasswordPolicy,
+    pub throttle: ThrottleConfig,
+    /// The proxies appending the client address to the `X-Forwarded-For` header,
+    /// without proxy the client address is the remote address
+    pub trusted_proxies: usize,
+    pub oidc: Option<OidcConfig>,
 }
 
 impl ServerConfig {
     pub fn new(host: String, port: u32, graphql_path: String, rest_path: String) -> Self {
+        let token = TokenConfig::default();
+        let mailer = MailerConfig::default();
+        let password_policy = PasswordPolicy::default();
+        let throttle = ThrottleConfig::default();
+        let trusted_proxies = 0;
+        let oidc = None;
+
         ServerConfig {
             host,
             port,
             graphql_path,
             rest_path,
+            token,
+            mailer,
+            password_policy,
+            throttle,
+            trusted_proxies,
+            oidc,
+        }
+    }
+
+    /// Sign the session tokens with these secrets
+    pub fn with_token(self, token: TokenConfig) -> Self {
+        Self { token, ..self }
+    }
+
+    /// Send the invitations and the password resets with this mailer
+    pub fn with_mailer(self, mailer: MailerConfig) -> Self {
+        Self { mailer, ..self }
+    }
+
+    /// Check the new passwords with this policy
+    pub fn with_password_policy(self, password_policy: PasswordPolicy) -> Self {
+        Self {
+            password_policy,
+            ..self
+        }
+    }
+
+    /// Lock the logins after too many failures
+    pub fn with_throttle(self, throttle: ThrottleConfig) -> Self {
+        Self { throttle, ..self }
+    }
+
+    /// Read the client address from the `X-Forwarded-For` header set by these proxies
+    pub fn with_trusted_proxies(self, trusted_proxies: usize) -> Self {
+        Self {
+            trusted_proxies,
+            ..self
+        }
+    }
+
+    /// Also login with this identity provider
+    pub fn with_oidc(self, oidc: OidcConfig) -> Self {
+        Self {
+            oidc: Some(oidc),
+            ..self
         }
     }
 }
@@ -51,12 +123,24 @@ impl Default for ServerConfig {
         let port = 8080;
         let graphql_path = "graphql".into();
         let rest_path = "api".into();
+        let token = TokenConfig::default();
+        let mailer = MailerConfig::default();
+        let password_policy = PasswordPolicy::default();
+        let throttle = ThrottleConfig::default();
+        let trusted_proxies = 0;
+        let oidc = None;
 
         ServerConfig {
             host,
             port,
             graphql_path,
             rest_path,
+            token,
+            mailer,
+            password_policy,
+            throttle,
+            trusted_proxies,
+            oidc,
         }
     }
 }
@@ -68,6 +152,8 @@ pub struct ServerContext {
     mongo_config: MongodbConfig,
     server_config: ServerConfig,
     repos: Repositories,
+    tokens: Tokens,
+    mailer: Arc<dyn Mailer>,
 }
 
 impl ServerContext {
@@ -78,12 +164,16 @@ impl ServerContext {
         server_config: ServerConfig,
     ) -> Result<Self> {
         let repos = Repositories::build(&mongo_config).await?;
+        let tokens = Tokens::new(&server_config.token);
+        let mailer = server_config.mailer.build();
         let result = ServerContext {
             site_config,
             ch_config,
             mongo_config,
             server_config,
             repos,
+            tokens,
+            mailer,
         };
 
         Ok(result)
@@ -104,12 +194,49 @@ impl ServerContext {
     pub fn repos(&self) -> Repositories {
         self.repos.clone()
     }
+    pub fn tokens(&self) -> Tokens {
+        self.tokens.clone()
+    }
+    pub fn mailer(&self) -> Arc<dyn Mailer> {
+        self.mailer.clone()
+    }
 }
 
+/// The repositories of the requested event, recording the mutations on behalf of the authenticated user
 fn with_repo(
+    context: &ServerContext,
+) -> impl Filter<Extract = (Repositories,), Error = Rejection> + Clone {
+    let repos = context.repos();
+    warp::any()
+        .and(with_user(context))
+        .and(with_event())
+        .and_then(move |user, event| {
+            let repos = repos.clone();
+            async move {
+                let repos = event_repositories(repos, event).await?;
+                Ok::<_, Rejection>(repos.as_user(Some(user)))
+            }
+        })
+}
+
+/// The event of the `X-Event` header, if any
+fn with_event() -> impl Filter<Extract = (Option<EventId>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("x-event").map(|event: Option<String>| event.map(EventId::new))
+}
+
+/// The repositories of the event, a unknown event is not found
+async fn event_repositories(
     repos: Repositories,
-) -> impl Filter<Extract = (Repositories,), Error = Infallible> + Clone {
-    warp::any().map(move || repos.clone())
+    event: Option<EventId>,
+) -> Result<Repositories, Rejection> {
+    match event {
+        Some(event) => repos
+            .existing_event(&event)
+            .await
+            .map_err(Oops::db)?
+            .ok_or_else(warp::reject::not_found),
+        None => Ok(repos),
+    }
 }
 
 fn with_context(
@@ -118,8 +245,6 @@ fn with_context(
     warp::any().map(move || context.clone())
 }
 
-// FIXME authentication authorization
-
 // GraphQL routes
 #[cfg(not(feature = "graphql"))]
 fn graphql_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
@@ -162,7 +287,8 @@ fn auth_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
 fn routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let cors = warp::cors()
         .allow_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
-        .allow_headers(vec!["Content-Type"])
+        .allow_headers(vec!["Authorization", "Content-Type", "If-Match", "X-Event"])
+        .expose_headers(vec!["ETag", "X-Total-Count"])
         .allow_any_origin();
 
     auth_routes(context)
@@ -186,3 +312,70 @@ pub async fn run_server(context: ServerContext) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use uuid::Uuid;
+
+    use dftk_common::acl::user::{Email, User};
+    use dftk_database::storage::StorageKind;
+
+    use super::*;
+    use crate::authentication::open_session;
+
+    /// A server without database, to drive the REST and GraphQL routes
+    pub(crate) async fn memory_context() -> ServerContext {
+        memory_context_with(ServerConfig::default()).await
+    }
+
+    pub(crate) async fn memory_context_with(server_config: ServerConfig) -> ServerContext {
+        let mongo_config = MongodbConfig::default().with_storage(StorageKind::Memory);
+
+        ServerContext::build(
+            SiteConfig::default(),
+            ConferenceHallConfig::new("".into(), "".into(), "".into()),
+            mongo_config,
+            server_config,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// A temporary directory receiving the mails
+    pub(crate) fn mail_dir() -> PathBuf {
+        std::env::temp_dir().join(Uuid::new_v4().to_string())
+    }
+
+    /// A server writing its mails to the directory
+    pub(crate) async fn context_with_mails(dir: PathBuf) -> ServerContext {
+        let mailer = MailerConfig::new(
+            "noreply@devfest.fr".into(),
+            "http://localhost:8080/#password".into(),
+            None,
+            Some(dir),
+        );
+
+        memory_context_with(ServerConfig::default().with_mailer(mailer)).await
+    }
+
+    /// The session token of an administrator
+    pub(crate) async fn admin_token(context: &ServerContext) -> String {
+        let user = User::Admin {
+            email: "admin@devfest.fr".parse().unwrap(),
+        };
+
+        user_token(context, &user).await
+    }
+
+    /// The session token of a user with an e-mail
+    pub(crate) async fn user_token(context: &ServerContext, user: &User) -> String {
+        let email: Email = user.email().expect("Expected a user with an e-mail");
+        let (token, _, _) = open_session(&context.repos(), context, user, &email)
+            .await
+            .unwrap_or_else(|_| panic!("Cannot open the session of {:?}", user));
+
+        token
+    }
+}
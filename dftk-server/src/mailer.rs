@@ -0,0 +1,231 @@
+//! Send the mails of the users, e.g. the invitations and the password resets
+
+use std::fmt::{self, Debug, Formatter};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use lettre::smtp::authentication::Credentials;
+use lettre::{SmtpClient, Transport};
+use lettre_email::EmailBuilder;
+
+use dftk_common::acl::user::Email;
+
+/// A plain text mail
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mail {
+    pub to: Email,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    pub fn invitation(to: Email, link: &str) -> Self {
+        let subject = "Your DevFest toolkit account".into();
+        let body = format!(
+            "Hello,\n\n\
+             An account has been created for you, choose your password with this link:\n\n\
+             {}\n\n\
+             The link can be used once, and expires in a few days.\n",
+            link
+        );
+
+        Self { to, subject, body }
+    }
+
+    pub fn password_reset(to: Email, link: &str) -> Self {
+        let subject = "Reset your DevFest toolkit password".into();
+        let body = format!(
+            "Hello,\n\n\
+             Choose a new password with this link:\n\n\
+             {}\n\n\
+             The link can be used once, and expires in an hour.\n\
+             Ignore this mail if you did not ask to reset your password.\n",
+            link
+        );
+
+        Self { to, subject, body }
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+/// The SMTP server sending the mails, using TLS on the submission port
+#[derive(Clone)]
+pub struct SmtpConfig {
+    host: String,
+    credentials: Option<(String, String)>,
+}
+
+impl SmtpConfig {
+    pub fn new(host: String, credentials: Option<(String, String)>) -> Self {
+        Self { host, credentials }
+    }
+}
+
+impl Debug for SmtpConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmtpConfig")
+            .field("host", &self.host)
+            .field("username", &self.credentials.as_ref().map(|it| &it.0))
+            .finish()
+    }
+}
+
+/// How the mails are sent, and where the links of the mails lead
+#[derive(Clone, Debug)]
+pub struct MailerConfig {
+    from: String,
+    password_url: String,
+    smtp: Option<SmtpConfig>,
+    dir: Option<PathBuf>,
+}
+
+impl MailerConfig {
+    /// Without SMTP server, the mails are logged and written into the directory if any
+    pub fn new(
+        from: String,
+        password_url: String,
+        smtp: Option<SmtpConfig>,
+        dir: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            from,
+            password_url,
+            smtp,
+            dir,
+        }
+    }
+
+    /// The link to the page choosing a password with the token
+    pub fn password_link(&self, token: &str) -> String {
+        let separator = if self.password_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+
+        format!("{}{}token={}", self.password_url, separator, token)
+    }
+
+    pub fn build(&self) -> Arc<dyn Mailer> {
+        match &self.smtp {
+            Some(smtp) => Arc::new(SmtpMailer {
+                from: self.from.clone(),
+                config: smtp.clone(),
+            }),
+            None => Arc::new(FileMailer {
+                from: self.from.clone(),
+                dir: self.dir.clone(),
+            }),
+        }
+    }
+}
+
+impl Default for MailerConfig {
+    fn default() -> Self {
+        Self::new(
+            "noreply@localhost".into(),
+            "http://localhost:8080/#password".into(),
+            None,
+            None,
+        )
+    }
+}
+
+struct SmtpMailer {
+    from: String,
+    config: SmtpConfig,
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        let to: String = mail.to.clone().into();
+        let email = EmailBuilder::new()
+            .to(to.as_str())
+            .from(self.from.as_str())
+            .subject(mail.subject.as_str())
+            .text(mail.body.as_str())
+            .build()
+            .map_err(|err| anyhow!("Invalid mail to {}: {}", to, err))?;
+        let SmtpConfig { host, credentials } = self.config.clone();
+
+        // The lettre transport is blocking
+        tokio::task::spawn_blocking(move || {
+            let client = SmtpClient::new_simple(host.as_str())
+                .map_err(|err| anyhow!("Cannot connect to {}: {}", host, err))?;
+            let client = match credentials {
+                Some((username, password)) => {
+                    client.credentials(Credentials::new(username, password))
+                }
+                None => client,
+            };
+            client
+                .transport()
+                .send(email.into())
+                .map_err(|err| anyhow!("Cannot send the mail to {}: {}", to, err))?;
+            info!("Mail sent to {}", to);
+
+            Ok(())
+        })
+        .await?
+    }
+}
+
+/// Log the mails, for a local use
+struct FileMailer {
+    from: String,
+    dir: Option<PathBuf>,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: &Mail) -> Result<()> {
+        let to: String = mail.to.clone().into();
+        let content = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}",
+            self.from, to, mail.subject, mail.body
+        );
+        info!("Mail to {}\n{}", to, content);
+
+        if let Some(dir) = &self.dir {
+            tokio::fs::create_dir_all(dir).await?;
+            let file = dir.join(format!("{}-{}.eml", Utc::now().timestamp_millis(), to));
+            tokio::fs::write(&file, content).await?;
+            debug!("Mail written into {:?}", file);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_build_password_link() {
+        let config = MailerConfig::default();
+        assert_eq!(
+            config.password_link("abc.def"),
+            "http://localhost:8080/#password?token=abc.def"
+        );
+
+        let config = MailerConfig::new(
+            "noreply@devfest.fr".into(),
+            "https://devfest.fr/admin?page=password".into(),
+            None,
+            None,
+        );
+        assert_eq!(
+            config.password_link("abc.def"),
+            "https://devfest.fr/admin?page=password&token=abc.def"
+        );
+    }
+}
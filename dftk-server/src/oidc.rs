@@ -0,0 +1,634 @@
+//! Login with an OpenID Connect identity provider, using the authorization code flow
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use anyhow::{anyhow, ensure, Result};
+use chrono::{Duration, Utc};
+use cookie::{Cookie, SameSite};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+use warp::filters::BoxedFilter;
+use warp::http::{Response, StatusCode};
+use warp::{Filter, Rejection, Reply};
+
+use dftk_common::acl::user::{Email, User};
+use dftk_database::login_attempts::LoginOutcome;
+use dftk_database::Repositories;
+
+use crate::authentication::{open_session, with_client_ip};
+use crate::rejection::Oops;
+use crate::{with_context, with_repo, ServerContext};
+
+/// The cookie binding the callback to the browser starting the login
+const STATE_COOKIE: &str = "oidc_state";
+
+/// The lifetime of a pending login
+fn state_ttl() -> Duration {
+    Duration::minutes(10)
+}
+
+/// The lifetime of the discovery document
+fn discovery_ttl() -> std::time::Duration {
+    std::time::Duration::from_secs(3600)
+}
+
+/// The identity provider, and the domains allowed to use it
+#[derive(Clone)]
+pub struct OidcConfig {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    redirect_url: String,
+    allowed_domains: Vec<String>,
+    post_login_url: String,
+    discovery: Arc<RwLock<Option<(Instant, Discovery)>>>,
+}
+
+impl OidcConfig {
+    /// Only the existing accounts login, the unknown e-mails are denied.
+    /// Without allowed domain, every domain is allowed
+    pub fn new(
+        issuer: String,
+        client_id: String,
+        client_secret: String,
+        redirect_url: String,
+        allowed_domains: Vec<String>,
+        post_login_url: String,
+    ) -> Self {
+        let allowed_domains = allowed_domains
+            .iter()
+            .map(|it| it.trim().to_lowercase())
+            .filter(|it| !it.is_empty())
+            .collect();
+
+        Self {
+            issuer,
+            client_id,
+            client_secret,
+            redirect_url,
+            allowed_domains,
+            post_login_url,
+            discovery: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    fn is_allowed(&self, email: &Email) -> bool {
+        let address: String = email.clone().into();
+        let domain = address
+            .trim_end_matches('>')
+            .rsplit('@')
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        self.allowed_domains.is_empty() || self.allowed_domains.contains(&domain)
+    }
+}
+
+impl Debug for OidcConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OidcConfig")
+            .field("issuer", &self.issuer)
+            .field("client_id", &self.client_id)
+            .field("redirect_url", &self.redirect_url)
+            .field("allowed_domains", &self.allowed_domains)
+            .field("post_login_url", &self.post_login_url)
+            .finish()
+    }
+}
+
+/// Provide OpenID Connect routes, not found without an identity provider
+///
+/// `GET    oidc/login`: redirect to the identity provider
+///
+/// `GET    oidc/callback`: open the session of the verified e-mail, and redirect to the application
+pub fn build_oidc_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let login = warp::path!("oidc" / "login")
+        .and(warp::get())
+        .and(with_oidc(context))
+        .and(with_repo(context))
+        .and_then(do_oidc_login);
+
+    let callback = warp::path!("oidc" / "callback")
+        .and(warp::get())
+        .and(with_oidc(context))
+        .and(with_repo(context))
+        .and(with_context(context.clone()))
+        .and(with_client_ip(context))
+        .and(warp::cookie::optional(STATE_COOKIE))
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(do_oidc_callback);
+
+    login.or(callback).boxed()
+}
+
+fn with_oidc(
+    context: &ServerContext,
+) -> impl Filter<Extract = (OidcConfig,), Error = Rejection> + Clone {
+    let config = context.server_config().oidc;
+
+    warp::any().and_then(move || {
+        let config = config.clone();
+        async move { config.ok_or_else(warp::reject::not_found) }
+    })
+}
+
+/// The endpoints of the identity provider
+#[derive(Deserialize, Debug, Clone)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+/// The discovery document, fetched again once expired
+async fn discover(config: &OidcConfig) -> Result<Discovery> {
+    let cached = config
+        .discovery
+        .read()
+        .map_err(|_| anyhow!("Poisoned discovery cache"))?
+        .clone();
+    if let Some((fetched_at, discovery)) = cached {
+        if fetched_at.elapsed() < discovery_ttl() {
+            return Ok(discovery);
+        }
+    }
+
+    let discovery = fetch_discovery(config).await?;
+    let mut cache = config
+        .discovery
+        .write()
+        .map_err(|_| anyhow!("Poisoned discovery cache"))?;
+    *cache = Some((Instant::now(), discovery.clone()));
+
+    Ok(discovery)
+}
+
+async fn fetch_discovery(config: &OidcConfig) -> Result<Discovery> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        config.issuer.trim_end_matches('/')
+    );
+    let client = reqwest::Client::new();
+    let response = client.get(&url).send().await?.error_for_status()?;
+    let discovery = response.json::<Discovery>().await?;
+    ensure!(
+        discovery.issuer.trim_end_matches('/') == config.issuer.trim_end_matches('/'),
+        "Unexpected issuer {}",
+        discovery.issuer
+    );
+
+    Ok(discovery)
+}
+
+async fn do_oidc_login(
+    config: OidcConfig,
+    repos: Repositories,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let discovery = discover(&config).await.map_err(Oops::other)?;
+    let state = Uuid::new_v4().to_string();
+    let nonce = Uuid::new_v4().to_string();
+    repos
+        .oidc_state()
+        .create(state.as_str(), nonce.as_str(), state_ttl())
+        .await
+        .map_err(Oops::db)?;
+
+    let params = [
+        ("response_type", "code"),
+        ("client_id", config.client_id.as_str()),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("scope", "openid email"),
+        ("state", state.as_str()),
+        ("nonce", nonce.as_str()),
+    ];
+    let url = Url::parse_with_params(discovery.authorization_endpoint.as_str(), &params)
+        .map_err(|err| Oops::other(err.into()))?;
+    // Lax, the callback is a navigation from the identity provider
+    let cookie = Cookie::build(STATE_COOKIE, state)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(state_ttl().num_seconds()))
+        .finish();
+
+    let result = Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", url.as_str())
+        .header("Set-Cookie", cookie.to_string())
+        .body("")
+        .map_err(|err| Oops::other(err.into()))?;
+
+    Ok(result)
+}
+
+/// The verified e-mail is mapped to its existing user,
+/// the login is throttled and recorded like a password login
+async fn do_oidc_callback(
+    config: OidcConfig,
+    repos: Repositories,
+    context: ServerContext,
+    ip: Option<IpAddr>,
+    state_cookie: Option<String>,
+    query: HashMap<String, String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Some(error) = query.get("error") {
+        let err = anyhow!("Denied by the identity provider: {}", error);
+        return Err(Oops::auth(err));
+    }
+    let code = query.get("code").ok_or_else(|| Oops::missing("code"))?;
+    let state = query.get("state").ok_or_else(|| Oops::missing("state"))?;
+    if state_cookie.as_ref() != Some(state) {
+        return Err(Oops::auth(anyhow!("The state does not match the browser")));
+    }
+    let nonce = repos
+        .oidc_state()
+        .consume(state.as_str())
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(|| Oops::auth(anyhow!("Unknown or expired state")))?;
+
+    let discovery = discover(&config).await.map_err(Oops::other)?;
+    let id_token = exchange_code(&config, &discovery, code.as_str())
+        .await
+        .map_err(Oops::auth)?;
+    let claims = decode_id_token(id_token.as_str()).map_err(Oops::auth)?;
+    let email = check_claims(
+        &claims,
+        discovery.issuer.as_str(),
+        config.client_id.as_str(),
+        nonce.as_str(),
+    )
+    .map_err(Oops::auth)?;
+
+    let throttle = context.server_config().throttle;
+    let attempts = repos.login_attempt();
+    let locked_until = attempts
+        .reserve(&email, ip, &throttle)
+        .await
+        .map_err(Oops::db)?;
+    if let Some(until) = locked_until {
+        attempts
+            .record(&email, ip, LoginOutcome::Locked)
+            .await
+            .map_err(Oops::db)?;
+        return Err(Oops::locked(until));
+    }
+
+    let result = find_user(&repos, &config, &email).await;
+    let outcome = if result.is_ok() {
+        LoginOutcome::Success
+    } else {
+        LoginOutcome::Failure
+    };
+    attempts
+        .record(&email, ip, outcome)
+        .await
+        .map_err(Oops::db)?;
+    let user = result?;
+    info!("User authenticated by the identity provider {:?}", user);
+    let (_, _, cookie) = open_session(&repos, &context, &user, &email).await?;
+
+    let state_cookie = Cookie::build(STATE_COOKIE, "")
+        .path("/")
+        .max_age(time::Duration::zero())
+        .finish();
+    let result = Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header("Location", config.post_login_url.as_str())
+        .header("Set-Cookie", cookie.to_string())
+        .header("Set-Cookie", state_cookie.to_string())
+        .body("")
+        .map_err(|err| Oops::other(err.into()))?;
+
+    Ok(result)
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+async fn exchange_code(config: &OidcConfig, discovery: &Discovery, code: &str) -> Result<String> {
+    let params = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", config.client_secret.as_str()),
+    ];
+    let client = reqwest::Client::new();
+    let response = client
+        .post(&discovery.token_endpoint)
+        .form(&params)
+        .send()
+        .await?
+        .error_for_status()?;
+    let token = response.json::<TokenResponse>().await?;
+
+    Ok(token.id_token)
+}
+
+/// The claims of an ID token
+#[derive(Deserialize, Debug)]
+struct IdClaims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    email: Option<String>,
+    /// Some providers send a string
+    email_verified: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|it| it == client_id),
+        }
+    }
+}
+
+/// Read the claims without checking the signature,
+/// the token comes straight from the token endpoint over TLS
+fn decode_id_token(id_token: &str) -> Result<IdClaims> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| anyhow!("Invalid ID token"))?;
+    let payload = base64::decode_config(payload.trim_end_matches('='), base64::URL_SAFE_NO_PAD)?;
+    let claims = serde_json::from_slice(&payload)?;
+
+    Ok(claims)
+}
+
+/// The verified e-mail of the claims
+fn check_claims(claims: &IdClaims, issuer: &str, client_id: &str, nonce: &str) -> Result<Email> {
+    ensure!(
+        claims.iss.trim_end_matches('/') == issuer.trim_end_matches('/'),
+        "Unexpected issuer {}",
+        claims.iss
+    );
+    ensure!(claims.aud.contains(client_id), "Unexpected audience");
+    ensure!(claims.exp > Utc::now().timestamp(), "Expired ID token");
+    ensure!(
+        claims.nonce.as_deref() == Some(nonce),
+        "Unexpected nonce in the ID token"
+    );
+    let verified = match &claims.email_verified {
+        Some(Value::Bool(verified)) => *verified,
+        Some(Value::String(verified)) => verified == "true",
+        _ => false,
+    };
+    ensure!(verified, "The e-mail is not verified");
+    let email = claims
+        .email
+        .as_ref()
+        .ok_or_else(|| anyhow!("Missing e-mail in the ID token"))?;
+
+    Email::from_str(email.as_str())
+}
+
+/// The user of the e-mail, no account is created for an unknown e-mail
+async fn find_user(
+    repos: &Repositories,
+    config: &OidcConfig,
+    email: &Email,
+) -> Result<User, Rejection> {
+    if !config.is_allowed(email) {
+        let err = anyhow!("Domain not allowed for {:?}", email);
+        return Err(Oops::auth(err));
+    }
+
+    repos
+        .user()
+        .find_by_email(email)
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(|| Oops::auth(anyhow!("No account for {:?}", email)))
+}
+
+/// The stand-in identity provider of the example
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../examples/oidc_provider.rs"]
+mod provider;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use dftk_database::login_attempts::ThrottleConfig;
+
+    use super::*;
+    use crate::routes;
+    use crate::tests::memory_context_with;
+    use crate::ServerConfig;
+
+    fn claims(value: Value) -> IdClaims {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn valid() -> Value {
+        json!({
+            "iss": "https://accounts.google.com",
+            "aud": "dftk",
+            "exp": Utc::now().timestamp() + 300,
+            "nonce": "n1",
+            "email": "team@devfest.fr",
+            "email_verified": true
+        })
+    }
+
+    #[test]
+    fn should_check_claims() {
+        let issuer = "https://accounts.google.com";
+        let email = check_claims(&claims(valid()), issuer, "dftk", "n1").unwrap();
+        assert_eq!(email, Email::from_str("team@devfest.fr").unwrap());
+
+        let mut value = valid();
+        value["aud"] = json!(["other", "dftk"]);
+        value["email_verified"] = json!("true");
+        assert!(check_claims(&claims(value), issuer, "dftk", "n1").is_ok());
+
+        assert!(check_claims(&claims(valid()), issuer, "other", "n1").is_err());
+        assert!(check_claims(&claims(valid()), issuer, "dftk", "n2").is_err());
+        assert!(check_claims(&claims(valid()), "https://evil.com", "dftk", "n1").is_err());
+        let mut value = valid();
+        value["email_verified"] = json!(false);
+        assert!(check_claims(&claims(value), issuer, "dftk", "n1").is_err());
+        let mut value = valid();
+        value["exp"] = json!(Utc::now().timestamp() - 1);
+        assert!(check_claims(&claims(value), issuer, "dftk", "n1").is_err());
+    }
+
+    #[test]
+    fn should_decode_id_token() {
+        let payload = base64::encode_config(valid().to_string(), base64::URL_SAFE_NO_PAD);
+        let id_token = format!("eyJhbGciOiJub25lIn0.{}.", payload);
+
+        let result = decode_id_token(id_token.as_str()).unwrap();
+        assert_eq!(result.email, Some("team@devfest.fr".into()));
+        assert!(decode_id_token("invalid").is_err());
+    }
+
+    #[test]
+    fn should_allow_domains() {
+        let config = OidcConfig::new(
+            "https://accounts.google.com".into(),
+            "dftk".into(),
+            "secret".into(),
+            "http://localhost:8080/auth/oidc/callback".into(),
+            vec!["DevFest.fr".into(), " ".into()],
+            "http://localhost:8080/".into(),
+        );
+
+        assert!(config.is_allowed(&Email::from_str("team@devfest.fr").unwrap()));
+        assert!(!config.is_allowed(&Email::from_str("team@gmail.com").unwrap()));
+        assert!(!format!("{:?}", config).contains("secret"));
+
+        let config = oidc_config("https://accounts.google.com".into(), vec![]);
+        assert!(config.is_allowed(&Email::from_str("team@gmail.com").unwrap()));
+    }
+
+    fn oidc_config(issuer: String, allowed_domains: Vec<String>) -> OidcConfig {
+        OidcConfig::new(
+            issuer,
+            "dftk".into(),
+            "secret".into(),
+            "http://localhost:8080/auth/oidc/callback".into(),
+            allowed_domains,
+            "http://localhost:8080/".into(),
+        )
+    }
+
+    /// A server using the stand-in identity provider, on an ephemeral port
+    async fn context_with_provider(allowed_domains: Vec<String>) -> ServerContext {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        let provider = warp::serve(provider::routes(issuer.clone()));
+        tokio::spawn(provider.run_incoming(listener));
+
+        let throttle = ThrottleConfig {
+            max_failures_per_email: 2,
+            ..ThrottleConfig::default()
+        };
+        let server_config = ServerConfig::default()
+            .with_throttle(throttle)
+            .with_oidc(oidc_config(issuer, allowed_domains));
+
+        memory_context_with(server_config).await
+    }
+
+    fn query_param(location: &str, name: &str) -> String {
+        let url = Url::parse(location).unwrap();
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap_or_else(|| panic!("Missing {} in {}", name, location))
+    }
+
+    /// Drive the login, the approval by the identity provider, then the callback
+    async fn oidc_login(context: &ServerContext, email: &str) -> Response<bytes::Bytes> {
+        let response = warp::test::request()
+            .path("/auth/oidc/login")
+            .reply(&routes(context))
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()["Location"].to_str().unwrap();
+        let authorize = format!("{}&login_hint={}", location, email);
+
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let approval = client.get(&authorize).send().await.unwrap();
+        assert_eq!(approval.status().as_u16(), StatusCode::SEE_OTHER.as_u16());
+        let callback = approval.headers()["Location"].to_str().unwrap();
+        let code = query_param(callback, "code");
+        let state = query_param(callback, "state");
+        let path = format!("/auth/oidc/callback?code={}&state={}", code, state);
+
+        warp::test::request()
+            .path(path.as_str())
+            .header("Cookie", format!("{}={}", STATE_COOKIE, state))
+            .reply(&routes(context))
+            .await
+    }
+
+    #[tokio::test]
+    async fn should_login_existing_users_with_the_provider() {
+        let context = context_with_provider(vec!["devfest.fr".into()]).await;
+        let users = context.repos().user();
+        for email in &["jane@devfest.fr", "john@gmail.com"] {
+            let user = User::Team {
+                email: email.parse().unwrap(),
+                roles: vec![],
+            };
+            users.new_user(user).await.unwrap();
+        }
+
+        let response = oidc_login(&context, "jane@devfest.fr").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()["Location"], "http://localhost:8080/");
+        let cookies = response.headers().get_all("Set-Cookie").iter().count();
+        assert_eq!(
+            cookies, 2,
+            "Expected the session and the cleared state cookies"
+        );
+        let config = context.server_config().oidc.unwrap();
+        assert!(config.discovery.read().unwrap().is_some());
+
+        // No account is created for an unknown e-mail
+        let response = oidc_login(&context, "bob@devfest.fr").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let bob = Email::from_str("bob@devfest.fr").unwrap();
+        assert_eq!(users.find_by_email(&bob).await.unwrap(), None);
+
+        let response = oidc_login(&context, "john@gmail.com").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn should_lock_the_provider_logins() {
+        let context = context_with_provider(vec![]).await;
+        let email = Email::from_str("jane@devfest.fr").unwrap();
+        let user = User::Team {
+            email: email.clone(),
+            roles: vec![],
+        };
+        context.repos().user().new_user(user).await.unwrap();
+        let throttle = context.server_config().throttle;
+        for _ in 0..2 {
+            let attempts = context.repos().login_attempt();
+            assert!(attempts
+                .reserve(&email, None, &throttle)
+                .await
+                .unwrap()
+                .is_none());
+            attempts
+                .record(&email, None, LoginOutcome::Failure)
+                .await
+                .unwrap();
+        }
+
+        let response = oidc_login(&context, "jane@devfest.fr").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use dftk_common::acl::user::{Email, User};
+use dftk_database::password_tokens::PasswordTokenKind;
+use dftk_database::Repositories;
+
+use crate::mailer::Mail;
+use crate::rejection::Oops;
+use crate::{with_context, with_repo, ServerContext, MAX_BODY_LENGTH};
+
+/// The lifetime of an invitation
+fn invitation_ttl() -> Duration {
+    Duration::days(7)
+}
+
+/// The lifetime of a password reset
+fn reset_ttl() -> Duration {
+    Duration::hours(1)
+}
+
+/// The delay before another password reset of a user with a pending token
+fn reset_cooldown() -> Duration {
+    Duration::minutes(5)
+}
+
+/// Provide password routes, without authentication
+///
+/// `POST   password/forgot`: send a password reset to the `email` of the form,
+/// at most once by cooldown, the pending invitation is kept
+///
+/// `POST   password/reset`: redeem the `token` of an invitation or a reset, and set the `password` of the form
+pub fn build_password_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let forgot = warp::path!("password" / "forgot")
+        .and(warp::post())
+        .and(with_repo(context))
+        .and(with_context(context.clone()))
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::form())
+        .and_then(do_forgot_password);
+
+    let reset = warp::path!("password" / "reset")
+        .and(warp::post())
+        .and(with_repo(context))
+        .and(with_context(context.clone()))
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::form())
+        .and_then(do_reset_password);
+
+    forgot.or(reset).boxed()
+}
+
+/// Create the user, and send the invitation to choose its password,
+/// returning the expiry of the invitation
+///
+/// A speaker or a sponsor user should match an existing speaker or sponsor,
+/// it is bound to the current event,
+/// the user is removed if the invitation cannot be sent.
+pub(crate) async fn invite_user(
+    context: &ServerContext,
+    repos: &Repositories,
+    user: User,
+) -> Result<DateTime<Utc>> {
+    let email = user
+        .email()
+        .ok_or_else(|| anyhow!("Cannot invite {:?}", user))?;
+    let user = user.in_event(repos.event());
+    repos.check_user(&user).await?;
+    repos.user().new_user(user).await?;
+
+    match send_invitation(context, repos, &email).await {
+        Ok(expires_at) => Ok(expires_at),
+        Err(err) => {
+            // Without the invitation the user could never choose its password
+            repos.password_token().revoke_all(&email).await?;
+            repos.user().delete_user(&email).await?;
+            info!("Removed the user {:?} without invitation", email);
+            Err(err)
+        }
+    }
+}
+
+/// Issue an invitation of the existing user, and send it,
+/// returning the expiry of the invitation
+pub(crate) async fn send_invitation(
+    context: &ServerContext,
+    repos: &Repositories,
+    email: &Email,
+) -> Result<DateTime<Utc>> {
+    let ttl = invitation_ttl();
+    let token = repos
+        .password_token()
+        .issue(email, PasswordTokenKind::Invitation, ttl)
+        .await?;
+    let link = context.server_config().mailer.password_link(token.as_str());
+    context
+        .mailer()
+        .send(&Mail::invitation(email.clone(), link.as_str()))
+        .await?;
+
+    Ok(Utc::now() + ttl)
+}
+
+/// The answer does not tell if the user exists
+async fn do_forgot_password(
+    repos: Repositories,
+    context: ServerContext,
+    form: HashMap<String, String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let email = form.get("email").ok_or_else(|| Oops::missing("email"))?;
+    let email = Email::from_str(email.as_str()).map_err(|err| Oops::bad("email", err))?;
+
+    let user = repos.user().find_by_email(&email).await.map_err(Oops::db)?;
+    let last_issued = repos
+        .password_token()
+        .last_issued(&email)
+        .await
+        .map_err(Oops::db)?;
+    if user.is_none() {
+        info!("Ignore the password reset of unknown user {:?}", email);
+    } else if let Some(at) = last_issued.filter(|it| Utc::now() - *it < reset_cooldown()) {
+        info!(
+            "Ignore the password reset of {:?}, a token was issued at {}",
+            email, at
+        );
+    } else if let Err(err) = send_password_reset(&context, &repos, email.clone()).await {
+        warn!("Cannot send the password reset to {:?}: {}", email, err);
+    }
+
+    Ok(warp::reply::with_status(
+        warp::reply(),
+        StatusCode::ACCEPTED,
+    ))
+}
+
+async fn send_password_reset(
+    context: &ServerContext,
+    repos: &Repositories,
+    email: Email,
+) -> Result<()> {
+    let token = repos
+        .password_token()
+        .issue(&email, PasswordTokenKind::Reset, reset_ttl())
+        .await?;
+    let link = context.server_config().mailer.password_link(token.as_str());
+
+    context
+        .mailer()
+        .send(&Mail::password_reset(email, link.as_str()))
+        .await
+}
+
+/// Set the password, the other tokens and the previous sessions of the user are revoked
+async fn do_reset_password(
+    repos: Repositories,
+    context: ServerContext,
+    form: HashMap<String, String>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let token = form.get("token").ok_or_else(|| Oops::missing("token"))?;
+    let password = form
+        .get("password")
+        .ok_or_else(|| Oops::missing("password"))?;
+    // Before consuming the token
+    let policy = context.server_config().password_policy;
+    policy.check(password.as_bytes()).map_err(Oops::db)?;
+
+    let redeemed = repos
+        .password_token()
+        .redeem(token.as_str())
+        .await
+        .map_err(Oops::auth)?;
+    info!("Redeemed {:?}", redeemed);
+    let email = redeemed.email;
+    repos
+        .user()
+        .set_password(&email, password.as_bytes(), &policy)
+        .await
+        .map_err(Oops::db)?;
+    repos
+        .password_token()
+        .revoke_all(&email)
+        .await
+        .map_err(Oops::db)?;
+    let revoked = repos
+        .user_session()
+        .revoke_all(&email)
+        .await
+        .map_err(Oops::db)?;
+    info!("Revoked {} session(s) of {:?}", revoked, email);
+
+    Ok(warp::reply())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::routes;
+    use crate::tests::{context_with_mails, mail_dir};
+
+    fn jane() -> User {
+        User::Team {
+            email: "jane@devfest.fr".parse().unwrap(),
+            roles: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn should_remove_the_user_without_invitation() {
+        // The mails cannot be written below a file
+        let context = context_with_mails(PathBuf::from("/dev/null/mails")).await;
+        let repos = context.repos();
+
+        assert!(invite_user(&context, &repos, jane()).await.is_err());
+        let email = jane().email().unwrap();
+        assert_eq!(repos.user().find_by_email(&email).await.unwrap(), None);
+        let last_issued = repos.password_token().last_issued(&email).await.unwrap();
+        assert_eq!(last_issued, None);
+    }
+
+    #[tokio::test]
+    async fn should_throttle_the_password_resets() {
+        let dir = mail_dir();
+        let context = context_with_mails(dir.clone()).await;
+        context.repos().user().new_user(jane()).await.unwrap();
+
+        for _ in 0..2 {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/auth/password/forgot")
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("email=jane@devfest.fr")
+                .reply(&routes(&context))
+                .await;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+        }
+
+        let mails = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(mails, 1, "Expected a single password reset");
+    }
+}
@@ -1,17 +1,25 @@
 use std::convert::Infallible;
 
 use anyhow::Error;
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use warp::body::BodyDeserializeError;
 use warp::http::StatusCode;
 use warp::reject::{MethodNotAllowed, Reject};
 use warp::{Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
+use dftk_database::indexes::Conflict;
+use dftk_database::revision::StaleRevision;
+use dftk_database::user::{Escalation, PasswordRejected, UnknownKey};
+
 /// An API error serializable to JSON.
 #[derive(Serialize)]
 struct ErrorMessage {
     code: u16,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revision: Option<u32>,
 }
 
 /// Bad things happen
@@ -20,13 +28,36 @@ pub enum Oops {
     DatabaseIssue(String),
     ConferenceHallIssue(String),
     Authentication(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Locked(String),
     MissingField(String),
     BadField(String),
+    Conflict(String),
+    StaleRevision(String, Option<u32>),
     Other(String),
 }
 
 impl Oops {
     pub fn db(err: Error) -> Rejection {
+        if let Some(conflict) = err.downcast_ref::<Conflict>() {
+            return warp::reject::custom(Oops::Conflict(conflict.to_string()));
+        }
+        if let Some(stale) = err.downcast_ref::<StaleRevision>() {
+            let oops = Oops::StaleRevision(stale.to_string(), stale.current());
+            return warp::reject::custom(oops);
+        }
+        if let Some(rejected) = err.downcast_ref::<PasswordRejected>() {
+            let message = format!("Invalid field 'password': {}", rejected);
+            return warp::reject::custom(Oops::BadField(message));
+        }
+        if let Some(unknown) = err.downcast_ref::<UnknownKey>() {
+            let message = format!("Invalid field 'key': {}", unknown);
+            return warp::reject::custom(Oops::BadField(message));
+        }
+        if let Some(escalation) = err.downcast_ref::<Escalation>() {
+            return warp::reject::custom(Oops::Forbidden(escalation.to_string()));
+        }
         let message = format!("Database issue: {}", err);
         warp::reject::custom(Oops::DatabaseIssue(message))
     }
@@ -38,6 +69,18 @@ impl Oops {
         let message = format!("Authentication issue: {}", err);
         warp::reject::custom(Oops::Authentication(message))
     }
+    pub fn unauthorized() -> Rejection {
+        let message = "Authentication required".into();
+        warp::reject::custom(Oops::Unauthorized(message))
+    }
+    pub fn forbidden(operation: &Operation) -> Rejection {
+        let message = format!("Not allowed to {:?}", operation);
+        warp::reject::custom(Oops::Forbidden(message))
+    }
+    pub fn locked(until: DateTime<Utc>) -> Rejection {
+        let message = format!("Too many failed logins, locked until {}", until);
+        warp::reject::custom(Oops::Locked(message))
+    }
     pub fn missing(field: &str) -> Rejection {
         let message = format!("Missing the field '{}'", field);
         warp::reject::custom(Oops::MissingField(message))
@@ -59,6 +102,7 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
 
     let code;
     let message;
+    let mut revision = None;
 
     if err.is_not_found() {
         code = StatusCode::NOT_FOUND;
@@ -66,6 +110,28 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     } else if let Some(e) = err.find::<BodyDeserializeError>() {
         code = StatusCode::BAD_REQUEST;
         message = format!("{}", e)
+    } else if let Some(Oops::Authentication(auth)) | Some(Oops::Unauthorized(auth)) =
+        err.find::<Oops>()
+    {
+        code = StatusCode::UNAUTHORIZED;
+        message = auth.clone();
+    } else if let Some(Oops::Forbidden(forbidden)) = err.find::<Oops>() {
+        code = StatusCode::FORBIDDEN;
+        message = forbidden.clone();
+    } else if let Some(Oops::Locked(locked)) = err.find::<Oops>() {
+        code = StatusCode::TOO_MANY_REQUESTS;
+        message = locked.clone();
+    } else if let Some(Oops::MissingField(field)) | Some(Oops::BadField(field)) = err.find::<Oops>()
+    {
+        code = StatusCode::BAD_REQUEST;
+        message = field.clone();
+    } else if let Some(Oops::Conflict(conflict)) = err.find::<Oops>() {
+        code = StatusCode::CONFLICT;
+        message = conflict.clone();
+    } else if let Some(Oops::StaleRevision(stale, current)) = err.find::<Oops>() {
+        code = StatusCode::CONFLICT;
+        message = stale.clone();
+        revision = *current;
     } else if err.find::<MethodNotAllowed>().is_some() {
         code = StatusCode::METHOD_NOT_ALLOWED;
         message = "METHOD_NOT_ALLOWED".into();
@@ -78,6 +144,7 @@ pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible>
     let json = warp::reply::json(&ErrorMessage {
         code: code.as_u16(),
         message,
+        revision,
     });
 
     Ok(warp::reply::with_status(json, code))
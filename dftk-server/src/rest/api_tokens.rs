@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_common::acl::role::{Permission, RoleKey};
+use dftk_common::acl::user::Email;
+use dftk_database::api_tokens::ApiToken;
+use dftk_database::Repositories;
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::{ServerContext, MAX_BODY_LENGTH};
+
+/// Provide API token routes, the tokens are shared by every event
+///
+/// `GET    admin/api-tokens`: list the tokens, without their secret
+///
+/// `POST   admin/api-tokens`: create a token with a role and/or permissions, the reply holds the only copy of the token
+///
+/// `DELETE admin/api-tokens/{id}`: revoke a token
+///
+/// The clients send the token with an `Authorization: Bearer` header.
+/// The API tokens need the `manage_users` permission,
+/// a created token belongs to the current user unless it is an administrator,
+/// and it cannot hold more than the permissions of the current user
+pub fn build_api_tokens_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let list = warp::path("api-tokens")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and_then(list_api_tokens);
+
+    let create = warp::path("api-tokens")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::json())
+        .and_then(create_api_token);
+
+    let revoke = warp::path!("api-tokens" / String)
+        .and(warp::delete())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and_then(revoke_api_token);
+
+    list.or(create).or(revoke).boxed()
+}
+
+async fn list_api_tokens(repos: Repositories) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Getting list of API tokens");
+    let tokens = repos.api_token().find_all().await.map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&tokens))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ApiTokenInput {
+    name: String,
+    owner: Email,
+    role: Option<String>,
+    #[serde(default)]
+    permissions: Vec<Permission>,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+struct ApiTokenCreated {
+    token: String,
+    api_token: ApiToken,
+}
+
+async fn create_api_token(
+    repos: Repositories,
+    input: ApiTokenInput,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Creating API token {:?}", input);
+    let ApiTokenInput {
+        name,
+        owner,
+        role,
+        permissions,
+        expires_at,
+    } = input;
+    let role = role.map(|it| RoleKey::new(it.as_str()));
+    repos
+        .check_api_token(&owner, role.clone(), &permissions)
+        .await
+        .map_err(Oops::db)?;
+    let (api_token, token) = repos
+        .api_token()
+        .create(name, owner, role, permissions, expires_at)
+        .await
+        .map_err(|err| Oops::bad("api token", err))?;
+    let body = ApiTokenCreated { token, api_token };
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&body),
+        StatusCode::CREATED,
+    ))
+}
+
+async fn revoke_api_token(
+    id: String,
+    repos: Repositories,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Revoking API token {}", id);
+    let result = repos
+        .api_token()
+        .revoke(id.as_str())
+        .await
+        .map_err(Oops::db)?;
+
+    result
+        .map(|it| warp::reply::json(&it))
+        .ok_or_else(warp::reject::not_found)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use dftk_common::acl::role::Role;
+    use dftk_common::acl::user::User;
+
+    use super::*;
+    use crate::routes;
+    use crate::tests::{memory_context, user_token};
+
+    #[tokio::test]
+    async fn should_not_create_tokens_beyond_the_user_manager() {
+        let context = memory_context().await;
+        let role = Role::new(
+            RoleKey::new("user-manager"),
+            "User manager".into(),
+            vec![Permission::ViewSite, Permission::ManageUsers],
+        );
+        context.repos().role().save(&role).await.unwrap();
+        let manager = User::Team {
+            email: "manager@devfest.fr".parse().unwrap(),
+            roles: vec![role.key()],
+        };
+        let authorization = format!("Bearer {}", user_token(&context, &manager).await);
+        let expires_at = Utc::now() + chrono::Duration::days(1);
+
+        let admin_token = json!({
+            "name": "ci",
+            "owner": "admin@devfest.fr",
+            "role": "admin",
+            "expires_at": expires_at,
+        });
+        let own_admin_token = json!({
+            "name": "ci",
+            "owner": "manager@devfest.fr",
+            "permissions": ["administration"],
+            "expires_at": expires_at,
+        });
+        for input in &[admin_token, own_admin_token] {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/api/admin/api-tokens")
+                .header("Authorization", authorization.as_str())
+                .json(input)
+                .reply(&routes(&context))
+                .await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+
+        let own_token = json!({
+            "name": "ci",
+            "owner": "manager@devfest.fr",
+            "permissions": ["manage_users"],
+            "expires_at": expires_at,
+        });
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/admin/api-tokens")
+            .header("Authorization", authorization.as_str())
+            .json(&own_token)
+            .reply(&routes(&context))
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_database::backup::{Archive, RestoreMode};
+use dftk_database::Repositories;
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::ServerContext;
+
+/// The maximum size of an uploaded archive
+const MAX_ARCHIVE_LENGTH: u64 = 64 * 1024 * 1024; // 64Mb
+
+/// Provide backup routes
+///
+/// `GET    admin/backup?passwords={bool}`: export every collection, of every event, into a JSON archive
+///
+/// `POST   admin/restore?mode={replace|merge}`: restore a JSON archive
+///
+/// Backup and restore need the `administration` permission
+pub fn build_backup_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let backup = warp::path("backup")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_permission(context, Operation::Administration))
+        .and(warp::query::<BackupQuery>())
+        .and_then(backup);
+
+    let restore = warp::path("restore")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(with_permission(context, Operation::Administration))
+        .and(warp::query::<RestoreQuery>())
+        .and(warp::body::content_length_limit(MAX_ARCHIVE_LENGTH))
+        .and(warp::body::json())
+        .and_then(restore);
+
+    backup.or(restore).boxed()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct BackupQuery {
+    #[serde(default)]
+    passwords: bool,
+}
+
+async fn backup(
+    repos: Repositories,
+    query: BackupQuery,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Backup the database, with passwords: {}", query.passwords);
+    let archive = repos.backup(query.passwords).await.map_err(Oops::db)?;
+    let filename = format!(
+        "devfest-{}.json",
+        archive.created_at().format("%Y%m%d-%H%M%S")
+    );
+    let result = warp::reply::with_header(
+        warp::reply::json(&archive),
+        "Content-Disposition",
+        format!("attachment; filename=\"{}\"", filename),
+    );
+
+    Ok(result)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RestoreQuery {
+    mode: RestoreMode,
+}
+
+async fn restore(
+    repos: Repositories,
+    query: RestoreQuery,
+    archive: Archive,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!(
+        "Restore an archive of {} document(s) with {:?}",
+        archive.nb_documents(),
+        query.mode
+    );
+    let result = repos
+        .restore(&archive, query.mode)
+        .await
+        .map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&result))
+}
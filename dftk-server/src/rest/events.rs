@@ -0,0 +1,52 @@
+use warp::filters::BoxedFilter;
+use warp::{Filter, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_common::models::site::EventId;
+use dftk_database::Repositories;
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::ServerContext;
+
+/// Provide event routes, the current event is selected by the `X-Event` header
+///
+/// `GET    admin/events`: list the events
+///
+/// `POST   admin/events/{event}/clone`: start the new event with the reusable data of the current one
+///
+/// The events need the `administration` permission
+pub fn build_events_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let list = warp::path("events")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_permission(context, Operation::Administration))
+        .and_then(list_events);
+
+    let clone = warp::path!("events" / String / "clone")
+        .and(warp::post())
+        .and(with_permission(context, Operation::Administration))
+        .and_then(clone_edition);
+
+    list.or(clone).boxed()
+}
+
+async fn list_events(repos: Repositories) -> Result<impl warp::Reply, warp::Rejection> {
+    let events = repos.events().await.map_err(Oops::db)?;
+    let result: Vec<String> = events.into_iter().map(|it| it.into()).collect();
+
+    Ok(warp::reply::json(&result))
+}
+
+async fn clone_edition(
+    event: String,
+    repos: Repositories,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Clone the current edition into {}", event);
+    let result = repos
+        .clone_edition(&EventId::new(event))
+        .await
+        .map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&result))
+}
@@ -2,11 +2,13 @@ use uuid::Uuid;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_database::Repositories;
 
+use crate::authentication::with_permission;
 use crate::rejection::Oops;
 use crate::rest::NameDescription;
-use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::{ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide session formats routes
@@ -18,25 +20,28 @@ use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 /// `PUT    site/formats/{id}` update a session format
 ///
 /// `DELETE site/formats/{id}` delete a session format
+///
+/// The team can read them, the writes need the `edit_program` permission
 
 pub fn build_session_formats_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditProgram))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_format);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
         .and_then(list_formats);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditProgram))
         .and(warp::path::param::<Uuid>())
         .and_then(delete_format);
 
     let update = warp::put()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditProgram))
         .and(warp::path::param::<Uuid>())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
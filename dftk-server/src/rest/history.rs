@@ -0,0 +1,108 @@
+use serde::Deserialize;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_database::history::Entity;
+use dftk_database::Repositories;
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::ServerContext;
+
+///
+/// Provide history routes
+///
+/// `GET    admin/history/{entity}/{id}`: list the versions of an entity
+///
+/// `GET    admin/history/{entity}/{id}/diff?from={version}&to={version}`: compare two versions
+///
+/// `POST   admin/history/{entity}/{id}/revert/{version}`: restore the entity state after a version
+///
+/// The entity is one of `session`, `speaker`, `sponsor`, `team_member`, `site_info`, `schedule`
+///
+/// The history needs the `administration` permission
+///
+pub fn build_history_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let list = warp::get()
+        .and(with_permission(context, Operation::Administration))
+        .and(warp::path!(Entity / String))
+        .and_then(list_history);
+
+    let diff = warp::get()
+        .and(with_permission(context, Operation::Administration))
+        .and(warp::path!(Entity / String / "diff"))
+        .and(warp::query::<DiffQuery>())
+        .and_then(diff_history);
+
+    let revert = warp::post()
+        .and(with_permission(context, Operation::Administration))
+        .and(warp::path!(Entity / String / "revert" / u32))
+        .and_then(revert_history);
+
+    warp::path("history").and(list.or(diff).or(revert)).boxed()
+}
+
+async fn list_history(
+    repos: Repositories,
+    entity: Entity,
+    id: String,
+) -> Result<impl Reply, Rejection> {
+    info!("Getting history of {} [{}]", entity, id);
+    let result = repos
+        .history()
+        .find(entity, id.as_str())
+        .await
+        .map_err(Oops::db)?;
+    let result = warp::reply::json(&result);
+
+    Ok(result)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct DiffQuery {
+    from: u32,
+    to: u32,
+}
+
+async fn diff_history(
+    repos: Repositories,
+    entity: Entity,
+    id: String,
+    query: DiffQuery,
+) -> Result<impl Reply, Rejection> {
+    info!(
+        "Compare {} [{}] versions {} and {}",
+        entity, id, query.from, query.to
+    );
+    let result = repos
+        .history()
+        .diff(entity, id.as_str(), query.from, query.to)
+        .await
+        .map_err(Oops::db)?;
+    let result = warp::reply::json(&result);
+
+    Ok(result)
+}
+
+async fn revert_history(
+    repos: Repositories,
+    entity: Entity,
+    id: String,
+    version: u32,
+) -> Result<impl Reply, Rejection> {
+    info!("Revert {} [{}] to the version {}", entity, id, version);
+    let result = repos
+        .revert(entity, id.as_str(), version)
+        .await
+        .map_err(Oops::db)?;
+    info!(
+        "Reverted {} [{}] as version {}",
+        entity,
+        id,
+        result.version()
+    );
+    let result = warp::reply::json(&result);
+
+    Ok(result)
+}
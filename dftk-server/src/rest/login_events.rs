@@ -0,0 +1,49 @@
+use serde::Deserialize;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Rejection, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_common::acl::user::Email;
+use dftk_database::Repositories;
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::rest::{json_page, page_request};
+use crate::ServerContext;
+
+/// Provide login events routes, the events are shared by every event
+///
+/// `GET    admin/login-events?email={email}&offset={offset}&limit={limit}`: list the login attempts, the most recent first
+///
+/// The login events need the `manage_users` permission
+pub fn build_login_events_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    warp::path("login-events")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::query::<LoginEventsQuery>())
+        .and_then(list_login_events)
+        .boxed()
+}
+
+#[derive(Deserialize, Debug)]
+struct LoginEventsQuery {
+    email: Option<Email>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+async fn list_login_events(
+    repos: Repositories,
+    query: LoginEventsQuery,
+) -> Result<impl Reply, Rejection> {
+    info!("Getting login events {:?}", query);
+    let page = page_request(query.offset, query.limit);
+    let result = repos
+        .login_attempt()
+        .find_events(query.email.as_ref(), &page)
+        .await
+        .map_err(Oops::db)?;
+
+    Ok(json_page(&result))
+}
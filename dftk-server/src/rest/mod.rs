@@ -1,10 +1,23 @@
 #[cfg(feature = "rest")]
-use serde::Deserialize;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 use warp::filters::BoxedFilter;
-use warp::{Filter, Reply};
+use warp::http::HeaderValue;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
 
+use dftk_database::query::{Page, PageRequest};
+
+use crate::rejection::Oops;
+use crate::rest::api_tokens::build_api_tokens_routes;
+use crate::rest::backup::build_backup_routes;
 use crate::rest::categories::build_session_categories_routes;
+use crate::rest::events::build_events_routes;
 use crate::rest::formats::build_session_formats_routes;
+use crate::rest::history::build_history_routes;
+use crate::rest::login_events::build_login_events_routes;
+use crate::rest::roles::build_roles_routes;
+use crate::rest::schedule::build_schedule_routes;
 use crate::rest::sessions::build_sessions_routes;
 use crate::rest::site::build_site_routes;
 use crate::rest::speakers::build_speakers_routes;
@@ -15,8 +28,15 @@ use crate::rest::team_member_type::build_team_member_types_routes;
 use crate::rest::users::build_users_routes;
 use crate::ServerContext;
 
+mod api_tokens;
+mod backup;
 mod categories;
+mod events;
 mod formats;
+mod history;
+mod login_events;
+mod roles;
+mod schedule;
 mod sessions;
 mod site;
 mod speakers;
@@ -32,6 +52,61 @@ struct NameDescription {
     description: Option<String>,
 }
 
+/// The revision expected by the `If-Match` header, e.g. `If-Match: "3"`
+fn with_expected_revision() -> impl Filter<Extract = (Option<u32>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-match").and_then(|value: Option<String>| async move {
+        value
+            .map(|it| parse_etag(it.as_str()))
+            .transpose()
+            .map_err(|err| Oops::bad("If-Match", err))
+    })
+}
+
+fn parse_etag(value: &str) -> Result<u32> {
+    let revision = value.trim().trim_start_matches("W/").trim_matches('"');
+
+    revision
+        .parse()
+        .map_err(|_| anyhow!("Expected a revision, got {}", value))
+}
+
+fn etag(revision: u32) -> String {
+    format!("\"{}\"", revision)
+}
+
+/// The requested page, e.g. `?offset=20&limit=10`
+fn page_request(offset: Option<u64>, limit: Option<u64>) -> PageRequest {
+    PageRequest::new(offset.unwrap_or_default(), limit)
+}
+
+/// Reply the elements of the page as a JSON array, with the total count as `X-Total-Count`
+fn json_page<T>(page: &Page<T>) -> Response
+where
+    T: Serialize,
+{
+    let mut response = warp::reply::json(&page.items()).into_response();
+    response
+        .headers_mut()
+        .insert("X-Total-Count", HeaderValue::from(page.total()));
+
+    response
+}
+
+/// Reply the element as JSON, with its revision as `ETag`
+fn json_with_revision<T>(element: &T, revision: Option<u32>) -> Response
+where
+    T: Serialize,
+{
+    let mut response = warp::reply::json(element).into_response();
+    if let Some(revision) = revision {
+        if let Ok(value) = HeaderValue::from_str(etag(revision).as_str()) {
+            response.headers_mut().insert("ETag", value);
+        }
+    }
+
+    response
+}
+
 pub fn build_rest_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let users = warp::path("users").and(build_users_routes(context));
     let site = warp::path("site").and(
@@ -39,6 +114,7 @@ pub fn build_rest_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)>
             .or(build_session_categories_routes(context))
             .or(build_session_formats_routes(context))
             .or(build_sessions_routes(context))
+            .or(build_schedule_routes(context))
             .or(build_speakers_routes(context))
             .or(build_teams_routes(context))
             .or(build_team_member_types_routes(context))
@@ -46,5 +122,92 @@ pub fn build_rest_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)>
             .or(build_sponsor_categoryies_routes(context)),
     );
 
-    users.or(site).with(warp::log("rest::api")).boxed()
+    let admin = warp::path("admin").and(
+        build_backup_routes(context)
+            .or(build_events_routes(context))
+            .or(build_history_routes(context))
+            .or(build_roles_routes(context))
+            .or(build_login_events_routes(context))
+            .or(build_api_tokens_routes(context)),
+    );
+
+    users
+        .or(site)
+        .or(admin)
+        .with(warp::log("rest::api"))
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use dftk_common::models::language::Lang;
+    use dftk_common::models::session::category::CategoryKey;
+    use dftk_common::models::session::format::FormatKey;
+    use dftk_common::models::session::PartialSession;
+    use dftk_common::models::speaker::SpeakerKey;
+    use warp::http::StatusCode;
+
+    use super::*;
+    use crate::routes;
+    use crate::tests::{admin_token, memory_context};
+
+    fn session(title: &str) -> PartialSession {
+        PartialSession::new(
+            title.into(),
+            None,
+            FormatKey::new("quickie"),
+            vec![SpeakerKey::new("jane-doe")],
+            CategoryKey::new("backend"),
+            Lang::default(),
+            None,
+            None,
+            None,
+            None,
+            "A talk".to_string().into(),
+        )
+    }
+
+    #[test]
+    fn should_parse_etag() {
+        assert_eq!(parse_etag("\"3\"").unwrap(), 3);
+        assert_eq!(parse_etag("W/\"12\"").unwrap(), 12);
+        assert!(parse_etag("*").is_err());
+    }
+
+    #[tokio::test]
+    async fn should_create_and_list_sessions_in_memory() {
+        let context = memory_context().await;
+        let routes = routes(&context);
+        let authorization = format!("Bearer {}", admin_token(&context).await);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/site/sessions")
+            .json(&session("Rust"))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        for title in ["Rust", "Java"].iter() {
+            let response = warp::test::request()
+                .method("POST")
+                .path("/api/site/sessions")
+                .header("Authorization", authorization.as_str())
+                .json(&session(title))
+                .reply(&routes)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = warp::test::request()
+            .path("/api/site/sessions?sort=key&direction=desc&limit=1")
+            .header("Authorization", authorization.as_str())
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Total-Count"], "2");
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["key"], "rust");
+        assert_eq!(body.as_array().map(|it| it.len()), Some(1));
+    }
 }
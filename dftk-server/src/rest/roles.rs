@@ -0,0 +1,84 @@
+use serde::Deserialize;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_common::acl::role::{Permission, Role, RoleKey};
+use dftk_database::Repositories;
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::{ServerContext, MAX_BODY_LENGTH};
+
+/// Provide role routes, the roles are shared by every event
+///
+/// `GET    admin/roles`: list the roles with their permissions
+///
+/// `PUT    admin/roles/{key}`: create or replace a role
+///
+/// `DELETE admin/roles/{key}`: delete a role, its users lose its permissions
+///
+/// The roles need the `manage_users` permission
+pub fn build_roles_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let list = warp::path("roles")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and_then(list_roles);
+
+    let save = warp::path!("roles" / String)
+        .and(warp::put())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::json())
+        .and_then(save_role);
+
+    let delete = warp::path!("roles" / String)
+        .and(warp::delete())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and_then(delete_role);
+
+    list.or(save).or(delete).boxed()
+}
+
+async fn list_roles(repos: Repositories) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Getting list of roles");
+    let roles = repos.role().find().await.map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&roles))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RoleInput {
+    name: String,
+    permissions: Vec<Permission>,
+}
+
+async fn save_role(
+    key: String,
+    repos: Repositories,
+    input: RoleInput,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Save role {} {:?}", key, input);
+    let RoleInput { name, permissions } = input;
+    let role = Role::new(RoleKey::new(key.as_str()), name, permissions);
+    let result = repos.role().save(&role).await.map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&result))
+}
+
+async fn delete_role(
+    key: String,
+    repos: Repositories,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Deleting role {}", key);
+    let result = repos
+        .role()
+        .delete(RoleKey::new(key.as_str()))
+        .await
+        .map_err(Oops::db)?;
+
+    result
+        .map(|it| warp::reply::json(&it))
+        .ok_or_else(warp::reject::not_found)
+}
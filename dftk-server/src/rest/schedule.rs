@@ -0,0 +1,160 @@
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+use dftk_common::acl::operation::Operation;
+use dftk_common::models::schedule::grid::{export_grid, import_grid};
+use dftk_common::models::schedule::now::now_next;
+use dftk_common::models::schedule::RoomKey;
+use dftk_database::Repositories;
+use dftk_hugo_site::frab::{frab_schedule, FrabFormat};
+
+use crate::authentication::with_permission;
+use crate::rejection::Oops;
+use crate::rest::{etag, json_with_revision, with_expected_revision};
+use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+
+///
+/// Provide schedule routes
+///
+/// `GET    site/schedule.xml`: export the schedule with the frab XML format
+///
+/// `GET    site/schedule.json`: export the schedule with the frab JSON format
+///
+/// `GET    site/schedule.csv`: export the schedule grid as CSV, with the schedule revision as `ETag`
+///
+/// `POST   site/schedule/grid/preview`: read a CSV schedule grid, and return the changes without saving
+///
+/// `POST   site/schedule/grid`: read a CSV schedule grid, and save the schedule if every cell is resolved,
+/// rejected with a `409` if the `If-Match` revision is stale
+///
+/// `GET    site/schedule/now?room={room}&at={instant}`: current and next session per room
+///
+/// The frab exports and the now/next feed are public, like the generated site,
+/// the team can read the grid, importing a grid needs the `edit_schedule` permission
+///
+pub fn build_schedule_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
+    let xml = warp::path("schedule.xml")
+        .and(warp::path::end())
+        .map(|| FrabFormat::Xml);
+
+    let json = warp::path("schedule.json")
+        .and(warp::path::end())
+        .map(|| FrabFormat::Json);
+
+    let export = xml
+        .or(json)
+        .unify()
+        .and(warp::get())
+        .and(with_repo(context))
+        .and_then(export_schedule);
+
+    let now = warp::path!("schedule" / "now")
+        .and(warp::get())
+        .and(with_repo(context))
+        .and(warp::query::<NowQuery>())
+        .and_then(schedule_now);
+
+    let export_grid = warp::path("schedule.csv")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_permission(context, Operation::ViewSite))
+        .and_then(export_schedule_grid);
+
+    let preview_grid = warp::path!("schedule" / "grid" / "preview")
+        .and(warp::post())
+        .and(with_permission(context, Operation::EditSchedule))
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::bytes())
+        .map(|repos, body| (repos, body, None, false))
+        .untuple_one()
+        .and_then(import_schedule_grid);
+
+    let import_grid = warp::path!("schedule" / "grid")
+        .and(warp::post())
+        .and(with_permission(context, Operation::EditSchedule))
+        .and(with_expected_revision())
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::bytes())
+        .map(|repos, expected, body| (repos, body, expected, true))
+        .untuple_one()
+        .and_then(import_schedule_grid);
+
+    export
+        .or(now)
+        .or(export_grid)
+        .or(preview_grid)
+        .or(import_grid)
+        .boxed()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct NowQuery {
+    room: Option<RoomKey>,
+    /// Override the current instant, useful for rehearsals
+    at: Option<DateTime<Utc>>,
+}
+
+async fn export_schedule(format: FrabFormat, repos: Repositories) -> Result<impl Reply, Rejection> {
+    info!("Export the schedule with the {:?} format", format);
+    let site = repos.load_site().await.map_err(Oops::db)?;
+    let content = frab_schedule(&site).export(format).map_err(Oops::other)?;
+    let result = warp::reply::with_header(content, "Content-Type", format.content_type());
+
+    Ok(result)
+}
+
+async fn schedule_now(repos: Repositories, query: NowQuery) -> Result<impl Reply, Rejection> {
+    info!("Getting current and next sessions {:?}", query);
+    let site = repos.load_site().await.map_err(Oops::db)?;
+    let at = query.at.unwrap_or_else(Utc::now);
+    let result = now_next(&site, at, query.room);
+    let result = warp::reply::json(&result);
+
+    Ok(result)
+}
+
+async fn export_schedule_grid(repos: Repositories) -> Result<impl Reply, Rejection> {
+    info!("Export the schedule grid");
+    let site = repos.load_site().await.map_err(Oops::db)?;
+    let content = export_grid(&site).map_err(Oops::other)?;
+    let revision = repos.schedule_revision().await.map_err(Oops::db)?;
+    let result = warp::reply::with_header(content, "Content-Type", "text/csv");
+    let result = warp::reply::with_header(result, "ETag", etag(revision));
+
+    Ok(result)
+}
+
+async fn import_schedule_grid(
+    repos: Repositories,
+    body: Bytes,
+    expected: Option<u32>,
+    save: bool,
+) -> Result<impl Reply, Rejection> {
+    info!(
+        "Import the schedule grid, save: {} with revision {:?}",
+        save, expected
+    );
+    let site = repos.load_site().await.map_err(Oops::db)?;
+    let result = import_grid(&site, body.as_ref()).map_err(|err| Oops::bad("grid", err))?;
+
+    let status = if !result.is_valid() {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else if save {
+        repos
+            .update_schedule(result.schedule(), expected)
+            .await
+            .map_err(Oops::db)?;
+        StatusCode::OK
+    } else {
+        StatusCode::OK
+    };
+    debug!("Schedule grid changes {:?}", result.changes());
+    let revision = repos.schedule_revision().await.map_err(Oops::db)?;
+    let result = warp::reply::with_status(json_with_revision(&result, Some(revision)), status);
+
+    Ok(result)
+}
@@ -1,51 +1,67 @@
+use serde::{Deserialize, Serialize};
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
-use dftk_common::models::session::{PartialSession, SessionId, SessionKey};
-use dftk_database::sessions::SessionPatch;
+use dftk_common::acl::operation::Operation;
+use dftk_common::models::language::Lang;
+use dftk_common::models::schedule::placement::SessionPlacement;
+use dftk_common::models::session::category::CategoryKey;
+use dftk_common::models::session::format::FormatKey;
+use dftk_common::models::session::{PartialSession, SessionId, SessionKey, SessionLevel};
+use dftk_common::models::speaker::SpeakerKey;
+use dftk_database::query::{Direction, Query, SessionFilter, SessionSort, Sort};
+use dftk_database::sessions::{SessionDocument, SessionPatch};
 use dftk_database::Repositories;
 
+use crate::authentication::{authorize, with_permission};
 use crate::rejection::Oops;
+use crate::rest::{json_page, json_with_revision, page_request, with_expected_revision};
 use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide sessions routes
 ///
-/// `GET    site/sessions`: list all sessions
+/// `GET    site/sessions`: list the sessions, filtered by `category`, `format`, `language`, `level`, `draft` or `speaker`,
+/// sorted with `sort=key|title|date` and `direction=asc|desc`, paginated with `offset` and `limit`, the total count is `X-Total-Count`
 ///
-/// `GET    site/sessions/{key}`: get a session
+/// `GET    site/sessions/{key}`: get a session, with its slot, room, start and end if scheduled, and its revision as `ETag`
 ///
 /// `POST   site/sessions`: create a session
 ///
-/// `PUT    site/sessions/{id}` update a session
+/// `PATCH  site/sessions/{id}` update a session, rejected with a `409` if the `If-Match` revision is stale
 ///
 /// `DELETE site/sessions/{id}` delete a session
 ///
+/// A session can be read and updated by its speakers, the team can read everything, the writes need the `edit_sessions` permission
+///
 
 pub fn build_sessions_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSessions))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_session);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
+        .and(warp::query::<SessionsQuery>())
         .and_then(list_sessions);
 
     let get = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(with_repo(context)) //
         .and(warp::path::param::<SessionKey>())
         .and_then(get_session);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSessions))
         .and(warp::path::param::<SessionId>())
         .and_then(delete_session);
 
     let patch = warp::patch()
-        .and(with_repo(context.repos()))
+        .and(with_repo(context))
         .and(warp::path::param::<SessionId>())
+        .and(with_expected_revision())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(patch_session);
@@ -71,36 +87,98 @@ async fn create_session(
     Ok(result)
 }
 
-async fn list_sessions(repos: Repositories) -> Result<impl Reply, Rejection> {
-    info!("Getting list of sessions");
-    let result = repos.session().find_all().await.map_err(Oops::db)?;
-    let result = warp::reply::json(&result);
+#[derive(Deserialize, Debug)]
+struct SessionsQuery {
+    category: Option<String>,
+    format: Option<String>,
+    language: Option<Lang>,
+    level: Option<String>,
+    draft: Option<bool>,
+    speaker: Option<String>,
+    sort: Option<SessionSort>,
+    direction: Option<Direction>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl Into<Query<SessionFilter, SessionSort>> for SessionsQuery {
+    fn into(self) -> Query<SessionFilter, SessionSort> {
+        let filter = SessionFilter {
+            category: self.category.map(|it| CategoryKey::new(it.as_str())),
+            format: self.format.map(|it| FormatKey::new(it.as_str())),
+            language: self.language,
+            level: self.level.map(SessionLevel::from),
+            draft: self.draft,
+            speaker: self.speaker.map(|it| SpeakerKey::new(it.as_str())),
+        };
+        let sort = self
+            .sort
+            .map(|it| Sort::new(it, self.direction.unwrap_or_default()));
+        let page = page_request(self.offset, self.limit);
+
+        Query::new(filter, sort, page)
+    }
+}
+
+async fn list_sessions(repos: Repositories, query: SessionsQuery) -> Result<impl Reply, Rejection> {
+    info!("Getting list of sessions {:?}", query);
+    let query = query.into();
+    let result = repos.session().find_page(&query).await.map_err(Oops::db)?;
+    let result = json_page(&result);
 
     Ok(result)
 }
 
+#[derive(Serialize, Debug)]
+struct ScheduledSession {
+    #[serde(flatten)]
+    session: SessionDocument,
+    schedule: Option<SessionPlacement>,
+}
+
 async fn get_session(repos: Repositories, key: SessionKey) -> Result<impl Reply, Rejection> {
     info!("Getting session {:?}", key);
-    let result = repos.session().find_by_key(key).await.map_err(Oops::db)?;
+    authorize(&repos, &Operation::ViewSession(key.clone())).await?;
+    let session = repos
+        .session()
+        .find_by_key(key.clone())
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(warp::reject::not_found)?;
+    let schedule = repos.find_placement(&key).await.map_err(Oops::db)?;
+    let revision = repos
+        .session()
+        .revision(session.id())
+        .await
+        .map_err(Oops::db)?;
+    let result = ScheduledSession { session, schedule };
+    let result = json_with_revision(&result, revision);
 
-    result
-        .map(|it| warp::reply::json(&it))
-        .ok_or_else(warp::reject::not_found)
+    Ok(result)
 }
 
 async fn patch_session(
     repos: Repositories,
     id: SessionId,
+    expected: Option<u32>,
     input: SessionPatch,
 ) -> Result<impl Reply, Rejection> {
-    info!("Update session {:?}", input);
+    info!("Update session {:?} with revision {:?}", input, expected);
+    let session = repos
+        .session()
+        .find_by_id(id.clone())
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(warp::reject::not_found)?;
+    authorize(&repos, &Operation::EditSession(session.key())).await?;
     let result = repos
         .session()
-        .update_session(id, input)
+        .update_session(id.clone(), input, expected)
         .await
         .map_err(Oops::db)?;
     info!("Updated the sessions {:?}", result);
-    let result = warp::reply::json(&result);
+    let revision = repos.session().revision(id).await.map_err(Oops::db)?;
+    let result = json_with_revision(&result, revision);
 
     Ok(result)
 }
@@ -1,13 +1,16 @@
 use warp::filters::BoxedFilter;
 use warp::{Filter, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_common::models::site::SiteInfo;
 use dftk_conference_hall::read_event;
 use dftk_database::Repositories;
 use dftk_hugo_site::generate;
 
+use crate::authentication::with_permission;
 use crate::rejection::Oops;
-use crate::{with_context, with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::rest::{json_with_revision, with_expected_revision};
+use crate::{with_context, ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide site routes
@@ -18,20 +21,23 @@ use crate::{with_context, with_repo, ServerContext, MAX_BODY_LENGTH};
 ///
 /// `POST site/generate`: generate Hugo Site files (speaker, sessions, team, sponsors, schedule)
 ///
-/// `GET  site/info`: get site info
+/// `GET  site/info`: get site info, with its revision as `ETag`
 ///
-/// `POST site/info`: set site info
+/// `POST site/info`: set site info, rejected with a `409` if the `If-Match` revision is stale
+///
+/// The team can read the site, the other routes need the `synchronize`, `generate` or `edit_site` permission
 ///
 
 pub fn build_site_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let site = warp::path::end().and(
         warp::get()
-            .and(with_repo(context.repos()))
+            .and(with_permission(context, Operation::ViewSite))
             .and_then(get_site),
     );
 
     let synchronize = warp::path("synchronize").and(
         warp::post()
+            .and(with_permission(context, Operation::Synchronize))
             .and(with_context(context.clone()))
             .and(warp::body::content_length_limit(0))
             .and_then(synchronize_site),
@@ -39,6 +45,7 @@ pub fn build_site_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)>
 
     let generate = warp::path("generate").and(
         warp::post()
+            .and(with_permission(context, Operation::Generate))
             .and(with_context(context.clone()))
             .and(warp::body::content_length_limit(0))
             .and_then(generate_site),
@@ -46,13 +53,14 @@ pub fn build_site_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)>
 
     let site_info = warp::path("info").and(
         warp::get() //
-            .and(with_repo(context.repos()))
+            .and(with_permission(context, Operation::ViewSite))
             .and_then(get_site_info),
     );
 
     let update_site_info = warp::path("info").and(
         warp::post()
-            .and(with_repo(context.repos()))
+            .and(with_permission(context, Operation::EditSite))
+            .and(with_expected_revision())
             .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
             .and(warp::body::json())
             .and_then(set_site_info),
@@ -73,19 +81,25 @@ async fn get_site(repos: Repositories) -> Result<impl warp::Reply, warp::Rejecti
     Ok(result)
 }
 
-async fn synchronize_site(context: ServerContext) -> Result<impl warp::Reply, warp::Rejection> {
-    let site = read_event(&context.ch_config).await.map_err(Oops::ch)?;
-    let result = context.repos.synchronize(site).await.map_err(Oops::db)?;
+async fn synchronize_site(
+    repos: Repositories,
+    context: ServerContext,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let site = read_event(&context.ch_config()).await.map_err(Oops::ch)?;
+    let result = repos.synchronize(site).await.map_err(Oops::db)?;
     let result = warp::reply::json(&result);
 
     Ok(result)
 }
 
-async fn generate_site(context: ServerContext) -> Result<impl warp::Reply, warp::Rejection> {
-    let site = context.repos.load_site().await.map_err(Oops::ch)?;
+async fn generate_site(
+    repos: Repositories,
+    context: ServerContext,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let site = repos.load_site().await.map_err(Oops::db)?;
     let result = generate(&context.site_config(), site)
         .await
-        .map_err(Oops::db)?;
+        .map_err(Oops::other)?;
     let result = warp::reply::json(&result);
 
     Ok(result)
@@ -94,19 +108,29 @@ async fn generate_site(context: ServerContext) -> Result<impl warp::Reply, warp:
 async fn get_site_info(repos: Repositories) -> Result<impl warp::Reply, warp::Rejection> {
     info!("Getting site info");
     let site_info = repos.info().find_first().await.map_err(Oops::db)?;
-    let result = warp::reply::json(&site_info);
+    let id: String = site_info.id().into();
+    let revision = repos.info().revision(id.as_str()).await.map_err(Oops::db)?;
+    let result = json_with_revision(&site_info, revision);
 
     Ok(result)
 }
 
 async fn set_site_info(
     repos: Repositories,
+    expected: Option<u32>,
     site_info: SiteInfo,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("Update site info {:?}", site_info);
-    repos.info().remove_all().await.map_err(Oops::db)?;
-    repos.info().insert(&site_info).await.map_err(Oops::db)?;
+    info!(
+        "Update site info {:?} with revision {:?}",
+        site_info, expected
+    );
+    let id: String = site_info.id().into();
+    let revision = repos
+        .info()
+        .save(id.as_str(), &site_info, expected)
+        .await
+        .map_err(Oops::db)?;
 
-    let result = warp::reply::reply();
+    let result = json_with_revision(&site_info, Some(revision));
     Ok(result)
 }
@@ -1,51 +1,62 @@
+use serde::Deserialize;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_common::models::speaker::{PartialSpeaker, SpeakerId, SpeakerKey};
+use dftk_database::query::{Direction, Query, Sort, SpeakerFilter, SpeakerSort};
 use dftk_database::speakers::SpeakerPatch;
 use dftk_database::Repositories;
 
+use crate::authentication::{authorize, with_permission};
 use crate::rejection::Oops;
+use crate::rest::{json_page, json_with_revision, page_request, with_expected_revision};
 use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide speakers routes
 ///
-/// `GET    site/speakers`: list all speakers
+/// `GET    site/speakers`: list the speakers, filtered by `featured`, `company`, `city` or `draft`,
+/// sorted with `sort=key|name|date` and `direction=asc|desc`, paginated with `offset` and `limit`, the total count is `X-Total-Count`
 ///
-/// `GET    site/speakers/{key}`: get a speaker
+/// `GET    site/speakers/{key}`: get a speaker, with its revision as `ETag`
 ///
 /// `POST   site/speakers`: create a speaker
 ///
-/// `PUT    site/speakers/{id}` update a speaker
+/// `PATCH  site/speakers/{id}` update a speaker, rejected with a `409` if the `If-Match` revision is stale
 ///
 /// `DELETE site/speakers/{id}` delete a speaker
 ///
+/// A speaker can read and update its own profile, the team can read everything, the writes need the `edit_speakers` permission
+///
 
 pub fn build_speakers_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSpeakers))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_speaker);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
+        .and(warp::query::<SpeakersQuery>())
         .and_then(list_speakers);
 
     let get = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(with_repo(context)) //
         .and(warp::path::param::<SpeakerKey>())
         .and_then(get_speaker);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSpeakers))
         .and(warp::path::param::<SpeakerId>())
         .and_then(delete_speaker);
 
     let patch = warp::patch()
-        .and(with_repo(context.repos()))
+        .and(with_repo(context))
         .and(warp::path::param::<SpeakerId>())
+        .and(with_expected_revision())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(patch_speaker);
@@ -71,36 +82,85 @@ async fn create_speaker(
     Ok(result)
 }
 
-async fn list_speakers(repos: Repositories) -> Result<impl Reply, Rejection> {
-    info!("Getting list of speakers");
-    let result = repos.speaker().find_all().await.map_err(Oops::db)?;
-    let result = warp::reply::json(&result);
+#[derive(Deserialize, Debug)]
+struct SpeakersQuery {
+    featured: Option<bool>,
+    company: Option<String>,
+    city: Option<String>,
+    draft: Option<bool>,
+    sort: Option<SpeakerSort>,
+    direction: Option<Direction>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl Into<Query<SpeakerFilter, SpeakerSort>> for SpeakersQuery {
+    fn into(self) -> Query<SpeakerFilter, SpeakerSort> {
+        let filter = SpeakerFilter {
+            featured: self.featured,
+            company: self.company,
+            city: self.city,
+            draft: self.draft,
+        };
+        let sort = self
+            .sort
+            .map(|it| Sort::new(it, self.direction.unwrap_or_default()));
+        let page = page_request(self.offset, self.limit);
+
+        Query::new(filter, sort, page)
+    }
+}
+
+async fn list_speakers(repos: Repositories, query: SpeakersQuery) -> Result<impl Reply, Rejection> {
+    info!("Getting list of speakers {:?}", query);
+    let query = query.into();
+    let result = repos.speaker().find_page(&query).await.map_err(Oops::db)?;
+    let result = json_page(&result);
 
     Ok(result)
 }
 
 async fn get_speaker(repos: Repositories, key: SpeakerKey) -> Result<impl Reply, Rejection> {
     info!("Getting speaker {:?}", key);
-    let result = repos.speaker().find_by_key(key).await.map_err(Oops::db)?;
+    authorize(&repos, &Operation::ViewSpeaker(key.clone())).await?;
+    let speaker = repos
+        .speaker()
+        .find_by_key(key)
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(warp::reject::not_found)?;
+    let revision = repos
+        .speaker()
+        .revision(speaker.id())
+        .await
+        .map_err(Oops::db)?;
+    let result = json_with_revision(&speaker, revision);
 
-    result
-        .map(|it| warp::reply::json(&it))
-        .ok_or_else(warp::reject::not_found)
+    Ok(result)
 }
 
 async fn patch_speaker(
     repos: Repositories,
     id: SpeakerId,
+    expected: Option<u32>,
     input: SpeakerPatch,
 ) -> Result<impl Reply, Rejection> {
-    info!("Update speaker {:?}", input);
+    info!("Update speaker {:?} with revision {:?}", input, expected);
+    let speaker = repos
+        .speaker()
+        .find_by_id(id.clone())
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(warp::reject::not_found)?;
+    authorize(&repos, &Operation::EditSpeaker(speaker.key())).await?;
     let result = repos
         .speaker()
-        .update_speaker(id, input)
+        .update_speaker(id.clone(), input, expected)
         .await
         .map_err(Oops::db)?;
     info!("Updated the speakers {:?}", result);
-    let result = warp::reply::json(&result);
+    let revision = repos.speaker().revision(id).await.map_err(Oops::db)?;
+    let result = json_with_revision(&result, revision);
 
     Ok(result)
 }
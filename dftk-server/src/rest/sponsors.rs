@@ -1,50 +1,62 @@
+use serde::Deserialize;
 use uuid::Uuid;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
+use dftk_common::models::sponsor::category::SponsorCategoryKey;
 use dftk_common::models::sponsor::{PartialSponsor, SponsorKey};
+use dftk_database::query::{Direction, Query, Sort, SponsorFilter, SponsorSort};
 use dftk_database::Repositories;
 
+use crate::authentication::{authorize, with_permission};
 use crate::rejection::Oops;
+use crate::rest::{json_page, json_with_revision, page_request, with_expected_revision};
 use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide sponsors routes
 ///
-/// `GET    site/sponsors`: list all sponsors
+/// `GET    site/sponsors`: list the sponsors, filtered by `category`,
+/// sorted with `sort=key|title|category|order` and `direction=asc|desc`, paginated with `offset` and `limit`, the total count is `X-Total-Count`
 ///
-/// `GET    site/sponsors/{key}`: get a sponsor
+/// `GET    site/sponsors/{key}`: get a sponsor, with its revision as `ETag`
 ///
 /// `POST   site/sponsors`: create a sponsor
 ///
-/// `PUT    site/sponsors/{id}` update a sponsor
+/// `PUT    site/sponsors/{id}` update a sponsor, rejected with a `409` if the `If-Match` revision is stale
 ///
 /// `DELETE site/sponsors/{id}` delete a sponsor
+///
+/// A sponsor can read and update its own page, the team can read everything, the writes need the `edit_sponsors` permission
 
 pub fn build_sponsors_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSponsors))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_sponsor);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
+        .and(warp::query::<SponsorsQuery>())
         .and_then(list_sponsors);
 
     let get = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(with_repo(context)) //
         .and(warp::path::param::<SponsorKey>())
         .and_then(get_sponsor);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSponsors))
         .and(warp::path::param::<Uuid>())
         .and_then(delete_sponsor);
 
     let update = warp::put()
-        .and(with_repo(context.repos()))
+        .and(with_repo(context))
         .and(warp::path::param::<Uuid>())
+        .and(with_expected_revision())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(update_sponsor);
@@ -66,36 +78,79 @@ async fn create_sponsor(
     Ok(result)
 }
 
-async fn list_sponsors(repos: Repositories) -> Result<impl Reply, Rejection> {
-    info!("Getting list of sponsors");
-    let result = repos.sponsor().find().await.map_err(Oops::db)?;
-    let result = warp::reply::json(&result);
+#[derive(Deserialize, Debug)]
+struct SponsorsQuery {
+    category: Option<String>,
+    sort: Option<SponsorSort>,
+    direction: Option<Direction>,
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl Into<Query<SponsorFilter, SponsorSort>> for SponsorsQuery {
+    fn into(self) -> Query<SponsorFilter, SponsorSort> {
+        let filter = SponsorFilter {
+            category: self.category.map(|it| SponsorCategoryKey::new(it.as_str())),
+        };
+        let sort = self
+            .sort
+            .map(|it| Sort::new(it, self.direction.unwrap_or_default()));
+        let page = page_request(self.offset, self.limit);
+
+        Query::new(filter, sort, page)
+    }
+}
+
+async fn list_sponsors(repos: Repositories, query: SponsorsQuery) -> Result<impl Reply, Rejection> {
+    info!("Getting list of sponsors {:?}", query);
+    let query = query.into();
+    let result = repos.sponsor().find_page(&query).await.map_err(Oops::db)?;
+    let result = json_page(&result);
 
     Ok(result)
 }
 
 async fn get_sponsor(repos: Repositories, key: SponsorKey) -> Result<impl Reply, Rejection> {
     info!("Getting sponsor {:?}", key);
-    let result = repos.sponsor().find_by_key(key).await.map_err(Oops::db)?;
+    authorize(&repos, &Operation::ViewSponsor(key.clone())).await?;
+    let sponsor = repos
+        .sponsor()
+        .find_by_key(key)
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(warp::reject::not_found)?;
+    let revision = repos
+        .sponsor()
+        .revision(sponsor.id())
+        .await
+        .map_err(Oops::db)?;
+    let result = json_with_revision(&sponsor, revision);
 
-    result
-        .map(|it| warp::reply::json(&it))
-        .ok_or_else(warp::reject::not_found)
+    Ok(result)
 }
 
 async fn update_sponsor(
     repos: Repositories,
     uuid: Uuid,
+    expected: Option<u32>,
     input: PartialSponsor,
 ) -> Result<impl Reply, Rejection> {
-    info!("Update sponsor {:?}", input);
+    info!("Update sponsor {:?} with revision {:?}", input, expected);
+    let sponsor = repos
+        .sponsor()
+        .find_by_id(uuid)
+        .await
+        .map_err(Oops::db)?
+        .ok_or_else(warp::reject::not_found)?;
+    authorize(&repos, &Operation::EditSponsor(sponsor.key())).await?;
     let result = repos
         .sponsor()
-        .update(uuid, input)
+        .update(uuid, input, expected)
         .await
         .map_err(Oops::db)?;
     info!("Updated the sponsors {:?}", result);
-    let result = warp::reply::json(&result);
+    let revision = repos.sponsor().revision(uuid).await.map_err(Oops::db)?;
+    let result = json_with_revision(&result, revision);
 
     Ok(result)
 }
@@ -2,10 +2,12 @@ use uuid::Uuid;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_database::Repositories;
 
+use crate::authentication::with_permission;
 use crate::rejection::Oops;
-use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::{ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide team member types routes
@@ -17,25 +19,28 @@ use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 /// `PUT    site/sponsor-categories/{id}` update a sponsor category
 ///
 /// `DELETE site/sponsor-categories/{id}` delete a sponsor category
+///
+/// The team can read them, the writes need the `edit_sponsors` permission
 
 pub fn build_sponsor_categoryies_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSponsors))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_sponsor_category);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
         .and_then(list_sponsor_categories);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSponsors))
         .and(warp::path::param::<Uuid>())
         .and_then(delete_sponsor_category);
 
     let update = warp::put()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditSponsors))
         .and(warp::path::param::<Uuid>())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
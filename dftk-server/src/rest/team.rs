@@ -2,11 +2,14 @@ use uuid::Uuid;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_common::models::team::PartialTeamMember;
 use dftk_database::Repositories;
 
+use crate::authentication::with_permission;
 use crate::rejection::Oops;
-use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::rest::{json_with_revision, with_expected_revision};
+use crate::{ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide team members routes
@@ -15,29 +18,33 @@ use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 ///
 /// `POST   site/team`: create a team member
 ///
-/// `PUT    site/team/{id}` update a team member
+/// `PUT    site/team/{id}` update a team member, rejected with a `409` if the `If-Match` revision is stale
 ///
 /// `DELETE site/team/{id}` delete a team member
+///
+/// The team can read them, the writes need the `edit_team` permission
 
 pub fn build_teams_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditTeam))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_team_member);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
         .and_then(list_teams);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditTeam))
         .and(warp::path::param::<Uuid>())
         .and_then(delete_team_member);
 
     let update = warp::put()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditTeam))
         .and(warp::path::param::<Uuid>())
+        .and(with_expected_revision())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(update_team_member);
@@ -70,12 +77,21 @@ async fn list_teams(repos: Repositories) -> Result<impl Reply, Rejection> {
 async fn update_team_member(
     repos: Repositories,
     uuid: Uuid,
+    expected: Option<u32>,
     input: PartialTeamMember,
 ) -> Result<impl Reply, Rejection> {
-    info!("Update team member {:?}", input);
-    let result = repos.team().update(uuid, input).await.map_err(Oops::db)?;
+    info!(
+        "Update team member {:?} with revision {:?}",
+        input, expected
+    );
+    let result = repos
+        .team()
+        .update(uuid, input, expected)
+        .await
+        .map_err(Oops::db)?;
     info!("Updated the team members {:?}", result);
-    let result = warp::reply::json(&result);
+    let revision = repos.team().revision(uuid).await.map_err(Oops::db)?;
+    let result = json_with_revision(&result, revision);
 
     Ok(result)
 }
@@ -2,10 +2,12 @@ use uuid::Uuid;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Rejection, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_database::Repositories;
 
+use crate::authentication::with_permission;
 use crate::rejection::Oops;
-use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::{ServerContext, MAX_BODY_LENGTH};
 
 ///
 /// Provide team member types routes
@@ -17,25 +19,28 @@ use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
 /// `PUT    site/member-types/{id}` update a member type
 ///
 /// `DELETE site/member-types/{id}` delete a member type
+///
+/// The team can read them, the writes need the `edit_team` permission
 
 pub fn build_team_member_types_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditTeam))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_member_type);
 
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ViewSite))
         .and_then(list_member_types);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditTeam))
         .and(warp::path::param::<Uuid>())
         .and_then(delete_member_type);
 
     let update = warp::put()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::EditTeam))
         .and(warp::path::param::<Uuid>())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
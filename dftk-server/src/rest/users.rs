@@ -1,62 +1,189 @@
+use anyhow::anyhow;
+use bytes::Bytes;
 use serde::Deserialize;
 use warp::filters::BoxedFilter;
 use warp::{Filter, Reply};
 
+use dftk_common::acl::operation::Operation;
 use dftk_common::acl::user::{Email, User};
 use dftk_database::Repositories;
 
+use crate::accounts::{self, create_speaker_accounts, create_sponsor_accounts, parse_mapping};
+use crate::authentication::{authorize, with_permission};
+use crate::password::invite_user;
 use crate::rejection::Oops;
-use crate::{with_repo, ServerContext, MAX_BODY_LENGTH};
+use crate::{with_context, with_repo, ServerContext, MAX_BODY_LENGTH};
+
+/// The maximum size of an uploaded mapping file
+const MAX_MAPPING_LENGTH: u64 = 1024 * 1024; // 1Mb
 
 /// Provide user routes
 ///
 /// `GET     users`: list users
 ///
-/// `POST    users`: create a new user
+/// `POST    users`: create a new user, and send the invitation to choose its password
+///
+/// `POST    users/speakers`: create the accounts of the accepted speakers, from a `key,email` mapping file
+///
+/// `POST    users/sponsors`: create the accounts of the sponsors, from a `key,email` mapping file
 ///
 /// `PUT     users/{email}`: update user password
 ///
+/// `PATCH   users/{email}`: update the kind, the key or the roles of the user
+///
 /// `DELETE  users/{email}`: delete user
+///
+/// `GET     users/{email}/sessions`: list the active sessions of the user
+///
+/// `DELETE  users/{email}/sessions`: revoke every session of the user
+///
+/// `DELETE  users/{email}/sessions/{id}`: revoke a session of the user
+///
+/// A speaker or a sponsor user should match an existing speaker or sponsor,
+/// the bulk creations skip the existing users, invite again the ones still without password,
+/// and reply with a report.
+/// Changing the password, updating or deleting the user revokes all its sessions,
+/// deleting the user also revokes its API tokens.
+/// A user can change its own password, the other routes need the `manage_users` permission,
+/// a created or updated user cannot be granted more than the permissions of the current user,
+/// and a deleted user or a user with revoked sessions cannot hold more than them either
 
 pub fn build_users_routes(context: &ServerContext) -> BoxedFilter<(impl Reply,)> {
     let create = warp::post()
-        .and(with_repo(context.repos()))
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(with_context(context.clone()))
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(create_user);
 
+    let speakers = warp::post()
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(with_context(context.clone()))
+        .and(warp::path!("speakers"))
+        .and(warp::body::content_length_limit(MAX_MAPPING_LENGTH))
+        .and(warp::body::bytes())
+        .and_then(create_speakers);
+
+    let sponsors = warp::post()
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(with_context(context.clone()))
+        .and(warp::path!("sponsors"))
+        .and(warp::body::content_length_limit(MAX_MAPPING_LENGTH))
+        .and(warp::body::bytes())
+        .and_then(create_sponsors);
+
     let list = warp::get() //
-        .and(with_repo(context.repos())) //
+        .and(warp::path::end())
+        .and(with_permission(context, Operation::ManageUsers))
         .and_then(list_users);
 
     let delete = warp::delete()
-        .and(with_repo(context.repos()))
+        .and(with_permission(context, Operation::ManageUsers))
         .and(warp::path::param::<Email>())
+        .and(warp::path::end())
         .and_then(delete_user);
 
     let update = warp::put()
-        .and(with_repo(context.repos()))
+        .and(with_repo(context))
+        .and(with_context(context.clone()))
         .and(warp::path::param::<Email>())
+        .and(warp::path::end())
         .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
         .and(warp::body::json())
         .and_then(update_user);
 
-    create.or(list).or(delete).or(update).boxed()
+    let patch = warp::patch()
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::path::param::<Email>())
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(MAX_BODY_LENGTH))
+        .and(warp::body::json())
+        .and_then(patch_user);
+
+    let list_sessions = warp::get()
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::path!(Email / "sessions"))
+        .and_then(list_sessions);
+
+    let revoke_sessions = warp::delete()
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::path!(Email / "sessions"))
+        .and_then(revoke_sessions);
+
+    let revoke_session = warp::delete()
+        .and(with_permission(context, Operation::ManageUsers))
+        .and(warp::path!(Email / "sessions" / String))
+        .and_then(revoke_session);
+
+    create
+        .or(speakers)
+        .or(sponsors)
+        .or(list)
+        .or(delete)
+        .or(update)
+        .or(patch)
+        .or(list_sessions)
+        .or(revoke_sessions)
+        .or(revoke_session)
+        .boxed()
 }
 
-async fn create_user(repos: Repositories, user: User) -> Result<impl warp::Reply, warp::Rejection> {
+async fn create_user(
+    repos: Repositories,
+    context: ServerContext,
+    user: User,
+) -> Result<impl warp::Reply, warp::Rejection> {
     info!("Creating a new user {:?}", user);
-    let pwd = repos
-        .user()
-        .new_user(user.clone())
+    repos.check_grant(&user).await.map_err(Oops::db)?;
+    let expires_at = invite_user(&context, &repos, user.clone())
         .await
         .map_err(Oops::db)?;
-    // FIXME remote the password from log, send an email
-    debug!("Created user {:?} with the new password: '{}'", user, pwd);
+    info!("Invited user {:?} until {}", user, expires_at);
     let result = warp::reply::reply();
     Ok(result)
 }
 
+async fn create_speakers(
+    repos: Repositories,
+    context: ServerContext,
+    body: Bytes,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mapping = read_mapping(&body)?;
+    info!(
+        "Creating the accounts of the speakers from {} e-mail(s)",
+        mapping.len()
+    );
+    let report = create_speaker_accounts(&context, &repos, &mapping)
+        .await
+        .map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&report))
+}
+
+async fn create_sponsors(
+    repos: Repositories,
+    context: ServerContext,
+    body: Bytes,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mapping = read_mapping(&body)?;
+    info!(
+        "Creating the accounts of the sponsors from {} e-mail(s)",
+        mapping.len()
+    );
+    let report = create_sponsor_accounts(&context, &repos, &mapping)
+        .await
+        .map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&report))
+}
+
+fn read_mapping(body: &Bytes) -> Result<Vec<(String, Email)>, warp::Rejection> {
+    let content = std::str::from_utf8(body).map_err(|err| Oops::bad("mapping", err.into()))?;
+
+    parse_mapping(content).map_err(|err| Oops::bad("mapping", err))
+}
+
 async fn list_users(repos: Repositories) -> Result<impl warp::Reply, warp::Rejection> {
     info!("Getting list of users");
     let users = repos.user().find_all().await.map_err(Oops::db)?;
@@ -73,28 +200,253 @@ struct ChangePassword {
 
 async fn update_user(
     repos: Repositories,
+    context: ServerContext,
     email: Email,
     change_password: ChangePassword,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     info!("Update user {:?} password", email);
+    if repos.current_user().email() != Some(email.clone()) {
+        authorize(&repos, &Operation::ManageUsers).await?;
+    }
     let ChangePassword { old, new } = change_password;
     let info = repos
         .user()
-        .change_password(&email, old.as_bytes(), new.as_bytes())
+        .change_password(
+            &email,
+            old.as_bytes(),
+            new.as_bytes(),
+            &context.server_config().password_policy,
+        )
         .await
         .map_err(Oops::db)?;
     info!("Updated user {:?}", info);
+    let revoked = repos
+        .user_session()
+        .revoke_all(&email)
+        .await
+        .map_err(Oops::db)?;
+    info!("Revoked {} session(s) of {:?}", revoked, email);
     let result = warp::reply::reply();
     Ok(result)
 }
 
+async fn patch_user(
+    repos: Repositories,
+    email: Email,
+    user: User,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Update user {:?} to {:?}", email, user);
+    if user.email().as_ref() != Some(&email) {
+        let err = anyhow!("Expected the e-mail {:?}", email);
+        return Err(Oops::bad("email", err));
+    }
+    let result = accounts::update_user(&repos, &email, user)
+        .await
+        .map_err(Oops::db)?;
+    let user = result.ok_or_else(warp::reject::not_found)?;
+
+    Ok(warp::reply::json(&user))
+}
+
 async fn delete_user(
     repos: Repositories,
     email: Email,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     info!("Deleting user {:?}", email);
+    accounts::check_managed_user(&repos, &email)
+        .await
+        .map_err(Oops::db)?;
     repos.user().delete_user(&email).await.map_err(Oops::db)?;
     info!("Deleted user {:?}", email);
+    let revoked = repos
+        .user_session()
+        .revoke_all(&email)
+        .await
+        .map_err(Oops::db)?;
+    info!("Revoked {} session(s) of {:?}", revoked, email);
+    let revoked = repos
+        .api_token()
+        .revoke_all(&email)
+        .await
+        .map_err(Oops::db)?;
+    info!("Revoked {} API token(s) of {:?}", revoked, email);
+    let result = warp::reply::reply();
+    Ok(result)
+}
+
+async fn list_sessions(
+    repos: Repositories,
+    email: Email,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Getting sessions of user {:?}", email);
+    accounts::check_managed_user(&repos, &email)
+        .await
+        .map_err(Oops::db)?;
+    let sessions = repos
+        .user_session()
+        .find_by_email(&email)
+        .await
+        .map_err(Oops::db)?;
+
+    Ok(warp::reply::json(&sessions))
+}
+
+async fn revoke_sessions(
+    repos: Repositories,
+    email: Email,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Revoking sessions of user {:?}", email);
+    accounts::check_managed_user(&repos, &email)
+        .await
+        .map_err(Oops::db)?;
+    let revoked = repos
+        .user_session()
+        .revoke_all(&email)
+        .await
+        .map_err(Oops::db)?;
+    info!("Revoked {} session(s) of {:?}", revoked, email);
     let result = warp::reply::reply();
     Ok(result)
 }
+
+async fn revoke_session(
+    repos: Repositories,
+    email: Email,
+    id: String,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    info!("Revoking session {} of user {:?}", id, email);
+    accounts::check_managed_user(&repos, &email)
+        .await
+        .map_err(Oops::db)?;
+    let sessions = repos
+        .user_session()
+        .find_by_email(&email)
+        .await
+        .map_err(Oops::db)?;
+    if !sessions.iter().any(|it| it.id() == id) {
+        return Err(warp::reject::not_found());
+    }
+    repos
+        .user_session()
+        .revoke(id.as_str())
+        .await
+        .map_err(Oops::db)?;
+    let result = warp::reply::reply();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use warp::http::StatusCode;
+
+    use dftk_common::acl::role::{Permission, Role, RoleKey};
+
+    use super::*;
+    use crate::routes;
+    use crate::tests::{memory_context, user_token};
+
+    #[tokio::test]
+    async fn should_not_grant_more_than_the_user_manager() {
+        let context = memory_context().await;
+        let repos = context.repos();
+        let role = Role::new(
+            RoleKey::new("user-manager"),
+            "User manager".into(),
+            vec![Permission::ViewSite, Permission::ManageUsers],
+        );
+        repos.role().save(&role).await.unwrap();
+        let manager = User::Team {
+            email: "manager@devfest.fr".parse().unwrap(),
+            roles: vec![role.key()],
+        };
+        let jane = User::Team {
+            email: "jane@devfest.fr".parse().unwrap(),
+            roles: vec![],
+        };
+        repos.user().new_user(jane).await.unwrap();
+        let authorization = format!("Bearer {}", user_token(&context, &manager).await);
+
+        let admin = json!({"type": "Admin", "email": "jane@devfest.fr"});
+        let response = warp::test::request()
+            .method("PATCH")
+            .path("/api/users/jane@devfest.fr")
+            .header("Authorization", authorization.as_str())
+            .json(&admin)
+            .reply(&routes(&context))
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let committee = json!({
+            "type": "Team",
+            "email": "john@devfest.fr",
+            "roles": ["program-committee"]
+        });
+        let response = warp::test::request()
+            .method("POST")
+            .path("/api/users")
+            .header("Authorization", authorization.as_str())
+            .json(&committee)
+            .reply(&routes(&context))
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let jane: Email = "jane@devfest.fr".parse().unwrap();
+        let user = repos.user().find_by_email(&jane).await.unwrap();
+        assert_eq!(user.map(|it| it.roles()), Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn should_not_delete_more_than_the_user_manager() {
+        let context = memory_context().await;
+        let repos = context.repos();
+        let role = Role::new(
+            RoleKey::new("user-manager"),
+            "User manager".into(),
+            vec![Permission::ViewSite, Permission::ManageUsers],
+        );
+        repos.role().save(&role).await.unwrap();
+        let manager = User::Team {
+            email: "manager@devfest.fr".parse().unwrap(),
+            roles: vec![role.key()],
+        };
+        let admin = User::Admin {
+            email: "admin@devfest.fr".parse().unwrap(),
+        };
+        repos.user().new_user(admin.clone()).await.unwrap();
+        let admin_session = user_token(&context, &admin).await;
+        let authorization = format!("Bearer {}", user_token(&context, &manager).await);
+
+        for (method, path) in &[
+            ("GET", "/api/users/admin@devfest.fr/sessions"),
+            ("DELETE", "/api/users/admin@devfest.fr/sessions"),
+            ("DELETE", "/api/users/admin@devfest.fr"),
+        ] {
+            let response = warp::test::request()
+                .method(method)
+                .path(path)
+                .header("Authorization", authorization.as_str())
+                .reply(&routes(&context))
+                .await;
+            assert_eq!(
+                response.status(),
+                StatusCode::FORBIDDEN,
+                "{} {}",
+                method,
+                path
+            );
+        }
+
+        let email = admin.email().unwrap();
+        assert_eq!(
+            repos.user().find_by_email(&email).await.unwrap(),
+            Some(admin)
+        );
+        let response = warp::test::request()
+            .path("/api/users")
+            .header("Authorization", format!("Bearer {}", admin_session))
+            .reply(&routes(&context))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
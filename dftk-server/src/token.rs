@@ -0,0 +1,307 @@
+//! Signed session tokens: JSON Web Tokens signed with HMAC SHA-256
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, ensure, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use dftk_common::acl::user::User;
+
+const ALGORITHM: &str = "HS256";
+
+/// Accepted clock difference on the issued-at claim, in seconds
+const LEEWAY: i64 = 60;
+
+/// The secrets and the lifetime of the tokens
+///
+/// The first secret signs the new tokens, the others are still accepted,
+/// so a secret can be rotated without disconnecting everybody.
+#[derive(Clone)]
+pub struct TokenConfig {
+    secrets: Vec<String>,
+    ttl: Duration,
+}
+
+impl TokenConfig {
+    pub fn new(secrets: Vec<String>, ttl: Duration) -> Self {
+        Self { secrets, ttl }
+    }
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        Self::new(vec![], Duration::days(1))
+    }
+}
+
+impl Debug for TokenConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenConfig")
+            .field("secrets", &self.secrets.len())
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Claims {
+    /// The user email, or `guest`
+    sub: String,
+    iat: i64,
+    exp: i64,
+    /// The token id
+    jti: String,
+    user: User,
+}
+
+impl Claims {
+    pub fn subject(&self) -> String {
+        self.sub.clone()
+    }
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.iat, 0)
+    }
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.exp, 0)
+    }
+    pub fn id(&self) -> String {
+        self.jti.clone()
+    }
+    pub fn user(&self) -> User {
+        self.user.clone()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Header {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
+struct SigningKey {
+    id: String,
+    secret: Vec<u8>,
+}
+
+impl SigningKey {
+    /// The key id is derived from the secret, so it does not depend on the order of the secrets
+    fn new(secret: &str) -> Self {
+        let digest = Sha256::digest(secret.as_bytes());
+        let id = digest
+            .iter()
+            .take(4)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let secret = secret.as_bytes().to_vec();
+
+        Self { id, secret }
+    }
+
+    fn mac(&self, content: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_varkey(&self.secret).expect("HMAC accepts keys of any size");
+        mac.input(content.as_bytes());
+
+        mac
+    }
+}
+
+/// Issue and verify the session tokens
+#[derive(Clone)]
+pub struct Tokens {
+    keys: Arc<Vec<SigningKey>>,
+    ttl: Duration,
+}
+
+impl Tokens {
+    pub fn new(config: &TokenConfig) -> Self {
+        let mut keys: Vec<SigningKey> = config
+            .secrets
+            .iter()
+            .filter(|it| !it.is_empty())
+            .map(|it| SigningKey::new(it))
+            .collect();
+        if keys.is_empty() {
+            warn!("No token secret configured, the sessions will not survive a restart");
+            let secret = format!("{}{}", Uuid::new_v4(), Uuid::new_v4());
+            keys.push(SigningKey::new(secret.as_str()));
+        }
+        let keys = Arc::new(keys);
+
+        Self {
+            keys,
+            ttl: config.ttl,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// A new signed token for the user, with its claims
+    pub fn issue(&self, user: &User) -> Result<(String, Claims)> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: subject(user),
+            iat: now.timestamp(),
+            exp: (now + self.ttl).timestamp(),
+            jti: Uuid::new_v4().to_string(),
+            user: user.clone(),
+        };
+        let key = &self.keys[0];
+        let header = Header {
+            alg: ALGORITHM.into(),
+            typ: "JWT".into(),
+            kid: key.id.clone(),
+        };
+        let content = format!("{}.{}", encode(&header)?, encode(&claims)?);
+        let signature = key.mac(content.as_str()).result().code();
+        let token = format!("{}.{}", content, base64_url(&signature));
+
+        Ok((token, claims))
+    }
+
+    /// The claims of a token, if its signature is valid and it is not expired
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let mut parts = token.rsplitn(2, '.');
+        let signature = parts.next().unwrap_or_default();
+        let content = parts.next().ok_or_else(|| anyhow!("Malformed token"))?;
+        let mut segments = content.splitn(2, '.');
+        let header: Header = decode(segments.next().unwrap_or_default())?;
+        let claims = segments.next().ok_or_else(|| anyhow!("Malformed token"))?;
+        ensure!(
+            header.alg == ALGORITHM,
+            "Unsupported algorithm {}",
+            header.alg
+        );
+
+        let key = self
+            .keys
+            .iter()
+            .find(|it| it.id == header.kid)
+            .ok_or_else(|| anyhow!("Unknown signing key {}", header.kid))?;
+        let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)?;
+        key.mac(content)
+            .verify(&signature)
+            .map_err(|_| anyhow!("Invalid token signature"))?;
+
+        let claims: Claims = decode(claims)?;
+        let now = Utc::now().timestamp();
+        if claims.exp <= now {
+            bail!("Token expired at {}", claims.expires_at());
+        }
+        ensure!(claims.iat <= now + LEEWAY, "Token issued in the future");
+
+        Ok(claims)
+    }
+}
+
+fn subject(user: &User) -> String {
+    user.email()
+        .map(|it| it.into())
+        .unwrap_or_else(|| "guest".into())
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+fn encode<T>(value: &T) -> Result<String>
+where
+    T: Serialize,
+{
+    let json = serde_json::to_vec(value)?;
+
+    Ok(base64_url(&json))
+}
+
+fn decode<T>(segment: &str) -> Result<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let json = base64::decode_config(segment, base64::URL_SAFE_NO_PAD)?;
+    let result = serde_json::from_slice(&json)?;
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use dftk_common::acl::user::Email;
+
+    use super::*;
+
+    fn admin() -> User {
+        let email = Email::from_str("admin@devfest.fr").unwrap();
+        User::Admin { email }
+    }
+
+    fn tokens(secrets: &[&str], ttl: Duration) -> Tokens {
+        let secrets = secrets.iter().map(|it| String::from(*it)).collect();
+
+        Tokens::new(&TokenConfig::new(secrets, ttl))
+    }
+
+    #[test]
+    fn should_verify_issued_token() {
+        let tokens = tokens(&["s3cr3t"], Duration::hours(1));
+        let (token, claims) = tokens.issue(&admin()).unwrap();
+
+        let result = tokens.verify(token.as_str()).unwrap();
+        assert_eq!(result, claims);
+        assert_eq!(result.user(), admin());
+        assert_eq!(result.subject(), "<admin@devfest.fr>");
+    }
+
+    #[test]
+    fn should_reject_forged_token() {
+        let tokens = tokens(&["s3cr3t"], Duration::hours(1));
+        let (token, _) = tokens.issue(&admin()).unwrap();
+        let forger = sign_with(&token, "guess");
+
+        assert!(tokens.verify(forger.as_str()).is_err());
+        assert!(tokens.verify("not.a.token").is_err());
+    }
+
+    /// Sign the same header and claims with another secret
+    fn sign_with(token: &str, secret: &str) -> String {
+        let mut parts = token.rsplitn(2, '.');
+        parts.next();
+        let content = parts.next().unwrap();
+        let key = SigningKey {
+            id: String::new(),
+            secret: secret.as_bytes().to_vec(),
+        };
+        let signature = key.mac(content).result().code();
+
+        format!("{}.{}", content, base64_url(&signature))
+    }
+
+    #[test]
+    fn should_reject_expired_token() {
+        let tokens = tokens(&["s3cr3t"], Duration::seconds(-1));
+        let (token, _) = tokens.issue(&admin()).unwrap();
+
+        assert!(tokens.verify(token.as_str()).is_err());
+    }
+
+    #[test]
+    fn should_accept_rotated_secret() {
+        let old = tokens(&["old"], Duration::hours(1));
+        let (token, _) = old.issue(&admin()).unwrap();
+
+        let rotated = tokens(&["new", "old"], Duration::hours(1));
+        assert!(rotated.verify(token.as_str()).is_ok());
+
+        let revoked = tokens(&["new"], Duration::hours(1));
+        assert!(revoked.verify(token.as_str()).is_err());
+    }
+}
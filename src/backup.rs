@@ -0,0 +1,43 @@
+use std::fs::read;
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use dftk_database::backup::{Archive, RestoreMode, RestoreResult};
+use dftk_database::{MongodbConfig, Repositories};
+
+use crate::schedule::write_output;
+
+pub async fn run_export(
+    mongo_config: &MongodbConfig,
+    with_passwords: bool,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    info!("Export the database {}", mongo_config.database);
+    let repos = Repositories::build(mongo_config).await?;
+    let archive = repos.backup(with_passwords).await?;
+    info!(
+        "Exported {} document(s) from {} collection(s)",
+        archive.nb_documents(),
+        archive.collections().len()
+    );
+    let content = serde_json::to_string_pretty(&archive)?;
+
+    write_output(content, output)
+}
+
+pub async fn run_import(
+    mongo_config: &MongodbConfig,
+    input: PathBuf,
+    mode: RestoreMode,
+) -> Result<RestoreResult> {
+    info!(
+        "Import the archive {:?} into {} with {:?}",
+        input, mongo_config.database, mode
+    );
+    let data = read(input)?;
+    let archive: Archive = serde_json::from_slice(data.as_slice())?;
+    let repos = Repositories::build(mongo_config).await?;
+
+    repos.restore(&archive, mode).await
+}
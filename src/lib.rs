@@ -5,14 +5,20 @@ use anyhow::Result;
 
 use dftk_server::{run_server, ServerContext};
 
+use crate::backup::{run_export, run_import};
 use crate::clean::run_clean;
 use crate::generate::run_generate;
-use crate::opts::Command;
+use crate::migrate::run_migrate;
+use crate::opts::{Command, ScheduleCommand};
+use crate::schedule::{run_schedule_export, run_schedule_export_grid, run_schedule_import_grid};
 use crate::synchronize::run_synchronize;
 
+pub mod backup;
 pub mod clean;
 pub mod generate;
+pub mod migrate;
 pub mod opts;
+pub mod schedule;
 pub mod synchronize;
 
 pub async fn run_command(command: Command) -> Result<()> {
@@ -48,6 +54,46 @@ pub async fn run_command(command: Command) -> Result<()> {
             info!("Generate result: {:?}", result);
         }
 
+        Command::Schedule { mongodb, command } => match command {
+            ScheduleCommand::Export { format, output } => {
+                // export schedule
+                run_schedule_export(&mongodb.into(), format, output).await?
+            }
+            ScheduleCommand::ExportGrid { output } => {
+                // export schedule grid
+                run_schedule_export_grid(&mongodb.into(), output).await?
+            }
+            ScheduleCommand::ImportGrid { input, dry_run } => {
+                // import schedule grid
+                run_schedule_import_grid(&mongodb.into(), input, dry_run).await?
+            }
+        },
+
+        Command::Migrate { mongodb, dry_run } => {
+            // migrate database
+            let result = run_migrate(&mongodb.into(), dry_run).await?;
+            info!("Migration result: {:?}", result);
+        }
+
+        Command::Export {
+            mongodb,
+            with_passwords,
+            output,
+        } => {
+            // export database
+            run_export(&mongodb.into(), with_passwords, output).await?
+        }
+
+        Command::Import {
+            mongodb,
+            input,
+            mode,
+        } => {
+            // import database
+            let result = run_import(&mongodb.into(), input, mode).await?;
+            info!("Import result: {:?}", result);
+        }
+
         Command::Clean { site_dir } => {
             // just clean
             run_clean(site_dir).await?
@@ -0,0 +1,30 @@
+use anyhow::Result;
+
+use dftk_database::migrations::{migrate, MigrationResult};
+use dftk_database::MongodbConfig;
+
+pub async fn run_migrate(
+    mongo_config: &MongodbConfig,
+    dry_run: bool,
+) -> Result<Vec<MigrationResult>> {
+    info!("Migrate the database {}", mongo_config.database);
+    let results = migrate(mongo_config, dry_run).await?;
+
+    for result in results.iter() {
+        let scope = result
+            .event()
+            .unwrap_or_else(|| "shared collections".into());
+        for step in result.steps() {
+            info!(
+                "{}[{}] #{} {}: {} document(s)",
+                if dry_run { "[dry-run] " } else { "" },
+                scope,
+                step.version(),
+                step.description(),
+                step.nb_documents()
+            );
+        }
+    }
+
+    Ok(results)
+}
@@ -1,10 +1,20 @@
 use std::path::PathBuf;
 
+use chrono::Duration;
 use structopt::StructOpt;
 
+use dftk_common::models::site::EventId;
 use dftk_conference_hall::ConferenceHallConfig;
+use dftk_database::backup::RestoreMode;
+use dftk_database::login_attempts::ThrottleConfig;
+use dftk_database::storage::StorageKind;
+use dftk_database::user::PasswordPolicy;
 use dftk_database::MongodbConfig;
+use dftk_hugo_site::frab::FrabFormat;
 use dftk_hugo_site::SiteConfig;
+use dftk_server::mailer::{MailerConfig, SmtpConfig};
+use dftk_server::oidc::OidcConfig;
+use dftk_server::token::TokenConfig;
 use dftk_server::ServerConfig;
 
 #[derive(Debug, Clone, StructOpt)]
@@ -46,6 +56,43 @@ pub enum Command {
         #[structopt(flatten)]
         server: ServerOpts,
     },
+    /// Schedule utilities
+    Schedule {
+        #[structopt(flatten)]
+        mongodb: MongodbOpts,
+        #[structopt(subcommand)]
+        command: ScheduleCommand,
+    },
+    /// Migrate the database schema to the current version
+    Migrate {
+        #[structopt(flatten)]
+        mongodb: MongodbOpts,
+        /// Only display the pending migrations, without applying them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Export every collection of the database into a JSON archive
+    Export {
+        #[structopt(flatten)]
+        mongodb: MongodbOpts,
+        /// Include the users password hashes
+        #[structopt(long)]
+        with_passwords: bool,
+        /// The output file, default to the standard output
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Import a JSON archive into the database
+    Import {
+        #[structopt(flatten)]
+        mongodb: MongodbOpts,
+        /// The archive file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// The restore mode: replace or merge
+        #[structopt(short, long, default_value = "merge")]
+        mode: RestoreMode,
+    },
     /// Cleaning some data
     Clean {
         /// The output site directory
@@ -53,6 +100,34 @@ pub enum Command {
     },
 }
 
+#[derive(Debug, Clone, StructOpt)]
+pub enum ScheduleCommand {
+    /// Export the schedule with the frab format (pretalx, C3VOC tooling)
+    Export {
+        /// The export format: xml or json
+        #[structopt(short, long, default_value = "xml")]
+        format: FrabFormat,
+        /// The output file, default to the standard output
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Export the schedule grid as CSV: rows are slots, columns are rooms
+    ExportGrid {
+        /// The output file, default to the standard output
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    /// Import the schedule grid from CSV, cells are session keys or titles
+    ImportGrid {
+        /// The CSV file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Only display the changes, without saving the schedule
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
 #[derive(StructOpt, Debug, Clone)]
 pub struct SiteDirOpts {
     /// The output site directory
@@ -97,10 +172,11 @@ impl Into<ConferenceHallConfig> for ConferenceHallOpts {
 
 #[derive(StructOpt, Debug, Clone)]
 pub struct MongodbOpts {
-    /// The MongoDB URI
+    /// The database URL, `mongodb://...` or `file://path/to/devfest.json` for a single file
     #[structopt(
         short = "u",
         long,
+        alias = "database-url",
         env = "MONGODB_URI",
         default_value = "mongodb://localhost:27017/"
     )]
@@ -109,6 +185,14 @@ pub struct MongodbOpts {
     /// The MongoDB database
     #[structopt(short = "d", long, env = "MONGODB_DATABASE", default_value = "devfest")]
     pub(crate) database: String,
+
+    /// The storage, `mongodb`, `file` or `memory` to run without database, deduced from the URL by default
+    #[structopt(long, env = "STORAGE")]
+    pub(crate) storage: Option<StorageKind>,
+
+    /// The event, e.g. `devfest-2021`, without event the collections are not scoped
+    #[structopt(long, env = "EVENT")]
+    pub(crate) event: Option<String>,
 }
 
 impl Into<MongodbConfig> for MongodbOpts {
@@ -116,9 +200,16 @@ impl Into<MongodbConfig> for MongodbOpts {
         let MongodbOpts {
             uri: mongodb_uri,
             database: mongodb_database,
+            storage,
+            event,
         } = self;
 
-        MongodbConfig::new(mongodb_uri, mongodb_database)
+        let event = event.map(EventId::new);
+        let config = MongodbConfig::new(mongodb_uri, mongodb_database).with_event(event);
+        match storage {
+            Some(storage) => config.with_storage(storage),
+            None => config,
+        }
     }
 }
 
@@ -139,6 +230,100 @@ pub struct ServerOpts {
     /// The REST api path
     #[structopt(long, env = "REST_PATH", default_value = "api")]
     pub(crate) rest_path: String,
+
+    /// The secrets signing the session tokens, the first one signs the new tokens,
+    /// the others are still accepted to rotate the secrets
+    #[structopt(long = "token-secret", env = "TOKEN_SECRETS", use_delimiter = true)]
+    pub(crate) token_secrets: Vec<String>,
+
+    /// The lifetime of the session tokens, in hours
+    #[structopt(long, env = "TOKEN_TTL_HOURS", default_value = "24")]
+    pub(crate) token_ttl_hours: i64,
+
+    /// The sender of the invitations and the password resets
+    #[structopt(long, env = "MAIL_FROM", default_value = "noreply@localhost")]
+    pub(crate) mail_from: String,
+
+    /// The page choosing a password, the mails link to it with a `token` parameter
+    #[structopt(
+        long,
+        env = "PASSWORD_URL",
+        default_value = "http://localhost:8080/#password"
+    )]
+    pub(crate) password_url: String,
+
+    /// The SMTP server sending the mails, without it the mails are only logged
+    #[structopt(long, env = "SMTP_HOST")]
+    pub(crate) smtp_host: Option<String>,
+
+    /// The SMTP username
+    #[structopt(long, env = "SMTP_USERNAME")]
+    pub(crate) smtp_username: Option<String>,
+
+    /// The SMTP password
+    #[structopt(long, env = "SMTP_PASSWORD", hide_env_values = true)]
+    pub(crate) smtp_password: Option<String>,
+
+    /// Write the mails into this directory when there is no SMTP server
+    #[structopt(long, env = "MAIL_DIR", parse(from_os_str))]
+    pub(crate) mail_dir: Option<PathBuf>,
+
+    /// The minimum length of a new password
+    #[structopt(long, env = "PASSWORD_MIN_LENGTH", default_value = "10")]
+    pub(crate) password_min_length: usize,
+
+    /// The failed logins of an e-mail before its lockout
+    #[structopt(long, env = "LOGIN_MAX_FAILURES", default_value = "5")]
+    pub(crate) login_max_failures: u32,
+
+    /// The failed logins of an IP address before its lockout
+    #[structopt(long, env = "LOGIN_MAX_FAILURES_PER_IP", default_value = "20")]
+    pub(crate) login_max_failures_per_ip: u32,
+
+    /// The duration of a lockout, and of the window counting the failed logins, in minutes
+    #[structopt(long, env = "LOGIN_LOCKOUT_MINUTES", default_value = "15")]
+    pub(crate) login_lockout_minutes: i64,
+
+    /// The reverse proxies appending the client address to the `X-Forwarded-For` header,
+    /// the remote address is the client address without proxy
+    #[structopt(long, env = "TRUSTED_PROXIES", default_value = "0")]
+    pub(crate) trusted_proxies: usize,
+
+    /// The OpenID Connect issuer, e.g. `https://accounts.google.com`, without it only the passwords are used
+    #[structopt(long, env = "OIDC_ISSUER")]
+    pub(crate) oidc_issuer: Option<String>,
+
+    /// The OpenID Connect client id
+    #[structopt(long, env = "OIDC_CLIENT_ID")]
+    pub(crate) oidc_client_id: Option<String>,
+
+    /// The OpenID Connect client secret
+    #[structopt(long, env = "OIDC_CLIENT_SECRET", hide_env_values = true)]
+    pub(crate) oidc_client_secret: Option<String>,
+
+    /// The callback registered with the identity provider
+    #[structopt(
+        long,
+        env = "OIDC_REDIRECT_URL",
+        default_value = "http://localhost:8080/auth/oidc/callback"
+    )]
+    pub(crate) oidc_redirect_url: String,
+
+    /// The domains allowed to login with OpenID Connect, all of them by default. Only the existing accounts login
+    #[structopt(
+        long = "oidc-allowed-domain",
+        env = "OIDC_ALLOWED_DOMAINS",
+        use_delimiter = true
+    )]
+    pub(crate) oidc_allowed_domains: Vec<String>,
+
+    /// The page opened after an OpenID Connect login
+    #[structopt(
+        long,
+        env = "OIDC_POST_LOGIN_URL",
+        default_value = "http://localhost:8080/"
+    )]
+    pub(crate) oidc_post_login_url: String,
 }
 
 impl Into<ServerConfig> for ServerOpts {
@@ -148,8 +333,60 @@ impl Into<ServerConfig> for ServerOpts {
             port,
             graphql_path,
             rest_path,
+            token_secrets,
+            token_ttl_hours,
+            mail_from,
+            password_url,
+            smtp_host,
+            smtp_username,
+            smtp_password,
+            mail_dir,
+            password_min_length,
+            login_max_failures,
+            login_max_failures_per_ip,
+            login_lockout_minutes,
+            trusted_proxies,
+            oidc_issuer,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_redirect_url,
+            oidc_allowed_domains,
+            oidc_post_login_url,
         } = self;
+        let token = TokenConfig::new(token_secrets, Duration::hours(token_ttl_hours));
+        let credentials = smtp_username.zip(smtp_password);
+        let smtp = smtp_host.map(|host| SmtpConfig::new(host, credentials));
+        let mailer = MailerConfig::new(mail_from, password_url, smtp, mail_dir);
+        let password_policy = PasswordPolicy {
+            min_length: password_min_length,
+        };
+        let throttle = ThrottleConfig {
+            max_failures_per_email: login_max_failures,
+            max_failures_per_ip: login_max_failures_per_ip,
+            window: Duration::minutes(login_lockout_minutes),
+            lockout: Duration::minutes(login_lockout_minutes),
+        };
+
+        let config = ServerConfig::new(host, port, graphql_path, rest_path)
+            .with_token(token)
+            .with_mailer(mailer)
+            .with_password_policy(password_policy)
+            .with_throttle(throttle)
+            .with_trusted_proxies(trusted_proxies);
 
-        ServerConfig::new(host, port, graphql_path, rest_path)
+        match (oidc_issuer, oidc_client_id, oidc_client_secret) {
+            (Some(issuer), Some(client_id), Some(client_secret)) => {
+                let oidc = OidcConfig::new(
+                    issuer,
+                    client_id,
+                    client_secret,
+                    oidc_redirect_url,
+                    oidc_allowed_domains,
+                    oidc_post_login_url,
+                );
+                config.with_oidc(oidc)
+            }
+            _ => config,
+        }
     }
 }
@@ -0,0 +1,114 @@
+use std::fs::{read, File};
+use std::io::{stdout, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use dftk_common::models::schedule::grid::{export_grid, import_grid};
+use dftk_database::{MongodbConfig, Repositories};
+use dftk_hugo_site::frab::{frab_schedule, FrabFormat};
+
+pub async fn run_schedule_export(
+    mongo_config: &MongodbConfig,
+    format: FrabFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    info!(
+        "Export the schedule from {} with the {:?} format",
+        mongo_config.database, format
+    );
+    let repos = Repositories::build(mongo_config).await?;
+    let site = repos.load_site().await?;
+    let content = frab_schedule(&site).export(format)?;
+
+    write_output(content, output)
+}
+
+pub async fn run_schedule_export_grid(
+    mongo_config: &MongodbConfig,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    info!("Export the schedule grid from {}", mongo_config.database);
+    let repos = Repositories::build(mongo_config).await?;
+    let site = repos.load_site().await?;
+    let content = export_grid(&site)?;
+
+    write_output(content, output)
+}
+
+pub async fn run_schedule_import_grid(
+    mongo_config: &MongodbConfig,
+    input: PathBuf,
+    dry_run: bool,
+) -> Result<()> {
+    info!(
+        "Import the schedule grid {:?} into {}",
+        input, mongo_config.database
+    );
+    let repos = Repositories::build(mongo_config).await?;
+    let site = repos.load_site().await?;
+    let data = read(input)?;
+    let result = import_grid(&site, data.as_slice())?;
+
+    for change in result.changes() {
+        info!(
+            "{:?} at {:?}: {:?} -> {:?}",
+            change.room(),
+            change.slot(),
+            change.before(),
+            change.after()
+        );
+    }
+    for fuzzy in result.fuzzy() {
+        warn!(
+            "'{}' matched {:?} (score {:.2})",
+            fuzzy.value(),
+            fuzzy.session(),
+            fuzzy.score()
+        );
+    }
+    for unknown in result.unknown() {
+        warn!(
+            "Unknown '{}' at line {} in column '{}'",
+            unknown.value(),
+            unknown.line(),
+            unknown.column()
+        );
+    }
+    for duplicated in result.duplicated() {
+        warn!(
+            "{:?} placed again at line {} in column '{}'",
+            duplicated.session(),
+            duplicated.line(),
+            duplicated.column()
+        );
+    }
+
+    if !result.is_valid() {
+        bail!(
+            "Could not import the schedule grid, {} unknown cell(s), {} duplicated session(s)",
+            result.unknown().len(),
+            result.duplicated().len()
+        );
+    }
+    if dry_run {
+        info!("Dry run, {} change(s) not saved", result.changes().len());
+    } else {
+        repos.update_schedule(result.schedule(), None).await?;
+        info!("Schedule saved with {} change(s)", result.changes().len());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_output(content: String, output: Option<PathBuf>) -> Result<()> {
+    if let Some(output) = output {
+        debug!("Writing to {:?}", output);
+        let mut file = File::create(output)?;
+        file.write_all(content.as_bytes())?;
+    } else {
+        stdout().write_all(content.as_bytes())?;
+    }
+
+    Ok(())
+}